@@ -0,0 +1,212 @@
+// Benchmarks of the hot paths of a run over a synthetic graph of 1k registry crates: looking
+// crates up in cargo's registry, copying them and rewriting manifests. `cargo bench` measures them,
+// `cargo bench -- --save-baseline <name>` and `--baseline <name>` compare against an earlier run.
+// `cargo test --benches` runs each once, which only shows they still run: nothing here fails on a
+// slowdown, comparing against a baseline is how one is found.
+use cargo_localize::bench;
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CRATES: usize = 1000;
+// Files per crate besides its manifest and lib.rs, and their size
+const MODULES: usize = 8;
+const MODULE_SIZE: usize = 2048;
+// Crates the workspace depends on directly, each crate depends on the next few
+const DIRECT: usize = 50;
+const FAN_OUT: usize = 3;
+const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+fn name(index: usize) -> String {
+    format!("crate{index:04}")
+}
+
+fn dependencies(index: usize) -> impl Iterator<Item = usize> {
+    (index + 1..CRATES).take(FAN_OUT)
+}
+
+fn manifest(name: &str, dependencies: impl Iterator<Item = usize>) -> String {
+    let mut manifest =
+        format!("[package]\nname = \"{name}\"\nversion = \"1.0.0\"\nedition = \"2021\"\n\n[dependencies]\n");
+    for dependency in dependencies {
+        manifest.push_str(&format!("{} = \"1.0\"\n", self::name(dependency)));
+    }
+    manifest
+}
+
+// cargo's `registry/src` with every crate extracted into `<index>/<name>-<version>`, the total
+// size of their files
+fn registry(root: &Path) -> u64 {
+    let index = root.join("index.crates.io-0000000000000000");
+    let mut size = 0;
+    for index_of_crate in 0..CRATES {
+        let crate_dir = index.join(format!("{}-1.0.0", name(index_of_crate)));
+        fs::create_dir_all(crate_dir.join("src")).unwrap();
+        let files = [(
+            "Cargo.toml".to_string(),
+            manifest(&name(index_of_crate), dependencies(index_of_crate)),
+        )]
+        .into_iter()
+        .chain([("src/lib.rs".to_string(), "pub fn f() {}\n".to_string())])
+        .chain((0..MODULES).map(|module| (format!("src/m{module}.rs"), "// filler\n".repeat(MODULE_SIZE / 10))));
+        for (path, content) in files {
+            size += content.len() as u64;
+            fs::write(crate_dir.join(path), content).unwrap();
+        }
+    }
+    size
+}
+
+fn registry_index(c: &mut Criterion) {
+    let scratch = tempfile::tempdir().unwrap();
+    registry(scratch.path());
+    let mut group = c.benchmark_group("registry index");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(CRATES as u64));
+    group.bench_function("find every crate", |b| {
+        let names: Vec<String> = (0..CRATES).map(name).collect();
+        b.iter(|| bench::find_crate_sources(scratch.path(), names.iter().map(|name| (name.as_str(), "1.0.0"))).unwrap())
+    });
+    group.finish();
+}
+
+fn copy_throughput(c: &mut Criterion) {
+    let scratch = tempfile::tempdir().unwrap();
+    let registry_src = scratch.path().join("src");
+    let size = registry(&registry_src);
+    let sources: Vec<PathBuf> = fs::read_dir(registry_src.join("index.crates.io-0000000000000000"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    let third_party = scratch.path().join("3rd-party");
+    let mut group = c.benchmark_group("copy");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(size));
+    for buffer_size in [64 * 1024, 1024 * 1024] {
+        group.bench_function(format!("{} KiB buffers", buffer_size / 1024), |b| {
+            b.iter_batched(
+                || {
+                    let _ = fs::remove_dir_all(&third_party);
+                    fs::create_dir_all(&third_party).unwrap();
+                },
+                |()| {
+                    for source in &sources {
+                        bench::copy_crate(source, &third_party.join(source.file_name().unwrap()), buffer_size).unwrap();
+                    }
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn package(name: &str, manifest_path: &Path, source: Option<&str>, dependencies: Vec<String>) -> Value {
+    json!({
+        "name": name,
+        "version": "1.0.0",
+        "id": id(name, source),
+        "source": source,
+        "manifest_path": manifest_path,
+        "dependencies": dependencies.iter().map(|dependency| json!({
+            "name": dependency,
+            "source": REGISTRY,
+            "req": "^1.0",
+            "kind": null,
+            "optional": false,
+            "uses_default_features": true,
+            "features": [],
+            "target": null,
+            "rename": null,
+            "registry": null,
+        })).collect::<Vec<_>>(),
+        "targets": [],
+        "features": {},
+        "edition": "2021",
+        "authors": [],
+        "categories": [],
+        "keywords": [],
+    })
+}
+
+fn id(name: &str, source: Option<&str>) -> String {
+    match source {
+        Some(source) => format!("{source}#{name}@1.0.0"),
+        None => format!("path+file:///bench#{name}@1.0.0"),
+    }
+}
+
+// The workspace and the vendored crates, with their manifests as cargo left them, and the
+// metadata cargo reports for them right after the copy
+fn vendored_project(root: &Path) -> (Value, Vec<(PathBuf, String)>) {
+    let third_party = root.join("3rd-party");
+    let registry_src = root.join("registry");
+    let direct: Vec<String> = (0..DIRECT).map(name).collect();
+    let mut manifests = vec![(root.join("Cargo.toml"), manifest("app", 0..DIRECT))];
+    let mut packages = vec![package("app", &root.join("Cargo.toml"), None, direct.clone())];
+    let mut nodes = vec![json!({
+        "id": id("app", None),
+        "deps": direct.iter().map(|dependency| json!({"name": dependency, "pkg": id(dependency, Some(REGISTRY)), "dep_kinds": []})).collect::<Vec<_>>(),
+        "dependencies": direct.iter().map(|dependency| id(dependency, Some(REGISTRY))).collect::<Vec<_>>(),
+        "features": [],
+    })];
+    for index in 0..CRATES {
+        let name = name(index);
+        let depends_on: Vec<String> = dependencies(index).map(self::name).collect();
+        manifests.push((
+            third_party.join(format!("{name}-1.0.0")).join("Cargo.toml"),
+            manifest(&name, dependencies(index)),
+        ));
+        packages.push(package(
+            &name,
+            &registry_src.join(format!("{name}-1.0.0")).join("Cargo.toml"),
+            Some(REGISTRY),
+            depends_on.clone(),
+        ));
+        nodes.push(json!({
+            "id": id(&name, Some(REGISTRY)),
+            "deps": depends_on.iter().map(|dependency| json!({"name": dependency, "pkg": id(dependency, Some(REGISTRY)), "dep_kinds": []})).collect::<Vec<_>>(),
+            "dependencies": depends_on.iter().map(|dependency| id(dependency, Some(REGISTRY))).collect::<Vec<_>>(),
+            "features": ["default"],
+        }));
+    }
+    let metadata = json!({
+        "packages": packages,
+        "workspace_members": [id("app", None)],
+        "resolve": {"nodes": nodes, "root": id("app", None)},
+        "workspace_root": root,
+        "target_directory": root.join("target"),
+        "version": 1,
+    });
+    (metadata, manifests)
+}
+
+fn manifest_rewriting(c: &mut Criterion) {
+    let scratch = tempfile::tempdir().unwrap();
+    let (metadata, manifests) = vendored_project(scratch.path());
+    let metadata: cargo_metadata::Metadata = serde_json::from_value(metadata).unwrap();
+    let mut group = c.benchmark_group("rewrite");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(manifests.len() as u64));
+    group.bench_function("every manifest", |b| {
+        b.iter_batched(
+            || {
+                for (path, content) in &manifests {
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(path, content).unwrap();
+                    let _ = fs::remove_file(path.with_extension("toml.bak"));
+                }
+            },
+            |()| bench::rewrite_manifests(&metadata, scratch.path(), "3rd-party").unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+    // Entries left alone would make it measure next to nothing
+    let root_manifest = fs::read_to_string(scratch.path().join("Cargo.toml")).unwrap();
+    assert!(root_manifest.contains("path = \"3rd-party/crate0000-1.0.0\""));
+}
+
+criterion_group!(benches, registry_index, copy_throughput, manifest_rewriting);
+criterion_main!(benches);
@@ -0,0 +1,422 @@
+use crate::journal::Journal;
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock, LockedPackage, Strategy};
+use anyhow::{Context, Result, bail};
+use cargo_metadata::Metadata;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+const CRATES_IO_SOURCE: &str = "registry+https://github.com/rust-lang/crates.io-index";
+// Files packaging tools add next to a crate's sources
+pub const PACKAGING_FILES: [&str; 5] = [
+    ".cargo-ok",
+    ".cargo-checksum.json",
+    ".cargo_vcs_info.json",
+    "Cargo.toml.orig",
+    "Cargo.toml.bak",
+];
+const DEPENDENCY_TABLES: [&str; 4] = [
+    "dependencies",
+    "dev-dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+#[derive(clap::Args)]
+pub struct AdoptArgs {
+    #[clap(flatten)]
+    project: crate::ProjectArgs,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Only show what would be adopted
+    #[clap(long)]
+    dry_run: bool,
+}
+
+// How a vendored directory compares with the registry's version of the crate
+enum Provenance {
+    Pristine,
+    // Tests, benches and examples removed, like `--trim`
+    Trimmed,
+    // Files that differ, were added or were removed
+    Modified(Vec<String>),
+    // Not on crates.io, or not downloadable right now
+    Unknown,
+}
+
+// Brings a hand-vendored tree under management: every path dependency into the 3rd-party folder is
+// matched against crates.io, and localize.lock is written as if cargo-localize had vendored it.
+// Nothing is copied or rewritten, the manifests already point at the tree.
+pub fn adopt(args: &AdoptArgs) -> Result<()> {
+    let project_path = args.project.path.root()?;
+    if LocalizeLock::load(&project_path)?.is_some() {
+        bail!("{LOCK_FILE_NAME} already exists, the tree is managed by cargo-localize");
+    }
+    let third_party_dir = crate::config::third_party_dir(&args.project, &project_path)?;
+    let third_party_path = project_path.join(&third_party_dir);
+    if !third_party_path.is_dir() {
+        bail!(
+            "{} doesn't exist, pass the vendored folder with --third-party-dir",
+            third_party_path.display()
+        );
+    }
+    let third_party_path = third_party_path.canonicalize().context("Invalid 3rd-party path")?;
+
+    info!("Getting metadata...");
+    let metadata = args
+        .network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(args.network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let vendored = vendored_packages(&metadata, &third_party_path);
+    if vendored.is_empty() {
+        bail!(
+            "No dependency resolves into {}, pass the vendored folder with --third-party-dir",
+            third_party_path.display()
+        );
+    }
+
+    let missing: Vec<(String, String)> = vendored
+        .iter()
+        .map(|(package, _)| (package.name.clone(), package.version.to_string()))
+        .filter(|(name, version)| crate::registry::crates_io_archive(name, version).is_none())
+        .collect();
+    if !missing.is_empty() && !args.network.offline() && args.network.allows_registry() {
+        info!("Downloading {} crate(s) to compare with...", missing.len());
+        download(metadata.target_directory.as_std_path(), &missing, None, &args.network)?;
+    }
+
+    let mut localize_lock = LocalizeLock::new(Strategy::Rewrite, &third_party_dir);
+    let mut report = String::new();
+    let mut modified = 0;
+    for (package, features) in &vendored {
+        let version = package.version.to_string();
+        let crate_dir = package
+            .manifest_path
+            .parent()
+            .context("Manifest has no parent directory")?
+            .as_std_path();
+        let provenance = provenance(crate_dir, &package.name, &version)?;
+        let (source, checksum) = match &provenance {
+            Provenance::Unknown => (None, None),
+            _ => {
+                let archive = crate::registry::crates_io_archive(&package.name, &version)
+                    .context("The compared .crate disappeared")?;
+                (
+                    Some(CRATES_IO_SOURCE.to_string()),
+                    Some(crate::journal::file_sha256(&archive)?),
+                )
+            }
+        };
+        let status = match &provenance {
+            Provenance::Pristine => "matches crates.io".to_string(),
+            Provenance::Trimmed => "matches crates.io, trimmed".to_string(),
+            Provenance::Modified(files) => {
+                modified += 1;
+                let shown: Vec<&str> = files.iter().take(5).map(String::as_str).collect();
+                let more = files.len().saturating_sub(shown.len());
+                let more = if more > 0 {
+                    format!(" and {more} more")
+                } else {
+                    String::new()
+                };
+                format!("differs from crates.io: {}{more}", shown.join(", "))
+            }
+            Provenance::Unknown => "not found on crates.io, kept as a local crate".to_string(),
+        };
+        writeln!(report, "  {} v{version}: {status}", package.name)?;
+
+        localize_lock.packages.push(LockedPackage {
+            name: package.name.clone(),
+            version,
+            source,
+            checksum,
+            rev: None,
+            features: features.clone(),
+            path: crate_dir
+                .strip_prefix(&third_party_path)
+                .context("Vendored crate outside the 3rd-party folder")?
+                .to_string_lossy()
+                .replace('\\', "/"),
+            trimmed: matches!(provenance, Provenance::Trimmed),
+            ignored: Vec::new(),
+            declared_features: false,
+            pruned_targets: Vec::new(),
+            nested_lockfile: None,
+            frozen: false,
+            patches: Vec::new(),
+        });
+    }
+
+    println!("{} crate(s) vendored by hand in {third_party_dir}/:", vendored.len());
+    print!("{report}");
+    if modified > 0 {
+        warn!("{modified} crate(s) differ from their crates.io version, the differences are kept as they are");
+    }
+    if args.dry_run {
+        println!("Dry run, {LOCK_FILE_NAME} wasn't written");
+        return Ok(());
+    }
+    let journal = Journal::default();
+    localize_lock.save(&project_path, &journal)?;
+    crate::index::write(&project_path, &localize_lock, &journal)?;
+    println!("Wrote {LOCK_FILE_NAME}, the tree is now managed by cargo-localize");
+    Ok(())
+}
+
+// Packages resolving into the 3rd-party folder that aren't workspace members, with the features
+// cargo resolved for them
+fn vendored_packages<'a>(
+    metadata: &'a Metadata,
+    third_party_path: &Path,
+) -> Vec<(&'a cargo_metadata::Package, Vec<String>)> {
+    let features: HashMap<_, _> = metadata
+        .resolve
+        .iter()
+        .flat_map(|resolve| &resolve.nodes)
+        .map(|node| (&node.id, node.features.clone()))
+        .collect();
+    let mut vendored: Vec<_> = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none() && package.manifest_path.starts_with(third_party_path))
+        .filter(|package| {
+            let member = metadata.workspace_members.contains(&package.id);
+            if member {
+                debug!("{} is a workspace member, not adopted", package.name);
+            }
+            !member
+        })
+        .map(|package| (package, features.get(&package.id).cloned().unwrap_or_default()))
+        .collect();
+    vendored.sort_by(|a, b| (&a.0.name, &a.0.version).cmp(&(&b.0.name, &b.0.version)));
+    vendored
+}
+
+// Fetches the given crate versions through a throwaway project, so cargo downloads them into its
+// cache with the usual network settings. Crates of an alternative registry name it as it's called
+// in cargo's config; cargo authenticates with the token from its credentials file or
+// `CARGO_REGISTRIES_<NAME>_TOKEN`, for git and sparse indexes alike.
+pub fn download(
+    target_dir: &Path,
+    crates: &[(String, String)],
+    registry: Option<&str>,
+    network: &crate::NetworkArgs,
+) -> Result<()> {
+    let scratch = target_dir.join("cargo-localize-adopt");
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch).context("Failed to clean the scratch project")?;
+    }
+    fs::create_dir_all(scratch.join("src")).context("Failed to create the scratch project")?;
+    fs::write(scratch.join("src/lib.rs"), "").context("Failed to create the scratch project")?;
+
+    // One at a time, a crate that isn't on the registry would fail the whole resolution otherwise
+    for (name, version) in crates {
+        let requirement = match registry {
+            Some(registry) => format!("{{ version = \"={version}\", registry = \"{registry}\" }}"),
+            None => format!("\"={version}\""),
+        };
+        let manifest = format!(
+            "[package]\nname = \"cargo-localize-adopt\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+             [workspace]\n\n[dependencies]\n{name} = {requirement}\n"
+        );
+        fs::write(scratch.join("Cargo.toml"), manifest).context("Failed to write the scratch manifest")?;
+        let _ = fs::remove_file(scratch.join("Cargo.lock"));
+        // Run from inside the project, so its `.cargo/config.toml` and the registries it names apply
+        let output = network
+            .cargo("fetch")
+            .arg("--manifest-path")
+            .arg(scratch.join("Cargo.toml"))
+            .current_dir(&scratch)
+            .output()
+            .context("Failed to run cargo fetch")?;
+        if output.status.success() {
+            continue;
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(registry) = registry
+            && is_auth_error(&stderr)
+        {
+            warn!(
+                "Couldn't download {name} v{version}, registry `{registry}` wants a token: set \
+                 CARGO_REGISTRIES_{}_TOKEN or run `cargo login --registry {registry}`, with \
+                 `global-credential-providers = [\"cargo:token\"]` under `[registry]` in cargo's config",
+                registry.to_uppercase().replace('-', "_")
+            );
+            continue;
+        }
+        debug!("Couldn't download {name} v{version}: {}", stderr.trim());
+    }
+    fs::remove_dir_all(&scratch).context("Failed to remove the scratch project")
+}
+
+// What cargo says when a registry wants a token it didn't have or rejected. Crate names and
+// versions show up in the same output, so only cargo's own messages and the status count.
+fn is_auth_error(stderr: &str) -> bool {
+    stderr.lines().any(|line| {
+        line.contains("no token found")
+            || line.contains("token rejected for")
+            || http_status(line).is_some_and(|status| status == 401 || status == 403)
+    })
+}
+
+// Status in cargo's "failed to get successful HTTP response from `<url>` (<ip>), got <status>"
+fn http_status(line: &str) -> Option<u16> {
+    let (_, response) = line.split_once("failed to get successful HTTP response from `")?;
+    let (_, status) = response.rsplit_once(", got ")?;
+    status.split_whitespace().next()?.parse().ok()
+}
+
+fn provenance(crate_dir: &Path, name: &str, version: &str) -> Result<Provenance> {
+    let Some(archive) = crate::registry::crates_io_archive(name, version) else {
+        return Ok(Provenance::Unknown);
+    };
+    let bytes = fs::read(&archive).context(format!("Failed to read {}", archive.display()))?;
+    let mut published = crate::registry::archive_files(&bytes)?;
+    let original_manifest = published.remove(Path::new("Cargo.toml.orig"));
+    let normalized_manifest = published.remove(Path::new("Cargo.toml"));
+    for file in PACKAGING_FILES {
+        published.remove(Path::new(file));
+    }
+
+    let mut vendored: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+    for entry in WalkDir::new(crate_dir)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(crate_dir)?.to_path_buf();
+        if PACKAGING_FILES.iter().any(|file| relative == Path::new(file)) {
+            continue;
+        }
+        let content = fs::read(entry.path()).context(format!("Failed to read {}", entry.path().display()))?;
+        vendored.insert(relative, content);
+    }
+    let vendored_manifest = vendored.remove(Path::new("Cargo.toml"));
+
+    let mut differences = Vec::new();
+    let mut trimmed = false;
+    for (path, content) in &published {
+        match vendored.get(path) {
+            Some(vendored) if vendored == content => {}
+            Some(_) => differences.push(display(path)),
+            None if crate::trim::TRIM_DIRS.iter().any(|dir| path.starts_with(dir)) => trimmed = true,
+            None => differences.push(format!("{} (removed)", display(path))),
+        }
+    }
+    for path in vendored.keys().filter(|path| !published.contains_key(*path)) {
+        differences.push(format!("{} (added)", display(path)));
+    }
+
+    // Vendored from the registry the manifest is cargo's normalized one, from a checkout the
+    // author's. Dependency entries are left out, vendoring by hand repoints them at the tree,
+    // and trimming drops the test, bench and example sections.
+    let comparable = |manifest: &[u8]| comparable_manifest(manifest, trimmed);
+    let manifest_matches = vendored_manifest
+        .as_deref()
+        .and_then(comparable)
+        .is_some_and(|manifest| {
+            [&normalized_manifest, &original_manifest]
+                .into_iter()
+                .flatten()
+                .any(|published| comparable(published).as_ref() == Some(&manifest))
+        });
+    if !manifest_matches {
+        differences.insert(0, "Cargo.toml".to_string());
+    }
+
+    Ok(if !differences.is_empty() {
+        Provenance::Modified(differences)
+    } else if trimmed {
+        Provenance::Trimmed
+    } else {
+        Provenance::Pristine
+    })
+}
+
+// The manifest without dependency tables, or the target tables holding them, which vendoring
+// rewrites or prunes
+pub fn comparable_manifest(manifest: &[u8], trimmed: bool) -> Option<String> {
+    let mut doc = std::str::from_utf8(manifest).ok()?.parse::<DocumentMut>().ok()?;
+    for table in DEPENDENCY_TABLES {
+        doc.remove(table);
+    }
+    if let Some(targets) = doc.get_mut("target").and_then(|targets| targets.as_table_like_mut()) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_like_mut() {
+                for table in DEPENDENCY_TABLES {
+                    target.remove(table);
+                }
+            }
+        }
+        let emptied: Vec<String> = targets
+            .iter()
+            .filter(|(_, target)| target.as_table_like().is_some_and(|target| target.is_empty()))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for key in emptied {
+            targets.remove(&key);
+        }
+        if targets.is_empty() {
+            doc.remove("target");
+        }
+    }
+    if trimmed {
+        crate::trim::remove_trimmed_sections(&mut doc);
+    }
+    // The empty `[workspace]` vendoring adds
+    if doc
+        .get("workspace")
+        .and_then(|workspace| workspace.as_table_like())
+        .is_some_and(|workspace| workspace.is_empty())
+    {
+        doc.remove("workspace");
+    }
+    Some(doc.to_string())
+}
+
+fn display(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_errors_are_told_by_cargo_messages() {
+        for stderr in [
+            "error: no token found for `internal`, please run `cargo login --registry internal`",
+            "error: token rejected for `internal`, please run `cargo login --registry internal`",
+            "error: failed to download from `https://crates.example.com/api/v1/crates/serde/1.0.0/download`\n\n\
+             Caused by:\n  failed to get successful HTTP response from \
+             `https://crates.example.com/api/v1/crates/serde/1.0.0/download` (10.0.0.1), got 401\n  body:\n  {}",
+            "Caused by:\n  failed to get successful HTTP response from `https://index.example.com/config.json`, got 403",
+        ] {
+            assert!(is_auth_error(stderr), "{stderr}");
+        }
+    }
+
+    #[test]
+    fn other_failures_are_not_auth_errors() {
+        for stderr in [
+            "error: failed to select a version for the requirement `credential-store = \"^0.401\"`",
+            "error: no matching package named `authenticator` found\nlocation searched: registry `internal`",
+            "error: failed to download `unauthorized-access v4.0.3`",
+            "Caused by:\n  failed to get successful HTTP response from `https://index.example.com/40/3/403`, got 404",
+            "error: failed to get successful HTTP response from `https://crates.example.com/401`, got 500",
+            "warning: spurious network error (2 tries remaining): [28] Timeout was reached",
+        ] {
+            assert!(!is_auth_error(stderr), "{stderr}");
+        }
+    }
+}
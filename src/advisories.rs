@@ -0,0 +1,297 @@
+use crate::NetworkArgs;
+use crate::lock::LockedPackage;
+use crate::warnings::{self, WarningClass};
+use anyhow::{Context, Result, bail};
+use rustsec::Advisory;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+const ADVISORY_DB_URL: &str = "https://github.com/rustsec/advisory-db.git";
+// How old the local copy of the database may get before a run updates it
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(clap::Args, Clone, Default)]
+pub struct AdvisoryArgs {
+    /// Check the vendored crate versions against the RustSec advisory database
+    #[clap(long)]
+    pub advisories: bool,
+    /// Fail the run if a vendored crate version has a RustSec vulnerability advisory, same as
+    /// `--deny advisory`
+    #[clap(long)]
+    pub deny_advisories: bool,
+    /// Accept the risk of an advisory (`RUSTSEC-YYYY-NNNN` or one of its aliases), repeat for
+    /// several advisories
+    #[clap(long = "ignore-advisory", value_name = "ID")]
+    pub ignore_advisories: Vec<String>,
+    /// Local copy of the RustSec advisory database to check against, used as is
+    /// (default: `~/.cargo/advisory-db`, shared with cargo-audit and updated daily)
+    #[clap(long, value_name = "DIR")]
+    pub advisory_db: Option<PathBuf>,
+}
+
+impl AdvisoryArgs {
+    // Looking crates up fetches the database, so it's only done when asked for
+    pub fn requested(&self) -> bool {
+        self.advisories || self.deny_advisories || self.advisory_db.is_some()
+    }
+}
+
+// An advisory affecting a vendored crate version
+pub struct Found {
+    pub name: String,
+    pub version: String,
+    pub id: String,
+    pub title: String,
+    pub patched: Vec<String>,
+    // `unmaintained`, `unsound` or `notice`, `None` for vulnerabilities
+    pub informational: Option<String>,
+}
+
+impl Found {
+    // Vulnerabilities and unsoundness, the rest is only worth a mention
+    pub fn is_warning(&self) -> bool {
+        matches!(self.informational.as_deref(), None | Some("unsound"))
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://rustsec.org/advisories/{}", self.id)
+    }
+}
+
+// Checks the vendored crates.io crates against the RustSec advisory database. Vulnerabilities and
+// unsoundness raise `advisory` warnings, unmaintained crates and notices are only mentioned.
+pub fn scan(packages: &[LockedPackage], args: &AdvisoryArgs, network: &NetworkArgs) -> Result<()> {
+    if !args.requested() {
+        return Ok(());
+    }
+    let Some(db) = database(args, network) else {
+        if args.deny_advisories {
+            bail!("No advisory database to check the vendored crates against (--advisory-db)");
+        }
+        warn!("No advisory database, the vendored crates weren't checked for advisories");
+        return Ok(());
+    };
+
+    let (found, ignored) = find(&db, packages, &args.ignore_advisories)?;
+    for advisory in &found {
+        let patched = if advisory.patched.is_empty() {
+            "no patched version".to_string()
+        } else {
+            format!("patched: {}", advisory.patched.join(", "))
+        };
+        let message = format!(
+            "{} v{}: {} {} ({patched}), {}",
+            advisory.name,
+            advisory.version,
+            advisory.id,
+            advisory.title,
+            advisory.url()
+        );
+        if advisory.is_warning() {
+            warnings::warn(WarningClass::Advisory, message);
+        } else if let Some(kind) = &advisory.informational {
+            info!("{kind}: {message}");
+        }
+    }
+    if !found.iter().any(Found::is_warning) {
+        info!("No advisories for the vendored crates");
+    }
+    if ignored > 0 {
+        info!("{ignored} accepted advisory(ies) ignored");
+    }
+    Ok(())
+}
+
+// Advisories affecting the crates.io crates among `packages`, and how many accepted ones were ignored
+pub fn find(db: &Path, packages: &[LockedPackage], ignore: &[String]) -> Result<(Vec<Found>, usize)> {
+    let mut found = Vec::new();
+    let mut ignored = 0;
+    for package in packages.iter().filter(|package| package.is_from_crates_io()) {
+        let Ok(version) = rustsec::Version::parse(&package.version) else {
+            continue;
+        };
+        for advisory in advisories(db, &package.name)? {
+            if advisory.metadata.package.as_str() != package.name
+                || advisory.withdrawn()
+                || !advisory.versions.is_vulnerable(&version)
+            {
+                continue;
+            }
+            let advisory_id = advisory.id().as_str();
+            if ignore.iter().any(|ignore| {
+                ignore == advisory_id || advisory.metadata.aliases.iter().any(|alias| alias.as_str() == ignore)
+            }) {
+                debug!("{} v{}: {advisory_id} ignored", package.name, package.version);
+                ignored += 1;
+                continue;
+            }
+            found.push(Found {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                id: advisory_id.to_string(),
+                title: advisory.title().to_string(),
+                patched: advisory.versions.patched().iter().map(ToString::to_string).collect(),
+                informational: advisory
+                    .metadata
+                    .informational
+                    .as_ref()
+                    .map(|informational| informational.as_str().to_string()),
+            });
+        }
+    }
+    Ok((found, ignored))
+}
+
+// The database given with `--advisory-db`, or the shared copy, fetched or updated when the
+// network policy lets git through. `None` unless advisories were asked for.
+pub fn database(args: &AdvisoryArgs, network: &NetworkArgs) -> Option<PathBuf> {
+    if !args.requested() {
+        return None;
+    }
+    if let Some(db) = &args.advisory_db {
+        return db.join("crates").is_dir().then(|| db.clone());
+    }
+    let db = dirs::home_dir()?.join(".cargo").join("advisory-db");
+    if network.allows_git()
+        && let Err(error) = update(&db)
+    {
+        debug!("Couldn't update the advisory database: {error:#}");
+    }
+    db.join("crates").is_dir().then_some(db)
+}
+
+fn update(db: &Path) -> Result<()> {
+    let git = |args: &[&str]| -> Result<()> {
+        let output = Command::new("git").args(args).output().context("Failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    };
+
+    if !db.join(".git").exists() {
+        info!("Fetching the RustSec advisory database...");
+        return git(&[
+            "clone",
+            "--quiet",
+            "--depth",
+            "1",
+            ADVISORY_DB_URL,
+            &db.to_string_lossy(),
+        ]);
+    }
+    let fetched = [db.join(".git/FETCH_HEAD"), db.join(".git/HEAD")]
+        .iter()
+        .find_map(|file| fs::metadata(file).and_then(|metadata| metadata.modified()).ok());
+    let age = fetched.and_then(|fetched| SystemTime::now().duration_since(fetched).ok());
+    if age.is_some_and(|age| age < MAX_AGE) {
+        return Ok(());
+    }
+    info!("Updating the RustSec advisory database...");
+    let db = db.to_string_lossy();
+    git(&["-C", &db, "fetch", "--quiet", "--depth", "1", "origin", "HEAD"])?;
+    git(&["-C", &db, "reset", "--quiet", "--hard", "FETCH_HEAD"])
+}
+
+// Advisories filed against a crate. One the database holds in a form this version of rustsec
+// can't read is skipped, the others still count.
+fn advisories(db: &Path, name: &str) -> Result<Vec<Advisory>> {
+    let dir = db.join("crates").join(name);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut advisories = Vec::new();
+    for entry in fs::read_dir(&dir).context(format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "md") {
+            continue;
+        }
+        match Advisory::load_file(&path) {
+            Ok(advisory) => advisories.push(advisory),
+            Err(error) => warn!("Skipping advisory {}: {error}", path.display()),
+        }
+    }
+    advisories.sort_by(|a, b| a.id().as_str().cmp(b.id().as_str()));
+    Ok(advisories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    fn locked(name: &str, version: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: Some(REGISTRY.to_string()),
+            checksum: None,
+            rev: None,
+            features: Vec::new(),
+            path: format!("{name}-{version}"),
+            trimmed: false,
+            ignored: Vec::new(),
+            declared_features: false,
+            pruned_targets: Vec::new(),
+            nested_lockfile: None,
+            frozen: false,
+            patches: Vec::new(),
+        }
+    }
+
+    fn write_advisory(db: &Path, name: &str, id: &str, front_matter: &str) {
+        let dir = db.join("crates").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(format!("{id}.md")),
+            format!("```toml\n{front_matter}```\n\n# {name} does something bad\n\nDetails.\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn find_skips_advisories_it_cant_read() {
+        let db = tempfile::tempdir().unwrap();
+        write_advisory(
+            db.path(),
+            "foo",
+            "RUSTSEC-2020-0001",
+            "[advisory]\nid = \"RUSTSEC-2020-0001\"\npackage = \"foo\"\ndate = \"2020-01-01\"\naliases = [\"CVE-2020-1234\"]\n\n[versions]\npatched = [\">= 1.0.1\"]\n",
+        );
+        write_advisory(
+            db.path(),
+            "foo",
+            "RUSTSEC-2020-0002",
+            "[advisory]\nid = \"RUSTSEC-2020-0002\"\npackage = \"foo\"\ndate = \"not a date\"\n",
+        );
+
+        let packages = [locked("foo", "1.0.0"), locked("foo", "1.0.1")];
+        let (found, ignored) = find(db.path(), &packages, &[]).unwrap();
+        assert_eq!(ignored, 0);
+        assert!(matches!(
+            found.as_slice(),
+            [advisory] if advisory.id == "RUSTSEC-2020-0001" && advisory.version == "1.0.0" && advisory.is_warning()
+        ));
+        assert_eq!(found[0].patched, [">=1.0.1"]);
+        assert_eq!(found[0].title, "foo does something bad");
+
+        let (found, ignored) = find(db.path(), &packages, &["CVE-2020-1234".to_string()]).unwrap();
+        assert!(found.is_empty());
+        assert_eq!(ignored, 1);
+    }
+
+    #[test]
+    fn advisories_are_only_looked_up_when_asked_for() {
+        let args = AdvisoryArgs::default();
+        assert!(!args.requested());
+        assert!(database(&args, &NetworkArgs::default()).is_none());
+    }
+}
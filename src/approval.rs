@@ -0,0 +1,158 @@
+use crate::NetworkArgs;
+use crate::filter::PackageSpec;
+use crate::lock::{LocalizeLock, LockedPackage};
+use anyhow::{Context, Result, bail};
+use cargo_metadata::semver::Version;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+// DER prefix of an Ed25519 SubjectPublicKeyInfo, the 32-byte key follows it
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+#[derive(clap::Args, Clone, Default)]
+pub struct ApprovalArgs {
+    /// Organization-wide list of approved crates (a path or an http(s) URL), one `name@version`
+    /// per line; `--check` fails for vendored crates it doesn't approve
+    #[clap(long, value_name = "PATH|URL")]
+    pub approved_list: Option<String>,
+    /// Ed25519 public key (PEM or base64) the list's detached signature, `<list>.sig`, has to
+    /// verify against
+    #[clap(long, value_name = "FILE")]
+    pub approved_list_key: Option<PathBuf>,
+}
+
+// Vendored crates the approved list doesn't cover, none if no list is configured. A list fetched
+// over the network is cached, offline runs check against the last copy.
+pub fn unapproved<'a>(
+    packages: &'a [LockedPackage],
+    args: &ApprovalArgs,
+    network: &NetworkArgs,
+) -> Result<Vec<&'a LockedPackage>> {
+    let Some(location) = &args.approved_list else {
+        return Ok(Vec::new());
+    };
+    let list = read(location, network)?;
+    match &args.approved_list_key {
+        Some(key) => {
+            let signature = read(&format!("{location}.sig"), network)?;
+            verify(&list, &signature, key).context(format!("The approved list {location} can't be trusted"))?;
+            debug!("Signature of {location} verified");
+        }
+        None => warn!("The approved list isn't signed, pass --approved-list-key to verify it"),
+    }
+    let approved = parse(&String::from_utf8_lossy(&list)).context(format!("Invalid approved list {location}"))?;
+    info!("Checking the vendored crates against {} approval(s)", approved.len());
+
+    Ok(packages
+        .iter()
+        .filter(|locked| {
+            !Version::parse(&locked.version)
+                .is_ok_and(|version| approved.iter().any(|spec| spec.matches(&locked.name, &version)))
+        })
+        .collect())
+}
+
+// What to submit for approval, one line per crate with what reviewers look at
+pub fn submission(localize_lock: &LocalizeLock, unapproved: &[&LockedPackage], project_path: &Path) -> String {
+    let mut report = String::from("Submit for approval:");
+    for locked in unapproved {
+        let license = crate::export::inventory_entry(localize_lock, locked, project_path)
+            .ok()
+            .and_then(|entry| entry.license)
+            .unwrap_or_else(|| "no license declared".to_string());
+        let origin = match (&locked.source, &locked.checksum) {
+            (Some(source), Some(checksum)) => format!("{source}, sha256 {checksum}"),
+            (Some(source), None) => source.clone(),
+            (None, _) => "local".to_string(),
+        };
+        report.push_str(&format!(
+            "\n  {}@{}  {license}  ({origin})",
+            locked.name, locked.version
+        ));
+    }
+    report
+}
+
+// Specs, one per line; `#` starts a comment and text after the spec (a ticket, a reviewer) is
+// ignored
+fn parse(content: &str) -> Result<Vec<PackageSpec>> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|spec| spec.parse().map_err(anyhow::Error::msg))
+        .collect()
+}
+
+fn read(location: &str, network: &NetworkArgs) -> Result<Vec<u8>> {
+    if !location.starts_with("http://") && !location.starts_with("https://") {
+        return fs::read(location).context(format!("Failed to read {location}"));
+    }
+    let cached = dirs::cache_dir()
+        .context("No cache directory")?
+        .join("cargo-localize")
+        .join("approved")
+        .join(&crate::journal::sha256(location.as_bytes())[..16]);
+    if network.offline() {
+        return fs::read(&cached).context(format!("{location} wasn't fetched yet, run once with network access"));
+    }
+    match crate::http::get(location) {
+        Ok(content) => {
+            if let Some(parent) = cached.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&cached, &content).context(format!("Failed to cache {location}"))?;
+            Ok(content)
+        }
+        Err(error) if cached.is_file() => {
+            warn!("{error:#}, using the copy fetched last");
+            fs::read(&cached).context(format!("Failed to read {}", cached.display()))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// The signature is the raw 64 bytes, as `openssl pkeyutl -sign -rawin` writes it, or their base64
+fn verify(list: &[u8], signature: &[u8], key_path: &Path) -> Result<()> {
+    let key = fs::read_to_string(key_path).context(format!("Failed to read {}", key_path.display()))?;
+    let encoded: String = key.lines().filter(|line| !line.starts_with("-----")).collect();
+    let key = base64(&encoded)?;
+    let key = match key.strip_prefix(&ED25519_SPKI_PREFIX[..]) {
+        Some(key) => key,
+        None => &key,
+    };
+    if key.len() != 32 {
+        bail!("{} isn't an Ed25519 public key", key_path.display());
+    }
+    let signature = match signature.len() {
+        64 => signature.to_vec(),
+        _ => base64(&String::from_utf8_lossy(signature))?,
+    };
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, key)
+        .verify(list, &signature)
+        .map_err(|_| anyhow::anyhow!("Its signature doesn't match {}", key_path.display()))
+}
+
+// Standard or URL-safe alphabet, padding optional
+fn base64(text: &str) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => bail!("Invalid base64"),
+        };
+        bits = (bits << 6 | value as u32) & 0xffff;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            decoded.push((bits >> count) as u8);
+        }
+    }
+    Ok(decoded)
+}
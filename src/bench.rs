@@ -0,0 +1,64 @@
+// Internals the benchmarks in `benches/` measure, which they can't reach through the public API.
+// Not part of the API, anything here may change with the code it wraps.
+use crate::journal::Journal;
+use crate::lock::{LocalizeLock, LockedPackage, Strategy};
+use anyhow::Result;
+use cargo_metadata::Metadata;
+use fs_extra::dir::CopyOptions;
+use std::path::{Path, PathBuf};
+
+// Where cargo extracted each of the registry crates, looked up like a run does for the crates it
+// copies: the registry is scanned once, then every crate is found in the index
+pub fn find_crate_sources<'a>(
+    registry_src: &Path,
+    crates: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Vec<PathBuf>> {
+    let index = crate::registry::SourceIndex::scan(vec![registry_src.to_path_buf()])?;
+    crates
+        .into_iter()
+        .map(|(name, version)| index.find(name, version).map(Path::to_path_buf))
+        .collect()
+}
+
+// Copies one crate the way a run does, through buffers of `buffer_size` bytes
+pub fn copy_crate(from: &Path, to: &Path, buffer_size: usize) -> Result<()> {
+    let options = CopyOptions::new().overwrite(true).buffer_size(buffer_size);
+    Journal::default().copy_dir(from, to, &options, crate::config::SymlinkPolicy::default())
+}
+
+// Points the workspace's manifests and those of the crates vendored in `third_party_dir`, one
+// folder per crate named `<name>-<version>`, at the vendored crates
+pub fn rewrite_manifests(metadata: &Metadata, project_path: &Path, third_party_dir: &str) -> Result<()> {
+    let mut localize_lock = LocalizeLock::new(Strategy::Rewrite, third_party_dir);
+    localize_lock.packages = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_some())
+        .map(|package| LockedPackage {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            source: package.source.as_ref().map(|source| source.repr.clone()),
+            checksum: None,
+            rev: None,
+            features: Vec::new(),
+            path: format!("{}-{}", package.name, package.version),
+            trimmed: false,
+            ignored: Vec::new(),
+            declared_features: false,
+            pruned_targets: Vec::new(),
+            nested_lockfile: None,
+            frozen: false,
+            patches: Vec::new(),
+        })
+        .collect();
+    let third_party_path = localize_lock.third_party_path(project_path);
+    crate::update_cargo_toml(
+        metadata,
+        project_path,
+        &third_party_path,
+        &localize_lock,
+        None,
+        &crate::observer::Observers::default(),
+        &Journal::default(),
+    )
+}
@@ -0,0 +1,367 @@
+use crate::lock::LocalizeLock;
+use crate::util::{dir_size, format_size};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Crates listed under each direct dependency, the rest are summed up
+const CRATES_PER_CHANGE: usize = 5;
+const UNATTRIBUTED: &str = "(not reachable from a direct dependency)";
+
+#[derive(clap::Args)]
+pub struct BisectSizeArgs {
+    /// Cargo.lock file or git revision before the change
+    from: String,
+    /// Cargo.lock file or git revision after the change (default: the project's Cargo.lock)
+    to: Option<String>,
+    #[clap(long, default_value = ".")]
+    project_path: PathBuf,
+    /// Number of direct dependencies to list
+    #[clap(long, value_name = "N", default_value = "10")]
+    top: usize,
+}
+
+#[derive(Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<LockEntry>,
+}
+
+#[derive(Deserialize)]
+struct LockEntry {
+    name: String,
+    version: String,
+    source: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+type Key = (String, String);
+
+// Dependency graph resolved by a Cargo.lock
+struct Graph {
+    packages: Vec<LockEntry>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn parse(content: &str, origin: &str) -> Result<Self> {
+        let lock: CargoLock = toml::from_str(content).context(format!("Failed to parse the Cargo.lock of {origin}"))?;
+        let dependencies = lock
+            .package
+            .iter()
+            .map(|package| {
+                package
+                    .dependencies
+                    .iter()
+                    .filter_map(|dependency| {
+                        // `name`, `name version` or `name version (source)`, whichever is unambiguous
+                        let mut parts = dependency.split_whitespace();
+                        let name = parts.next()?;
+                        let version = parts.next();
+                        lock.package.iter().position(|candidate| {
+                            candidate.name == name && version.is_none_or(|version| candidate.version == version)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            packages: lock.package,
+            dependencies,
+        })
+    }
+
+    fn key(&self, index: usize) -> Key {
+        let package = &self.packages[index];
+        (package.name.clone(), package.version.clone())
+    }
+
+    // Workspace members and other path crates, they're never vendored
+    fn is_local(&self, index: usize) -> bool {
+        self.packages[index].source.is_none()
+    }
+
+    fn external(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.packages.len()).filter(|&index| !self.is_local(index))
+    }
+
+    // External crates local packages depend on
+    fn direct(&self) -> BTreeSet<usize> {
+        (0..self.packages.len())
+            .filter(|&index| self.is_local(index))
+            .flat_map(|index| self.dependencies[index].iter().copied())
+            .filter(|&dependency| !self.is_local(dependency))
+            .collect()
+    }
+
+    fn direct_versions(&self) -> BTreeMap<String, BTreeSet<String>> {
+        let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for index in self.direct() {
+            let (name, version) = self.key(index);
+            versions.entry(name).or_default().insert(version);
+        }
+        versions
+    }
+
+    // How many edges away each crate reachable from `start` is
+    fn distances(&self, start: usize) -> HashMap<usize, usize> {
+        let mut distances = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(index) = queue.pop_front() {
+            let distance = distances[&index];
+            for &dependency in &self.dependencies[index] {
+                if !self.is_local(dependency) && !distances.contains_key(&dependency) {
+                    distances.insert(dependency, distance + 1);
+                    queue.push_back(dependency);
+                }
+            }
+        }
+        distances
+    }
+
+    // Direct dependency each of `changed` is attributed to: among those pulling it in, one whose
+    // own version changed if there is one, then the closest
+    fn attribute(&self, changed: &HashSet<usize>, changed_directs: &HashSet<String>) -> HashMap<usize, String> {
+        let mut best: HashMap<usize, (bool, usize, String)> = HashMap::new();
+        for direct in self.direct() {
+            let name = self.packages[direct].name.clone();
+            let unchanged = !changed_directs.contains(&name);
+            for (index, distance) in self.distances(direct) {
+                if !changed.contains(&index) {
+                    continue;
+                }
+                let candidate = (unchanged, distance, name.clone());
+                if best.get(&index).is_none_or(|current| candidate < *current) {
+                    best.insert(index, candidate);
+                }
+            }
+        }
+        best.into_iter().map(|(index, (_, _, name))| (index, name)).collect()
+    }
+}
+
+// Sizes of crates once vendored, measured in the 3rd-party folder or the cargo registry
+struct Sizes {
+    vendored: HashMap<Key, PathBuf>,
+    known: HashMap<Key, Option<u64>>,
+}
+
+impl Sizes {
+    fn get(&mut self, package: &LockEntry) -> Option<u64> {
+        let key = (package.name.clone(), package.version.clone());
+        if let Some(size) = self.known.get(&key) {
+            return *size;
+        }
+        let size = match self.vendored.get(&key).filter(|path| path.is_dir()) {
+            Some(path) => Some(dir_size(path)),
+            None if package.source.as_deref().is_some_and(is_registry) => {
+                crate::registry::crate_size(&package.name, &package.version)
+            }
+            // Git checkouts aren't kept by revision
+            None => None,
+        };
+        self.known.insert(key, size);
+        size
+    }
+}
+
+#[derive(Default)]
+struct Change {
+    // Crates added and removed with their size, `None` if unknown
+    added: Vec<(Key, Option<u64>)>,
+    removed: Vec<(Key, Option<u64>)>,
+}
+
+impl Change {
+    fn net(&self) -> i64 {
+        let added: u64 = self.added.iter().filter_map(|(_, size)| *size).sum();
+        let removed: u64 = self.removed.iter().filter_map(|(_, size)| *size).sum();
+        added as i64 - removed as i64
+    }
+}
+
+pub fn bisect_size(args: &BisectSizeArgs) -> Result<()> {
+    let project_path = args.project_path.canonicalize().context("Invalid project path")?;
+    let from = load_graph(&project_path, &args.from)?;
+    let to = match &args.to {
+        Some(to) => load_graph(&project_path, to)?,
+        None => {
+            let cargo_lock = project_path.join("Cargo.lock");
+            if !cargo_lock.exists() {
+                anyhow::bail!(
+                    "No Cargo.lock in the project (localized projects drop it), pass the revision to compare with"
+                );
+            }
+            load_graph(&project_path, &cargo_lock.to_string_lossy())?
+        }
+    };
+
+    let vendored = LocalizeLock::load(&project_path)?
+        .map(|lock| {
+            let third_party_path = lock.third_party_path(&project_path);
+            lock.packages
+                .into_iter()
+                .map(|locked| ((locked.name, locked.version), third_party_path.join(locked.path)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut sizes = Sizes {
+        vendored,
+        known: HashMap::new(),
+    };
+
+    let from_keys: HashSet<Key> = from.external().map(|index| from.key(index)).collect();
+    let to_keys: HashSet<Key> = to.external().map(|index| to.key(index)).collect();
+    let added: HashSet<usize> = to
+        .external()
+        .filter(|&index| !from_keys.contains(&to.key(index)))
+        .collect();
+    let removed: HashSet<usize> = from
+        .external()
+        .filter(|&index| !to_keys.contains(&from.key(index)))
+        .collect();
+
+    let from_directs = from.direct_versions();
+    let to_directs = to.direct_versions();
+    let changed_directs: HashSet<String> = from_directs
+        .keys()
+        .chain(to_directs.keys())
+        .filter(|name| from_directs.get(*name) != to_directs.get(*name))
+        .cloned()
+        .collect();
+
+    // Additions are traced through the new graph, removals through the old one
+    let mut changes: BTreeMap<String, Change> = BTreeMap::new();
+    let added_to = to.attribute(&added, &changed_directs);
+    for &index in &added {
+        let direct = added_to.get(&index).map_or(UNATTRIBUTED, String::as_str);
+        let size = sizes.get(&to.packages[index]);
+        changes
+            .entry(direct.to_string())
+            .or_default()
+            .added
+            .push((to.key(index), size));
+    }
+    let removed_from = from.attribute(&removed, &changed_directs);
+    for &index in &removed {
+        let direct = removed_from.get(&index).map_or(UNATTRIBUTED, String::as_str);
+        let size = sizes.get(&from.packages[index]);
+        changes
+            .entry(direct.to_string())
+            .or_default()
+            .removed
+            .push((from.key(index), size));
+    }
+
+    let from_total: u64 = from
+        .external()
+        .filter_map(|index| sizes.get(&from.packages[index]))
+        .sum();
+    let to_total: u64 = to.external().filter_map(|index| sizes.get(&to.packages[index])).sum();
+    println!(
+        "Vendored size: {} -> {} ({}), {} -> {} crate(s)",
+        format_size(from_total),
+        format_size(to_total),
+        signed_size(to_total as i64 - from_total as i64),
+        from_keys.len(),
+        to_keys.len()
+    );
+    if changes.is_empty() {
+        println!("The vendored set is the same");
+        return Ok(());
+    }
+
+    let mut ordered: Vec<(&String, &mut Change)> = changes.iter_mut().collect();
+    ordered.sort_by(|a, b| b.1.net().cmp(&a.1.net()).then(a.0.cmp(b.0)));
+    println!();
+    println!("Largest increases by direct dependency:");
+    for (direct, change) in ordered.iter_mut().take(args.top) {
+        let label = if *direct == UNATTRIBUTED {
+            direct.to_string()
+        } else {
+            direct_label(direct, from_directs.get(*direct), to_directs.get(*direct))
+        };
+        println!("  {:>10}  {label}", signed_size(change.net()));
+
+        let mut lines: Vec<(char, &Key, Option<u64>)> = change
+            .added
+            .iter()
+            .map(|(key, size)| ('+', key, *size))
+            .chain(change.removed.iter().map(|(key, size)| ('-', key, *size)))
+            .collect();
+        lines.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(b.1)));
+        for (sign, (name, version), size) in lines.iter().take(CRATES_PER_CHANGE) {
+            let size = size.map_or("size unknown".to_string(), format_size);
+            println!("              {sign} {name} {version} ({size})");
+        }
+        if lines.len() > CRATES_PER_CHANGE {
+            println!("              ... {} more", lines.len() - CRATES_PER_CHANGE);
+        }
+    }
+    if ordered.len() > args.top {
+        println!("  ... {} more, --top lists more", ordered.len() - args.top);
+    }
+
+    let unknown = changes
+        .values()
+        .flat_map(|change| change.added.iter().chain(&change.removed))
+        .filter(|(_, size)| size.is_none())
+        .count();
+    if unknown > 0 {
+        println!();
+        println!(
+            "{unknown} changed crate(s) of unknown size weren't counted: git dependencies, or crates cargo never \
+             downloaded (`cargo fetch` at both revisions to include them)"
+        );
+    }
+    Ok(())
+}
+
+// A lockfile on disk, or the project's Cargo.lock at a git revision
+fn load_graph(project_path: &Path, spec: &str) -> Result<Graph> {
+    let path = Path::new(spec);
+    if path.is_file() {
+        let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        return Graph::parse(&content, spec);
+    }
+
+    let output = Command::new("git")
+        .args(["show", &format!("{spec}:./Cargo.lock")])
+        .current_dir(project_path)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{spec} is neither a file nor a git revision with a Cargo.lock: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Graph::parse(&String::from_utf8_lossy(&output.stdout), spec)
+}
+
+fn direct_label(name: &str, from: Option<&BTreeSet<String>>, to: Option<&BTreeSet<String>>) -> String {
+    let versions = |versions: &BTreeSet<String>| versions.iter().cloned().collect::<Vec<_>>().join(", ");
+    match (from, to) {
+        (Some(from), Some(to)) if from != to => format!("{name} {} -> {}", versions(from), versions(to)),
+        (Some(_), Some(to)) => format!("{name} {} (unchanged, its dependencies changed)", versions(to)),
+        (None, Some(to)) => format!("{name} {} (new direct dependency)", versions(to)),
+        (Some(from), None) => format!("{name} {} (no longer a direct dependency)", versions(from)),
+        (None, None) => format!("{name} (transitive)"),
+    }
+}
+
+fn is_registry(source: &str) -> bool {
+    source.starts_with("registry+") || source.starts_with("sparse+")
+}
+
+fn signed_size(bytes: i64) -> String {
+    if bytes < 0 {
+        format!("-{}", format_size(bytes.unsigned_abs()))
+    } else {
+        format!("+{}", format_size(bytes as u64))
+    }
+}
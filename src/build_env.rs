@@ -0,0 +1,303 @@
+use crate::lock::LocalizeLock;
+use crate::report::OutputFormat;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use walkdir::WalkDir;
+
+// Set by cargo for every build script, nothing to configure
+const CARGO_PROVIDED: [&str; 12] = [
+    "OUT_DIR",
+    "TARGET",
+    "HOST",
+    "NUM_JOBS",
+    "OPT_LEVEL",
+    "DEBUG",
+    "PROFILE",
+    "RUSTC",
+    "RUSTDOC",
+    "RUSTC_LINKER",
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+];
+// Build helpers and the variables they consult on behalf of a build script. `*` stands for a
+// library name or target triple.
+const HELPERS: [(&str, &[&str]); 5] = [
+    (
+        "cc",
+        &[
+            "CC",
+            "CXX",
+            "AR",
+            "CFLAGS",
+            "CXXFLAGS",
+            "ARFLAGS",
+            "CC_*",
+            "CFLAGS_*",
+            "CRATE_CC_NO_DEFAULTS",
+        ],
+    ),
+    (
+        "pkg-config",
+        &[
+            "PKG_CONFIG",
+            "PKG_CONFIG_PATH",
+            "PKG_CONFIG_LIBDIR",
+            "PKG_CONFIG_SYSROOT_DIR",
+            "PKG_CONFIG_ALLOW_CROSS",
+            "PKG_CONFIG_ALL_STATIC",
+            "*_NO_PKG_CONFIG",
+        ],
+    ),
+    (
+        "cmake",
+        &["CMAKE", "CMAKE_GENERATOR", "CMAKE_TOOLCHAIN_FILE", "CMAKE_PREFIX_PATH"],
+    ),
+    ("vcpkg", &["VCPKG_ROOT", "VCPKGRS_DYNAMIC", "VCPKGRS_NO_*"]),
+    ("bindgen", &["LIBCLANG_PATH", "CLANG_PATH", "BINDGEN_EXTRA_CLANG_ARGS"]),
+];
+
+#[derive(clap::Args)]
+pub struct BuildEnvArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Report format: one block per crate, or a JSON document
+    #[clap(long, value_enum, default_value = "human")]
+    output: OutputFormat,
+}
+
+#[derive(Serialize)]
+struct CrateEnv {
+    name: String,
+    version: String,
+    build_script: String,
+    // Read by the build script itself
+    variables: BTreeSet<String>,
+    // Consulted by a build helper it uses, by helper
+    via: BTreeMap<String, Vec<String>>,
+    // Lookups whose variable name is computed at run time
+    computed: usize,
+}
+
+// Environment variables the build scripts of vendored crates consult, so an offline build
+// environment can be set up before the first build fails
+pub fn build_env(args: &BuildEnvArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+
+    let mut crates = Vec::new();
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        let Some(build_script) = build_script(&crate_dir)? else {
+            continue;
+        };
+        let mut scan = Scan::default();
+        for file in script_files(&crate_dir, &build_script) {
+            let source = fs::read_to_string(&file).context(format!("Failed to read {}", file.display()))?;
+            scan.source(&source);
+        }
+        let via = helpers(&crate_dir)?;
+        if scan.variables.is_empty() && via.is_empty() && scan.computed == 0 {
+            continue;
+        }
+        crates.push(CrateEnv {
+            name: locked.name.clone(),
+            version: locked.version.clone(),
+            build_script: build_script.to_string_lossy().replace('\\', "/"),
+            variables: scan.variables,
+            via,
+            computed: scan.computed,
+        });
+    }
+
+    if args.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "crates": crates }))?
+        );
+        return Ok(());
+    }
+    if crates.is_empty() {
+        println!("No vendored build script reads environment variables");
+        return Ok(());
+    }
+    for krate in &crates {
+        println!("{} v{} ({}):", krate.name, krate.version, krate.build_script);
+        if !krate.variables.is_empty() {
+            println!("  {}", krate.variables.iter().cloned().collect::<Vec<_>>().join(", "));
+        }
+        for (helper, variables) in &krate.via {
+            println!("  via {helper}: {}", variables.join(", "));
+        }
+        if krate.computed > 0 {
+            println!("  and {} lookup(s) of computed names", krate.computed);
+        }
+    }
+    let all: BTreeSet<&String> = crates.iter().flat_map(|krate| &krate.variables).collect();
+    println!();
+    println!(
+        "{} build script(s) read {} variable(s) directly, `*` stands for a computed part",
+        crates.len(),
+        all.len()
+    );
+    Ok(())
+}
+
+// `package.build`, or `build.rs` when the manifest doesn't say
+pub fn build_script(crate_dir: &Path) -> Result<Option<PathBuf>> {
+    let content = fs::read_to_string(crate_dir.join("Cargo.toml")).context("Failed to read Cargo.toml")?;
+    let doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    let build = doc.get("package").and_then(|package| package.get("build"));
+    Ok(match build.and_then(|build| build.as_value()) {
+        Some(value) if value.as_bool() == Some(false) => None,
+        Some(value) => value.as_str().map(PathBuf::from),
+        None => crate_dir.join("build.rs").is_file().then(|| PathBuf::from("build.rs")),
+    })
+}
+
+// The script, and the modules next to it when it has a directory of its own
+pub fn script_files(crate_dir: &Path, build_script: &Path) -> Vec<PathBuf> {
+    let script = crate_dir.join(build_script);
+    let Some(dir) = build_script.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return vec![script];
+    };
+    WalkDir::new(crate_dir.join(dir))
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+// Build helpers among the build-dependencies, with what they consult
+fn helpers(crate_dir: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut via = BTreeMap::new();
+    for name in build_dependencies(crate_dir)? {
+        if let Some((helper, variables)) = HELPERS.iter().find(|(helper, _)| *helper == name) {
+            via.insert(
+                helper.to_string(),
+                variables.iter().map(|variable| variable.to_string()).collect(),
+            );
+        }
+    }
+    Ok(via)
+}
+
+// Packages the crate's build-dependencies name, of every platform
+pub fn build_dependencies(crate_dir: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(crate_dir.join("Cargo.toml")).context("Failed to read Cargo.toml")?;
+    let doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    let mut tables = vec![doc.get("build-dependencies")];
+    if let Some(targets) = doc.get("target").and_then(|targets| targets.as_table_like()) {
+        tables.extend(targets.iter().map(|(_, target)| target.get("build-dependencies")));
+    }
+    Ok(tables
+        .into_iter()
+        .flatten()
+        .filter_map(|table| table.as_table_like())
+        .flat_map(|table| table.iter())
+        // A renamed dependency names the package in `package`
+        .map(|(key, dependency)| {
+            dependency
+                .get("package")
+                .and_then(|package| package.as_str())
+                .unwrap_or(key)
+                .to_string()
+        })
+        .collect())
+}
+
+#[derive(Default)]
+struct Scan {
+    variables: BTreeSet<String>,
+    computed: usize,
+}
+
+impl Scan {
+    // Finds `env::var("X")`, `env::var_os`, `env!`, `option_env!`, `rerun-if-env-changed=X` and
+    // helpers like `env("X")` the script defines around them
+    fn source(&mut self, source: &str) {
+        for (index, _) in source.match_indices('(') {
+            let before = &source[..index];
+            let callee: String = before
+                .chars()
+                .rev()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '!' || *c == ':')
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .collect();
+            let function = callee.rsplit("::").next().unwrap_or_default();
+            let std_lookup = (callee.ends_with("env::var") || callee.ends_with("env::var_os"))
+                || function == "env!"
+                || function == "option_env!";
+            let helper = !std_lookup && function.trim_end_matches('!').to_ascii_lowercase().contains("env");
+            if !std_lookup && !helper {
+                continue;
+            }
+            match argument(&source[index + 1..]) {
+                Some(name) if is_variable_name(&name) => self.add(name),
+                // Helpers take all sorts of strings, only names count for them
+                Some(_) | None if helper => {}
+                _ => self.computed += 1,
+            }
+        }
+        for (index, marker) in source.match_indices("rerun-if-env-changed=") {
+            let rest = &source[index + marker.len()..];
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '{' || *c == '}')
+                .collect();
+            let name = placeholders(&name);
+            if is_variable_name(&name) {
+                self.add(name);
+            } else {
+                self.computed += 1;
+            }
+        }
+    }
+
+    fn add(&mut self, name: String) {
+        if !name.starts_with("CARGO") && !CARGO_PROVIDED.contains(&name.as_str()) {
+            self.variables.insert(name);
+        }
+    }
+}
+
+// The string literal a call starts with, `format!` placeholders turned into `*`
+fn argument(rest: &str) -> Option<String> {
+    let rest = rest.trim_start().trim_start_matches('&');
+    let rest = rest.strip_prefix("format!(").map(str::trim_start).unwrap_or(rest);
+    let literal = rest.strip_prefix('"')?;
+    let end = literal.find('"')?;
+    Some(placeholders(&literal[..end]))
+}
+
+fn placeholders(name: &str) -> String {
+    let mut result = String::new();
+    let mut in_placeholder = false;
+    for c in name.chars() {
+        match c {
+            '{' => {
+                in_placeholder = true;
+                result.push('*');
+            }
+            '}' => in_placeholder = false,
+            c if !in_placeholder => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+// Upper-case with digits, underscores and `*` for computed parts, at least one letter
+fn is_variable_name(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '*')
+}
@@ -0,0 +1,129 @@
+use crate::build_env::{build_dependencies, build_script, script_files};
+use crate::lock::LockedPackage;
+use crate::warnings::{self, WarningClass};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+// Build-dependencies that only make sense for fetching something
+const NETWORK_CRATES: [&str; 8] = [
+    "reqwest",
+    "curl",
+    "ureq",
+    "hyper",
+    "attohttpc",
+    "minreq",
+    "isahc",
+    "ftp",
+];
+// What gives a build script away, matched in lowercase against each line of code outside comments
+const NETWORK_PATTERNS: [(&str, &str); 9] = [
+    ("tcpstream", "opens a TCP connection"),
+    ("udpsocket", "opens a UDP socket"),
+    ("http://", "names a URL"),
+    ("https://", "names a URL"),
+    ("download", "downloads"),
+    ("command::new(\"git\")", "runs git"),
+    ("command::new(\"curl\")", "runs curl"),
+    ("command::new(\"wget\")", "runs wget"),
+    ("command::new(\"npm\")", "runs npm"),
+];
+const FILESYSTEM_PATTERNS: [(&str, &str); 10] = [
+    ("\"/usr/", "reads an absolute path"),
+    ("\"/opt/", "reads an absolute path"),
+    ("\"/etc/", "reads an absolute path"),
+    ("\"/home/", "reads an absolute path"),
+    ("\"/var/", "reads an absolute path"),
+    ("\"/tmp/", "reads an absolute path"),
+    ("\"/library/", "reads an absolute path"),
+    ("\"c:\\\\", "reads an absolute path"),
+    ("\"c:/", "reads an absolute path"),
+    ("home_dir()", "reads the home directory"),
+];
+
+// Where a build script looked like it reaches outside the crate, e.g. `build.rs:12 runs git`
+struct Findings {
+    network: Vec<String>,
+    filesystem: Vec<String>,
+}
+
+// Warns about build scripts of vendored crates that look like they reach the network or files
+// outside the crate and its `OUT_DIR` at compile time, which a tree vendored for offline builds
+// doesn't expect. The patterns are telltales, not proof: a URL in an error message counts too.
+pub fn audit(third_party_path: &Path, packages: &[LockedPackage]) -> Result<()> {
+    let mut scripts = 0;
+    let mut flagged = 0;
+    for locked in packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        if !crate_dir.join("Cargo.toml").is_file() {
+            continue;
+        }
+        let Some(script) = build_script(&crate_dir)? else {
+            continue;
+        };
+        scripts += 1;
+        let findings = scan(&crate_dir, &script)?;
+        if !findings.network.is_empty() {
+            warnings::warn(
+                WarningClass::NetworkBuildScript,
+                format!(
+                    "{} v{}: build script may reach the network: {}",
+                    locked.name,
+                    locked.version,
+                    findings.network.join(", ")
+                ),
+            );
+        }
+        if !findings.filesystem.is_empty() {
+            warnings::warn(
+                WarningClass::FilesystemBuildScript,
+                format!(
+                    "{} v{}: build script may read files outside the crate: {}",
+                    locked.name,
+                    locked.version,
+                    findings.filesystem.join(", ")
+                ),
+            );
+        }
+        if !findings.network.is_empty() || !findings.filesystem.is_empty() {
+            flagged += 1;
+        }
+    }
+    debug!("{flagged} of {scripts} vendored build script(s) reach outside the crate");
+    Ok(())
+}
+
+fn scan(crate_dir: &Path, script: &Path) -> Result<Findings> {
+    let mut findings = Findings {
+        network: build_dependencies(crate_dir)?
+            .into_iter()
+            .filter(|name| NETWORK_CRATES.contains(&name.as_str()))
+            .map(|name| format!("depends on {name}"))
+            .collect(),
+        filesystem: Vec::new(),
+    };
+    for file in script_files(crate_dir, script) {
+        let source = fs::read_to_string(&file).context(format!("Failed to read {}", file.display()))?;
+        let relative = crate::util::slashed(file.strip_prefix(crate_dir).unwrap_or(&file));
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim().to_lowercase();
+            if line.starts_with("//") {
+                continue;
+            }
+            for (patterns, found) in [
+                (&NETWORK_PATTERNS[..], &mut findings.network),
+                (&FILESYSTEM_PATTERNS[..], &mut findings.filesystem),
+            ] {
+                for (pattern, what) in patterns {
+                    // The first place of each kind is enough to go and look
+                    let finding = format!("{relative}:{} {what}", index + 1);
+                    if line.contains(pattern) && !found.iter().any(|found| found.ends_with(what)) {
+                        found.push(finding);
+                    }
+                }
+            }
+        }
+    }
+    Ok(findings)
+}
@@ -0,0 +1,17 @@
+use crate::Args;
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+#[derive(clap::Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+// Prints the script to stdout, e.g. `cargo-localize completions bash > /etc/bash_completion.d/cargo-localize`
+pub fn completions(args: &CompletionsArgs) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, &mut command, name, &mut std::io::stdout());
+}
@@ -0,0 +1,306 @@
+use crate::filter::PackageSpec;
+use crate::hooks::Hooks;
+use crate::lock::{LockfilePolicy, Shard, Strategy};
+use crate::nested::NestedPolicy;
+use crate::obligations::Form;
+use crate::packaging::PackagingPolicy;
+use crate::{LocalizeArgs, ProjectArgs};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_FILE_NAME: &str = ".cargo-localize.toml";
+
+// Settings committed at the project root, so every contributor localizes the same way
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    third_party_dir: Option<String>,
+    mode: Option<Strategy>,
+    shard: Option<Shard>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    strip: Option<Strip>,
+    features: Option<FeaturePolicy>,
+    nested_lockfiles: Option<LockfilePolicy>,
+    packaging_files: Option<PackagingPolicy>,
+    nested: Option<NestedPolicy>,
+    symlinks: Option<SymlinkPolicy>,
+    // Globs of files left out of copied crates
+    #[serde(default)]
+    ignore: Vec<String>,
+    #[serde(default)]
+    targets: Vec<String>,
+    #[serde(default)]
+    prune_targets: bool,
+    depth: Option<u32>,
+    #[serde(default)]
+    no_dev_deps: bool,
+    #[serde(default)]
+    no_build_deps: bool,
+    #[serde(default)]
+    keep_version: bool,
+    #[serde(default)]
+    no_workspace_table: bool,
+    #[serde(default)]
+    pin_git: bool,
+    #[serde(default)]
+    external_paths: bool,
+    #[serde(default)]
+    fix_filenames: bool,
+    #[serde(default)]
+    allow_licenses: Vec<String>,
+    #[serde(default)]
+    deny_licenses: Vec<String>,
+    #[serde(default)]
+    advisories: bool,
+    #[serde(default)]
+    deny_advisories: bool,
+    #[serde(default)]
+    deny_network_build_scripts: bool,
+    // Advisories whose risk is accepted
+    #[serde(default)]
+    ignore_advisories: Vec<String>,
+    // Organization-wide approved crates, a path relative to the project or a URL
+    approved_list: Option<String>,
+    approved_list_key: Option<String>,
+    // Settings of single crates, keyed by package spec
+    #[serde(default)]
+    crates: BTreeMap<String, CrateConfig>,
+    // Products built from the workspace, keyed by name
+    #[serde(default)]
+    channels: BTreeMap<String, Channel>,
+    #[serde(default)]
+    hooks: Hooks,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrateConfig {
+    // `false` leaves the crate on the registry, like `--exclude`
+    localize: Option<bool>,
+    strip: Option<Strip>,
+    features: Option<FeaturePolicy>,
+    // Globs of files left out of the crate, on top of the project's
+    #[serde(default)]
+    ignore: Vec<String>,
+    // `true` freezes the crate, like `cargo-localize freeze`
+    #[serde(default)]
+    frozen: bool,
+}
+
+// A product shipped from the workspace, e.g. a server binary or an SDK
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Channel {
+    // Workspace members it's built from
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub form: Form,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strip {
+    /// Vendor crates as published
+    None,
+    /// Remove tests, benches and examples, like `--trim`
+    Trim,
+    /// Also remove docs and CI configuration, like `--minimal`
+    Minimal,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeaturePolicy {
+    /// Rewritten entries list the features cargo resolved for the crate
+    #[default]
+    Resolved,
+    /// Rewritten entries keep the features they declared
+    Declared,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Copy what the link points to
+    Follow,
+    /// Copy the link itself
+    Preserve,
+    /// Leave the link out
+    Skip,
+}
+
+// Creating symlinks on Windows takes developer mode or admin rights
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        if cfg!(windows) { Self::Follow } else { Self::Preserve }
+    }
+}
+
+// Fills in what the command line left unset from the project's config file. Values given on the
+// command line win, flags switch settings on and package specs given there replace the config's.
+pub fn apply(args: &LocalizeArgs, project_path: &Path) -> Result<LocalizeArgs> {
+    let mut args = args.clone();
+    let Some(config) = load(project_path)? else {
+        return Ok(args);
+    };
+
+    if args.project.third_party_dir.is_none() {
+        args.project.third_party_dir = config.third_party_dir;
+    }
+    args.mode = args.mode.or(config.mode);
+    args.shard = args.shard.or(config.shard);
+    if args.packages.is_empty() {
+        args.packages = parse_specs(&config.include)?;
+    }
+    if args.exclude.is_empty() {
+        args.exclude = parse_specs(&config.exclude)?;
+    }
+    match config.strip {
+        Some(Strip::Trim) => args.trim = true,
+        Some(Strip::Minimal) => args.minimal = true,
+        Some(Strip::None) | None => {}
+    }
+    args.feature_policy = args.feature_policy.or(config.features);
+    args.nested_lockfiles = args.nested_lockfiles.or(config.nested_lockfiles);
+    args.packaging_files = args.packaging_files.or(config.packaging_files);
+    args.symlinks = args.symlinks.or(config.symlinks);
+    args.nested = args.nested.or(config.nested);
+    args.ignore.extend(config.ignore);
+    if args.targets.is_empty() {
+        args.targets = config.targets;
+    }
+    args.prune_targets |= config.prune_targets;
+    if args.depth.is_none() && !args.direct_only {
+        args.depth = config.depth;
+    }
+    args.no_dev_deps |= config.no_dev_deps;
+    args.no_build_deps |= config.no_build_deps;
+    args.keep_version |= config.keep_version;
+    args.no_workspace_table |= config.no_workspace_table;
+    args.pin_git |= config.pin_git;
+    args.external_paths |= config.external_paths;
+    args.fix_filenames |= config.fix_filenames;
+    if args.licenses.allow_licenses.is_empty() {
+        args.licenses.allow_licenses = config.allow_licenses;
+    }
+    if args.licenses.deny_licenses.is_empty() {
+        args.licenses.deny_licenses = config.deny_licenses;
+    }
+    args.advisories.advisories |= config.advisories;
+    args.advisories.deny_advisories |= config.deny_advisories;
+    args.deny_network_build_scripts |= config.deny_network_build_scripts;
+    args.advisories.ignore_advisories.extend(config.ignore_advisories);
+    if args.approvals.approved_list.is_none() {
+        args.approvals.approved_list = config.approved_list.map(|list| {
+            if list.contains("://") {
+                list
+            } else {
+                project_path.join(list).to_string_lossy().into_owned()
+            }
+        });
+    }
+    if args.approvals.approved_list_key.is_none() {
+        args.approvals.approved_list_key = config.approved_list_key.map(|key| project_path.join(key));
+    }
+
+    if !args.no_hooks {
+        args.hooks = config.hooks.rooted(project_path);
+    }
+
+    for (spec, crate_config) in config.crates {
+        let spec: PackageSpec = spec
+            .parse()
+            .map_err(|error: String| anyhow::anyhow!("{CONFIG_FILE_NAME}: {error}"))?;
+        if crate_config.localize == Some(false) {
+            args.exclude.push(spec.clone());
+        }
+        args.crates.push((spec, crate_config));
+    }
+    Ok(args)
+}
+
+// Folder crates are vendored into before a run recorded it in localize.lock
+pub fn third_party_dir(project: &ProjectArgs, project_path: &Path) -> Result<String> {
+    Ok(match &project.third_party_dir {
+        Some(third_party_dir) => third_party_dir.clone(),
+        None => load(project_path)?
+            .and_then(|config| config.third_party_dir)
+            .unwrap_or_else(|| project.third_party_dir().to_string()),
+    })
+}
+
+pub fn channels(project_path: &Path) -> Result<BTreeMap<String, Channel>> {
+    Ok(load(project_path)?.map(|config| config.channels).unwrap_or_default())
+}
+
+// Specs of the crates the config freezes
+pub fn frozen(project_path: &Path) -> Result<Vec<PackageSpec>> {
+    let Some(config) = load(project_path)? else {
+        return Ok(Vec::new());
+    };
+    let specs: Vec<String> = config
+        .crates
+        .into_iter()
+        .filter(|(_, crate_config)| crate_config.frozen)
+        .map(|(spec, _)| spec)
+        .collect();
+    parse_specs(&specs)
+}
+
+fn load(project_path: &Path) -> Result<Option<Config>> {
+    let config_path = project_path.join(CONFIG_FILE_NAME);
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&config_path).context(format!("Failed to read {CONFIG_FILE_NAME}"))?;
+    let config = toml::from_str(&content).context(format!("Failed to parse {CONFIG_FILE_NAME}"))?;
+    Ok(Some(config))
+}
+
+fn parse_specs(specs: &[String]) -> Result<Vec<PackageSpec>> {
+    crate::filter::parse_specs(specs).map_err(|error| anyhow::anyhow!("{CONFIG_FILE_NAME}: {error}"))
+}
+
+impl LocalizeArgs {
+    // The first crate entry matching the package wins over the project-wide setting
+    fn crate_config<T>(&self, name: &str, version: &str, setting: impl Fn(&CrateConfig) -> Option<T>) -> Option<T> {
+        let version = version.parse().ok()?;
+        self.crates
+            .iter()
+            .filter(|(spec, _)| spec.matches(name, &version))
+            .find_map(|(_, crate_config)| setting(crate_config))
+    }
+
+    pub(crate) fn crate_strip(&self, name: &str, version: &str) -> Strip {
+        let project = if self.minimal {
+            Strip::Minimal
+        } else if self.trim {
+            Strip::Trim
+        } else {
+            Strip::None
+        };
+        self.crate_config(name, version, |crate_config| crate_config.strip)
+            .unwrap_or(project)
+    }
+
+    pub(crate) fn crate_ignore(&self, name: &str, version: &str) -> Vec<String> {
+        let crate_ignore = self
+            .crate_config(name, version, |crate_config| {
+                (!crate_config.ignore.is_empty()).then(|| crate_config.ignore.clone())
+            })
+            .unwrap_or_default();
+        self.ignore.iter().cloned().chain(crate_ignore).collect()
+    }
+
+    pub(crate) fn crate_feature_policy(&self, name: &str, version: &str) -> FeaturePolicy {
+        self.crate_config(name, version, |crate_config| crate_config.features)
+            .or(self.feature_policy)
+            .unwrap_or_default()
+    }
+}
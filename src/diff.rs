@@ -0,0 +1,845 @@
+use crate::filter::PackageSpec;
+use crate::lock::{LocalizeLock, LockedPackage};
+use crate::report::OutputFormat;
+use anyhow::{Context, Result, bail};
+use cargo_metadata::semver::Version;
+use serde::Serialize;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Diff only packages matching the spec (`name`, `name@version` or a glob)
+    #[clap(short = 'p', long = "package", value_name = "SPEC")]
+    packages: Vec<PackageSpec>,
+    /// Write a `<name>-<version>.patch` file per changed crate into DIR (e.g. `patches`) instead
+    /// of printing the diffs
+    #[clap(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Verify only packages matching the spec (`name`, `name@version` or a glob)
+    #[clap(short = 'p', long = "package", value_name = "SPEC")]
+    packages: Vec<PackageSpec>,
+    /// Report format: a list of changed files, or a JSON document
+    #[clap(long, value_enum, default_value = "human")]
+    output: OutputFormat,
+}
+
+// Files of a crate by path relative to it
+type Files = BTreeMap<PathBuf, Vec<u8>>;
+
+// Files packaging adds that vendoring may remove, `--packaging-files delete` does, but never changes
+const KEPT_PACKAGING_FILES: [&str; 3] = [".cargo-ok", ".cargo_vcs_info.json", "Cargo.toml.orig"];
+// Keys of a dependency entry that rewriting it replaces with `path`, or keeps next to it
+const SOURCE_KEYS: [&str; 9] = [
+    "version",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "registry",
+    "registry-index",
+    "path",
+    "features",
+];
+
+// How a vendored file differs from the source it was vendored from
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Change {
+    fn marker(self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Removed => 'D',
+            Change::Modified => 'M',
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChangedFile {
+    path: String,
+    change: Change,
+}
+
+// One vendored crate against its pristine source, `files` is `None` when there's none to compare with
+#[derive(Serialize)]
+struct Verified<'a> {
+    name: &'a str,
+    version: &'a str,
+    path: &'a str,
+    files: Option<Vec<ChangedFile>>,
+}
+
+// Unified diffs between vendored crates and the sources they were vendored from, with paths
+// relative to the crate (`patch -p1` or `git apply` inside the crate directory)
+pub fn diff(args: &DiffArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let selected = select(&localize_lock, &args.packages)?;
+    download_missing(&project_path, &selected, &args.network)?;
+
+    if let Some(output) = &args.output {
+        fs::create_dir_all(output).context(format!("Failed to create {}", output.display()))?;
+    }
+    let mut changed = 0;
+    for locked in selected {
+        let Some(pristine) = pristine(locked)? else {
+            warn!(
+                "{} v{}: no pristine source to compare with, skipped",
+                locked.name, locked.version
+            );
+            continue;
+        };
+        let crate_dir = third_party_path.join(&locked.path);
+        let patch = crate_patch(&localize_lock, locked, &pristine, &vendored(&crate_dir)?);
+        if patch.is_empty() {
+            debug!("{} v{}: unchanged", locked.name, locked.version);
+            continue;
+        }
+        changed += 1;
+        match &args.output {
+            Some(output) => {
+                let path = output.join(format!("{}-{}.patch", locked.name, locked.version));
+                fs::write(&path, patch).context(format!("Failed to write {}", path.display()))?;
+                println!("Wrote {}", path.display());
+            }
+            None => print!("{patch}"),
+        }
+    }
+    info!("{changed} crate(s) differ from their pristine source");
+    Ok(())
+}
+
+// Lists the files of vendored crates that were added, removed or modified since they were
+// published, so reviewers can confirm nothing slipped into the vendored tree. What vendoring itself
+// changes doesn't count, and the project's patches are applied to the published source before it's
+// compared. Fails when a crate differs, crates without a pristine source are only reported.
+pub fn verify(args: &VerifyArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let selected = select(&localize_lock, &args.packages)?;
+    download_missing(&project_path, &selected, &args.network)?;
+    let patches = crate::patches::Patches::load(&project_path)?;
+
+    let mut verified = Vec::new();
+    for locked in &selected {
+        let files = match pristine(locked)? {
+            Some(pristine) => {
+                if !patches.is_current(locked) {
+                    warn!(
+                        "{} v{}: its patches changed since it was vendored, sync before verifying",
+                        locked.name, locked.version
+                    );
+                }
+                let expected = crate::patches::patched(&pristine, patches.for_crate(&locked.name))
+                    .context(format!("Failed to patch {} v{}", locked.name, locked.version))?;
+                let vendored = vendored(&third_party_path.join(&locked.path))?;
+                Some(
+                    changes(&localize_lock, locked, &expected, &vendored)
+                        .into_iter()
+                        .map(|(path, change)| ChangedFile {
+                            path: slashed(&path),
+                            change,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }
+            None => None,
+        };
+        verified.push(Verified {
+            name: &locked.name,
+            version: &locked.version,
+            path: &locked.path,
+            files,
+        });
+    }
+
+    let changed = verified
+        .iter()
+        .filter(|krate| krate.files.as_ref().is_some_and(|files| !files.is_empty()))
+        .count();
+    let unverified = verified.iter().filter(|krate| krate.files.is_none()).count();
+    if args.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "crates": verified }))?
+        );
+    } else {
+        for krate in &verified {
+            match &krate.files {
+                None => println!(
+                    "{} v{} ({}/): no pristine source to compare with",
+                    krate.name, krate.version, krate.path
+                ),
+                Some(files) if !files.is_empty() => {
+                    println!("{} v{} ({}/):", krate.name, krate.version, krate.path);
+                    for file in files {
+                        println!("  {} {}", file.change.marker(), file.path);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        println!(
+            "{} of {} vendored crate(s) match their pristine source, {changed} differ, {unverified} couldn't be checked",
+            verified.len() - changed - unverified,
+            verified.len()
+        );
+    }
+    if changed > 0 {
+        bail!("{changed} vendored crate(s) differ from their pristine source, `cargo-localize diff` shows how");
+    }
+    Ok(())
+}
+
+// The vendored crates matching the specs, all of them without any
+fn select<'a>(localize_lock: &'a LocalizeLock, packages: &[PackageSpec]) -> Result<Vec<&'a LockedPackage>> {
+    let selected: Vec<&LockedPackage> = localize_lock
+        .packages
+        .iter()
+        .filter(|locked| {
+            packages.is_empty()
+                || Version::parse(&locked.version)
+                    .is_ok_and(|version| packages.iter().any(|spec| spec.matches(&locked.name, &version)))
+        })
+        .collect();
+    if selected.is_empty() {
+        bail!("No vendored crate matches the given specs");
+    }
+    Ok(selected)
+}
+
+// Fetches the `.crate` archives of registry crates cargo's cache lost, unless offline
+fn download_missing(project_path: &Path, selected: &[&LockedPackage], network: &crate::NetworkArgs) -> Result<()> {
+    // By registry, `None` for crates.io. Crates of a registry cargo's config doesn't name can't be
+    // asked for.
+    let registries = crate::registry::alternative_registries(project_path);
+    let mut missing: BTreeMap<Option<&str>, Vec<(String, String)>> = BTreeMap::new();
+    for locked in selected {
+        let Some(source) = locked.source.as_deref().filter(|source| !source.starts_with("git+")) else {
+            continue;
+        };
+        let registry = match registries.get(source) {
+            Some(name) => Some(name.as_str()),
+            None if locked.is_from_crates_io() => None,
+            None => continue,
+        };
+        if crate::registry::registry_archive(locked).is_none() {
+            missing
+                .entry(registry)
+                .or_default()
+                .push((locked.name.clone(), locked.version.clone()));
+        }
+    }
+    if !missing.is_empty() && !network.offline() && network.allows_registry() {
+        info!(
+            "Downloading {} crate(s) to compare with...",
+            missing.values().map(Vec::len).sum::<usize>()
+        );
+        for (registry, crates) in &missing {
+            crate::adopt::download(&project_path.join("target"), crates, *registry, network)?;
+        }
+    }
+    Ok(())
+}
+
+// The `.crate` archive of a registry crate, or the checkout of a git crate
+pub fn pristine(locked: &LockedPackage) -> Result<Option<Files>> {
+    let Some(rev) = &locked.rev else {
+        let Some(archive) = crate::registry::registry_archive(locked) else {
+            return Ok(None);
+        };
+        let bytes = fs::read(&archive).context(format!("Failed to read {}", archive.display()))?;
+        return crate::registry::archive_files(&bytes).map(Some);
+    };
+    match git_checkout(&locked.name, rev)? {
+        Some(crate_dir) => vendored(&crate_dir).map(Some),
+        None => Ok(None),
+    }
+}
+
+// `~/.cargo/git/checkouts/<repo>-<hash>/<short rev>/`, searched for the crate's manifest
+fn git_checkout(name: &str, rev: &str) -> Result<Option<PathBuf>> {
+    let Some(checkouts) = crate::registry::src_dir()?
+        .parent()
+        .and_then(Path::parent)
+        .map(|cargo_home| cargo_home.join("git/checkouts"))
+    else {
+        return Ok(None);
+    };
+    let Ok(repositories) = fs::read_dir(&checkouts) else {
+        return Ok(None);
+    };
+    for repository in repositories.flatten() {
+        let Ok(revisions) = fs::read_dir(repository.path()) else {
+            continue;
+        };
+        for revision in revisions.flatten() {
+            if !rev.starts_with(&*revision.file_name().to_string_lossy()) {
+                continue;
+            }
+            for entry in WalkDir::new(revision.path())
+                .max_depth(4)
+                .into_iter()
+                .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "target")
+                .flatten()
+            {
+                if entry.file_name() == "Cargo.toml"
+                    && let Ok(manifest) = fs::read_to_string(entry.path())
+                    && manifest
+                        .parse::<toml_edit::DocumentMut>()
+                        .ok()
+                        .and_then(|doc| doc.get("package")?.get("name")?.as_str().map(|found| found == name))
+                        .unwrap_or(false)
+                {
+                    return Ok(entry.path().parent().map(Path::to_path_buf));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn vendored(crate_dir: &Path) -> Result<Files> {
+    let mut files = Files::new();
+    for entry in WalkDir::new(crate_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+    {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(crate_dir)?.to_path_buf();
+            let content = fs::read(entry.path()).context(format!("Failed to read {}", entry.path().display()))?;
+            files.insert(relative, content);
+        }
+    }
+    Ok(files)
+}
+
+// Files of the vendored crate that differ from `pristine`. What vendoring itself changed is left
+// out: packaging files it writes or removes, the nested lockfile, trimmed directories, files
+// matching the crate's ignore globs and the dependency entries of the manifest it pointed at
+// vendored crates.
+fn changes(
+    localize_lock: &LocalizeLock,
+    locked: &LockedPackage,
+    pristine: &Files,
+    vendored: &Files,
+) -> Vec<(PathBuf, Change)> {
+    let ignored = |path: &Path| {
+        crate::adopt::PACKAGING_FILES
+            .iter()
+            .filter(|file| !KEPT_PACKAGING_FILES.contains(file))
+            .any(|file| path == Path::new(file))
+            || path == Path::new("Cargo.toml")
+            || (locked.nested_lockfile.is_some()
+                && (path == Path::new("Cargo.lock") || path == Path::new(crate::trim::RENAMED_LOCKFILE)))
+    };
+    let removed = |path: &Path| {
+        let relative = slashed(path);
+        KEPT_PACKAGING_FILES.iter().any(|file| path == Path::new(file))
+            || locked
+                .ignored
+                .iter()
+                .any(|pattern| crate::filter::path_glob_match(pattern, &relative))
+            || locked.trimmed
+                && (crate::trim::TRIM_DIRS
+                    .iter()
+                    .chain(&crate::trim::MINIMAL_DIRS)
+                    .any(|dir| path.starts_with(dir))
+                    || crate::trim::MINIMAL_FILES.iter().any(|file| path == Path::new(file)))
+    };
+
+    let mut changes = Vec::new();
+    let manifest = Path::new("Cargo.toml");
+    match (pristine.get(manifest), vendored.get(manifest)) {
+        (Some(before), Some(after)) => {
+            let (before, after) = comparable_manifests(localize_lock, locked, before, after);
+            if before != after {
+                changes.push((manifest.to_path_buf(), Change::Modified));
+            }
+        }
+        (Some(_), None) => changes.push((manifest.to_path_buf(), Change::Removed)),
+        _ => {}
+    }
+    for (path, before) in pristine.iter().filter(|(path, _)| !ignored(path)) {
+        match vendored.get(path) {
+            Some(after) if after == before => {}
+            Some(_) => changes.push((path.clone(), Change::Modified)),
+            None if removed(path) => {}
+            None => changes.push((path.clone(), Change::Removed)),
+        }
+    }
+    for path in vendored
+        .keys()
+        .filter(|path| !ignored(path) && !pristine.contains_key(*path))
+    {
+        changes.push((path.clone(), Change::Added));
+    }
+    changes
+}
+
+// The published and the vendored manifest with what vendoring changed undone on the vendored
+// side: dependency entries pointed at the vendored crate they resolved to, the qualified version
+// and the empty `[workspace]`. Target tables it pruned and sections trimming dropped are taken
+// out of the published one. Anything else, such as a new dependency, another version, source or
+// features, still tells them apart. Manifests that can't be parsed are compared as they are.
+fn comparable_manifests(
+    localize_lock: &LocalizeLock,
+    locked: &LockedPackage,
+    before: &[u8],
+    after: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+    let parse = |manifest: &[u8]| std::str::from_utf8(manifest).ok()?.parse::<DocumentMut>().ok();
+    let (Some(mut pristine), Some(mut vendored)) = (parse(before), parse(after)) else {
+        return (before.to_vec(), after.to_vec());
+    };
+
+    if let Some(targets) = pristine.get_mut("target").and_then(Item::as_table_like_mut) {
+        for key in &locked.pruned_targets {
+            targets.remove(key);
+        }
+        if targets.is_empty() {
+            pristine.remove("target");
+        }
+    }
+    if locked.trimmed {
+        crate::trim::remove_trimmed_sections(&mut pristine);
+    }
+    if pristine.get("workspace").is_none()
+        && vendored
+            .get("workspace")
+            .and_then(Item::as_table_like)
+            .is_some_and(|workspace| workspace.is_empty())
+    {
+        vendored.remove("workspace");
+    }
+    if let Some(qualifier) = locked.qualifier()
+        && let Some(version) = vendored
+            .get_mut("package")
+            .and_then(|package| package.get_mut("version"))
+        && [
+            format!("{}+{qualifier}", locked.version),
+            format!("{}.{qualifier}", locked.version),
+        ]
+        .iter()
+        .any(|qualified| version.as_str() == Some(qualified))
+        && let Some(published) = pristine.get("package").and_then(|package| package.get("version"))
+    {
+        *version = published.clone();
+    }
+
+    for (section, deps) in dependency_tables_mut(&mut vendored) {
+        let Some(published) = dependency_table(&pristine, &section) else {
+            continue;
+        };
+        for (key, entry) in deps.iter_mut() {
+            if let Some(published) = published.get(key.get())
+                && is_rewrite(localize_lock, locked, key.get(), published, entry)
+            {
+                *entry = published.clone();
+            }
+        }
+    }
+    (pristine.to_string().into_bytes(), vendored.to_string().into_bytes())
+}
+
+// Dependency tables of a manifest with the path of keys leading to them
+fn dependency_tables_mut(doc: &mut DocumentMut) -> Vec<(Vec<String>, &mut dyn TableLike)> {
+    const SECTIONS: [&str; 4] = [
+        "dependencies",
+        "dev-dependencies",
+        "build-dependencies",
+        "build_dependencies",
+    ];
+    let mut tables: Vec<(Vec<String>, &mut dyn TableLike)> = Vec::new();
+    for (key, item) in doc.as_table_mut().iter_mut() {
+        let key = key.get().to_string();
+        match key.as_str() {
+            section if SECTIONS.contains(&section) => {
+                tables.extend(item.as_table_like_mut().map(|deps| (vec![key], deps)));
+            }
+            "target" => {
+                let targets = item
+                    .as_table_like_mut()
+                    .into_iter()
+                    .flat_map(|targets| targets.iter_mut());
+                for (target, item) in targets {
+                    let target = target.get().to_string();
+                    for (section, deps) in item.as_table_like_mut().into_iter().flat_map(|item| item.iter_mut()) {
+                        if SECTIONS.contains(&section.get()) {
+                            let path = vec![key.clone(), target.clone(), section.get().to_string()];
+                            tables.extend(deps.as_table_like_mut().map(|deps| (path, deps)));
+                        }
+                    }
+                }
+            }
+            "workspace" => {
+                let deps = item.get_mut("dependencies").and_then(Item::as_table_like_mut);
+                tables.extend(deps.map(|deps| (vec![key, "dependencies".to_string()], deps)));
+            }
+            _ => {}
+        }
+    }
+    tables
+}
+
+fn dependency_table<'a>(doc: &'a DocumentMut, path: &[String]) -> Option<&'a dyn TableLike> {
+    let mut item = doc.as_item();
+    for key in path {
+        item = item.get(key)?;
+    }
+    item.as_table_like()
+}
+
+// Whether the vendored entry is the published one pointed at the vendored crate it resolves to:
+// the path leads to a crate of the 3rd-party folder with the name and a version the published
+// entry asks for, a version kept next to the path is that crate's and features are as published
+// or as resolved. Everything else the entry says is kept as written.
+fn is_rewrite(
+    localize_lock: &LocalizeLock,
+    locked: &LockedPackage,
+    key: &str,
+    published: &Item,
+    vendored: &Item,
+) -> bool {
+    let Some(entry) = vendored.as_table_like() else {
+        return false;
+    };
+    let Some(target) = entry
+        .get("path")
+        .and_then(Item::as_str)
+        .and_then(|path| resolve_within(Path::new(&locked.path), path))
+        .and_then(|path| localize_lock.packages.iter().find(|p| Path::new(&p.path) == path))
+    else {
+        return false;
+    };
+
+    let published_entry = published.as_table_like();
+    let name = published_entry
+        .and_then(|entry| entry.get("package"))
+        .and_then(Item::as_str)
+        .unwrap_or(key);
+    let requirement = match published.as_str() {
+        Some(requirement) => Some(requirement),
+        None => published_entry.and_then(|entry| entry.get("version")?.as_str()),
+    };
+    let matches_requirement = requirement.is_none_or(|requirement| {
+        match (
+            cargo_metadata::semver::VersionReq::parse(requirement),
+            Version::parse(&target.version),
+        ) {
+            (Ok(requirement), Ok(version)) => requirement.matches(&version),
+            _ => false,
+        }
+    });
+    if target.name != name || !matches_requirement {
+        return false;
+    }
+    if entry
+        .get("version")
+        .is_some_and(|version| version.as_str() != Some(target.version.as_str()))
+    {
+        return false;
+    }
+
+    let features = |entry: Option<&dyn TableLike>| -> Option<Vec<String>> {
+        let mut features: Vec<String> = entry?
+            .get("features")?
+            .as_array()?
+            .iter()
+            .filter_map(|feature| feature.as_str().map(str::to_string))
+            .collect();
+        features.sort();
+        Some(features)
+    };
+    let mut resolved = target.features.clone();
+    resolved.sort();
+    let vendored_features = features(Some(entry));
+    if vendored_features != features(published_entry) && vendored_features != Some(resolved) {
+        return false;
+    }
+
+    // What's left of both entries, e.g. `optional`, `default-features` or `package`
+    let rest = |entry: Option<&dyn TableLike>| -> BTreeMap<String, String> {
+        entry
+            .into_iter()
+            .flat_map(|entry| entry.iter())
+            .filter(|(key, _)| !SOURCE_KEYS.contains(key))
+            .map(|(key, item)| {
+                let value = item
+                    .as_value()
+                    .map_or_else(|| item.to_string(), |value| value.clone().decorated("", "").to_string());
+                (key.to_string(), value)
+            })
+            .collect()
+    };
+    rest(Some(entry)) == rest(published_entry)
+}
+
+// `path` taken from the directory `crate_path` of the 3rd-party folder, `None` if it leads out of it
+fn resolve_within(crate_path: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = crate_path.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if resolved.pop() => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+fn crate_patch(localize_lock: &LocalizeLock, locked: &LockedPackage, pristine: &Files, vendored: &Files) -> String {
+    let mut patch = String::new();
+    for (path, _) in changes(localize_lock, locked, pristine, vendored) {
+        let (mut before, mut after) = (pristine.get(&path).cloned(), vendored.get(&path).cloned());
+        if path == Path::new("Cargo.toml")
+            && let (Some(pristine), Some(vendored)) = (&before, &after)
+        {
+            let (pristine, vendored) = comparable_manifests(localize_lock, locked, pristine, vendored);
+            (before, after) = (Some(pristine), Some(vendored));
+        }
+        patch.push_str(&file_diff(&slashed(&path), before.as_deref(), after.as_deref()));
+    }
+    if patch.is_empty() {
+        return patch;
+    }
+    format!("# {} v{} ({}/)\n{patch}", locked.name, locked.version, locked.path)
+}
+
+// A git-style diff of one file, `None` for a side where it doesn't exist
+fn file_diff(path: &str, before: Option<&[u8]>, after: Option<&[u8]>) -> String {
+    let (old_name, new_name) = (
+        before.map_or("/dev/null".to_string(), |_| format!("a/{path}")),
+        after.map_or("/dev/null".to_string(), |_| format!("b/{path}")),
+    );
+    let mut header = format!("diff --git a/{path} b/{path}\n");
+    match (before, after) {
+        (None, Some(_)) => header.push_str("new file mode 100644\n"),
+        (Some(_), None) => header.push_str("deleted file mode 100644\n"),
+        _ => {}
+    }
+    let (Ok(before), Ok(after)) = (
+        std::str::from_utf8(before.unwrap_or_default()),
+        std::str::from_utf8(after.unwrap_or_default()),
+    ) else {
+        return format!("{header}Binary files {old_name} and {new_name} differ\n");
+    };
+    let diff = TextDiff::from_lines(before, after);
+    let unified = diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_name, &new_name)
+        .to_string();
+    if unified.is_empty() {
+        return String::new();
+    }
+    format!("{header}{unified}")
+}
+
+fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// A single version replaced by another one of the same crate is a bump. Takes the names of the
+// crates a change added and of those it removed, returns the bumps as pairs of indexes into them.
+pub fn bumps(added: &[&str], removed: &[&str]) -> Vec<(usize, usize)> {
+    let count = |names: &[&str], name: &str| names.iter().filter(|other| **other == name).count();
+    added
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| count(added, name) == 1 && count(removed, name) == 1)
+        .filter_map(|(index, name)| Some((index, removed.iter().position(|other| other == name)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::Strategy;
+
+    const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+    const PUBLISHED: &str = r#"[package]
+name = "app"
+version = "1.0.0"
+
+[dependencies]
+log = "0.4"
+
+[dependencies.serde]
+version = "1.0"
+optional = true
+default-features = false
+features = ["derive"]
+
+[target.'cfg(windows)'.dependencies]
+winapi = { version = "0.3", features = ["std"] }
+"#;
+
+    fn locked(name: &str, version: &str, features: &[&str]) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: Some(REGISTRY.to_string()),
+            checksum: None,
+            rev: None,
+            features: features.iter().map(ToString::to_string).collect(),
+            path: format!("{name}-{version}"),
+            trimmed: false,
+            ignored: Vec::new(),
+            declared_features: false,
+            pruned_targets: Vec::new(),
+            nested_lockfile: None,
+            frozen: false,
+            patches: Vec::new(),
+        }
+    }
+
+    fn localize_lock() -> LocalizeLock {
+        let mut localize_lock = LocalizeLock::new(Strategy::Rewrite, "3rd-party");
+        localize_lock.packages = vec![
+            locked("app", "1.0.0", &[]),
+            locked("log", "0.4.22", &["std"]),
+            locked("serde", "1.0.210", &["derive", "std"]),
+            locked("winapi", "0.3.9", &["std", "winbase"]),
+        ];
+        localize_lock
+    }
+
+    // `app` as published, and vendored with the manifest `vendored_manifest`
+    fn manifest_changes(vendored_manifest: &str) -> Vec<(PathBuf, Change)> {
+        let localize_lock = localize_lock();
+        let files = |manifest: &str| {
+            Files::from([
+                (PathBuf::from("Cargo.toml"), manifest.as_bytes().to_vec()),
+                (PathBuf::from("src/lib.rs"), b"pub fn app() {}\n".to_vec()),
+            ])
+        };
+        changes(
+            &localize_lock,
+            &localize_lock.packages[0],
+            &files(PUBLISHED),
+            &files(vendored_manifest),
+        )
+    }
+
+    const VENDORED: &str = r#"[package]
+name = "app"
+version = "1.0.0"
+
+[dependencies]
+log = { path = "../log-0.4.22", features = ["std"] }
+
+[dependencies.serde]
+optional = true
+default-features = false
+features = ["derive"]
+path = "../serde-1.0.210"
+
+[target.'cfg(windows)'.dependencies]
+winapi = { features = ["std", "winbase"], path = "../winapi-0.3.9" }
+
+[workspace]
+"#;
+
+    #[test]
+    fn verify_accepts_the_rewritten_manifest() {
+        assert!(manifest_changes(VENDORED).is_empty());
+        // A version kept next to the path is the vendored one
+        let kept = VENDORED.replace(
+            r#"path = "../log-0.4.22""#,
+            r#"path = "../log-0.4.22", version = "0.4.22""#,
+        );
+        assert!(manifest_changes(&kept).is_empty());
+    }
+
+    #[test]
+    fn verify_reports_tampered_dependency_tables() {
+        let tampered = [
+            // A dependency that wasn't published
+            VENDORED.replace("[dependencies]\n", "[dependencies]\nevil = { path = \"../evil\" }\n"),
+            VENDORED.replace(
+                "[dependencies]\n",
+                "[dependencies]\nevil = { git = \"https://example.com/evil\" }\n",
+            ),
+            // Pointed at another crate, or out of the 3rd-party folder
+            VENDORED.replace("../log-0.4.22", "../winapi-0.3.9"),
+            VENDORED.replace("../log-0.4.22", "../../../log-0.4.22"),
+            // Another version or source than the published entry asks for
+            VENDORED.replace(
+                r#"path = "../log-0.4.22""#,
+                r#"path = "../log-0.4.22", version = "0.4.1""#,
+            ),
+            VENDORED.replace(
+                r#"log = { path = "../log-0.4.22", features = ["std"] }"#,
+                r#"log = { git = "https://example.com/log" }"#,
+            ),
+            // Features neither published nor resolved, or an entry no longer optional
+            VENDORED.replace(r#"features = ["std", "winbase"]"#, r#"features = ["std", "evil"]"#),
+            VENDORED.replace("optional = true\n", ""),
+            // A target table that wasn't pruned, moved to another platform
+            VENDORED.replace("cfg(windows)", "cfg(unix)"),
+        ];
+        for manifest in tampered {
+            let changes = manifest_changes(&manifest);
+            assert!(
+                matches!(changes.as_slice(), [(path, Change::Modified)] if path == Path::new("Cargo.toml")),
+                "not reported:\n{manifest}"
+            );
+        }
+    }
+
+    #[test]
+    fn verify_compares_the_original_manifest() {
+        let localize_lock = localize_lock();
+        let locked = &localize_lock.packages[0];
+        let orig = PathBuf::from("Cargo.toml.orig");
+        let pristine = Files::from([(orig.clone(), b"[package]\nname = \"app\"\n".to_vec())]);
+
+        // Removed by `--packaging-files delete`
+        assert!(changes(&localize_lock, locked, &pristine, &Files::new()).is_empty());
+        let tampered = Files::from([(
+            orig.clone(),
+            b"[package]\nname = \"app\"\nbuild = \"evil.rs\"\n".to_vec(),
+        )]);
+        let changes = changes(&localize_lock, locked, &pristine, &tampered);
+        assert!(matches!(changes.as_slice(), [(path, Change::Modified)] if *path == orig));
+    }
+
+    #[test]
+    fn bumps_pair_a_single_version_replaced_by_another() {
+        // `log` is bumped, two `syn` versions replacing one could be anything, `rand` only went away
+        let added = ["log", "serde", "syn", "syn"];
+        let removed = ["rand", "syn", "log"];
+        assert_eq!(bumps(&added, &removed), vec![(0, 2)]);
+        assert!(bumps(&["log"], &[]).is_empty());
+    }
+}
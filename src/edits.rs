@@ -0,0 +1,204 @@
+use crate::journal::Journal;
+use crate::lock::{LocalizeLock, LockedPackage};
+use crate::merge::Carried;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+use walkdir::WalkDir;
+
+pub const CHECKSUMS_FILE_NAME: &str = "localize.checksums";
+
+// File hashes of a crate directory, by path relative to it
+type FileHashes = BTreeMap<String, String>;
+
+// Content of every vendored file as cargo-localize left it, by crate path. Anything differing
+// from it later is an edit made by hand.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct Checksums {
+    #[serde(default)]
+    crates: BTreeMap<String, FileHashes>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Changed(String),
+    Added(String),
+    Removed(String),
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edit::Changed(file) => write!(f, "{file} (changed)"),
+            Edit::Added(file) => write!(f, "{file} (added)"),
+            Edit::Removed(file) => write!(f, "{file} (removed)"),
+        }
+    }
+}
+
+impl Edit {
+    pub fn file(&self) -> &str {
+        match self {
+            Edit::Changed(file) | Edit::Added(file) | Edit::Removed(file) => file,
+        }
+    }
+}
+
+impl Checksums {
+    pub fn load(project_path: &Path) -> Result<Self> {
+        let path = project_path.join(CHECKSUMS_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path).context(format!("Failed to read {CHECKSUMS_FILE_NAME}"))?;
+        toml::from_str(&content).context(format!("Failed to parse {CHECKSUMS_FILE_NAME}"))
+    }
+
+    fn save(&self, project_path: &Path, journal: &Journal) -> Result<()> {
+        let content = toml::to_string(self).context(format!("Failed to serialize {CHECKSUMS_FILE_NAME}"))?;
+        let content = format!(
+            "# This file is generated by cargo-localize to tell local edits of vendored crates apart.\n{content}"
+        );
+        journal
+            .write(&project_path.join(CHECKSUMS_FILE_NAME), content)
+            .context(format!("Failed to write {CHECKSUMS_FILE_NAME}"))
+    }
+
+    // Drops the hashes of crate directories that are gone
+    pub fn forget(project_path: &Path, crate_paths: &[String], journal: &Journal) -> Result<()> {
+        let mut checksums = Self::load(project_path)?;
+        let before = checksums.crates.len();
+        checksums.crates.retain(|path, _| !crate_paths.contains(path));
+        if checksums.crates.len() != before {
+            checksums.save(project_path, journal)?;
+        }
+        Ok(())
+    }
+}
+
+// Local edits of the vendored crates with recorded hashes, by crate path
+pub fn find(project_path: &Path, localize_lock: &LocalizeLock) -> Result<BTreeMap<String, Vec<Edit>>> {
+    let checksums = Checksums::load(project_path)?;
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let mut edits = BTreeMap::new();
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        let Some(recorded) = checksums.crates.get(&locked.path) else {
+            continue;
+        };
+        if !crate_dir.is_dir() {
+            continue;
+        }
+        let crate_edits = compare(recorded, &hash_crate(&crate_dir)?);
+        if !crate_edits.is_empty() {
+            edits.insert(locked.path.clone(), crate_edits);
+        }
+    }
+    Ok(edits)
+}
+
+// Records the vendored crates as this run left them. Edits found before the run, or merged into a
+// new version, stay edits; only what cargo-localize changed itself is taken as the new pristine
+// content.
+pub fn record(
+    project_path: &Path,
+    localize_lock: &LocalizeLock,
+    before: &BTreeMap<String, Vec<Edit>>,
+    merged: &BTreeMap<String, Carried>,
+    journal: &Journal,
+) -> Result<()> {
+    let previous = Checksums::load(project_path)?;
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let mut checksums = Checksums::default();
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        if !crate_dir.is_dir() {
+            continue;
+        }
+        let mut hashes = hash_crate(&crate_dir)?;
+        if let (Some(edits), Some(recorded)) = (before.get(&locked.path), previous.crates.get(&locked.path)) {
+            for edit in edits {
+                match edit {
+                    Edit::Changed(file) | Edit::Removed(file) => {
+                        if let Some(hash) = recorded.get(file) {
+                            hashes.insert(file.clone(), hash.clone());
+                        }
+                    }
+                    Edit::Added(file) => {
+                        hashes.remove(file);
+                    }
+                }
+            }
+        }
+        for (file, hash) in merged.get(&locked.path).into_iter().flatten() {
+            match hash {
+                Some(hash) => hashes.insert(file.clone(), hash.clone()),
+                None => hashes.remove(file),
+            };
+        }
+        checksums.crates.insert(locked.path.clone(), hashes);
+    }
+    checksums.save(project_path, journal)
+}
+
+// Refuses to go on when crates with local edits would be dropped or overwritten, unless forced
+pub fn protect(at_risk: &[(&LockedPackage, &Vec<Edit>)], what_happens: &str, force: bool) -> Result<()> {
+    if at_risk.is_empty() {
+        return Ok(());
+    }
+    let mut report = format!("{} vendored crate(s) with local edits {what_happens}:", at_risk.len());
+    for (locked, edits) in at_risk {
+        let files: Vec<String> = edits.iter().map(Edit::to_string).collect();
+        report.push_str(&format!(
+            "\n  {} v{}: {}",
+            locked.name,
+            locked.version,
+            files.join(", ")
+        ));
+    }
+    if force {
+        warn!("{report}\nGoing ahead because of --force");
+        return Ok(());
+    }
+    Err(crate::LocalizeError::Refused {
+        reason: crate::Refusal::LocalEdits,
+        crates: at_risk.iter().map(|(locked, _)| (*locked).into()).collect(),
+        message: report,
+    }
+    .into())
+}
+
+pub fn hash_crate(crate_dir: &Path) -> Result<FileHashes> {
+    let mut hashes = FileHashes::new();
+    // Crates linked into the store are symlinks, their content is what counts
+    for entry in WalkDir::new(crate_dir).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(crate_dir)?;
+        hashes.insert(
+            relative.to_string_lossy().replace('\\', "/"),
+            crate::journal::file_sha256(entry.path())?,
+        );
+    }
+    Ok(hashes)
+}
+
+fn compare(recorded: &FileHashes, current: &FileHashes) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for (file, hash) in recorded {
+        match current.get(file) {
+            Some(current) if current == hash => {}
+            Some(_) => edits.push(Edit::Changed(file.clone())),
+            None => edits.push(Edit::Removed(file.clone())),
+        }
+    }
+    for file in current.keys().filter(|file| !recorded.contains_key(*file)) {
+        edits.push(Edit::Added(file.clone()));
+    }
+    edits
+}
@@ -0,0 +1,194 @@
+use crate::journal::JOURNAL_FILE_NAME;
+use crate::lock::{LOCK_FILE_NAME, LockedPackage};
+use crate::observer::Phase;
+use crate::warnings::WarningClass;
+use std::path::PathBuf;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A crate an error is about
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrateRef {
+    pub name: String,
+    pub version: String,
+    /// Its source or vendored directory, when known
+    pub path: Option<PathBuf>,
+}
+
+impl From<&LockedPackage> for CrateRef {
+    fn from(locked: &LockedPackage) -> Self {
+        CrateRef {
+            name: locked.name.clone(),
+            version: locked.version.clone(),
+            path: None,
+        }
+    }
+}
+
+/// Why a run refused to go on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Refusal {
+    /// Crates about to be vendored are outside `--allow-licenses`/`--deny-licenses`
+    License,
+    /// Vendored crates with local edits would be dropped or overwritten
+    LocalEdits,
+    /// Frozen vendored crates would be replaced, dropped or have their manifest restored
+    Frozen,
+    /// Vendored crates aren't on the approved list
+    Approval,
+}
+
+impl Refusal {
+    /// What the user can do about it
+    pub fn hint(self) -> &'static str {
+        match self {
+            Refusal::License => {
+                "Leave them on the registry with --exclude, replace them or widen --allow-licenses/--deny-licenses"
+            }
+            Refusal::LocalEdits => "Carry the edits over by hand, or pass --force to drop them",
+            Refusal::Frozen => "Pass --unfreeze to go ahead, or `cargo-localize unfreeze` them",
+            Refusal::Approval => "Submit them for approval, or replace them with approved versions",
+        }
+    }
+}
+
+/// Failures of the library API, for applications embedding cargo-localize to match on. Failures
+/// without a kind of their own are `Other`, carrying the whole chain of causes.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum LocalizeError {
+    /// The project has no localize.lock, it wasn't localized yet
+    #[error("No {LOCK_FILE_NAME} found, run cargo-localize first")]
+    NotLocalized { project: PathBuf },
+    /// An interrupted run left its journal behind, nothing runs until it's dealt with
+    #[error(
+        "An interrupted run left {JOURNAL_FILE_NAME}, run `cargo-localize doctor` to inspect and clean it up first"
+    )]
+    Interrupted { journal: PathBuf },
+    /// A phase of the run failed
+    #[error("Failed to {phase}")]
+    Phase {
+        phase: Phase,
+        #[source]
+        source: BoxError,
+    },
+    /// A crate couldn't be vendored
+    #[error("Failed to vendor {} v{}", krate.name, krate.version)]
+    Crate {
+        krate: CrateRef,
+        phase: Phase,
+        #[source]
+        source: BoxError,
+    },
+    /// The run stopped before changing anything, because of a policy or to protect crates
+    #[error("{message}\n{}", reason.hint())]
+    Refused {
+        reason: Refusal,
+        crates: Vec<CrateRef>,
+        message: String,
+    },
+    /// The run raised warnings `--deny` blocks
+    #[error("{count} blocking warning(s)")]
+    Denied { count: usize, classes: Vec<WarningClass> },
+    /// `--check` found the vendored tree differing from localize.lock
+    #[error("Vendored tree is out of sync with {LOCK_FILE_NAME}")]
+    OutOfSync { problems: Vec<String> },
+    /// The run failed after changing the tree, which its journal can restore
+    #[error("Localization didn't finish, run `cargo-localize doctor` to inspect what was changed")]
+    Unfinished {
+        journal: PathBuf,
+        #[source]
+        source: Box<LocalizeError>,
+    },
+    /// `--verify` found the localized project not resolving or not compiling
+    #[error(
+        "`cargo {command} --offline` failed on the localized project{}\n{output}",
+        blame(crates)
+    )]
+    Unverified {
+        command: String,
+        /// Crates cargo's output points at
+        crates: Vec<CrateRef>,
+        output: String,
+    },
+    /// The run failed after changing the tree, and every change was undone
+    #[error("Localization failed, every change it made was rolled back")]
+    RolledBack {
+        #[source]
+        source: Box<LocalizeError>,
+    },
+    /// Any other failure
+    #[error(transparent)]
+    Other(BoxError),
+}
+
+impl LocalizeError {
+    /// What to do about the failure, when the message doesn't say
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            LocalizeError::Interrupted { .. } => {
+                Some("`cargo-localize doctor --fix` undoes the interrupted run".to_string())
+            }
+            LocalizeError::Crate { krate, .. } => krate
+                .path
+                .as_ref()
+                .map(|path| format!("Check that {} is a complete crate source", path.display())),
+            LocalizeError::Refused { reason, .. } => Some(reason.hint().to_string()),
+            LocalizeError::Denied { .. } => Some("Fix what the warnings point out, or drop it from --deny".to_string()),
+            LocalizeError::OutOfSync { .. } => Some("Run `cargo-localize sync` to bring the tree in line".to_string()),
+            LocalizeError::Unverified { crates, .. } if !crates.is_empty() => Some(
+                "Leave the crates it points at on the registry with --exclude, or fix their vendored copy".to_string(),
+            ),
+            LocalizeError::Unfinished { .. } => Some(format!(
+                "`cargo-localize doctor --fix` undoes the run from {JOURNAL_FILE_NAME}"
+            )),
+            _ => None,
+        }
+    }
+
+    /// The crates the failure is about
+    pub fn crates(&self) -> &[CrateRef] {
+        match self {
+            LocalizeError::Crate { krate, .. } => std::slice::from_ref(krate),
+            LocalizeError::Refused { crates, .. } | LocalizeError::Unverified { crates, .. } => crates,
+            LocalizeError::Unfinished { source, .. } | LocalizeError::RolledBack { source } => source.crates(),
+            _ => &[],
+        }
+    }
+}
+
+fn blame(crates: &[CrateRef]) -> String {
+    if crates.is_empty() {
+        return String::new();
+    }
+    let crates: Vec<String> = crates
+        .iter()
+        .map(|krate| match &krate.path {
+            Some(path) => format!("{} v{} ({})", krate.name, krate.version, path.display()),
+            None => format!("{} v{}", krate.name, krate.version),
+        })
+        .collect();
+    format!(", it points at {}", crates.join(", "))
+}
+
+// Typed errors raised inside the tool travel as `anyhow::Error` and come back out here
+impl From<anyhow::Error> for LocalizeError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<LocalizeError>() {
+            Ok(error) => error,
+            Err(error) => LocalizeError::Other(error.into()),
+        }
+    }
+}
+
+// The message with its causes, like anyhow's `{:#}`
+pub fn chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,249 @@
+use crate::lock::{LocalizeLock, LockedPackage};
+use crate::util::{format_timestamp, unix_now};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Inventory format
+    #[clap(long, value_enum, default_value = "scancode")]
+    format: ExportFormat,
+    /// Write the inventory to a file instead of stdout
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExportFormat {
+    /// ScanCode-style package inventory (JSON)
+    Scancode,
+    /// ORT analyzer result (JSON)
+    Ort,
+}
+
+// What a license scanner needs to know about one vendored crate
+pub struct InventoryEntry<'a> {
+    pub locked: &'a LockedPackage,
+    // Path relative to the project root, with forward slashes
+    pub path: String,
+    pub license: Option<String>,
+    pub license_file: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub authors: Vec<String>,
+}
+
+impl InventoryEntry<'_> {
+    pub fn purl(&self) -> String {
+        format!("pkg:cargo/{}@{}", self.locked.name, self.locked.version)
+    }
+
+    pub fn download_url(&self) -> Option<String> {
+        self.locked.is_from_crates_io().then(|| {
+            format!(
+                "https://crates.io/api/v1/crates/{}/{}/download",
+                self.locked.name, self.locked.version
+            )
+        })
+    }
+
+    // Repository URL and revision of crates vendored from git
+    pub fn git_origin(&self) -> Option<(String, String)> {
+        let url = self.locked.source.as_deref()?.strip_prefix("git+")?;
+        let (url, revision) = url.split_once('#').unwrap_or((url, ""));
+        let url = url.split('?').next().unwrap_or(url);
+        Some((url.to_string(), revision.to_string()))
+    }
+}
+
+pub fn export(args: &ExportArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+
+    let entries = localize_lock
+        .packages
+        .iter()
+        .map(|locked| inventory_entry(&localize_lock, locked, &project_path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let inventory = match args.format {
+        ExportFormat::Scancode => scancode_inventory(&entries),
+        ExportFormat::Ort => ort_result(&entries, &project_path),
+    };
+    let content = serde_json::to_string_pretty(&inventory).context("Failed to serialize inventory")?;
+
+    match &args.output {
+        Some(output) => {
+            fs::write(output, content + "\n").context(format!("Failed to write {}", output.display()))?;
+            eprintln!("Exported {} crate(s) to {}", entries.len(), output.display());
+        }
+        None => println!("{content}"),
+    }
+    Ok(())
+}
+
+pub fn inventory_entry<'a>(
+    localize_lock: &LocalizeLock,
+    locked: &'a LockedPackage,
+    project_path: &Path,
+) -> Result<InventoryEntry<'a>> {
+    let path = format!("{}/{}", localize_lock.third_party_dir, locked.path);
+    let manifest_path = project_path.join(&path).join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context(format!("Failed to read {}", manifest_path.display()))?;
+    let doc = content
+        .parse::<DocumentMut>()
+        .context(format!("Failed to parse {}", manifest_path.display()))?;
+
+    let field = |key: &str| {
+        doc.get("package")
+            .and_then(|package| package.get(key))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    };
+    let authors = doc
+        .get("package")
+        .and_then(|package| package.get("authors"))
+        .and_then(|authors| authors.as_array())
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|a| a.as_str())
+                .map(|a| a.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(InventoryEntry {
+        locked,
+        license_file: field("license-file").map(|file| format!("{path}/{file}")),
+        path,
+        license: field("license"),
+        description: field("description"),
+        homepage: field("homepage"),
+        repository: field("repository"),
+        authors,
+    })
+}
+
+// Crates as they'd appear in the `packages` section of a ScanCode scan
+fn scancode_inventory(entries: &[InventoryEntry]) -> Value {
+    let packages: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "type": "cargo",
+                "namespace": null,
+                "name": entry.locked.name,
+                "version": entry.locked.version,
+                "purl": entry.purl(),
+                "description": entry.description,
+                "homepage_url": entry.homepage,
+                "vcs_url": entry.git_origin().map(|(url, _)| url).or_else(|| entry.repository.clone()),
+                "download_url": entry.download_url(),
+                "sha256": entry.locked.checksum,
+                "declared_license_expression_spdx": entry.license,
+                "extracted_license_statement": entry.license.as_ref().or(entry.license_file.as_ref()),
+                "parties": entry.authors.iter().map(|author| json!({"role": "author", "name": author})).collect::<Vec<_>>(),
+                "datafile_paths": [format!("{}/Cargo.toml", entry.path)],
+                "extra_data": {
+                    "vendored_path": entry.path,
+                    "source": entry.locked.source,
+                    "license_file": entry.license_file,
+                },
+            })
+        })
+        .collect();
+
+    json!({
+        "headers": [{
+            "tool_name": "cargo-localize",
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "end_timestamp": format_timestamp(unix_now()),
+        }],
+        "packages": packages,
+    })
+}
+
+// Minimal ORT analyzer result: one project for the workspace depending on every vendored crate
+fn ort_result(entries: &[InventoryEntry], project_path: &Path) -> Value {
+    let timestamp = format_timestamp(unix_now());
+    let project_name = project_path
+        .file_name()
+        .map_or("project".to_string(), |name| name.to_string_lossy().to_string());
+    let id = |entry: &InventoryEntry| format!("Crate::{}:{}", entry.locked.name, entry.locked.version);
+    let empty_vcs = json!({"type": "", "url": "", "revision": "", "path": ""});
+
+    let packages: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let vcs = match entry.git_origin() {
+                Some((url, revision)) => json!({"type": "Git", "url": url, "revision": revision, "path": ""}),
+                None => match &entry.repository {
+                    Some(url) => json!({"type": "Git", "url": url, "revision": "", "path": ""}),
+                    None => empty_vcs.clone(),
+                },
+            };
+            let source_artifact = match (entry.download_url(), &entry.locked.checksum) {
+                (Some(url), Some(checksum)) => json!({"url": url, "hash": {"value": checksum, "algorithm": "SHA-256"}}),
+                (Some(url), None) => json!({"url": url, "hash": {"value": "", "algorithm": "NONE"}}),
+                _ => json!({"url": "", "hash": {"value": "", "algorithm": "NONE"}}),
+            };
+
+            json!({
+                "id": id(entry),
+                "purl": entry.purl(),
+                "authors": entry.authors,
+                "declared_licenses": entry.license.iter().collect::<Vec<_>>(),
+                "declared_licenses_processed": entry.license.as_ref().map(|license| json!({"spdx_expression": license})),
+                "description": entry.description.clone().unwrap_or_default(),
+                "homepage_url": entry.homepage.clone().unwrap_or_default(),
+                "binary_artifact": {"url": "", "hash": {"value": "", "algorithm": "NONE"}},
+                "source_artifact": source_artifact,
+                "vcs": vcs,
+                "vcs_processed": vcs,
+                "labels": {
+                    "cargo-localize:path": entry.path,
+                    "cargo-localize:license-file": entry.license_file.clone().unwrap_or_default(),
+                },
+            })
+        })
+        .collect();
+
+    json!({
+        "repository": {
+            "vcs": empty_vcs,
+            "vcs_processed": empty_vcs,
+            "config": {},
+        },
+        "analyzer": {
+            "start_time": timestamp,
+            "end_time": timestamp,
+            "environment": {
+                "ort_version": format!("cargo-localize {}", env!("CARGO_PKG_VERSION")),
+                "os": std::env::consts::OS,
+            },
+            "config": {"allow_dynamic_versions": false},
+            "result": {
+                "projects": [{
+                    "id": format!("Cargo::{project_name}:"),
+                    "definition_file_path": "Cargo.toml",
+                    "declared_licenses": [],
+                    "vcs": empty_vcs,
+                    "vcs_processed": empty_vcs,
+                    "homepage_url": "",
+                    "scopes": [{
+                        "name": "dependencies",
+                        "dependencies": entries.iter().map(|entry| json!({"id": id(entry)})).collect::<Vec<_>>(),
+                    }],
+                }],
+                "packages": packages,
+            },
+        },
+    })
+}
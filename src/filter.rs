@@ -0,0 +1,183 @@
+use cargo_metadata::semver::Version;
+use cargo_metadata::{DepKindInfo, Metadata, PackageId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+// Package selector: `name`, `name@version` or a glob like `serde*`.
+// A partial version such as `syn@1` matches every 1.x.y release.
+#[derive(Clone, Debug)]
+pub struct PackageSpec {
+    name: String,
+    version: Option<String>,
+}
+
+impl FromStr for PackageSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec, None),
+        };
+
+        if name.is_empty() {
+            return Err(format!("package spec `{spec}` has no name"));
+        }
+        if let Some(version) = version
+            && (version.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            return Err(format!("package spec `{spec}` has an invalid version"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            version: version.map(|v| v.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{version}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl PackageSpec {
+    pub fn matches(&self, name: &str, version: &Version) -> bool {
+        glob_match(&self.name, name) && self.version.as_deref().is_none_or(|v| version_matches(v, version))
+    }
+}
+
+// Whether a package passes `-p` and `--exclude` selection
+pub fn is_selected(include: &[PackageSpec], exclude: &[PackageSpec], name: &str, version: &Version) -> bool {
+    (include.is_empty() || include.iter().any(|spec| spec.matches(name, version)))
+        && !exclude.iter().any(|spec| spec.matches(name, version))
+}
+
+// Shortest distance of every package from the workspace: direct dependencies are at depth 1
+pub fn dependency_depths(metadata: &Metadata) -> HashMap<PackageId, u32> {
+    let mut depths: HashMap<PackageId, u32> = metadata.workspace_members.iter().map(|id| (id.clone(), 0)).collect();
+    let Some(resolve) = metadata.resolve.as_ref() else {
+        return depths;
+    };
+    let nodes: HashMap<&PackageId, &cargo_metadata::Node> = resolve.nodes.iter().map(|n| (&n.id, n)).collect();
+
+    let mut queue: VecDeque<&PackageId> = metadata.workspace_members.iter().collect();
+    while let Some(id) = queue.pop_front() {
+        let depth = depths[id];
+        let Some(node) = nodes.get(id) else {
+            continue;
+        };
+        for dep in &node.deps {
+            // Workspace members stay at depth 0, so their dependencies count as direct too
+            if !depths.contains_key(&dep.pkg) {
+                depths.insert(dep.pkg.clone(), depth + 1);
+                queue.push_back(&dep.pkg);
+            }
+        }
+    }
+
+    depths
+}
+
+// Packages reachable from the workspace over dependency edges accepted by `follow`,
+// which is also told whether the edge starts at a workspace member
+pub fn reachable_packages(
+    metadata: &Metadata,
+    follow: impl Fn(bool, &DepKindInfo) -> bool,
+) -> Option<HashSet<PackageId>> {
+    let resolve = metadata.resolve.as_ref()?;
+    let nodes: HashMap<&PackageId, &cargo_metadata::Node> = resolve.nodes.iter().map(|n| (&n.id, n)).collect();
+
+    let mut reachable: HashSet<PackageId> = metadata.workspace_members.iter().cloned().collect();
+    let mut queue: Vec<&PackageId> = metadata.workspace_members.iter().collect();
+
+    while let Some(id) = queue.pop() {
+        let Some(node) = nodes.get(id) else {
+            continue;
+        };
+        let is_member = metadata.workspace_members.contains(id);
+
+        for dep in &node.deps {
+            if dep.dep_kinds.iter().any(|kind| follow(is_member, kind)) && reachable.insert(dep.pkg.clone()) {
+                queue.push(&dep.pkg);
+            }
+        }
+    }
+
+    Some(reachable)
+}
+
+// Parses specs stored as strings, e.g. in localize.lock
+pub fn parse_specs(specs: &[String]) -> Result<Vec<PackageSpec>, String> {
+    specs.iter().map(|spec| spec.parse()).collect()
+}
+
+fn version_matches(spec: &str, version: &Version) -> bool {
+    if spec.contains(['-', '+']) {
+        return spec == version.to_string();
+    }
+
+    let actual = [version.major, version.minor, version.patch];
+    let parts: Vec<&str> = spec.split('.').collect();
+    parts.len() <= 3
+        && version.pre.is_empty()
+        && parts
+            .iter()
+            .zip(actual)
+            .all(|(part, actual)| part.parse::<u64>().is_ok_and(|part| part == actual))
+}
+
+// Matches `*` (any run of characters) and `?` (any single character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Matches a `/`-separated path the way `.gitignore` does: `**` stands for any number of directories,
+// `*` and `?` stay within one name, and a pattern without a `/` matches names at any depth
+pub fn path_glob_match(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    let pattern: Vec<&str> = if pattern.contains('/') {
+        pattern.split('/').collect()
+    } else {
+        vec!["**", pattern]
+    };
+    let path: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skipped| segments_match(rest, &path[skipped..])),
+        Some((segment, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| glob_match(segment, name) && segments_match(rest, path)),
+    }
+}
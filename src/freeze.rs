@@ -0,0 +1,98 @@
+use crate::filter::PackageSpec;
+use crate::journal::Journal;
+use crate::lock::{LocalizeLock, LockedPackage};
+use anyhow::{Result, bail};
+use cargo_metadata::semver::Version;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(clap::Args)]
+pub struct FreezeArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Vendored crates to mark, matching the spec (`name`, `name@version` or a glob)
+    #[clap(short = 'p', long = "package", value_name = "SPEC", required = true)]
+    packages: Vec<PackageSpec>,
+}
+
+// Marks vendored crates as frozen in localize.lock, or clears the mark
+pub fn freeze(args: &FreezeArgs, frozen: bool) -> Result<()> {
+    let project_path = args.project.root()?;
+    let mut localize_lock = LocalizeLock::require(&project_path)?;
+
+    let mut matched = 0;
+    for locked in &mut localize_lock.packages {
+        if !matches(&args.packages, locked) {
+            continue;
+        }
+        matched += 1;
+        if locked.frozen != frozen {
+            locked.frozen = frozen;
+            println!(
+                "{} {} v{}",
+                if frozen { "Froze" } else { "Unfroze" },
+                locked.name,
+                locked.version
+            );
+        }
+    }
+    if matched == 0 {
+        bail!("No vendored crate matches the given specs");
+    }
+    localize_lock.save(&project_path, &Journal::default())?;
+
+    if !frozen {
+        let specs = crate::config::frozen(&project_path)?;
+        for locked in localize_lock
+            .packages
+            .iter()
+            .filter(|locked| matches(&args.packages, locked))
+        {
+            if matches(&specs, locked) {
+                warn!(
+                    "{} v{} stays frozen by `frozen = true` in {}",
+                    locked.name,
+                    locked.version,
+                    crate::config::CONFIG_FILE_NAME
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// Frozen crates among `packages`: marked in localize.lock or by the config
+pub fn frozen<'a>(
+    project_path: &Path,
+    packages: impl IntoIterator<Item = &'a LockedPackage>,
+) -> Result<Vec<&'a LockedPackage>> {
+    let specs = crate::config::frozen(project_path)?;
+    Ok(packages
+        .into_iter()
+        .filter(|locked| locked.frozen || matches(&specs, locked))
+        .collect())
+}
+
+pub fn protect(frozen: &[&LockedPackage], what_happens: &str, unfreeze: bool) -> Result<()> {
+    if frozen.is_empty() {
+        return Ok(());
+    }
+    let mut report = format!("{} frozen vendored crate(s) {what_happens}:", frozen.len());
+    for locked in frozen {
+        report.push_str(&format!("\n  {} v{}", locked.name, locked.version));
+    }
+    if unfreeze {
+        warn!("{report}\nGoing ahead because of --unfreeze");
+        return Ok(());
+    }
+    Err(crate::LocalizeError::Refused {
+        reason: crate::Refusal::Frozen,
+        crates: frozen.iter().map(|locked| (*locked).into()).collect(),
+        message: report,
+    }
+    .into())
+}
+
+fn matches(specs: &[PackageSpec], locked: &LockedPackage) -> bool {
+    Version::parse(&locked.version).is_ok_and(|version| specs.iter().any(|spec| spec.matches(&locked.name, &version)))
+}
@@ -0,0 +1,184 @@
+use crate::journal::Journal;
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock};
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(clap::Args)]
+pub struct GcArgs {
+    #[clap(flatten)]
+    project: crate::ProjectArgs,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Only list what would be removed
+    #[clap(long)]
+    dry_run: bool,
+    /// Remove unused crates even if they have local edits
+    #[clap(long)]
+    force: bool,
+    /// Remove unused crates even if they're frozen
+    #[clap(long)]
+    unfreeze: bool,
+}
+
+pub fn gc(args: &GcArgs) -> Result<()> {
+    let project_path = args.project.path.root()?;
+    let mut localize_lock = LocalizeLock::load(&project_path)?;
+    // The graph still resolves every vendored crate from the registry, all would look unused
+    if localize_lock.as_ref().is_some_and(|lock| lock.manifests_pending) {
+        anyhow::bail!("Manifests don't point at the vendored crates yet, run with --rewrite-only first");
+    }
+    let third_party_dir = match &localize_lock {
+        Some(lock) => lock.third_party_dir.clone(),
+        None => crate::config::third_party_dir(&args.project, &project_path)?,
+    };
+    // What else lives in a shared folder is only known to the enclosing project
+    if !crate::safety::is_contained(Path::new(&third_party_dir)) {
+        anyhow::bail!("{third_party_dir} is shared with an enclosing project, run gc there");
+    }
+    let third_party_path = crate::util::normalize_path(&project_path.join(third_party_dir));
+
+    println!("Getting metadata...");
+    let metadata = args
+        .network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(args.network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+
+    // Vendored directories the current graph still resolves to
+    let used: HashSet<PathBuf> = metadata
+        .packages
+        .iter()
+        .filter_map(|p| p.manifest_path.parent())
+        .filter_map(|dir| dir.as_std_path().strip_prefix(&third_party_path).ok())
+        .map(Path::to_path_buf)
+        .chain(crate::nested::shared_crates(
+            &project_path,
+            &third_party_path,
+            &args.network,
+        )?)
+        .collect();
+
+    let mut candidates: BTreeSet<PathBuf> = localize_lock
+        .iter()
+        .flat_map(|lock| &lock.packages)
+        .map(|p| PathBuf::from(&p.path))
+        .collect();
+    if third_party_path.is_dir() {
+        for entry in fs::read_dir(&third_party_path).context("Failed to read 3rd-party directory")? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            // Directories without a manifest are shards of a sharded layout, holding the crates
+            if entry.path().join("Cargo.toml").exists() {
+                candidates.insert(PathBuf::from(entry.file_name()));
+            } else {
+                for crate_entry in
+                    fs::read_dir(entry.path()).context(format!("Failed to read {}", entry.path().display()))?
+                {
+                    let crate_entry = crate_entry?;
+                    if crate_entry.file_type()?.is_dir() {
+                        candidates.insert(Path::new(&entry.file_name()).join(crate_entry.file_name()));
+                    }
+                }
+            }
+        }
+    }
+
+    let orphans: Vec<PathBuf> = candidates
+        .into_iter()
+        .filter(|dir| !used.contains(dir) && third_party_path.join(dir).exists())
+        .collect();
+    let orphan_paths: Vec<PathBuf> = orphans.iter().map(|dir| third_party_path.join(dir)).collect();
+    let dangling_backups = dangling_backups(&project_path, &orphan_paths);
+
+    if orphans.is_empty() && dangling_backups.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
+    }
+
+    if !args.dry_run
+        && let Some(localize_lock) = &localize_lock
+    {
+        let edits = crate::edits::find(&project_path, localize_lock)?;
+        let at_risk: Vec<_> = localize_lock
+            .packages
+            .iter()
+            .filter(|p| orphans.contains(&PathBuf::from(&p.path)))
+            .filter_map(|p| edits.get(&p.path).map(|edits| (p, edits)))
+            .collect();
+        crate::edits::protect(&at_risk, "would be removed", args.force)?;
+        let frozen = crate::freeze::frozen(
+            &project_path,
+            localize_lock
+                .packages
+                .iter()
+                .filter(|p| orphans.contains(&PathBuf::from(&p.path))),
+        )?;
+        crate::freeze::protect(&frozen, "would be removed", args.unfreeze)?;
+    }
+
+    let action = if args.dry_run { "Would remove" } else { "Removing" };
+    for dir in &orphan_paths {
+        println!("{action} unused crate: {}", dir.display());
+        if !args.dry_run {
+            fs::remove_dir_all(dir).context(format!("Failed to remove {}", dir.display()))?;
+            // Shards left empty go too
+            if let Some(shard_dir) = dir.parent().filter(|parent| *parent != third_party_path) {
+                let _ = fs::remove_dir(shard_dir);
+            }
+        }
+    }
+    for backup in &dangling_backups {
+        println!("{action} dangling backup: {}", backup.display());
+        if !args.dry_run {
+            fs::remove_file(backup).context(format!("Failed to remove {}", backup.display()))?;
+        }
+    }
+
+    if !args.dry_run
+        && let Some(localize_lock) = localize_lock.as_mut()
+    {
+        localize_lock
+            .packages
+            .retain(|p| !orphans.contains(&PathBuf::from(&p.path)));
+        let journal = Journal::default();
+        localize_lock.save(&project_path, &journal)?;
+        crate::index::write(&project_path, localize_lock, &journal)?;
+        let removed: Vec<String> = orphans
+            .iter()
+            .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+            .collect();
+        crate::edits::Checksums::forget(&project_path, &removed, &journal)?;
+        crate::prelude::refresh(&project_path, localize_lock, &journal)?;
+        println!("Updated {LOCK_FILE_NAME}");
+    }
+
+    println!(
+        "{} {} crate(s) and {} backup(s)",
+        if args.dry_run { "Would remove" } else { "Removed" },
+        orphans.len(),
+        dangling_backups.len()
+    );
+    Ok(())
+}
+
+// `Cargo.toml.bak` files whose manifest is gone, outside of directories being removed anyway
+fn dangling_backups(project_path: &Path, skip_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    WalkDir::new(project_path)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name();
+            name != "target" && name != ".git" && !skip_dirs.iter().any(|dir| e.path() == dir)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name() == "Cargo.toml.bak")
+        .filter(|e| !e.path().with_file_name("Cargo.toml").exists())
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+// Commands the config file's `[hooks]` table runs around a localization, through the platform's
+// shell from the project root. A command that fails fails the run.
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    // Per crate, before it's copied
+    #[serde(default)]
+    pre_copy: Commands,
+    // Per crate, once it's copied and stripped
+    #[serde(default)]
+    post_copy: Commands,
+    // Once per run, after the manifests were pointed at the vendored crates
+    #[serde(default)]
+    post_rewrite: Commands,
+    #[serde(skip)]
+    root: PathBuf,
+}
+
+// One command or a list of them
+#[derive(Deserialize, Clone, Default)]
+#[serde(from = "OneOrMany")]
+struct Commands(Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Commands {
+    fn from(commands: OneOrMany) -> Self {
+        match commands {
+            OneOrMany::One(command) => Commands(vec![command]),
+            OneOrMany::Many(commands) => Commands(commands),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    PreCopy,
+    PostCopy,
+    PostRewrite,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Hook::PreCopy => "pre-copy",
+            Hook::PostCopy => "post-copy",
+            Hook::PostRewrite => "post-rewrite",
+        })
+    }
+}
+
+// The crate a per-crate hook runs for
+pub struct HookedCrate<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    // Its folder in the 3rd-party folder, or the staging folder it's assembled in
+    pub path: &'a Path,
+    // Where it's copied from
+    pub source: &'a Path,
+}
+
+impl Hooks {
+    // Hooks run from the project at `root`
+    pub fn rooted(self, root: &Path) -> Self {
+        Hooks {
+            root: root.to_path_buf(),
+            ..self
+        }
+    }
+
+    fn commands(&self, hook: Hook) -> &[String] {
+        match hook {
+            Hook::PreCopy => &self.pre_copy.0,
+            Hook::PostCopy => &self.post_copy.0,
+            Hook::PostRewrite => &self.post_rewrite.0,
+        }
+    }
+
+    pub fn run_crate(&self, hook: Hook, krate: &HookedCrate, third_party_path: &Path) -> Result<()> {
+        self.run_commands(
+            hook,
+            &[
+                ("CARGO_LOCALIZE_CRATE_NAME", krate.name.into()),
+                ("CARGO_LOCALIZE_CRATE_VERSION", krate.version.into()),
+                ("CARGO_LOCALIZE_CRATE_PATH", krate.path.into()),
+                ("CARGO_LOCALIZE_CRATE_SOURCE", krate.source.into()),
+                ("CARGO_LOCALIZE_THIRD_PARTY_DIR", third_party_path.into()),
+            ],
+        )
+        .context(format!("{hook} hook failed for {} v{}", krate.name, krate.version))
+    }
+
+    pub fn run(&self, hook: Hook, third_party_path: &Path) -> Result<()> {
+        self.run_commands(hook, &[("CARGO_LOCALIZE_THIRD_PARTY_DIR", third_party_path.into())])
+            .context(format!("{hook} hook failed"))
+    }
+
+    fn run_commands(&self, hook: Hook, env: &[(&str, OsString)]) -> Result<()> {
+        for command in self.commands(hook) {
+            let output = shell(command)
+                .current_dir(&self.root)
+                .env("CARGO_LOCALIZE_HOOK", hook.to_string())
+                .env("CARGO_LOCALIZE_PROJECT", &self.root)
+                .envs(env.iter().map(|(key, value)| (key, value)))
+                .output()
+                .context(format!("Failed to run `{command}`"))?;
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                info!("  [{hook}] {line}");
+            }
+            if !output.status.success() {
+                anyhow::bail!(
+                    "`{command}` exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
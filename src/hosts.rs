@@ -0,0 +1,303 @@
+use crate::NetworkArgs;
+use crate::lock::RegistryHost;
+use crate::warnings::{self, WarningClass};
+use anyhow::{Context, Result};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::collections::BTreeSet;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Compares the hosts crate sources come from with the ones pinned by the first run, warning about
+// new hosts and hosts now vouched for by another root CA. Returns the hosts to record: the pinned
+// ones, or the current ones if this is the first run or they are trusted explicitly.
+// Leaf certificates are reissued every few months, only the root they chain to is compared.
+// Hosts are only contacted to pin a root they have none for yet, when they're trusted anew or with
+// `recheck`, the others keep their pin. Hosts the network policy keeps the run away from aren't
+// contacted at all, only their names are compared.
+pub fn check<'a>(
+    pinned: &[RegistryHost],
+    sources: impl Iterator<Item = &'a str>,
+    trust: bool,
+    recheck: bool,
+    network: &NetworkArgs,
+) -> Vec<RegistryHost> {
+    let current: Vec<RegistryHost> = sources
+        .filter_map(source_host)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|(host, tls, git)| {
+            let reachable = if git {
+                network.allows_git()
+            } else {
+                network.allows_registry()
+            };
+            let pinned_key = pinned
+                .iter()
+                .find(|pinned| pinned.host == host)
+                .and_then(|pinned| pinned.root_key.clone());
+            let root_key = if !reachable || (pinned_key.is_some() && !trust && !recheck) {
+                pinned_key
+            } else if tls {
+                root_key(&host)
+                    .inspect_err(|error| debug!("Couldn't verify the certificate of {host}: {error:#}"))
+                    .ok()
+            } else {
+                None
+            };
+            RegistryHost { host, root_key }
+        })
+        .collect();
+
+    if pinned.is_empty() || trust {
+        if !current.is_empty() {
+            info!("Pinned {} registry host(s)", current.len());
+        }
+        return current;
+    }
+
+    for host in &current {
+        match pinned.iter().find(|pinned| pinned.host == host.host) {
+            None => warnings::warn(
+                WarningClass::RegistryHost,
+                format!(
+                    "Crates now come from {}, which wasn't used when the hosts were pinned (--trust-hosts accepts it)",
+                    host.host
+                ),
+            ),
+            Some(pinned) => match (&pinned.root_key, &host.root_key) {
+                (Some(pinned_key), Some(key)) if pinned_key != key => warnings::warn(
+                    WarningClass::RegistryHost,
+                    format!(
+                        "{}'s certificate chains to another root CA than when it was pinned ({} -> {}, \
+                         --trust-hosts accepts it)",
+                        host.host,
+                        short(pinned_key),
+                        short(key)
+                    ),
+                ),
+                (Some(_), None) => warnings::warn(
+                    WarningClass::RegistryHost,
+                    format!(
+                        "{}'s certificate couldn't be verified against the public root CAs, as it was when pinned",
+                        host.host
+                    ),
+                ),
+                _ => {}
+            },
+        }
+    }
+    // Hosts pinned offline get the root they chain to once it can be looked up
+    pinned
+        .iter()
+        .map(|pinned| RegistryHost {
+            host: pinned.host.clone(),
+            root_key: pinned.root_key.clone().or_else(|| {
+                current
+                    .iter()
+                    .find(|host| host.host == pinned.host)
+                    .and_then(|host| host.root_key.clone())
+            }),
+        })
+        .collect()
+}
+
+// Host of a `registry+`, `sparse+` or `git+` source URL, with its port unless it's the scheme's
+// default, whether it's reached over TLS and whether it's a git repository
+fn source_host(source: &str) -> Option<(String, bool, bool)> {
+    let (kind, url) = source.split_once('+')?;
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let (name, port) = split_port(authority)?;
+    if name.is_empty() {
+        return None;
+    }
+    let tls = scheme == "https";
+    let host = match port {
+        Some(port) if port != default_port(tls) => authority.to_lowercase(),
+        _ if name.contains(':') => format!("[{name}]").to_lowercase(),
+        _ => name.to_lowercase(),
+    };
+    Some((host, tls, kind == "git"))
+}
+
+// Name and port of `host`, `host:port`, `[v6]` or `[v6]:port`
+fn split_port(authority: &str) -> Option<(&str, Option<u16>)> {
+    let (name, port) = match authority.strip_prefix('[') {
+        Some(rest) => {
+            let (name, rest) = rest.split_once(']')?;
+            (name, rest.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((name, port)) => (name, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => Some(port.parse().ok()?),
+        None => None,
+    };
+    Some((name, port))
+}
+
+fn default_port(tls: bool) -> u16 {
+    if tls { 443 } else { 80 }
+}
+
+// Verifies servers against the public root CAs, the tool never trusts the system store
+pub fn tls_config() -> Result<Arc<rustls::ClientConfig>> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+// Verifies a server the way `tls_config` does, and keeps the public key of the root CA the
+// chain was verified against
+#[derive(Debug)]
+struct RootRecorder {
+    verifier: Arc<WebPkiServerVerifier>,
+    root_key: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for RootRecorder {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .verifier
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        // The same path search over the same roots, this time telling which one it ended at
+        let general = |error: webpki::Error| rustls::Error::General(error.to_string());
+        let certificate = webpki::EndEntityCert::try_from(end_entity).map_err(general)?;
+        let path = certificate
+            .verify_for_usage(
+                rustls::crypto::ring::default_provider()
+                    .signature_verification_algorithms
+                    .all,
+                webpki_roots::TLS_SERVER_ROOTS,
+                intermediates,
+                now,
+                webpki::KeyUsage::server_auth(),
+                None,
+                None,
+            )
+            .map_err(general)?;
+        *self.root_key.lock().unwrap() = Some(crate::journal::sha256(path.anchor().subject_public_key_info.as_ref()));
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+// Connects to the host and finds the public root CA its certificate chain is verified against
+fn root_key(host: &str) -> Result<String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let recorder = Arc::new(RootRecorder {
+        verifier: WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?,
+        root_key: Mutex::new(None),
+    });
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(recorder.clone())
+        .with_no_client_auth();
+    let (name, port) = split_port(host).context(format!("Invalid host {host}"))?;
+    let server_name = ServerName::try_from(name.to_string())?;
+    let mut connection = rustls::ClientConnection::new(Arc::new(config), server_name)?;
+
+    let address = (name, port.unwrap_or(default_port(true)))
+        .to_socket_addrs()?
+        .next()
+        .context(format!("{host} doesn't resolve"))?;
+    let mut socket = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.set_write_timeout(Some(TIMEOUT))?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut socket)?;
+    }
+
+    let root_key = recorder.root_key.lock().unwrap().take();
+    root_key.context("The handshake didn't verify a certificate chain")
+}
+
+// Enough of a key's hash to tell two apart in a message
+fn short(key: &str) -> &str {
+    key.get(..16).unwrap_or(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_hosts_keep_ports_other_than_the_default() {
+        let host = |source: &str| source_host(source).map(|(host, _, _)| host);
+        assert_eq!(
+            host("sparse+https://index.crates.io/").as_deref(),
+            Some("index.crates.io")
+        );
+        assert_eq!(
+            host("sparse+https://Registry.example.com:443/index/").as_deref(),
+            Some("registry.example.com")
+        );
+        assert_eq!(
+            host("sparse+https://registry.example.com:8443/index/").as_deref(),
+            Some("registry.example.com:8443")
+        );
+        assert_eq!(
+            host("registry+http://user@registry.example.com:8080/git/index").as_deref(),
+            Some("registry.example.com:8080")
+        );
+        assert_eq!(host("git+https://[::1]:8443/repo.git").as_deref(), Some("[::1]:8443"));
+        assert_eq!(host("git+https://[::1]/repo.git").as_deref(), Some("[::1]"));
+        assert_eq!(host("sparse+https://registry.example.com:port/"), None);
+
+        assert_eq!(
+            split_port("registry.example.com:8443"),
+            Some(("registry.example.com", Some(8443)))
+        );
+        assert_eq!(split_port("[::1]:8443"), Some(("::1", Some(8443))));
+        assert_eq!(split_port("[::1]"), Some(("::1", None)));
+        assert_eq!(split_port("registry.example.com"), Some(("registry.example.com", None)));
+    }
+}
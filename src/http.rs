@@ -0,0 +1,93 @@
+use anyhow::{Context, Result, bail};
+use std::io::Read;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: u32 = 5;
+
+// Body of a plain GET, following redirects. Enough for the small files the tool reads from
+// servers of its own choosing; crates themselves are always downloaded by cargo.
+pub fn get(url: &str) -> Result<Vec<u8>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .timeout_write(TIMEOUT)
+        .redirects(MAX_REDIRECTS)
+        .user_agent(concat!("cargo-localize/", env!("CARGO_PKG_VERSION")))
+        .tls_config(crate::hosts::tls_config()?)
+        .build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => bail!("{url} answered {status} {}", response.status_text()),
+        Err(error) => return Err(error).context(format!("Failed to fetch {url}")),
+    };
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut body)
+        .context(format!("Failed to read the response from {url}"))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    // Answers a single request with `response`, and hands back the request line it got
+    fn serve(listener: TcpListener, response: &'static str) -> (SocketAddr, thread::JoinHandle<String>) {
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn get_reads_chunked_bodies() {
+        let (address, server) = serve(
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;name=value\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n",
+        );
+        let body = get(&format!("http://{address}/index.json")).unwrap();
+        assert_eq!(body, b"hello, world");
+        assert_eq!(server.join().unwrap(), "GET /index.json HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn get_reaches_ipv6_hosts() {
+        // Hosts without an IPv6 loopback have nothing to test against
+        let Ok(listener) = TcpListener::bind("[::1]:0") else {
+            return;
+        };
+        let (address, server) = serve(listener, "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let body = get(&format!("http://{address}/approved.toml")).unwrap();
+        assert_eq!(body, b"ok");
+        assert_eq!(server.join().unwrap(), "GET /approved.toml HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn get_reports_the_status_of_failed_requests() {
+        let (address, server) = serve(
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+        );
+        let error = get(&format!("http://{address}/missing")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("http://{address}/missing answered 404 Not Found")
+        );
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::journal::Journal;
+use crate::lock::LocalizeLock;
+use crate::util::format_size;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::Path;
+use walkdir::WalkDir;
+
+pub const INDEX_FILE_NAME: &str = "INDEX.md";
+pub const INDEX_JSON_FILE_NAME: &str = "index.json";
+
+// One row per vendored crate, for reviewers going through the 3rd-party folder and for tools.
+// Nothing in it changes unless the crates do, so it only shows up in a diff next to them.
+pub fn write(project_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    // A folder shared with an enclosing project is indexed by that project, which vendors all of it
+    if !crate::safety::is_contained(Path::new(&localize_lock.third_party_dir)) {
+        return Ok(());
+    }
+    let third_party_path = localize_lock.third_party_path(project_path);
+    if !third_party_path.is_dir() {
+        return Ok(());
+    }
+
+    let mut packages: Vec<_> = localize_lock.packages.iter().collect();
+    packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    let mut rows = Vec::new();
+    let mut crates = Vec::new();
+    let mut total = 0;
+    for locked in packages {
+        let entry = crate::export::inventory_entry(localize_lock, locked, project_path)?;
+        let size = size(&third_party_path.join(&locked.path));
+        total += size;
+        let source = match (entry.git_origin(), &locked.source) {
+            (Some((url, revision)), _) => Some(format!("{url}#{revision}")),
+            (None, Some(_)) if locked.is_from_crates_io() => {
+                Some(format!("https://crates.io/crates/{}/{}", locked.name, locked.version))
+            }
+            (None, source) => source.clone(),
+        };
+        rows.push(format!(
+            "| [{}]({}/) | {} | {} | {} | {} | {} |",
+            locked.name,
+            locked.path,
+            locked.version,
+            cell(entry.license.as_deref().unwrap_or("not declared")),
+            format_size(size),
+            source
+                .as_deref()
+                .map_or("local".to_string(), |source| format!("<{source}>")),
+            cell(entry.description.as_deref().unwrap_or_default()),
+        ));
+        crates.push(json!({
+            "name": locked.name,
+            "version": locked.version,
+            "path": locked.path,
+            "license": entry.license,
+            "description": entry.description,
+            "source": source,
+            "repository": entry.repository,
+            "size": size,
+        }));
+    }
+
+    let markdown = format!(
+        "# Vendored crates\n\n\
+         Generated by cargo-localize from localize.lock on every run, don't edit it by hand. \
+         {} crate(s), {} in total.\n\n\
+         | Crate | Version | License | Size | Source | Description |\n\
+         |---|---|---|---:|---|---|\n\
+         {}\n",
+        crates.len(),
+        format_size(total),
+        rows.join("\n")
+    );
+    let index = json!({
+        "crates": crates,
+        "size": total,
+    });
+    journal
+        .write(&third_party_path.join(INDEX_FILE_NAME), markdown)
+        .context(format!("Failed to write {INDEX_FILE_NAME}"))?;
+    journal
+        .write(
+            &third_party_path.join(INDEX_JSON_FILE_NAME),
+            serde_json::to_string_pretty(&index)? + "\n",
+        )
+        .context(format!("Failed to write {INDEX_JSON_FILE_NAME}"))
+}
+
+// Table cells are one line, without the column separator
+fn cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+// Crates linked from the store count with what they link to
+fn size(crate_dir: &Path) -> u64 {
+    WalkDir::new(crate_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
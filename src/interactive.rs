@@ -0,0 +1,245 @@
+use crate::LocalizeArgs;
+use crate::filter::PackageSpec;
+use anyhow::{Context, Result, bail};
+use cargo_metadata::{Metadata, Package, PackageId};
+use console::{Key, Term};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::info;
+
+// One line of the list: a direct dependency of the workspace heading the crates it pulls in, or
+// a crate with its checkbox (an index into the candidates)
+enum Row {
+    Group(String),
+    Crate(usize),
+}
+
+// Lets the user pick in the terminal which of the crates the run would copy it does copy, before
+// anything is locked or changed. Returns specs of the crates left out, which stay on the registry
+// like `--exclude`d ones, or None once the user cancels. Crates already vendored aren't listed,
+// dropping them takes `gc`.
+pub fn select(args: &LocalizeArgs, project_path: &Path) -> Result<Option<Vec<PackageSpec>>> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        bail!("--interactive needs a terminal, pick the crates with -p and --exclude instead");
+    }
+    let third_party_path = crate::util::normalize_path(&project_path.join(args.project.third_party_dir()));
+    let targets = args.target_platforms()?;
+    info!("Getting metadata...");
+    let metadata = args
+        .network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(crate::platform::cargo_options(
+            args.filter_platform.as_deref(),
+            &args.network,
+        ))
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let reachable = crate::needed_packages(&metadata, &targets, args.no_dev_deps, args.no_build_deps);
+
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let candidates: Vec<&Package> = metadata
+        .packages
+        .iter()
+        .filter(|package| {
+            !package.manifest_path.starts_with(&third_party_path)
+                && reachable
+                    .as_ref()
+                    .is_none_or(|reachable| reachable.contains(&package.id))
+                && !crate::is_workspace_package(package, workspace_root)
+                && (package.source.is_some() || args.external_paths)
+                && crate::filter::is_selected(&args.packages, &args.exclude, &package.name, &package.version)
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+
+    let rows = group(&metadata, &candidates);
+    let mut picked = vec![true; candidates.len()];
+    if !pick(&term, &rows, &candidates, &mut picked)? {
+        return Ok(None);
+    }
+
+    let left_out: Vec<String> = candidates
+        .iter()
+        .zip(&picked)
+        .filter(|(_, picked)| !**picked)
+        .map(|(package, _)| format!("{}@{}", package.name, package.version))
+        .collect();
+    if !left_out.is_empty() {
+        info!(
+            "Leaving {} crate(s) on the registry, the same as --exclude {}",
+            left_out.len(),
+            left_out.join(" --exclude ")
+        );
+    }
+    left_out
+        .iter()
+        .map(|spec| spec.parse().map_err(anyhow::Error::msg))
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+// The candidates under the direct dependency of the workspace that pulls them in, a crate several
+// of them share under the first by name
+fn group(metadata: &Metadata, candidates: &[&Package]) -> Vec<Row> {
+    let index: HashMap<&PackageId, usize> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, package)| (&package.id, i))
+        .collect();
+    let packages: HashMap<&PackageId, &Package> =
+        metadata.packages.iter().map(|package| (&package.id, package)).collect();
+    let nodes: HashMap<&PackageId, &cargo_metadata::Node> = metadata
+        .resolve
+        .iter()
+        .flat_map(|resolve| &resolve.nodes)
+        .map(|node| (&node.id, node))
+        .collect();
+    let members: HashSet<&PackageId> = metadata.workspace_members.iter().collect();
+    let deps = |id: &PackageId| nodes.get(id).map_or(&[][..], |node| node.deps.as_slice());
+
+    let mut direct: Vec<&PackageId> = members
+        .iter()
+        .flat_map(|member| deps(member))
+        .map(|dep| &dep.pkg)
+        .filter(|id| !members.contains(id) && packages.contains_key(id))
+        .collect();
+    direct.sort_by_key(|id| (&packages[id].name, &packages[id].version));
+    direct.dedup();
+
+    let mut placed = vec![false; candidates.len()];
+    let mut rows = Vec::new();
+    for head in direct {
+        let mut crates = Vec::new();
+        let mut seen = HashSet::from([head]);
+        let mut queue = vec![head];
+        while let Some(id) = queue.pop() {
+            if let Some(&i) = index.get(id)
+                && !placed[i]
+            {
+                placed[i] = true;
+                crates.push(i);
+            }
+            for dep in deps(id) {
+                if !members.contains(&dep.pkg) && seen.insert(&dep.pkg) {
+                    queue.push(&dep.pkg);
+                }
+            }
+        }
+        if crates.is_empty() {
+            continue;
+        }
+        crates.sort_by_key(|&i| (candidates[i].id != *head, &candidates[i].name, &candidates[i].version));
+        let package = packages[head];
+        rows.push(Row::Group(format!("{} {}", package.name, package.version)));
+        rows.extend(crates.into_iter().map(Row::Crate));
+    }
+    // Only reached through another route, e.g. a `[patch]` entry
+    let rest: Vec<usize> = (0..candidates.len()).filter(|&i| !placed[i]).collect();
+    if !rest.is_empty() {
+        rows.push(Row::Group("other".to_string()));
+        rows.extend(rest.into_iter().map(Row::Crate));
+    }
+    rows
+}
+
+// Draws the list and toggles checkboxes until the user confirms, or cancels, which returns false
+fn pick(term: &Term, rows: &[Row], candidates: &[&Package], picked: &mut [bool]) -> Result<bool> {
+    let mut cursor: usize = 0;
+    let mut top = 0;
+    let mut drawn = 0;
+    let _cursor = HiddenCursor::new(term)?;
+    let result = loop {
+        let height = usize::from(term.size().0).saturating_sub(3).max(5);
+        top = top.min(cursor).max((cursor + 1).saturating_sub(height));
+        let mut lines = vec![format!(
+            "{} of {} crate(s) picked. Space toggles a crate or a whole group, a toggles all, enter copies, esc cancels",
+            picked.iter().filter(|picked| **picked).count(),
+            candidates.len()
+        )];
+        for (i, row) in rows.iter().enumerate().skip(top).take(height) {
+            let marker = if i == cursor { '>' } else { ' ' };
+            lines.push(match row {
+                Row::Group(label) => {
+                    let crates = toggled_by(rows, i);
+                    let checkbox = match crates.iter().filter(|&&c| picked[c]).count() {
+                        0 => "[ ]",
+                        count if count == crates.len() => "[x]",
+                        _ => "[-]",
+                    };
+                    format!("{marker} {checkbox} {label}")
+                }
+                Row::Crate(c) => format!(
+                    "{marker}     [{}] {} {}",
+                    if picked[*c] { 'x' } else { ' ' },
+                    candidates[*c].name,
+                    candidates[*c].version
+                ),
+            });
+        }
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        drawn = lines.len();
+
+        let last = rows.len() - 1;
+        match term.read_key()? {
+            Key::ArrowUp | Key::Char('k') => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1).min(last),
+            Key::PageUp => cursor = cursor.saturating_sub(height),
+            Key::PageDown => cursor = (cursor + height).min(last),
+            Key::Home => cursor = 0,
+            Key::End => cursor = last,
+            Key::Char(' ') => {
+                let crates = toggled_by(rows, cursor);
+                let value = !crates.iter().all(|&c| picked[c]);
+                for c in crates {
+                    picked[c] = value;
+                }
+            }
+            Key::Char('a') => {
+                let value = !picked.iter().all(|picked| *picked);
+                picked.fill(value);
+            }
+            Key::Enter => break Ok(true),
+            Key::Escape | Key::Char('q') | Key::CtrlC => break Ok(false),
+            _ => {}
+        }
+    };
+    term.clear_last_lines(drawn)?;
+    result
+}
+
+// Keeps the cursor hidden while the list is drawn, and brings it back however `pick` returns
+struct HiddenCursor<'a>(&'a Term);
+
+impl<'a> HiddenCursor<'a> {
+    fn new(term: &'a Term) -> Result<Self> {
+        term.hide_cursor()?;
+        Ok(Self(term))
+    }
+}
+
+impl Drop for HiddenCursor<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.show_cursor();
+    }
+}
+
+// The crate of a row, or every crate of a group
+fn toggled_by(rows: &[Row], row: usize) -> Vec<usize> {
+    match rows[row] {
+        Row::Crate(c) => vec![c],
+        Row::Group(_) => rows[row + 1..]
+            .iter()
+            .map_while(|row| match row {
+                Row::Crate(c) => Some(*c),
+                Row::Group(_) => None,
+            })
+            .collect(),
+    }
+}
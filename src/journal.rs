@@ -0,0 +1,779 @@
+use crate::config::SymlinkPolicy;
+use crate::util::long_path;
+use anyhow::{Context, Result};
+use fs_extra::dir::{self, CopyOptions};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tracing::{debug, warn};
+
+pub const JOURNAL_FILE_NAME: &str = "localize.journal";
+// Directories and files the run removes or overwrites are kept here until it finishes, so a
+// rollback can bring them back
+pub const TRASH_DIR_NAME: &str = ".cargo-localize.trash";
+
+// Journal of a run. Every filesystem change is recorded before it's made, so a crashed run leaves
+// a precise account of what it touched. It lives as long as the run and is handed down to whatever
+// changes the project; the default one records nothing, for commands that aren't undone as a whole.
+#[derive(Default)]
+pub struct Journal {
+    file: Mutex<Option<File>>,
+    // What the run removes or overwrites goes in here, numbered by how much went in before
+    trash: Option<PathBuf>,
+    trashed: AtomicUsize,
+    // Whether the run recorded a change yet
+    changed: AtomicBool,
+    // Replace files through a temporary sibling, so watchers see one rename instead of a truncate and writes
+    atomic_writes: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+enum Entry {
+    Begin {
+        command: String,
+        started: u64,
+    },
+    CopyDir {
+        from: PathBuf,
+        to: PathBuf,
+        // Checksums of the files copied, by path relative to `to`
+        #[serde(default)]
+        files: BTreeMap<PathBuf, String>,
+    },
+    CopyFile {
+        from: PathBuf,
+        to: PathBuf,
+        sha256: String,
+    },
+    Write {
+        path: PathBuf,
+        before: Option<FileState>,
+        after: String,
+    },
+    RemoveFile {
+        path: PathBuf,
+        before: FileState,
+    },
+    RemoveDir {
+        path: PathBuf,
+        // Where the directory was moved instead of being deleted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trash: Option<PathBuf>,
+    },
+    MoveDir {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    CreateDir {
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct FileState {
+    sha256: String,
+    // Where the file was kept before it was changed, restored from there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash: Option<PathBuf>,
+    // Target of a symbolic link, which gets linked again instead of its content restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub struct DoctorArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Undo the changes of the interrupted run and remove the journal
+    #[clap(long)]
+    fix: bool,
+}
+
+impl Journal {
+    pub fn begin(project_path: &Path, command: &str, atomic_writes: bool) -> Result<Self> {
+        let journal_path = project_path.join(JOURNAL_FILE_NAME);
+        if journal_path.exists() {
+            return Err(crate::LocalizeError::Interrupted { journal: journal_path }.into());
+        }
+
+        let file = File::create(&journal_path).context(format!("Failed to create {JOURNAL_FILE_NAME}"))?;
+        let journal = Journal {
+            file: Mutex::new(Some(file)),
+            trash: Some(project_path.join(TRASH_DIR_NAME)),
+            atomic_writes,
+            ..Journal::default()
+        };
+        journal.record(&Entry::Begin {
+            command: command.to_string(),
+            started: crate::util::unix_now(),
+        })?;
+        Ok(journal)
+    }
+
+    // Closes the journal of a successful run
+    pub fn finish(self) -> Result<()> {
+        let Some(project_path) = self.project_path() else {
+            return Ok(());
+        };
+        empty_trash(&project_path)?;
+        fs::remove_file(project_path.join(JOURNAL_FILE_NAME)).context(format!("Failed to remove {JOURNAL_FILE_NAME}"))
+    }
+
+    // Closes the journal of a failed run, removing it if the run failed before changing anything.
+    // Returns whether it was kept for `doctor`.
+    pub fn abandon(self) -> Result<bool> {
+        let Some(project_path) = self.project_path() else {
+            return Ok(true);
+        };
+        if self.changed.load(Ordering::SeqCst) {
+            return Ok(true);
+        }
+        fs::remove_file(project_path.join(JOURNAL_FILE_NAME))
+            .context(format!("Failed to remove {JOURNAL_FILE_NAME}"))?;
+        Ok(false)
+    }
+
+    // Project the journal was begun in, the trash directory sits right in it
+    fn project_path(&self) -> Option<PathBuf> {
+        self.trash.as_deref().and_then(Path::parent).map(Path::to_path_buf)
+    }
+
+    fn record(&self, entry: &Entry) -> Result<()> {
+        let mut journal = self.file.lock().unwrap();
+        let Some(file) = journal.as_mut() else {
+            return Ok(());
+        };
+
+        if !matches!(entry, Entry::Begin { .. }) {
+            self.changed.store(true, Ordering::SeqCst);
+        }
+        let line = serde_json::to_string(entry).context("Failed to serialize journal entry")?;
+        writeln!(file, "{line}").context(format!("Failed to write {JOURNAL_FILE_NAME}"))?;
+        file.sync_data().context(format!("Failed to sync {JOURNAL_FILE_NAME}"))
+    }
+
+    fn is_active(&self) -> bool {
+        self.trash.is_some()
+    }
+
+    pub fn write(&self, path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+        let contents = contents.as_ref();
+        // Rewriting a file with the same content only wakes up file watchers
+        if fs::read(path).is_ok_and(|current| current == contents) {
+            return Ok(());
+        }
+
+        if self.is_active() {
+            let before = self.kept_state(path)?;
+            self.record(&Entry::Write {
+                path: path.to_path_buf(),
+                before: before.clone(),
+                after: sha256(contents),
+            })?;
+            if let Some(trash) = before.and_then(|before| before.trash) {
+                make_room(&trash)?;
+                fs::copy(long_path(path), long_path(&trash))?;
+            }
+        }
+
+        let path = long_path(path);
+        if self.atomic_writes {
+            let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+            temp_name.push(".cargo-localize-tmp");
+            let temp_path = path.with_file_name(temp_name);
+            fs::write(&temp_path, contents)?;
+            fs::rename(&temp_path, &path)?;
+        } else {
+            fs::write(&path, contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn copy_file(&self, from: &Path, to: &Path) -> Result<()> {
+        if self.is_active() {
+            self.record(&Entry::CopyFile {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                sha256: file_sha256(from)?,
+            })?;
+        }
+        fs::copy(long_path(from), long_path(to))?;
+        Ok(())
+    }
+
+    // Copies the contents of `from` into the new directory `to`
+    pub fn copy_dir(&self, from: &Path, to: &Path, options: &CopyOptions, symlinks: SymlinkPolicy) -> Result<()> {
+        self.copy_dir_except(from, to, options, &[], &[], symlinks)
+    }
+
+    // Copies the contents of `from` into the new directory `to`, but for the top-level entries named
+    // in `skip` and the files matching an `ignore` glob
+    pub fn copy_dir_except(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: &CopyOptions,
+        skip: &[&str],
+        ignore: &[String],
+        symlinks: SymlinkPolicy,
+    ) -> Result<()> {
+        let (long_from, long_to) = (long_path(from), long_path(to));
+        let entries: Vec<walkdir::DirEntry> =
+            crate::util::tree_entries(&long_from, skip, ignore, symlinks).collect::<walkdir::Result<_>>()?;
+        if self.is_active() {
+            let mut files = BTreeMap::new();
+            for entry in entries.iter().filter(|entry| entry.file_type().is_file()) {
+                files.insert(
+                    entry.path().strip_prefix(&long_from)?.to_path_buf(),
+                    file_sha256(entry.path())?,
+                );
+            }
+            self.record(&Entry::CopyDir {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                files,
+            })?;
+        }
+        crate::util::copy_entries(&long_from, &long_to, entries.into_iter().map(Ok), options, symlinks)
+    }
+
+    // Moves a directory assembled elsewhere into place, `to` must not exist yet
+    pub fn move_dir(&self, from: &Path, to: &Path) -> Result<()> {
+        self.record(&Entry::MoveDir {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })?;
+        rename_dir(&long_path(from), &long_path(to))
+    }
+
+    // Creates a directory and its missing parents, recording the topmost one created
+    pub fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let created = path.ancestors().take_while(|dir| !dir.exists()).last();
+        if let Some(created) = created {
+            self.record(&Entry::CreateDir {
+                path: created.to_path_buf(),
+            })?;
+        }
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    pub fn remove_file(&self, path: &Path) -> Result<()> {
+        if !self.is_active() {
+            fs::remove_file(path)?;
+            return Ok(());
+        }
+
+        // A symbolic link is kept as its target, it's removed rather than what it points to
+        let before = if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            let target = fs::read_link(path)?;
+            Some(FileState {
+                sha256: sha256(target.as_os_str().as_encoded_bytes()),
+                trash: None,
+                link: Some(target),
+            })
+        } else {
+            self.kept_state(path)?
+        };
+        if let Some(before) = &before {
+            self.record(&Entry::RemoveFile {
+                path: path.to_path_buf(),
+                before: before.clone(),
+            })?;
+        }
+        match before.and_then(|before| before.trash) {
+            Some(trash) => {
+                make_room(&trash)?;
+                move_file(&long_path(path), &long_path(&trash))
+            }
+            None => Ok(fs::remove_file(path)?),
+        }
+    }
+
+    pub fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        let Some(trash) = self.trash_slot() else {
+            fs::remove_dir_all(path)?;
+            return Ok(());
+        };
+
+        self.record(&Entry::RemoveDir {
+            path: path.to_path_buf(),
+            trash: Some(trash.clone()),
+        })?;
+        make_room(&trash)?;
+        rename_dir(&long_path(path), &long_path(&trash))
+    }
+
+    // Next free place in the trash, `None` without a journal
+    fn trash_slot(&self) -> Option<PathBuf> {
+        let trash = self.trash.as_ref()?;
+        Some(trash.join(self.trashed.fetch_add(1, Ordering::SeqCst).to_string()))
+    }
+
+    // State of a file about to be changed, with the place in the trash its original goes to
+    fn kept_state(&self, path: &Path) -> Result<Option<FileState>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(FileState {
+            sha256: file_sha256(path)?,
+            trash: self.trash_slot(),
+            link: None,
+        }))
+    }
+}
+
+// Creates the trash on first use
+fn make_room(trash: &Path) -> Result<()> {
+    if let Some(parent) = trash.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {TRASH_DIR_NAME}"))?;
+    }
+    Ok(())
+}
+
+// Undoes the changes of a run that failed, newest first, and removes its journal. Returns `false`
+// if some changes can't be undone, the journal is then kept for `doctor`.
+pub fn roll_back(project_path: &Path) -> Result<bool> {
+    let journal_path = project_path.join(JOURNAL_FILE_NAME);
+    let (entries, _) = read(&journal_path)?;
+    let mut unresolved = 0;
+    for entry in entries.iter().rev() {
+        // What couldn't be undone is told right away, the rest only matters when debugging
+        let notes = RefCell::new(Vec::new());
+        let undone = undo(entry, &|note| notes.borrow_mut().push(note))?;
+        for note in notes.into_inner() {
+            if undone {
+                debug!("{note}");
+            } else {
+                warn!("{note}");
+            }
+        }
+        if !undone {
+            unresolved += 1;
+        }
+    }
+    if unresolved > 0 {
+        return Ok(false);
+    }
+    empty_trash(project_path)?;
+    fs::remove_file(&journal_path).context(format!("Failed to remove {JOURNAL_FILE_NAME}"))?;
+    Ok(true)
+}
+
+fn empty_trash(project_path: &Path) -> Result<()> {
+    let trash = project_path.join(TRASH_DIR_NAME);
+    if trash.exists() {
+        fs::remove_dir_all(&trash).context(format!("Failed to remove {TRASH_DIR_NAME}"))?;
+    }
+    Ok(())
+}
+
+// Entries of a journal, and whether its last line was torn by a process dying mid-write
+fn read(journal_path: &Path) -> Result<(Vec<Entry>, bool)> {
+    let file = File::open(journal_path).context(format!("Failed to open {JOURNAL_FILE_NAME}"))?;
+    let mut entries = Vec::new();
+    let mut torn = false;
+    for line in BufReader::new(file).lines() {
+        let line = line.context(format!("Failed to read {JOURNAL_FILE_NAME}"))?;
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => torn = true,
+        }
+    }
+    Ok((entries, torn))
+}
+
+// Files kept in the trash may be on another filesystem than the project, e.g. in a staging directory
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn rename_dir(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        // Staging and destination may be on different filesystems
+        let options = CopyOptions::new().copy_inside(true);
+        dir::move_dir(from, to, &options)?;
+    }
+    Ok(())
+}
+
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    Ok(sha256(&bytes))
+}
+
+pub fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn current_sha256(path: &Path) -> Option<String> {
+    path.is_file().then(|| file_sha256(path).ok()).flatten()
+}
+
+pub fn doctor(args: &DoctorArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let journal_path = project_path.join(JOURNAL_FILE_NAME);
+    if !journal_path.exists() {
+        println!("No interrupted run found, the project is in a consistent state");
+        return Ok(());
+    }
+
+    let (entries, torn) = read(&journal_path)?;
+    if torn {
+        println!("Ignoring incomplete journal entry");
+    }
+
+    println!("Interrupted run recorded in {}:", journal_path.display());
+    for entry in &entries {
+        explain(entry);
+    }
+    let marker = project_path.join(crate::quiesce::MARKER_FILE_NAME);
+    if marker.exists() {
+        println!("  file watchers are still told to wait ({})", marker.display());
+    }
+
+    if !args.fix {
+        println!("Run `cargo-localize doctor --fix` to undo these changes");
+        return Ok(());
+    }
+
+    println!("Undoing changes...");
+    let mut unresolved = 0;
+    for entry in entries.iter().rev() {
+        if !undo(entry, &|note| println!("  {note}"))? {
+            unresolved += 1;
+        }
+    }
+
+    if marker.exists() {
+        fs::remove_file(&marker).context(format!("Failed to remove {}", marker.display()))?;
+        println!("  Removed {}", marker.display());
+    }
+    fs::remove_file(&journal_path).context(format!("Failed to remove {JOURNAL_FILE_NAME}"))?;
+    if unresolved > 0 {
+        println!("{unresolved} change(s) could not be undone automatically, see above");
+        if project_path.join(TRASH_DIR_NAME).exists() {
+            println!(
+                "Originals of what the run removed or overwrote are kept in {}",
+                project_path.join(TRASH_DIR_NAME).display()
+            );
+        }
+    } else {
+        empty_trash(&project_path)?;
+        println!("All changes of the interrupted run were undone");
+    }
+    Ok(())
+}
+
+fn explain(entry: &Entry) {
+    match entry {
+        Entry::Begin { command, started } => {
+            println!("  `{command}` started at {}", crate::util::format_timestamp(*started));
+        }
+        Entry::CopyDir { from, to, files } => {
+            let state = if !to.exists() {
+                "not created".to_string()
+            } else {
+                let copied = files
+                    .iter()
+                    .filter(|(file, sha256)| current_sha256(&to.join(file)).as_ref() == Some(*sha256))
+                    .count();
+                if copied == files.len() {
+                    "complete".to_string()
+                } else {
+                    format!("partial or changed since, {copied} of {} file(s) copied", files.len())
+                }
+            };
+            println!("  copy {} -> {} ({state})", from.display(), to.display());
+        }
+        Entry::CopyFile { to, sha256, .. } => {
+            let state = match current_sha256(to) {
+                Some(current) if &current == sha256 => "done",
+                Some(_) => "partial or changed since",
+                None => "not done",
+            };
+            println!("  backup {} ({state})", to.display());
+        }
+        Entry::Write { path, before, after } => {
+            let current = current_sha256(path);
+            let state = if current.as_ref() == Some(after) {
+                "written"
+            } else if current.as_ref() == before.as_ref().map(|b| &b.sha256) {
+                "not written yet"
+            } else {
+                "partially written or changed since"
+            };
+            println!("  write {} ({state})", path.display());
+        }
+        Entry::RemoveFile { path, .. } => {
+            let state = if fs::symlink_metadata(path).is_ok() {
+                "still present"
+            } else {
+                "removed"
+            };
+            println!("  remove {} ({state})", path.display());
+        }
+        Entry::RemoveDir { path, trash } => {
+            let state = match trash {
+                Some(trash) if trash.exists() => "moved aside, can be restored",
+                _ if path.exists() => "not removed yet",
+                Some(_) => "removed",
+                None => "removed, can't be restored",
+            };
+            println!("  remove directory {} ({state})", path.display());
+        }
+        Entry::MoveDir { from, to } => {
+            let state = if from.exists() { "not moved yet" } else { "moved" };
+            println!("  move {} -> {} ({state})", from.display(), to.display());
+        }
+        Entry::CreateDir { path } => {
+            let state = if path.exists() { "present" } else { "not created" };
+            println!("  create directory {} ({state})", path.display());
+        }
+    }
+}
+
+// Returns `false` if the change needs manual attention
+fn undo(entry: &Entry, note: &dyn Fn(String)) -> Result<bool> {
+    match entry {
+        Entry::Begin { .. } => Ok(true),
+        Entry::CopyDir { to, files, .. } => {
+            // Later changes to the copy were undone before, a file differing from what was copied
+            // was edited by someone else since
+            let changed: Vec<&PathBuf> = files
+                .iter()
+                .filter(|(file, sha256)| current_sha256(&to.join(file)).is_some_and(|current| current != **sha256))
+                .map(|(file, _)| file)
+                .collect();
+            if !changed.is_empty() {
+                note(format!(
+                    "Kept {}: {} file(s) changed since they were copied",
+                    to.display(),
+                    changed.len()
+                ));
+                return Ok(false);
+            }
+            if to.exists() {
+                fs::remove_dir_all(to).context(format!("Failed to remove {}", to.display()))?;
+                note(format!("Removed {}", to.display()));
+            }
+            Ok(true)
+        }
+        Entry::MoveDir { to, .. } => {
+            if to.exists() {
+                fs::remove_dir_all(to).context(format!("Failed to remove {}", to.display()))?;
+                note(format!("Removed {}", to.display()));
+            }
+            Ok(true)
+        }
+        Entry::CopyFile { to, .. } => {
+            if to.exists() {
+                fs::remove_file(to).context(format!("Failed to remove {}", to.display()))?;
+                note(format!("Removed {}", to.display()));
+            }
+            Ok(true)
+        }
+        Entry::Write { path, before, .. } => {
+            let current = current_sha256(path);
+            match before {
+                // The write never happened
+                Some(before) if current.as_ref() == Some(&before.sha256) => Ok(true),
+                // The file was inside a directory undone before, e.g. a crate that was copied by the run
+                _ if path.parent().is_some_and(|parent| !parent.exists()) => Ok(true),
+                None => {
+                    if path.exists() {
+                        fs::remove_file(path).context(format!("Failed to remove {}", path.display()))?;
+                        note(format!("Removed {}", path.display()));
+                    }
+                    Ok(true)
+                }
+                Some(FileState { trash: Some(trash), .. }) if trash.is_file() => {
+                    fs::copy(trash, path).context(format!("Failed to restore {}", path.display()))?;
+                    note(format!("Restored {}", path.display()));
+                    Ok(true)
+                }
+                Some(_) => {
+                    note(format!("Can't restore {}: its original wasn't kept", path.display()));
+                    Ok(false)
+                }
+            }
+        }
+        Entry::RemoveFile { path, before } => match (&before.link, &before.trash) {
+            // Never removed
+            _ if fs::symlink_metadata(path).is_ok() => Ok(true),
+            _ if path.parent().is_some_and(|parent| !parent.exists()) => Ok(true),
+            (Some(link), _) => {
+                let is_dir = path.parent().is_some_and(|parent| parent.join(link).is_dir());
+                crate::util::symlink(link, path, is_dir).context(format!("Failed to restore {}", path.display()))?;
+                note(format!("Restored {} -> {}", path.display(), link.display()));
+                Ok(true)
+            }
+            (None, Some(trash)) if trash.is_file() => {
+                move_file(trash, path).context(format!("Failed to restore {}", path.display()))?;
+                note(format!("Restored {}", path.display()));
+                Ok(true)
+            }
+            (None, _) => {
+                note(format!("Can't restore {}: it wasn't kept", path.display()));
+                Ok(false)
+            }
+        },
+        Entry::RemoveDir { path, trash } => match trash {
+            Some(trash) if trash.exists() => {
+                // The directory was inside one undone before, e.g. a crate that was copied by the run
+                if path.parent().is_some_and(|parent| !parent.exists()) {
+                    return Ok(true);
+                }
+                if path.exists() {
+                    note(format!(
+                        "Can't restore {}: it exists again, the removed one is in {}",
+                        path.display(),
+                        trash.display()
+                    ));
+                    return Ok(false);
+                }
+                rename_dir(trash, path).context(format!("Failed to restore {}", path.display()))?;
+                note(format!("Restored {}", path.display()));
+                Ok(true)
+            }
+            // The run died before moving it
+            _ if path.exists() => Ok(true),
+            _ => {
+                note(format!("Can't restore {}: it wasn't kept", path.display()));
+                Ok(false)
+            }
+        },
+        // Whatever the run put in it was undone before, anything else keeps it
+        Entry::CreateDir { path } => {
+            if path.exists() && remove_empty_dir(path) {
+                note(format!("Removed {}", path.display()));
+            }
+            Ok(true)
+        }
+    }
+}
+
+// Removes `dir` if nothing but empty directories is left in it, those created along with it
+fn remove_empty_dir(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let mut empty = true;
+    for entry in entries.flatten() {
+        empty &= entry.file_type().is_ok_and(|file_type| file_type.is_dir()) && remove_empty_dir(&entry.path());
+    }
+    empty && fs::remove_dir(dir).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    // Neither valid UTF-8 nor small, what the journal used to give up on
+    const BINARY: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0x00];
+
+    fn large() -> Vec<u8> {
+        vec![b'x'; 2 * 1024 * 1024]
+    }
+
+    #[test]
+    fn roll_back_restores_what_a_failed_run_changed() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        fs::write(root.join("logo.png"), BINARY).unwrap();
+        fs::write(root.join("large.txt"), large()).unwrap();
+        fs::write(root.join("Cargo.lock"), "version = 4\n").unwrap();
+        fs::create_dir_all(root.join("3rd-party/old-1.0.0")).unwrap();
+        fs::write(root.join("3rd-party/old-1.0.0/lib.rs"), BINARY).unwrap();
+
+        let journal = Journal::begin(root, "localize", false).unwrap();
+        journal.write(&root.join("logo.png"), "rewritten").unwrap();
+        journal.write(&root.join("large.txt"), "rewritten").unwrap();
+        journal.remove_file(&root.join("Cargo.lock")).unwrap();
+        journal.remove_dir_all(&root.join("3rd-party/old-1.0.0")).unwrap();
+        journal.create_dir_all(&root.join("3rd-party/new-2.0.0/src")).unwrap();
+        journal.write(&root.join("3rd-party/new-2.0.0/src/lib.rs"), "").unwrap();
+        assert!(journal.abandon().unwrap());
+        assert!(roll_back(root).unwrap());
+
+        assert_eq!(fs::read(root.join("logo.png")).unwrap(), BINARY);
+        assert_eq!(fs::read(root.join("large.txt")).unwrap(), large());
+        assert_eq!(fs::read_to_string(root.join("Cargo.lock")).unwrap(), "version = 4\n");
+        assert_eq!(fs::read(root.join("3rd-party/old-1.0.0/lib.rs")).unwrap(), BINARY);
+        assert!(!root.join("3rd-party/new-2.0.0").exists());
+        assert!(!root.join(JOURNAL_FILE_NAME).exists());
+        assert!(!root.join(TRASH_DIR_NAME).exists());
+    }
+
+    #[test]
+    fn abandon_drops_the_journal_of_a_run_that_changed_nothing() {
+        let project = tempfile::tempdir().unwrap();
+        let journal = Journal::begin(project.path(), "localize", false).unwrap();
+        assert!(!journal.abandon().unwrap());
+        assert!(!project.path().join(JOURNAL_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn doctor_fix_undoes_an_interrupted_run() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        let source = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("lib.rs"), BINARY).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        fs::write(root.join("logo.png"), BINARY).unwrap();
+
+        let journal = Journal::begin(root, "localize", false).unwrap();
+        journal
+            .write(&root.join("Cargo.toml"), "[package]\nname = \"changed\"\n")
+            .unwrap();
+        journal.remove_file(&root.join("logo.png")).unwrap();
+        journal.create_dir_all(&root.join("3rd-party")).unwrap();
+        journal
+            .copy_dir(
+                source.path(),
+                &root.join("3rd-party/dep-1.0.0"),
+                &CopyOptions::new(),
+                SymlinkPolicy::default(),
+            )
+            .unwrap();
+        // The process dies mid-entry, without closing the journal
+        drop(journal);
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(root.join(JOURNAL_FILE_NAME))
+            .unwrap();
+        write!(file, "{{\"op\":\"wri").unwrap();
+        assert!(Journal::begin(root, "localize", false).is_err());
+
+        let args = DoctorArgs {
+            project: crate::ProjectPath {
+                project_path: root.to_path_buf(),
+                manifest_path: None,
+            },
+            fix: true,
+        };
+        doctor(&args).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("Cargo.toml")).unwrap(),
+            "[package]\nname = \"app\"\n"
+        );
+        assert_eq!(fs::read(root.join("logo.png")).unwrap(), BINARY);
+        assert!(!root.join("3rd-party").exists());
+        assert!(!root.join(JOURNAL_FILE_NAME).exists());
+        assert!(!root.join(TRASH_DIR_NAME).exists());
+        Journal::begin(root, "localize", false).unwrap().finish().unwrap();
+    }
+}
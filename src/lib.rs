@@ -0,0 +1,2983 @@
+#![deny(warnings, clippy::unimplemented, clippy::todo)]
+
+mod adopt;
+mod advisories;
+mod approval;
+#[doc(hidden)]
+pub mod bench;
+mod bisect;
+mod build_env;
+mod build_scripts;
+mod completions;
+mod config;
+mod diff;
+mod edits;
+mod error;
+mod export;
+mod filter;
+mod freeze;
+mod gc;
+mod hooks;
+mod hosts;
+mod http;
+mod index;
+mod interactive;
+mod journal;
+mod license;
+mod lock;
+mod logging;
+mod merge;
+mod metrics;
+mod nested;
+mod network;
+mod notices;
+mod obligations;
+mod observer;
+mod outdated;
+mod pack;
+mod packaging;
+mod patch;
+mod patches;
+mod platform;
+mod policy;
+mod portability;
+mod prelude;
+mod progress;
+mod quiesce;
+mod registry;
+mod report;
+mod safety;
+mod sbom;
+mod spdx;
+mod stats;
+mod storage;
+mod store;
+mod summary;
+mod tree;
+mod trim;
+mod unpack;
+mod unsafe_audit;
+mod update;
+mod usage;
+mod util;
+mod vendor;
+mod verify;
+mod warnings;
+mod watch;
+
+use anyhow::{Context, Result};
+use cargo_metadata::semver::VersionReq;
+use cargo_metadata::{DependencyKind, Metadata, PackageId};
+use clap::Parser;
+use config::{FeaturePolicy, Strip};
+use fs_extra::dir::CopyOptions;
+use hooks::{Hook, HookedCrate};
+use journal::Journal;
+use lock::{LOCK_FILE_NAME, LocalizeLock, LockedPackage, LockfilePolicy};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use toml_edit::{Array, DocumentMut, Item, Table, TableLike, Value};
+use tracing::{debug, info, warn};
+
+pub use error::{CrateRef, LocalizeError, Refusal};
+pub use filter::PackageSpec;
+pub use license::{Classifier, Detection};
+pub use lock::{Shard, Strategy};
+pub use network::NetworkArgs;
+use observer::Observers;
+pub use observer::{Event, Observer, Phase, SkipReason};
+pub use warnings::WarningClass;
+
+#[derive(Parser)]
+#[clap(
+    name = "cargo-localize",
+    version,
+    about = "Localizes all dependencies into a 3rd-party folder",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    localize: LocalizeArgs,
+    /// Verify the vendored tree against localize.lock without changing anything
+    #[clap(long)]
+    check: bool,
+    /// Print a line for every crate and manifest instead of progress bars, `-vv` also shows
+    /// where crate sources are searched for
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Only print warnings and errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Format of the printed events (filter them with `CARGO_LOCALIZE_LOG`, e.g. `cargo_localize=debug`)
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    log_format: logging::LogFormat,
+    /// Print the lines about each crate as they happen, instead of together and in a stable order
+    #[clap(long, global = true)]
+    live: bool,
+}
+
+impl Args {
+    /// Parses the command line of `cargo-localize ...` as well as `cargo localize ...`, for which
+    /// cargo passes the subcommand name as the first argument
+    pub fn from_env() -> Self {
+        let mut args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+        let mut command = <Self as clap::CommandFactory>::command();
+        if args.get(1).is_some_and(|arg| arg == "localize") {
+            args.remove(1);
+            command = command.bin_name("cargo localize");
+        }
+        let matches = command.get_matches_from(args);
+        <Self as clap::FromArgMatches>::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
+    }
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Localize only dependencies added or changed since the last run
+    Sync(LocalizeArgs),
+    /// Sync again whenever the workspace's manifests or config file change
+    Watch(watch::WatchArgs),
+    /// Remove vendored crates no longer used by the dependency graph
+    Gc(gc::GcArgs),
+    /// Inspect and clean up after an interrupted run
+    Doctor(journal::DoctorArgs),
+    /// Report vendored crates with newer compatible or incompatible releases on their registry
+    Outdated(outdated::OutdatedArgs),
+    /// Update dependencies and re-localize after reviewing the vendored set changes
+    Update(update::UpdateArgs),
+    /// Replace one vendored crate with another release of it, pointing every manifest at the new one
+    Upgrade(update::UpgradeArgs),
+    /// Take over a hand-vendored folder: match its crates with crates.io and write localize.lock,
+    /// without copying anything
+    Adopt(adopt::AdoptArgs),
+    /// Write a build-script check that stops builds when the vendored crates were modified
+    Prelude(prelude::PreludeArgs),
+    /// Mark vendored crates as frozen: sync, update and gc leave them alone unless given `--unfreeze`
+    Freeze(freeze::FreezeArgs),
+    /// Clear the frozen mark of vendored crates
+    Unfreeze(freeze::FreezeArgs),
+    /// Show local changes of vendored crates as unified diffs against their pristine sources
+    Diff(diff::DiffArgs),
+    /// List files of vendored crates added, removed or modified since they were published, failing
+    /// if any were
+    Verify(diff::VerifyArgs),
+    /// List the environment variables build scripts of vendored crates consult, to set up offline
+    /// build environments
+    BuildEnv(build_env::BuildEnvArgs),
+    /// Summarize how the vendored crates changed since a git revision, as a pull request
+    /// description or commit message trailers
+    Summary(summary::SummaryArgs),
+    /// Bundle the 3rd-party folder and localize.lock into a reproducible `.tar.zst`, e.g. for an
+    /// air-gapped build environment or a release
+    Pack(pack::PackArgs),
+    /// Restore the 3rd-party folder from a `pack` archive, check it against the packed checksums
+    /// and point the manifests at it
+    Unpack(unpack::UnpackArgs),
+    /// Localize a project that uses `cargo vendor`, copying the crates from its vendored folder
+    ImportVendor(vendor::ImportVendorArgs),
+    /// Write the vendored crates in the layout `cargo vendor` produces, checksums included
+    ExportVendor(vendor::ExportVendorArgs),
+    /// Write an inventory of vendored crates for license scanners (ScanCode, ORT)
+    Export(export::ExportArgs),
+    /// Write a NOTICES file with each distinct license text of the vendored crates once
+    Notices(notices::NoticesArgs),
+    /// Show the vendored footprint, or how it changed over past runs with `--history`
+    Stats(stats::StatsArgs),
+    /// Count `unsafe` functions, blocks, impls and traits per vendored crate
+    AuditUnsafe(unsafe_audit::AuditUnsafeArgs),
+    /// Write a software bill of materials (SPDX or CycloneDX) for the vendored crates
+    Sbom(sbom::SbomArgs),
+    /// Find which dependency changes between two lockfiles or git revisions grew the vendored size most
+    BisectSize(bisect::BisectSizeArgs),
+    /// Show the dependency tree of the workspace over the vendored crates
+    Tree(tree::TreeArgs),
+    /// Explain which dependency chains of the workspace pull in a vendored crate
+    Why(tree::WhyArgs),
+    /// Print a shell completion script
+    Completions(completions::CompletionsArgs),
+}
+
+#[derive(clap::Args, Clone)]
+pub struct ProjectPath {
+    #[clap(default_value = ".")]
+    pub project_path: PathBuf,
+    /// Path to the Cargo.toml of the project, e.g. of a workspace member
+    #[clap(long, value_name = "PATH", conflicts_with = "project_path")]
+    pub manifest_path: Option<PathBuf>,
+}
+
+impl ProjectPath {
+    // Folder of the project's manifest, which localize.lock and the vendored crates are kept next to
+    pub fn root(&self) -> Result<PathBuf> {
+        let Some(manifest_path) = &self.manifest_path else {
+            return self.project_path.canonicalize().context("Invalid project path");
+        };
+        if manifest_path.file_name().is_none_or(|name| name != "Cargo.toml") {
+            anyhow::bail!("The manifest path must be a path to a Cargo.toml file");
+        }
+        let manifest_path = manifest_path
+            .canonicalize()
+            .context(format!("Manifest {} not found", manifest_path.display()))?;
+        Ok(manifest_path.parent().context("Invalid manifest path")?.to_path_buf())
+    }
+}
+
+#[derive(clap::Args, Clone)]
+pub struct ProjectArgs {
+    #[clap(flatten)]
+    pub path: ProjectPath,
+    /// Folder the crates are vendored into (default: `3rd-party`)
+    #[clap(long, value_name = "DIR")]
+    pub third_party_dir: Option<String>,
+}
+
+impl ProjectArgs {
+    pub fn third_party_dir(&self) -> &str {
+        self.third_party_dir.as_deref().unwrap_or("3rd-party")
+    }
+}
+
+#[derive(clap::Args, Clone)]
+pub struct LocalizeArgs {
+    #[clap(flatten)]
+    pub project: ProjectArgs,
+    /// How manifests are pointed at vendored crates (default: rewrite)
+    #[clap(long, value_enum)]
+    pub mode: Option<Strategy>,
+    /// Spread crate directories over subdirectories of the 3rd-party folder, for very large
+    /// dependency sets (default: the layout of an existing 3rd-party folder, or flat)
+    #[clap(long, value_enum, value_name = "LAYOUT")]
+    pub shard: Option<Shard>,
+    /// Remove tests, benches and examples from vendored crates
+    #[clap(long)]
+    pub trim: bool,
+    /// Like `--trim`, and also remove docs and CI configuration (`docs/`, `.github/`, `.travis.yml`, ...)
+    #[clap(long)]
+    pub minimal: bool,
+    /// Leave files matching the glob out of copied crates, e.g. `**/*.png` or `**/fuzz/**`
+    #[clap(long = "ignore", value_name = "GLOB")]
+    pub ignore: Vec<String>,
+    /// Strip `[target.'...']` dependency tables of other platforms from vendored manifests
+    #[clap(long)]
+    pub prune_targets: bool,
+    /// Vendor only what this target triple needs, repeat for several targets
+    /// (pruning defaults to the host)
+    #[clap(long = "target", value_name = "TRIPLE")]
+    pub targets: Vec<String>,
+    /// Localize only packages matching the spec (`name`, `name@version` or a glob)
+    #[clap(short = 'p', long = "package", value_name = "SPEC")]
+    pub packages: Vec<PackageSpec>,
+    /// Leave packages matching the spec on the registry
+    #[clap(long, value_name = "SPEC")]
+    pub exclude: Vec<PackageSpec>,
+    /// Pick the crates to localize in the terminal, from the resolved dependency list grouped by
+    /// direct dependency, before anything is copied; the others stay on the registry
+    #[clap(long, conflicts_with = "ci")]
+    pub interactive: bool,
+    /// Localize dependencies up to N levels deep, deeper ones stay on the registry
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub depth: Option<u32>,
+    /// Localize direct dependencies only, same as `--depth 1`
+    #[clap(long, conflicts_with = "depth")]
+    pub direct_only: bool,
+    /// Leave dev-dependencies of workspace members on the registry
+    #[clap(long)]
+    pub no_dev_deps: bool,
+    /// Leave build-dependencies and everything only they pull in on the registry
+    #[clap(long)]
+    pub no_build_deps: bool,
+    /// Resolve dependencies for this target triple only, other platforms' crates aren't vendored
+    #[clap(long, value_name = "TRIPLE")]
+    pub filter_platform: Option<String>,
+    #[clap(flatten)]
+    pub network: NetworkArgs,
+    #[clap(flatten)]
+    pub advisories: advisories::AdvisoryArgs,
+    #[clap(flatten)]
+    pub approvals: approval::ApprovalArgs,
+    #[clap(flatten)]
+    pub licenses: policy::LicensePolicyArgs,
+    /// Signal file watchers to hold off while running (marker file and watchman state)
+    #[clap(long)]
+    pub quiesce: bool,
+    /// Keep file events to a minimum: stage crates outside the tree, move each into place at once
+    /// and replace manifests atomically. Implies `--quiesce`
+    #[clap(long)]
+    pub fsevents_friendly: bool,
+    /// Rename vendored files whose names don't check out on Windows or macOS (`aux.rs`, trailing
+    /// dots, decomposed Unicode), pointing the manifest and `mod` declarations at the new names
+    #[clap(long)]
+    pub fix_filenames: bool,
+    /// Don't run the hooks of the config file
+    #[clap(long)]
+    pub no_hooks: bool,
+    /// Leave the changes of a failed run in place for `cargo-localize doctor`, instead of undoing them
+    #[clap(long)]
+    pub no_rollback: bool,
+    /// Go on even when vendored crates with local edits would be dropped or overwritten
+    #[clap(long)]
+    pub force: bool,
+    /// Go on even when frozen vendored crates would be replaced, dropped or have their manifest restored
+    #[clap(long)]
+    pub unfreeze: bool,
+    /// Only copy crates into the 3rd-party folder and write localize.lock, leave manifests alone
+    #[clap(long, conflicts_with = "rewrite_only")]
+    pub vendor_only: bool,
+    /// Only point manifests at crates already in the 3rd-party folder, as recorded in localize.lock
+    #[clap(long)]
+    pub rewrite_only: bool,
+    /// Crates copied at the same time (default: picked for the storage the 3rd-party folder is on)
+    #[clap(short, long, value_name = "N")]
+    pub jobs: Option<std::num::NonZeroUsize>,
+    /// Storage the 3rd-party folder is on, when detecting it picks the wrong copy settings
+    #[clap(long, value_enum, value_name = "KIND")]
+    pub storage: Option<storage::Storage>,
+    /// What to do with the Cargo.lock some crates ship: keep it, strip it or rename it
+    /// (default: keep)
+    #[clap(long, value_enum, value_name = "POLICY")]
+    pub nested_lockfiles: Option<LockfilePolicy>,
+    /// What to do with the files packaging leaves in registry crates (`.cargo-ok`,
+    /// `.cargo_vcs_info.json`, `Cargo.toml.orig`, `.cargo-checksum.json`): keep them, delete them,
+    /// or keep them with the checksums written again for the vendored files (default: delete)
+    #[clap(long, value_enum, value_name = "POLICY")]
+    pub packaging_files: Option<packaging::PackagingPolicy>,
+    /// What to do with symlinks in crate sources: copy what they point to, copy the links, or
+    /// leave them out (default: preserve, follow on Windows)
+    #[clap(long, value_enum, value_name = "POLICY")]
+    pub symlinks: Option<config::SymlinkPolicy>,
+    /// What to do with independent Cargo projects inside the project (fuzz targets, an xtask
+    /// outside the workspace): report them, localize them too into the same 3rd-party folder, or
+    /// ignore them (default: report)
+    #[clap(long, value_enum, value_name = "POLICY")]
+    pub nested: Option<nested::NestedPolicy>,
+    /// Features written into rewritten entries: those cargo resolved for the crate, or the ones
+    /// the entry declared (default: resolved)
+    #[clap(long, value_enum, value_name = "POLICY")]
+    pub feature_policy: Option<config::FeaturePolicy>,
+    /// Keep a `version` requirement on the vendored version next to `path` in rewritten entries,
+    /// so crates stay publishable and cargo falls back to the registry without the 3rd-party folder
+    #[clap(long)]
+    pub keep_version: bool,
+    /// Leave vendored manifests without the empty `[workspace]` table that keeps cargo from taking
+    /// the crates for members of the project's workspace
+    #[clap(long)]
+    pub no_workspace_table: bool,
+    /// Accept the hosts crates come from and the root CAs their certificates chain to as the new
+    /// pinned set, instead of warning about changes
+    #[clap(long)]
+    pub trust_hosts: bool,
+    /// Contact the pinned hosts again to check the root CAs their certificates chain to, instead
+    /// of only pinning hosts seen for the first time
+    #[clap(long)]
+    pub recheck_hosts: bool,
+    /// Pin git dependencies that stay on their repository (not localized) to the commit they resolved to
+    #[clap(long)]
+    pub pin_git: bool,
+    /// Vendor path dependencies outside the project too, so the localized project doesn't reach
+    /// outside of it
+    #[clap(long)]
+    pub external_paths: bool,
+    /// Keep crate sources once in a user-level store shared by all projects; the 3rd-party folder
+    /// only holds each crate's manifest and symlinks into the store
+    #[clap(long)]
+    pub store: bool,
+    /// Store location, implies `--store` (default: `<data dir>/cargo-localize/store`)
+    #[clap(long, value_name = "DIR")]
+    pub store_dir: Option<PathBuf>,
+    /// Cargo's `registry/src` directory to take registry crates from instead of the one under the
+    /// cargo home, repeatable (default: `CARGO_LOCALIZE_REGISTRY`, a list of paths)
+    #[clap(long, value_name = "PATH")]
+    pub registry_src: Vec<PathBuf>,
+    /// Write run metrics (phase durations, crates processed, bytes copied) to this file
+    #[clap(long, value_name = "FILE")]
+    pub metrics: Option<PathBuf>,
+    /// Format of the `--metrics` file
+    #[clap(long, value_enum, default_value = "prometheus", requires = "metrics")]
+    pub metrics_format: metrics::MetricsFormat,
+    /// What is printed: progress and summary lines, or a JSON document describing every vendored,
+    /// skipped and failed crate and the rewritten manifests
+    #[clap(long, value_enum, default_value = "human")]
+    pub output: report::OutputFormat,
+    /// Check the localized project with cargo, offline, before the run is done: `check` compiles
+    /// the workspace (the default), `resolve` only resolves its dependency graph. A project that
+    /// fails it is rolled back
+    #[clap(long, value_enum, value_name = "HOW", num_args = 0..=1, default_missing_value = "check")]
+    pub verify: Option<verify::Verification>,
+    /// Fail the run if it raises a warning of this class, repeat for several classes
+    #[clap(long, value_enum, value_name = "CLASS")]
+    pub deny: Vec<WarningClass>,
+    /// Fail the run if a vendored build script looks like it reaches the network, same as
+    /// `--deny network-build-script`
+    #[clap(long)]
+    pub deny_network_build_scripts: bool,
+    /// Pipeline preset: verify instead of localizing when run without a subcommand, plain output
+    /// without progress bars, no prompts and every warning class denied
+    #[clap(long)]
+    pub ci: bool,
+    // Per-crate settings from the config file
+    #[clap(skip)]
+    crates: Vec<(PackageSpec, config::CrateConfig)>,
+    // Hooks from the config file
+    #[clap(skip)]
+    hooks: hooks::Hooks,
+}
+
+impl LocalizeArgs {
+    fn mode(&self) -> Strategy {
+        self.mode.unwrap_or(Strategy::Rewrite)
+    }
+
+    fn blocking_warnings(&self) -> Vec<WarningClass> {
+        if self.ci {
+            return vec![WarningClass::All];
+        }
+        let mut blocking = self.deny.clone();
+        if self.advisories.deny_advisories {
+            blocking.push(WarningClass::Advisory);
+        }
+        if self.deny_network_build_scripts {
+            blocking.push(WarningClass::NetworkBuildScript);
+        }
+        blocking
+    }
+
+    fn max_depth(&self) -> Option<u32> {
+        if self.direct_only { Some(1) } else { self.depth }
+    }
+
+    // Platforms the vendored set is restricted to, empty if it isn't
+    fn target_platforms(&self) -> Result<Vec<platform::TargetPlatform>> {
+        if self.targets.is_empty() {
+            return if self.prune_targets {
+                Ok(vec![platform::TargetPlatform::new(None)?])
+            } else {
+                Ok(Vec::new())
+            };
+        }
+        self.targets
+            .iter()
+            .map(|triple| platform::TargetPlatform::new(Some(triple)))
+            .collect()
+    }
+
+    // Held for the whole run, watchers are released when it's dropped
+    fn quiesce(&self, project_path: &Path, command: &str) -> Result<Option<quiesce::Quiesce>> {
+        (self.quiesce || self.fsevents_friendly)
+            .then(|| quiesce::Quiesce::enter(project_path, command))
+            .transpose()
+    }
+}
+
+// Same defaults as a bare `cargo-localize` invocation
+impl Default for LocalizeArgs {
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[clap(flatten)]
+            localize: LocalizeArgs,
+        }
+        Defaults::parse_from(["cargo-localize"]).localize
+    }
+}
+
+pub fn run(args: &Args) -> Result<(), LocalizeError> {
+    logging::init(args.verbose, args.quiet, args.log_format, args.live);
+    match &args.command {
+        Some(Command::Sync(sync_args)) => localize(sync_args, true),
+        None if !args.check && !args.localize.ci => localize(&args.localize, false),
+        _ => run_command(args).map_err(LocalizeError::from),
+    }
+}
+
+fn run_command(args: &Args) -> Result<()> {
+    match &args.command {
+        Some(Command::Sync(sync_args)) => Ok(localize(sync_args, true)?),
+        Some(Command::Watch(watch_args)) => watch::watch(watch_args),
+        Some(Command::Gc(gc_args)) => gc::gc(gc_args),
+        Some(Command::Doctor(doctor_args)) => journal::doctor(doctor_args),
+        Some(Command::Outdated(outdated_args)) => outdated::outdated(outdated_args),
+        Some(Command::Update(update_args)) => update::update(update_args),
+        Some(Command::Upgrade(upgrade_args)) => update::upgrade(upgrade_args),
+        Some(Command::Adopt(adopt_args)) => adopt::adopt(adopt_args),
+        Some(Command::Prelude(prelude_args)) => prelude::prelude(prelude_args),
+        Some(Command::Freeze(freeze_args)) => freeze::freeze(freeze_args, true),
+        Some(Command::Unfreeze(freeze_args)) => freeze::freeze(freeze_args, false),
+        Some(Command::Diff(diff_args)) => diff::diff(diff_args),
+        Some(Command::Verify(verify_args)) => diff::verify(verify_args),
+        Some(Command::BuildEnv(build_env_args)) => build_env::build_env(build_env_args),
+        Some(Command::Summary(summary_args)) => summary::summary(summary_args),
+        Some(Command::Pack(pack_args)) => pack::pack(pack_args),
+        Some(Command::Unpack(unpack_args)) => unpack::unpack(unpack_args),
+        Some(Command::ImportVendor(import_args)) => vendor::import(import_args),
+        Some(Command::ExportVendor(export_args)) => vendor::export(export_args),
+        Some(Command::Export(export_args)) => export::export(export_args),
+        Some(Command::Notices(notices_args)) => notices::notices(notices_args),
+        Some(Command::Stats(stats_args)) => stats::stats(stats_args),
+        Some(Command::AuditUnsafe(audit_args)) => unsafe_audit::audit_unsafe(audit_args),
+        Some(Command::Sbom(sbom_args)) => sbom::sbom(sbom_args),
+        Some(Command::BisectSize(bisect_args)) => bisect::bisect_size(bisect_args),
+        Some(Command::Tree(tree_args)) => tree::tree(tree_args),
+        Some(Command::Why(why_args)) => tree::why(why_args),
+        Some(Command::Completions(completions_args)) => {
+            completions::completions(completions_args);
+            Ok(())
+        }
+        None if args.check || args.localize.ci => {
+            let project_path = args.localize.project.path.root()?;
+            let localize = &config::apply(&args.localize, &project_path)?;
+            check(
+                &project_path,
+                &localize.network,
+                &localize.advisories,
+                &localize.approvals,
+                &localize.blocking_warnings(),
+            )
+        }
+        None => Ok(localize(&args.localize, false)?),
+    }
+}
+
+pub fn localize(args: &LocalizeArgs, sync: bool) -> Result<(), LocalizeError> {
+    localize_observed(args, sync, Observers::default())
+}
+
+// Localizes like `localize`, reporting progress to `observer` along the way
+pub fn localize_with_observer(
+    args: &LocalizeArgs,
+    sync: bool,
+    observer: Arc<dyn Observer>,
+) -> Result<(), LocalizeError> {
+    let mut observers = Observers::default();
+    observers.add(observer);
+    localize_observed(args, sync, observers)
+}
+
+fn localize_observed(args: &LocalizeArgs, sync: bool, mut observers: Observers) -> Result<(), LocalizeError> {
+    let project_path = args.project.path.root()?;
+    let mut args = config::apply(args, &project_path)?;
+    // A nested project keeps vendoring into the folder it shares with the project around it
+    if args.project.third_party_dir.is_none()
+        && let Some(lock) = LocalizeLock::load(&project_path)?
+        && !safety::is_contained(Path::new(&lock.third_party_dir))
+    {
+        args.project.third_party_dir = Some(lock.third_party_dir);
+    }
+    // Crates left out in the terminal are recorded as excluded, so checks expect them on the registry.
+    // They're picked before the project is locked or journaled, so cancelling changes nothing.
+    if args.interactive && !args.rewrite_only {
+        let had_lockfile = project_path.join("Cargo.lock").exists();
+        let Some(left_out) = interactive::select(&args, &project_path)? else {
+            if !had_lockfile {
+                let _ = fs::remove_file(project_path.join("Cargo.lock"));
+            }
+            info!("Selection cancelled, nothing was changed");
+            return Ok(());
+        };
+        args.exclude.extend(left_out);
+        args.interactive = false;
+    }
+    let args = &args;
+
+    let metrics = args.metrics.as_ref().map(|_| Arc::new(metrics::Metrics::new()));
+    if let Some(metrics) = &metrics {
+        observers.add(metrics.clone());
+    }
+    // Bars stand in for the per-crate lines, and stay away from quiet runs
+    let bars = !args.ci && tracing::enabled!(tracing::Level::INFO) && !tracing::enabled!(tracing::Level::DEBUG);
+    if bars {
+        observers.add(Arc::new(progress::Progress::default()));
+    }
+    let report = (args.output == report::OutputFormat::Json).then(|| Arc::new(report::Report::default()));
+    if let Some(report) = &report {
+        observers.add(report.clone());
+    }
+    // Stdout carries nothing but the document
+    logging::set_stderr(report.is_some());
+
+    let command = if sync { "sync" } else { "localize" };
+    // cargo writes one while resolving, before the journal knows about it
+    let had_lockfile = project_path.join("Cargo.lock").exists();
+    let result = (|| -> Result<(), LocalizeError> {
+        let _quiesce = args.quiesce(&project_path, command)?;
+        let journal = Journal::begin(&project_path, command, args.fsevents_friendly)?;
+        // A project that doesn't verify, or raised warnings `--deny` blocks, counts as a failed
+        // run, rolled back like any other
+        if let Err(error) = localize_project(args, sync, &project_path, &observers, &journal)
+            .and_then(|()| nested::process(args, sync, &project_path, &observers, &journal))
+            .and_then(|()| match args.verify {
+                Some(verification) => observers.phase(Phase::Verify, || verify::verify(&project_path, verification)),
+                None => Ok(()),
+            })
+            .and_then(|()| warnings::deny(&args.blocking_warnings()))
+        {
+            if journal.abandon()? {
+                if !args.no_rollback && journal::roll_back(&project_path)? {
+                    if !had_lockfile {
+                        let _ = fs::remove_file(project_path.join("Cargo.lock"));
+                    }
+                    return Err(LocalizeError::RolledBack {
+                        source: Box::new(error.into()),
+                    });
+                }
+                return Err(LocalizeError::Unfinished {
+                    journal: project_path.join(journal::JOURNAL_FILE_NAME),
+                    source: Box::new(error.into()),
+                });
+            }
+            return Err(error.context("Localization didn't start, nothing was changed").into());
+        }
+        journal.finish()?;
+        Ok(())
+    })();
+
+    if let (Some(metrics), Some(output)) = (metrics, &args.metrics) {
+        let project = project_path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().to_string());
+        metrics.write(output, args.metrics_format, &project, result.is_ok())?;
+    }
+    if let Some(report) = report {
+        report.print(&project_path, result.as_ref().err())?;
+    }
+    result
+}
+
+pub(crate) fn localize_project(
+    args: &LocalizeArgs,
+    sync: bool,
+    project_path: &Path,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<()> {
+    if args.rewrite_only {
+        return rewrite_only(args, project_path, observers, journal);
+    }
+    let third_party_path = util::normalize_path(&project_path.join(args.project.third_party_dir()));
+
+    let targets = args.target_platforms()?;
+    let target_triples: Vec<String> = targets.iter().map(|t| t.triple.clone()).collect();
+
+    let previous_lock = LocalizeLock::load(project_path)?;
+    let shard = shard(args, previous_lock.as_ref())?;
+
+    // Pruning for a different target set is undone first, so cargo sees the full original graph
+    let retarget = previous_lock
+        .as_ref()
+        .is_some_and(|lock| lock.targets != target_triples || lock.prune_targets != args.prune_targets);
+    // Edits made by hand, which a run must neither drop nor overwrite unnoticed
+    let mut edits = match previous_lock.as_ref() {
+        Some(previous_lock) => edits::find(project_path, previous_lock)?,
+        None => BTreeMap::new(),
+    };
+    if retarget && let Some(previous_lock) = previous_lock.as_ref() {
+        let overwritten: Vec<_> = previous_lock
+            .packages
+            .iter()
+            .filter(|locked| !locked.pruned_targets.is_empty())
+            .filter_map(|locked| edits.get(&locked.path).map(|edits| (locked, edits)))
+            .filter(|(_, edits)| edits.iter().any(|edit| edit.file() == "Cargo.toml"))
+            .collect();
+        edits::protect(
+            &overwritten,
+            "would get their manifest restored from its backup",
+            args.force,
+        )?;
+    }
+    let frozen = match previous_lock.as_ref() {
+        Some(previous_lock) => freeze::frozen(project_path, &previous_lock.packages)?,
+        None => Vec::new(),
+    };
+    if retarget {
+        let restored: Vec<&LockedPackage> = frozen
+            .iter()
+            .copied()
+            .filter(|locked| !locked.pruned_targets.is_empty())
+            .collect();
+        freeze::protect(
+            &restored,
+            "would get their manifest restored from its backup",
+            args.unfreeze,
+        )?;
+    }
+    let restored = match previous_lock.as_ref() {
+        Some(previous_lock) if retarget => restore_pruned_manifests(previous_lock, &third_party_path, journal)?,
+        _ => HashSet::new(),
+    };
+
+    observers.phase(Phase::Fetch, || {
+        info!("Running cargo fetch...");
+        let status = args
+            .network
+            .cargo("fetch")
+            .current_dir(project_path)
+            .status()
+            .context("Failed to run cargo fetch")?;
+        // Without the flags, whatever cargo couldn't fetch surfaces once it's looked for
+        if !status.success() && (args.network.offline() || args.network.locked()) {
+            anyhow::bail!("cargo fetch failed");
+        }
+        registry::verify_sources(
+            &lock::read_cargo_lock_checksums(project_path)?,
+            &registry::src_dirs(&args.registry_src)?,
+        )
+    })?;
+
+    let metadata = observers.phase(Phase::Metadata, || {
+        info!("Getting metadata...");
+        args.network
+            .metadata()
+            .manifest_path(project_path.join("Cargo.toml"))
+            .other_options(platform::cargo_options(args.filter_platform.as_deref(), &args.network))
+            .exec()
+            .context("Failed to get cargo metadata")
+    })?;
+
+    // A crate version the graph stopped using is replaced or gone. The edits of a replaced one are
+    // merged into the new version once it's vendored, those of a gone one would be lost.
+    let resolved: HashSet<(&str, String)> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.name.as_str(), package.version.to_string()))
+        .collect();
+    let mut upgrades: Vec<(&LockedPackage, String)> = Vec::new();
+    let mut dropped = Vec::new();
+    for locked in previous_lock.iter().flat_map(|lock| &lock.packages) {
+        let Some(crate_edits) = edits.get(&locked.path) else {
+            continue;
+        };
+        if resolved.contains(&(locked.name.as_str(), locked.version.clone())) {
+            continue;
+        }
+        match upgraded_version(&metadata, previous_lock.as_ref(), locked) {
+            Some(version) => upgrades.push((locked, version)),
+            None => dropped.push((locked, crate_edits)),
+        }
+    }
+    edits::protect(&dropped, "are no longer used by the dependency graph", args.force)?;
+    let replaced: Vec<&LockedPackage> = frozen
+        .iter()
+        .copied()
+        .filter(|locked| !resolved.contains(&(locked.name.as_str(), locked.version.clone())))
+        .collect();
+    freeze::protect(
+        &replaced,
+        "are no longer used by the dependency graph, replaced by another version or dropped",
+        args.unfreeze,
+    )?;
+
+    // Crates whose patches in the project changed since they were vendored are patched again
+    let patches = patches::Patches::load(project_path)?;
+    let repatched: Vec<&LockedPackage> = previous_lock
+        .iter()
+        .flat_map(|lock| &lock.packages)
+        .filter(|locked| !patches.is_current(locked))
+        .filter(|locked| resolved.contains(&(locked.name.as_str(), locked.version.clone())))
+        .filter(|locked| third_party_path.join(&locked.path).is_dir())
+        .collect();
+    let repatched_edits: Vec<_> = repatched
+        .iter()
+        .filter_map(|locked| edits.get(&locked.path).map(|edits| (*locked, edits)))
+        .collect();
+    edits::protect(
+        &repatched_edits,
+        "would be patched again as their patches changed",
+        args.force,
+    )?;
+    let repatched_frozen: Vec<&LockedPackage> = frozen
+        .iter()
+        .copied()
+        .filter(|locked| repatched.iter().any(|repatched| repatched.path == locked.path))
+        .collect();
+    freeze::protect(
+        &repatched_frozen,
+        "would be patched again as their patches changed",
+        args.unfreeze,
+    )?;
+    let repatched: HashSet<String> = repatched.into_iter().map(|locked| locked.path.clone()).collect();
+
+    journal
+        .create_dir_all(&third_party_path)
+        .context("Failed to create 3rd-party directory")?;
+
+    info!("Copying dependencies...");
+    let reachable = needed_packages(&metadata, &targets, args.no_dev_deps, args.no_build_deps);
+    let mut localize_lock = LocalizeLock::new(args.mode(), args.project.third_party_dir());
+    localize_lock.shard = shard;
+    localize_lock.targets = target_triples;
+    localize_lock.prune_targets = args.prune_targets;
+    localize_lock.include = args.packages.iter().map(|spec| spec.to_string()).collect();
+    localize_lock.exclude = args.exclude.iter().map(|spec| spec.to_string()).collect();
+    localize_lock.depth = args.max_depth();
+    localize_lock.no_dev_deps = args.no_dev_deps;
+    localize_lock.no_build_deps = args.no_build_deps;
+    localize_lock.filter_platform = args.filter_platform.clone();
+    localize_lock.keep_version = args.keep_version;
+    localize_lock.no_workspace_table = args.no_workspace_table;
+    localize_lock.pin_git = args.pin_git;
+    localize_lock.external_paths = args.external_paths;
+    localize_lock.patch_entries = previous_lock
+        .as_ref()
+        .map(|lock| lock.patch_entries.clone())
+        .unwrap_or_default();
+    let (packages, copied) = observers.phase(Phase::Copy, || {
+        copy_dependencies(
+            &metadata,
+            &third_party_path,
+            previous_lock.as_ref(),
+            project_path,
+            args,
+            localize_lock.shard,
+            reachable.as_ref(),
+            observers,
+            journal,
+        )
+    })?;
+    localize_lock.packages = packages;
+    // The recorded sources, as crates localized earlier show up as path dependencies in the metadata
+    localize_lock.registry_hosts = hosts::check(
+        previous_lock
+            .as_ref()
+            .map_or(&[], |lock| lock.registry_hosts.as_slice()),
+        localize_lock
+            .packages
+            .iter()
+            .filter_map(|package| package.source.as_deref()),
+        args.trust_hosts,
+        args.recheck_hosts,
+        &args.network,
+    );
+    advisories::scan(&localize_lock.packages, &args.advisories, &args.network)?;
+    build_scripts::audit(&third_party_path, &localize_lock.packages)?;
+    for locked in localize_lock.packages.iter_mut().filter(|p| restored.contains(&p.path)) {
+        locked.pruned_targets.clear();
+    }
+
+    let mut changed: HashSet<String> = copied.union(&restored).cloned().collect();
+    for locked in localize_lock
+        .packages
+        .iter_mut()
+        .filter(|locked| repatched.contains(&locked.path) && !copied.contains(&locked.path))
+    {
+        let crate_dir = third_party_path.join(&locked.path);
+        let Some((applied, written)) = patches::refresh(locked, &crate_dir, &patches, journal)
+            .context(format!("Failed to patch {} v{}", locked.name, locked.version))?
+        else {
+            warnings::warn(
+                WarningClass::Patch,
+                format!(
+                    "Can't patch {} v{} again: its published source isn't in cargo's cache",
+                    locked.name, locked.version
+                ),
+            );
+            continue;
+        };
+        info!(
+            "Patched {} v{} again, {} file(s) changed",
+            locked.name,
+            locked.version,
+            written.len()
+        );
+        locked.patches = applied;
+        if let Some(crate_edits) = edits.get_mut(&locked.path) {
+            crate_edits.retain(|edit| !written.contains(edit.file()));
+        }
+        if written.contains("Cargo.toml") {
+            changed.insert(locked.path.clone());
+        }
+    }
+    if args.prune_targets {
+        observers.phase(Phase::Prune, || {
+            info!("Pruning target-specific dependencies...");
+            for locked in &mut localize_lock.packages {
+                if !retarget && !changed.contains(&locked.path) {
+                    continue;
+                }
+                let crate_dir = third_party_path.join(&locked.path);
+                if crate_dir.exists() {
+                    locked.pruned_targets = platform::prune_manifest(&crate_dir, &targets, journal)?;
+                    if !locked.pruned_targets.is_empty() {
+                        changed.insert(locked.path.clone());
+                    }
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut merged = BTreeMap::new();
+    if !upgrades.is_empty() {
+        observers.phase(Phase::Merge, || {
+            info!("Merging local edits into upgraded crates...");
+            let mut unmergeable = Vec::new();
+            for (old, version) in &upgrades {
+                let old_edits = &edits[&old.path];
+                let Some(new) = localize_lock.find(&old.name, version) else {
+                    unmergeable.push((*old, old_edits));
+                    continue;
+                };
+                match merge::merge_crate(old, new, old_edits, &third_party_path, journal)? {
+                    Some(crate_merged) => {
+                        merge::report(old, new, &crate_merged);
+                        merged.insert(new.path.clone(), crate_merged.carried);
+                    }
+                    None => unmergeable.push((*old, old_edits)),
+                }
+            }
+            edits::protect(
+                &unmergeable,
+                "can't be merged into the new version, its pristine source or the new crate is missing",
+                args.force,
+            )
+        })?;
+        // Carried over, the old copy is left for `gc` like any unused crate
+        for (old, _) in &upgrades {
+            edits.remove(&old.path);
+        }
+    }
+
+    if sync {
+        // Crates dropped from the graph stay vendored until they're explicitly removed
+        for previous in previous_lock.iter().flat_map(|lock| &lock.packages) {
+            if localize_lock
+                .find_source(&previous.name, &previous.version, previous.source.as_deref())
+                .is_none()
+                && third_party_path.join(&previous.path).exists()
+            {
+                warnings::warn(
+                    WarningClass::Unused,
+                    format!("{} v{} is no longer used", previous.name, previous.version),
+                );
+                localize_lock.packages.push(previous.clone());
+            }
+        }
+    }
+
+    // Entries rewritten by an earlier run need a `version` added or removed if `--keep-version` changed,
+    // manifests their `[workspace]` table after `--no-workspace-table` did, and nothing was rewritten
+    // yet after `--vendor-only`
+    let rewrite_changed_only = sync
+        && previous_lock.as_ref().is_none_or(|lock| {
+            lock.keep_version == args.keep_version
+                && lock.no_workspace_table == args.no_workspace_table
+                && !lock.manifests_pending
+        });
+
+    if args.vendor_only {
+        localize_lock.manifests_pending = true;
+    } else {
+        rewrite_manifests(
+            args,
+            project_path,
+            &metadata,
+            previous_lock.as_ref().map(|lock| lock.strategy),
+            &mut localize_lock,
+            rewrite_changed_only.then_some(&changed),
+            observers,
+            journal,
+        )?;
+    }
+
+    observers.phase(Phase::WriteLock, || {
+        info!("Writing {LOCK_FILE_NAME}...");
+        edits::record(project_path, &localize_lock, &edits, &merged, journal)?;
+        prelude::refresh(project_path, &localize_lock, journal)?;
+        index::write(project_path, &localize_lock, journal)?;
+        mark_vendored(project_path, &localize_lock, journal)?;
+        localize_lock.save(project_path, journal)
+    })?;
+
+    usage::print_report(&localize_lock, &third_party_path);
+    if let Err(error) = stats::record(project_path, &localize_lock, if sync { "sync" } else { "localize" }) {
+        warn!("Couldn't record run statistics: {error:#}");
+    }
+    if sync {
+        info!(
+            "Synced: {} new, {} already localized",
+            copied.len(),
+            localize_lock.packages.len() - copied.len()
+        );
+    }
+
+    // Manifests still point at the registry, cargo keeps needing the lockfile until they're rewritten
+    if args.vendor_only {
+        info!(
+            "Dependencies vendored to {}, run with --rewrite-only to point manifests at them",
+            third_party_path.display()
+        );
+        return Ok(());
+    }
+
+    let lock_file = project_path.join("Cargo.lock");
+    if lock_file.exists() {
+        journal.remove_file(&lock_file).context("Failed to remove Cargo.lock")?;
+    }
+
+    info!("Dependencies localized to {}", third_party_path.display());
+    Ok(())
+}
+
+// Points manifests at a 3rd-party folder populated earlier, e.g. restored from a `--vendor-only` job
+fn rewrite_only(args: &LocalizeArgs, project_path: &Path, observers: &Observers, journal: &Journal) -> Result<()> {
+    let mut localize_lock = LocalizeLock::load(project_path)?.context(format!(
+        "--rewrite-only needs the {LOCK_FILE_NAME} of a --vendor-only run"
+    ))?;
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let edits = edits::find(project_path, &localize_lock)?;
+
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        if !crate_dir.join("Cargo.toml").exists() {
+            anyhow::bail!(
+                "{} v{} isn't vendored at {}, restore the 3rd-party folder first",
+                locked.name,
+                locked.version,
+                crate_dir.display()
+            );
+        }
+    }
+
+    let metadata = observers.phase(Phase::Metadata, || {
+        info!("Getting metadata...");
+        args.network
+            .metadata()
+            .manifest_path(project_path.join("Cargo.toml"))
+            .other_options(platform::cargo_options(
+                localize_lock.filter_platform.as_deref(),
+                &args.network,
+            ))
+            .exec()
+            .context("Failed to get cargo metadata")
+    })?;
+
+    let previous_strategy = localize_lock.strategy;
+    localize_lock.strategy = args.mode();
+    localize_lock.keep_version = args.keep_version;
+    localize_lock.no_workspace_table = args.no_workspace_table;
+    localize_lock.pin_git = args.pin_git;
+    localize_lock.manifests_pending = false;
+    rewrite_manifests(
+        args,
+        project_path,
+        &metadata,
+        Some(previous_strategy),
+        &mut localize_lock,
+        None,
+        observers,
+        journal,
+    )?;
+
+    observers.phase(Phase::WriteLock, || {
+        info!("Writing {LOCK_FILE_NAME}...");
+        edits::record(project_path, &localize_lock, &edits, &BTreeMap::new(), journal)?;
+        prelude::refresh(project_path, &localize_lock, journal)?;
+        index::write(project_path, &localize_lock, journal)?;
+        mark_vendored(project_path, &localize_lock, journal)?;
+        localize_lock.save(project_path, journal)
+    })?;
+
+    let lock_file = project_path.join("Cargo.lock");
+    if lock_file.exists() {
+        journal.remove_file(&lock_file).context("Failed to remove Cargo.lock")?;
+    }
+
+    info!("Manifests pointed at {}", third_party_path.display());
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rewrite_manifests(
+    args: &LocalizeArgs,
+    project_path: &Path,
+    metadata: &Metadata,
+    previous_strategy: Option<Strategy>,
+    localize_lock: &mut LocalizeLock,
+    only_copied: Option<&HashSet<String>>,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<()> {
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let filter_platform = localize_lock.filter_platform.clone();
+    observers.phase(Phase::Rewrite, || {
+        info!("Updating Cargo.toml files...");
+        if args.mode() == Strategy::Rewrite && previous_strategy == Some(Strategy::Patch) {
+            patch::unpatch_root(project_path, &third_party_path, &localize_lock.patch_entries, journal)?;
+        }
+        match args.mode() {
+            Strategy::Rewrite => {
+                update_cargo_toml(
+                    metadata,
+                    project_path,
+                    &third_party_path,
+                    localize_lock,
+                    only_copied,
+                    observers,
+                    journal,
+                )?;
+                patch::point_root_patches(project_path, &third_party_path, metadata, localize_lock, journal)
+            }
+            Strategy::Patch => patch::localize_with_patches(
+                project_path,
+                &third_party_path,
+                metadata,
+                localize_lock,
+                filter_platform.as_deref(),
+                &args.network,
+                observers,
+                journal,
+            ),
+        }?;
+        if args.packaging_files == Some(packaging::PackagingPolicy::Regenerate) {
+            packaging::regenerate(&third_party_path, localize_lock, journal)?;
+        }
+        args.hooks.run(Hook::PostRewrite, &third_party_path)
+    })
+}
+
+// Crates already vendored stay where they are, so the layout can only change along with the whole folder
+fn shard(args: &LocalizeArgs, previous_lock: Option<&LocalizeLock>) -> Result<Shard> {
+    let previous = previous_lock
+        .filter(|lock| !lock.packages.is_empty())
+        .map(|lock| lock.shard);
+    match (args.shard, previous) {
+        (Some(shard), Some(previous)) if shard != previous => anyhow::bail!(
+            "The 3rd-party folder uses the {} layout, switching to {} needs a fresh localization",
+            format!("{previous:?}").to_lowercase(),
+            format!("{shard:?}").to_lowercase()
+        ),
+        (Some(shard), _) => Ok(shard),
+        (None, previous) => Ok(previous.unwrap_or_default()),
+    }
+}
+
+// Packages needed with the selected targets and dependency kinds, `None` if nothing is filtered out.
+// Workspace members keep dependencies of all platforms since their manifests are never pruned.
+fn needed_packages(
+    metadata: &Metadata,
+    targets: &[platform::TargetPlatform],
+    no_dev_deps: bool,
+    no_build_deps: bool,
+) -> Option<HashSet<PackageId>> {
+    if targets.is_empty() && !no_dev_deps && !no_build_deps {
+        return None;
+    }
+
+    filter::reachable_packages(metadata, |is_member, dep_kind| {
+        let kind_needed = match dep_kind.kind {
+            DependencyKind::Development => !no_dev_deps,
+            DependencyKind::Build => !no_build_deps,
+            _ => true,
+        };
+        let target_needed = is_member
+            || targets.is_empty()
+            || dep_kind
+                .target
+                .as_ref()
+                .is_none_or(|platform| targets.iter().any(|t| t.matches(platform)));
+        kind_needed && target_needed
+    })
+}
+
+// Puts back original manifests of crates whose target tables were pruned.
+// Returns paths of the restored crates, which need to be rewritten again.
+fn restore_pruned_manifests(
+    previous_lock: &LocalizeLock,
+    third_party_path: &Path,
+    journal: &Journal,
+) -> Result<HashSet<String>> {
+    let mut restored = HashSet::new();
+
+    for locked in previous_lock.packages.iter().filter(|p| !p.pruned_targets.is_empty()) {
+        let cargo_toml_path = third_party_path.join(&locked.path).join("Cargo.toml");
+        let bak_filepath = cargo_toml_path.to_string_lossy().to_string() + ".bak";
+        if !fs::exists(&bak_filepath).is_ok_and(|v| v) {
+            warnings::warn(
+                WarningClass::MissingBackup,
+                format!(
+                    "Can't restore pruned targets of {} v{}: no backup",
+                    locked.name, locked.version
+                ),
+            );
+            continue;
+        }
+
+        let content = fs::read_to_string(&bak_filepath).context("Failed to read Cargo.toml.bak")?;
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .context("Failed to parse Cargo.toml.bak")?;
+        if locked.trimmed {
+            trim::remove_trimmed_sections(&mut doc);
+        }
+        journal
+            .write(&cargo_toml_path, doc.to_string())
+            .context("Failed to write Cargo.toml")?;
+
+        info!("Restored pruned targets of {} v{}", locked.name, locked.version);
+        restored.insert(locked.path.clone());
+    }
+
+    Ok(restored)
+}
+
+fn check(
+    project_path: &Path,
+    network: &NetworkArgs,
+    advisories: &advisories::AdvisoryArgs,
+    approvals: &approval::ApprovalArgs,
+    blocking: &[WarningClass],
+) -> Result<()> {
+    if project_path.join(journal::JOURNAL_FILE_NAME).exists() {
+        warnings::warn(
+            WarningClass::Interrupted,
+            format!("An interrupted run left {}", journal::JOURNAL_FILE_NAME),
+        );
+    }
+    let localize_lock = LocalizeLock::require(project_path)?;
+    let third_party_path = localize_lock.third_party_path(project_path);
+
+    let mut problems = Vec::new();
+
+    for locked in &localize_lock.packages {
+        let manifest = third_party_path.join(&locked.path).join("Cargo.toml");
+        if !locked.pruned_targets.is_empty() && !manifest.with_extension("toml.bak").exists() {
+            warnings::warn(
+                WarningClass::MissingBackup,
+                format!("{} v{}: pruned manifest has no backup", locked.name, locked.version),
+            );
+        }
+        if !manifest.exists() {
+            problems.push(format!(
+                "{} v{}: vendored directory {} is missing",
+                locked.name,
+                locked.version,
+                third_party_path.join(&locked.path).display()
+            ));
+        }
+    }
+
+    // Cargo can't resolve the graph while vendored directories are missing
+    if problems.is_empty() {
+        let metadata = network
+            .metadata()
+            .manifest_path(project_path.join("Cargo.toml"))
+            .other_options(platform::cargo_options(
+                localize_lock.filter_platform.as_deref(),
+                network,
+            ))
+            .exec()
+            .context("Failed to get cargo metadata")?;
+        check_resolved_packages(&metadata, &third_party_path, &localize_lock, &mut problems)?;
+    }
+    advisories::scan(&localize_lock.packages, advisories, network)?;
+    build_scripts::audit(&third_party_path, &localize_lock.packages)?;
+    let unapproved = approval::unapproved(&localize_lock.packages, approvals, network)?;
+    for locked in &unapproved {
+        problems.push(format!("{} v{}: not on the approved list", locked.name, locked.version));
+    }
+
+    if problems.is_empty() {
+        println!("Vendored tree matches {LOCK_FILE_NAME}");
+        return warnings::deny(blocking);
+    }
+
+    problems.sort();
+    for problem in &problems {
+        println!("  {problem}");
+    }
+    if !unapproved.is_empty() {
+        println!("{}", approval::submission(&localize_lock, &unapproved, project_path));
+        if problems.len() == unapproved.len() {
+            return Err(LocalizeError::Refused {
+                reason: Refusal::Approval,
+                crates: unapproved.iter().map(|locked| (*locked).into()).collect(),
+                message: format!("{} vendored crate(s) aren't on the approved list", unapproved.len()),
+            }
+            .into());
+        }
+    }
+    Err(LocalizeError::OutOfSync { problems }.into())
+}
+
+fn check_resolved_packages(
+    metadata: &Metadata,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    problems: &mut Vec<String>,
+) -> Result<()> {
+    let include = filter::parse_specs(&localize_lock.include).map_err(anyhow::Error::msg)?;
+    let exclude = filter::parse_specs(&localize_lock.exclude).map_err(anyhow::Error::msg)?;
+    let depths = localize_lock.depth.map(|_| filter::dependency_depths(metadata));
+    let targets = localize_lock
+        .targets
+        .iter()
+        .map(|triple| platform::TargetPlatform::new(Some(triple)))
+        .collect::<Result<Vec<_>>>()?;
+    let needed = needed_packages(
+        metadata,
+        &targets,
+        localize_lock.no_dev_deps,
+        localize_lock.no_build_deps,
+    );
+
+    for locked in &localize_lock.packages {
+        let resolved = metadata.packages.iter().any(|package| {
+            package.manifest_path.starts_with(third_party_path)
+                && package.name == locked.name
+                && package.version.to_string() == locked.version
+        });
+        if !resolved {
+            warnings::warn(
+                WarningClass::Unused,
+                format!("{} v{} is vendored but no longer used", locked.name, locked.version),
+            );
+        }
+    }
+
+    for package in &metadata.packages {
+        if package.manifest_path.starts_with(third_party_path) {
+            if localize_lock.find_package(package, third_party_path).is_none() {
+                problems.push(format!(
+                    "{} v{}: vendored but not recorded in {LOCK_FILE_NAME}",
+                    package.name, package.version
+                ));
+            }
+        } else if !is_workspace_package(package, metadata.workspace_root.as_std_path())
+            && (package.source.is_some() || localize_lock.external_paths)
+            && !shares_member_version(metadata, package)
+            && filter::is_selected(&include, &exclude, &package.name, &package.version)
+            && within_depth(localize_lock.depth, depths.as_ref(), &package.id)
+            && needed.as_ref().is_none_or(|needed| needed.contains(&package.id))
+        {
+            problems.push(format!("{} v{}: not localized", package.name, package.version));
+        }
+    }
+
+    Ok(())
+}
+
+fn within_depth(max_depth: Option<u32>, depths: Option<&HashMap<PackageId, u32>>, id: &PackageId) -> bool {
+    match (max_depth, depths) {
+        (Some(max_depth), Some(depths)) => depths.get(id).is_some_and(|depth| *depth <= max_depth),
+        _ => true,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn copy_dependencies(
+    metadata: &Metadata,
+    third_party_path: &Path,
+    previous_lock: Option<&LocalizeLock>,
+    project_path: &Path,
+    args: &LocalizeArgs,
+    shard: Shard,
+    reachable: Option<&HashSet<PackageId>>,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<(Vec<LockedPackage>, HashSet<String>)> {
+    let registry = registry::SourceIndex::scan(registry::src_dirs(&args.registry_src)?)?;
+    let checksums = lock::read_cargo_lock_checksums(project_path)?;
+    let patches = patches::Patches::load(project_path)?;
+
+    // Create a map of PackageId to Package for quick lookup
+    let package_map: HashMap<PackageId, &cargo_metadata::Package> =
+        metadata.packages.iter().map(|p| (p.id.clone(), p)).collect();
+
+    // Get the resolved dependency graph
+    let resolve = metadata.resolve.as_ref().context("No resolve data in metadata")?;
+
+    let depths = args.max_depth().map(|_| filter::dependency_depths(metadata));
+
+    // Kept with their position in the graph, copies finish in any order
+    let mut locked_packages = Vec::new();
+    let mut copied = HashSet::new();
+    let mut jobs = Vec::new();
+    let store = (args.store || args.store_dir.is_some())
+        .then(|| store::Store::open(args.store_dir.as_deref()))
+        .transpose()?;
+    // Linked crates are assembled outside the tree too, the journal then records a single move each
+    let staging = if args.fsevents_friendly || store.is_some() {
+        Some(quiesce::staging_dir(metadata.target_directory.as_std_path())?)
+    } else {
+        None
+    };
+
+    // Why a package isn't copied, `None` if it has to be
+    let skip_reason = |id: &PackageId, package: &cargo_metadata::Package| {
+        if package.manifest_path.starts_with(third_party_path) {
+            // Packages localized by a previous run are path dependencies into the 3rd-party folder now
+            Some(SkipReason::AlreadyLocalized)
+        } else if reachable.is_some_and(|reachable| !reachable.contains(id)) {
+            // Only needed by pruned target tables or filtered out dependency kinds
+            Some(SkipReason::NotNeeded)
+        } else if is_workspace_package(package, metadata.workspace_root.as_std_path()) {
+            Some(SkipReason::WorkspaceMember)
+        } else if package.source.is_none() && !args.external_paths {
+            Some(SkipReason::ExternalPath)
+        } else if shares_member_version(metadata, package) {
+            Some(SkipReason::SharesMemberVersion)
+        } else if !filter::is_selected(&args.packages, &args.exclude, &package.name, &package.version) {
+            Some(SkipReason::NotSelected)
+        } else if !within_depth(args.max_depth(), depths.as_ref(), id) {
+            Some(SkipReason::TooDeep)
+        } else {
+            None
+        }
+    };
+
+    // The vendor layout names crates the graph has in a single version after the crate alone
+    let mut versions: HashMap<&str, usize> = HashMap::new();
+    // Sources of each name and version, more than one when e.g. a fork and crates.io provide it
+    let mut sources: HashMap<(&str, String), usize> = HashMap::new();
+    for package in metadata.packages.iter().filter(|package| package.source.is_some()) {
+        *versions.entry(package.name.as_str()).or_default() += 1;
+        *sources
+            .entry((package.name.as_str(), package.version.to_string()))
+            .or_default() += 1;
+    }
+    let crate_path = |package: &cargo_metadata::Package| {
+        // Crates.io keeps the plain name, the other sources of a colliding crate are qualified
+        let qualified = sources.get(&(package.name.as_str(), package.version.to_string())) > Some(&1)
+            && package
+                .source
+                .as_ref()
+                .is_some_and(|source| !lock::is_crates_io(&source.repr));
+        shard.vendored_path(
+            package,
+            qualified,
+            versions.get(package.name.as_str()) == Some(&1),
+            previous_lock,
+            third_party_path,
+        )
+    };
+
+    let candidates: Vec<&cargo_metadata::Package> = resolve
+        .nodes
+        .iter()
+        .filter_map(|node| package_map.get(&node.id).map(|package| (node, *package)))
+        .filter(|(node, package)| skip_reason(&node.id, package).is_none())
+        .map(|(_, package)| package)
+        .collect();
+    policy::check(metadata, &candidates, &args.licenses)?;
+    let to_copy: Vec<&cargo_metadata::Package> = candidates
+        .into_iter()
+        .filter(|package| {
+            let version = package.version.to_string();
+            !third_party_path.join(crate_path(package)).exists()
+                && store
+                    .as_ref()
+                    .is_none_or(|store| !store.contains(&Shard::Flat.crate_path(&package.name, &version)))
+        })
+        .collect();
+    let estimate = usage::preflight(&to_copy, store.as_ref().map_or(third_party_path, |store| store.dir()))?;
+    observers.emit(Event::CopyPlanned {
+        crates: to_copy.len(),
+        bytes: estimate,
+    });
+
+    for (position, node) in resolve.nodes.iter().enumerate() {
+        let package = package_map
+            .get(&node.id)
+            .context(format!("Package {} not found in metadata", node.id))?;
+        let version = package.version.to_string();
+        let _output = logging::buffer_crate(position);
+
+        match skip_reason(&node.id, package) {
+            Some(SkipReason::AlreadyLocalized) => {
+                debug!(name = %package.name, %version, "Already localized: {} v{}", package.name, version);
+                skipped(observers, package, SkipReason::AlreadyLocalized);
+                let previous = previous_lock.and_then(|lock| lock.find_package(package, third_party_path));
+                locked_packages.push((
+                    position,
+                    match previous {
+                        Some(previous) => previous.clone(),
+                        None => LockedPackage {
+                            name: package.name.clone(),
+                            version,
+                            source: None,
+                            checksum: None,
+                            rev: None,
+                            features: node.features.clone(),
+                            path: vendored_dir_name(package, third_party_path, shard),
+                            trimmed: false,
+                            ignored: Vec::new(),
+                            declared_features: args.crate_feature_policy(&package.name, &package.version.to_string())
+                                == FeaturePolicy::Declared,
+                            pruned_targets: Vec::new(),
+                            nested_lockfile: None,
+                            frozen: false,
+                            patches: Vec::new(),
+                        },
+                    },
+                ));
+                continue;
+            }
+            Some(reason) => {
+                let why = match reason {
+                    SkipReason::NotNeeded => "not needed for the selected targets and dependency kinds",
+                    SkipReason::WorkspaceMember => "workspace package",
+                    SkipReason::ExternalPath => "path dependency outside the project, see --external-paths",
+                    SkipReason::SharesMemberVersion => {
+                        "same name and version as a workspace package, left on the registry"
+                    }
+                    SkipReason::NotSelected => "not selected",
+                    _ => "deeper than --depth",
+                };
+                debug!(name = %package.name, version = %package.version, "Skipping {} v{}: {why}", package.name, package.version);
+                skipped(observers, package, reason);
+                continue;
+            }
+            None => {}
+        }
+
+        debug!(
+            name = %package.name,
+            %version,
+            "Processing dependency: {} v{} with features: {:?}",
+            package.name,
+            package.version,
+            node.features
+        );
+        let dest_name = crate_path(package);
+        let dest_path = safety::safe_join(third_party_path, &dest_name)?;
+
+        let mut locked = LockedPackage {
+            name: package.name.clone(),
+            version: version.clone(),
+            source: package.source.as_ref().map(|s| s.repr.clone()),
+            checksum: checksums.get(&(package.name.clone(), version.clone())).cloned(),
+            rev: package
+                .source
+                .as_ref()
+                .and_then(|s| lock::git_commit(&s.repr))
+                .map(|commit| commit.to_string()),
+            features: node.features.clone(),
+            path: dest_name,
+            trimmed: false,
+            ignored: args.crate_ignore(&package.name, &version),
+            declared_features: args.crate_feature_policy(&package.name, &version) == FeaturePolicy::Declared,
+            pruned_targets: Vec::new(),
+            nested_lockfile: None,
+            frozen: false,
+            patches: Vec::new(),
+        };
+
+        if dest_path.exists() {
+            debug!(name = %package.name, %version, "  Already exists: {}", dest_path.display());
+            skipped(observers, package, SkipReason::AlreadyVendored);
+            if let Some(previous) =
+                previous_lock.and_then(|lock| lock.find_source(&locked.name, &locked.version, locked.source.as_deref()))
+            {
+                locked.trimmed = previous.trimmed;
+                locked.ignored = previous.ignored.clone();
+                locked.pruned_targets = previous.pruned_targets.clone();
+                locked.nested_lockfile = previous.nested_lockfile;
+                locked.frozen = previous.frozen;
+                locked.patches = previous.patches.clone();
+            }
+            locked_packages.push((position, locked));
+            continue;
+        }
+
+        jobs.push(CopyJob {
+            position,
+            package,
+            dest_path,
+            locked,
+        });
+    }
+
+    // Crates are copied side by side, their events are emitted here as the workers report back
+    let tuning = storage::tune(
+        store.as_ref().map_or(third_party_path, |store| store.dir()),
+        args.jobs.map(std::num::NonZeroUsize::get),
+        args.storage,
+    );
+    let context = CopyContext {
+        registry: &registry,
+        third_party_path,
+        staging: staging.as_deref(),
+        store: store.as_ref(),
+        patches: &patches,
+        args,
+        buffer_size: tuning.buffer_size,
+        journal,
+    };
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut first_error: Option<(usize, LocalizeError)> = None;
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..tuning.jobs.clamp(1, jobs.len().max(1)) {
+            let sender = sender.clone();
+            let (jobs, context, next, failed) = (&jobs, &context, &next, &failed);
+            scope.spawn(move || {
+                // Crates not started yet are left alone once one failed
+                while !failed.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    let _ = sender.send(CopyProgress::Started(index));
+                    let _output = logging::buffer_crate(job.position);
+                    let result = vendor_crate(job, context);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    let _ = sender.send(CopyProgress::Finished(index, result));
+                }
+            });
+        }
+        drop(sender);
+
+        for progress in receiver {
+            let (index, result) = match progress {
+                CopyProgress::Started(index) => {
+                    observers.emit(Event::CrateStarted {
+                        name: &jobs[index].locked.name,
+                        version: &jobs[index].locked.version,
+                    });
+                    continue;
+                }
+                CopyProgress::Finished(index, result) => (index, result),
+            };
+            let job = &jobs[index];
+            match result {
+                Ok(vendored) => {
+                    observers.emit(Event::CrateCopied {
+                        name: &job.locked.name,
+                        version: &job.locked.version,
+                        source: &vendored.source_path,
+                        path: &job.dest_path,
+                        bytes: vendored.bytes,
+                    });
+                    let mut locked = job.locked.clone();
+                    locked.trimmed = vendored.trimmed;
+                    locked.nested_lockfile = vendored.nested_lockfile;
+                    locked.patches = vendored.patches;
+                    copied.insert(locked.path.clone());
+                    locked_packages.push((job.position, locked));
+                }
+                Err(error) => {
+                    let error = LocalizeError::Crate {
+                        krate: CrateRef {
+                            name: job.locked.name.clone(),
+                            version: job.locked.version.clone(),
+                            path: job
+                                .package
+                                .manifest_path
+                                .parent()
+                                .map(|dir| dir.as_std_path().to_path_buf()),
+                        },
+                        phase: Phase::Copy,
+                        source: error.into(),
+                    };
+                    observers.emit(Event::CrateFailed {
+                        name: &job.locked.name,
+                        version: &job.locked.version,
+                        error: &error,
+                    });
+                    // The first crate in plan order that failed is reported, whichever failed first
+                    if first_error.as_ref().is_none_or(|(first, _)| index < *first) {
+                        first_error = Some((index, error));
+                    }
+                }
+            }
+        }
+    });
+    if let Some((_, error)) = first_error {
+        return Err(error.into());
+    }
+
+    if let Some(staging) = staging {
+        fs::remove_dir_all(&staging).context("Failed to remove staging directory")?;
+    }
+
+    locked_packages.sort_by_key(|(position, _)| *position);
+    Ok((locked_packages.into_iter().map(|(_, locked)| locked).collect(), copied))
+}
+
+// A crate to copy into the 3rd-party folder
+struct CopyJob<'a> {
+    // Position of the crate in the dependency graph, which its output is printed in
+    position: usize,
+    package: &'a cargo_metadata::Package,
+    dest_path: PathBuf,
+    locked: LockedPackage,
+}
+
+// What every copy needs, shared by the workers
+struct CopyContext<'a> {
+    registry: &'a registry::SourceIndex,
+    third_party_path: &'a Path,
+    staging: Option<&'a Path>,
+    store: Option<&'a store::Store>,
+    patches: &'a patches::Patches,
+    args: &'a LocalizeArgs,
+    buffer_size: usize,
+    journal: &'a Journal,
+}
+
+enum CopyProgress {
+    Started(usize),
+    Finished(usize, Result<Vendored>),
+}
+
+struct Vendored {
+    // Where the crate was copied from
+    source_path: PathBuf,
+    // Size of the copy, measured by the worker so observers don't walk the crate again
+    bytes: u64,
+    trimmed: bool,
+    nested_lockfile: Option<LockfilePolicy>,
+    patches: Vec<lock::AppliedPatch>,
+}
+
+fn vendor_crate(job: &CopyJob, context: &CopyContext) -> Result<Vendored> {
+    let (package, locked, dest_path) = (job.package, &job.locked, &job.dest_path);
+    let journal = context.journal;
+    let version = &locked.version;
+    // Git crates are checked out somewhere in the repository cargo cloned
+    let source_path = match &locked.rev {
+        Some(_) => package
+            .manifest_path
+            .parent()
+            .context(format!("No source directory for {}", package.name))?
+            .as_std_path()
+            .to_path_buf(),
+        None => registry_source(package, context.registry)?,
+    };
+    let symlinks = context.args.symlinks.unwrap_or_default();
+    safety::verify_crate_tree(&source_path, symlinks)
+        .context(format!("Refusing to vendor {} v{}", package.name, version))?;
+    let copy_path = context.staging.unwrap_or(context.third_party_path).join(&locked.path);
+    let hooked = HookedCrate {
+        name: &package.name,
+        version,
+        path: &copy_path,
+        source: &source_path,
+    };
+    context
+        .args
+        .hooks
+        .run_crate(Hook::PreCopy, &hooked, context.third_party_path)?;
+    let copy_parent = copy_path.parent().context("Crate directory has no parent")?;
+    journal
+        .create_dir_all(copy_parent)
+        .context(format!("Failed to create {}", copy_parent.display()))?;
+    let patches = context.patches.for_crate(&package.name);
+    // Path crates change without a new version, the store would hand out a stale copy. Patched
+    // crates and those with files left out differ from the copy other projects share.
+    let store = context
+        .store
+        .filter(|_| package.source.is_some() && patches.is_empty() && locked.ignored.is_empty());
+    if let Some(store) = store {
+        // The store is shared by projects with different layouts
+        let stored = store.add(&source_path, &Shard::Flat.crate_path(&package.name, version), symlinks)?;
+        store::link_crate(&stored, &copy_path)?;
+        debug!(name = %package.name, %version, "  Linked: {} -> {}", dest_path.display(), stored.display());
+    } else {
+        // Copied straight to its own name, git checkouts are named after the commit
+        let options = CopyOptions::new().overwrite(true).buffer_size(context.buffer_size);
+        // Path crates outside the project come with their repository and build output
+        match package.source {
+            Some(_) => journal.copy_dir_except(&source_path, &copy_path, &options, &[], &locked.ignored, symlinks),
+            None => journal.copy_dir_except(
+                &source_path,
+                &copy_path,
+                &options,
+                &[".git", "target"],
+                &locked.ignored,
+                symlinks,
+            ),
+        }
+        .context(format!(
+            "Failed to copy {} to {}",
+            source_path.display(),
+            copy_path.display()
+        ))?;
+        debug!(name = %package.name, %version, "  Copied: {} -> {}", source_path.display(), dest_path.display());
+    }
+    packaging::clean(&copy_path, context.args.packaging_files.unwrap_or_default(), journal)?;
+    // A crate at the root of its repository comes with the clone's git directory
+    let git_dir = copy_path.join(".git");
+    if locked.rev.is_some() && fs::symlink_metadata(&git_dir).is_ok() {
+        if git_dir.is_dir() {
+            journal.remove_dir_all(&git_dir)
+        } else {
+            journal.remove_file(&git_dir)
+        }
+        .context(format!("Failed to remove {}", git_dir.display()))?;
+    }
+    let patches = patches::apply(&copy_path, patches, journal)
+        .context(format!("Failed to patch {} v{}", package.name, version))?;
+    if !patches.is_empty() {
+        debug!(name = %package.name, %version, "  Applied {} patch(es)", patches.len());
+    }
+
+    let args = context.args;
+    let trimmed = match args.crate_strip(&package.name, version) {
+        Strip::Trim => trim::trim_crate(package, &copy_path, false, journal)?,
+        Strip::Minimal => trim::trim_crate(package, &copy_path, true, journal)?,
+        Strip::None => false,
+    };
+    let nested_lockfile = trim::nested_lockfile(&copy_path, args.nested_lockfiles.unwrap_or_default(), journal)?;
+    let mut findings = portability::audit(&copy_path)?;
+    if !findings.is_empty() {
+        // Crates linked from the store are shared with other projects, they're left as they are
+        if args.fix_filenames && store.is_none() {
+            portability::fix(&copy_path, &mut findings, journal)?;
+        }
+        let findings: Vec<String> = findings.iter().map(ToString::to_string).collect();
+        warnings::warn(
+            WarningClass::Portability,
+            format!(
+                "{} v{version}: {} file name(s) won't check out on every platform: {}",
+                package.name,
+                findings.len(),
+                findings.join(", ")
+            ),
+        );
+    }
+    // Crates linked from the store are shared with other projects, hooks don't get to change them
+    if store.is_none() {
+        args.hooks
+            .run_crate(Hook::PostCopy, &hooked, context.third_party_path)?;
+    }
+    if context.staging.is_some() {
+        if let Some(shard_dir) = dest_path.parent() {
+            journal
+                .create_dir_all(shard_dir)
+                .context(format!("Failed to create {}", shard_dir.display()))?;
+        }
+        journal
+            .move_dir(&copy_path, dest_path)
+            .context(format!("Failed to move {} into place", dest_path.display()))?;
+    }
+    Ok(Vendored {
+        bytes: util::dir_size(dest_path),
+        source_path,
+        trimmed,
+        nested_lockfile,
+        patches,
+    })
+}
+
+// The version a crate with local edits was upgraded to: the newest one of the same name the graph
+// resolves to that wasn't vendored before
+fn upgraded_version(
+    metadata: &Metadata,
+    previous_lock: Option<&LocalizeLock>,
+    locked: &LockedPackage,
+) -> Option<String> {
+    metadata
+        .packages
+        .iter()
+        .filter(|package| package.name == locked.name && package.source.is_some())
+        .filter(|package| {
+            previous_lock.is_none_or(|lock| {
+                lock.find_source(
+                    &package.name,
+                    &package.version.to_string(),
+                    package.source.as_ref().map(|source| source.repr.as_str()),
+                )
+                .is_none()
+            })
+        })
+        .map(|package| &package.version)
+        .max()
+        .map(ToString::to_string)
+}
+
+fn skipped(observers: &Observers, package: &cargo_metadata::Package, reason: SkipReason) {
+    observers.emit(Event::CrateSkipped {
+        name: &package.name,
+        version: &package.version.to_string(),
+        reason,
+    });
+}
+
+fn vendored_dir_name(package: &cargo_metadata::Package, third_party_path: &Path, shard: Shard) -> String {
+    package
+        .manifest_path
+        .parent()
+        .and_then(|dir| dir.as_std_path().strip_prefix(third_party_path).ok())
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_else(|| shard.crate_path(&package.name, &package.version.to_string()))
+}
+
+fn is_workspace_package(package: &cargo_metadata::Package, workspace_root: &Path) -> bool {
+    // Check if the package manifest is within the workspace; registry crates resolved through a
+    // `cargo vendor` folder in it aren't
+    package.source.is_none() && package.manifest_path.starts_with(workspace_root)
+}
+
+// Vendored, an external crate and a workspace package of the same name and version would both
+// be path crates, which cargo can't tell apart
+fn shares_member_version(metadata: &Metadata, package: &cargo_metadata::Package) -> bool {
+    package.source.is_some()
+        && metadata.packages.iter().any(|member| {
+            member.source.is_none()
+                && member.name == package.name
+                && member.version == package.version
+                && is_workspace_package(member, metadata.workspace_root.as_std_path())
+        })
+}
+
+// `cargo metadata` reports where each crate's manifest is: in the directory cargo extracted it
+// into, one per registry index, or in the folder `cargo vendor` wrote, which `import-vendor`
+// copies from. Either way it's the crate of the registry it resolved from, so the same name and
+// version from another registry isn't picked up. The registry is only searched when the directory
+// is gone, e.g. with a registry cache mounted somewhere else than where it was extracted.
+fn registry_source(package: &cargo_metadata::Package, registry: &registry::SourceIndex) -> Result<PathBuf> {
+    match package.manifest_path.parent() {
+        Some(source_dir) if source_dir.join("Cargo.toml").is_file() => Ok(source_dir.as_std_path().to_path_buf()),
+        _ => registry
+            .find(&package.name, &package.version.to_string())
+            .map(Path::to_path_buf),
+    }
+}
+
+fn update_cargo_toml(
+    metadata: &Metadata,
+    project_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    only_copied: Option<&HashSet<String>>,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<()> {
+    // Always update the main Cargo.toml
+    let root_manifest = project_path.join("Cargo.toml");
+    let members: Vec<&cargo_metadata::Package> = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|member| member.manifest_path != root_manifest)
+        .collect();
+    // Manifests of crates vendored earlier are already rewritten
+    let vendored: Vec<PathBuf> = localize_lock
+        .packages
+        .iter()
+        .filter(|locked| only_copied.is_none_or(|copied| copied.contains(&locked.path)))
+        .map(|locked| third_party_path.join(&locked.path).join("Cargo.toml"))
+        .filter(|cargo_toml_path| cargo_toml_path.exists())
+        .collect();
+    observers.emit(Event::RewritePlanned {
+        manifests: 1 + members.len() + vendored.len(),
+    });
+
+    debug!("Updating main Cargo.toml");
+    update_single_cargo_toml(
+        metadata,
+        &root_manifest,
+        third_party_path,
+        localize_lock,
+        only_copied.is_some(),
+        observers,
+        journal,
+    )?;
+
+    // Other workspace members declare dependencies of their own, or inherit them from the root
+    for member in members {
+        debug!("Updating member Cargo.toml: {}", member.manifest_path);
+        update_single_cargo_toml(
+            metadata,
+            member.manifest_path.as_std_path(),
+            third_party_path,
+            localize_lock,
+            only_copied.is_some(),
+            observers,
+            journal,
+        )?;
+    }
+
+    // Update Cargo.toml files for each vendored dependency
+    for (position, cargo_toml_path) in vendored.iter().enumerate() {
+        let _output = logging::buffer_crate(position);
+        debug!("Updating dependency Cargo.toml: {}", cargo_toml_path.display());
+        update_single_cargo_toml(
+            metadata,
+            cargo_toml_path,
+            third_party_path,
+            localize_lock,
+            only_copied.is_some(),
+            observers,
+            journal,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn update_single_cargo_toml(
+    metadata: &Metadata,
+    cargo_toml_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    skip_path_deps: bool,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<()> {
+    backup_manifest(cargo_toml_path, journal)?;
+    let content = fs::read_to_string(cargo_toml_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+
+    let owner = manifest_owner(metadata, cargo_toml_path, third_party_path, localize_lock);
+
+    // Process all dependency sections, including target-specific ones
+    for deps in dependency_tables_mut(&mut doc) {
+        update_dependencies(
+            deps,
+            metadata,
+            owner,
+            cargo_toml_path,
+            third_party_path,
+            localize_lock,
+            skip_path_deps,
+        )?;
+    }
+    // `[workspace.dependencies]` aren't owned by one package, members inheriting them resolve them
+    if let Some(deps) = workspace_dependencies_mut(&mut doc) {
+        update_dependencies(
+            deps,
+            metadata,
+            None,
+            cargo_toml_path,
+            third_party_path,
+            localize_lock,
+            skip_path_deps,
+        )?;
+    }
+    qualify_version(&mut doc, cargo_toml_path, third_party_path, localize_lock);
+    detach_from_workspace(&mut doc, cargo_toml_path, third_party_path, localize_lock);
+    exclude_from_workspace(&mut doc, cargo_toml_path, third_party_path);
+
+    journal
+        .write(cargo_toml_path, doc.to_string())
+        .context("Failed to write Cargo.toml")?;
+    observers.emit(Event::ManifestRewritten { path: cargo_toml_path });
+
+    Ok(())
+}
+
+fn dependency_tables_mut(doc: &mut DocumentMut) -> Vec<&mut Table> {
+    let sections = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let mut tables = Vec::new();
+
+    for (key, item) in doc.as_table_mut().iter_mut() {
+        if sections.contains(&key.get()) {
+            tables.extend(item.as_table_mut());
+        } else if key.get() == "target"
+            && let Some(target_table) = item.as_table_mut()
+        {
+            for (_, target_value) in target_table.iter_mut() {
+                if let Some(target_spec) = target_value.as_table_mut() {
+                    for (section, deps) in target_spec.iter_mut() {
+                        if sections.contains(&section.get()) {
+                            tables.extend(deps.as_table_mut());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    tables
+}
+
+fn backup_manifest(cargo_toml_path: &Path, journal: &Journal) -> Result<()> {
+    let bak_filepath = cargo_toml_path.to_string_lossy().to_string() + ".bak";
+    if !fs::exists(&bak_filepath).is_ok_and(|v| v) {
+        journal
+            .copy_file(cargo_toml_path, Path::new(&bak_filepath))
+            .context("Failed to backup Cargo.toml to Cargo.toml.bak")?;
+    }
+    Ok(())
+}
+
+fn workspace_dependencies_mut(doc: &mut DocumentMut) -> Option<&mut Table> {
+    doc.get_mut("workspace")?.get_mut("dependencies")?.as_table_mut()
+}
+
+// Cargo can't tell apart path crates of the same name and version, so a crate another source
+// provides as well gets its qualifier as build metadata: `1.0.0+1a2b3c4d`. Requirements ignore it.
+fn qualify_version(
+    doc: &mut DocumentMut,
+    cargo_toml_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+) {
+    let Some(crate_dir) = cargo_toml_path
+        .parent()
+        .and_then(|dir| dir.strip_prefix(third_party_path).ok())
+    else {
+        return;
+    };
+    let Some(locked) = localize_lock
+        .packages
+        .iter()
+        .find(|locked| Path::new(&locked.path) == crate_dir)
+    else {
+        return;
+    };
+    if let Some(qualifier) = locked.qualifier()
+        && let Some(package) = doc.get_mut("package").and_then(Item::as_table_like_mut)
+    {
+        // Versions with build metadata of their own get it as one more identifier
+        let separator = if locked.version.contains('+') { '.' } else { '+' };
+        let version = format!("{}{separator}{qualifier}", locked.version);
+        debug!("  Qualified version: {version}");
+        package.insert("version", toml_edit::value(version));
+    }
+}
+
+// Cargo looks for the workspace of a crate in the directories above it, so vendored crates would
+// be taken for members of the project's workspace or of one enclosing it, inheriting its profiles
+// and lints. An empty `[workspace]` makes each of them a workspace of its own. Crates that are
+// workspace roots already keep their table.
+fn detach_from_workspace(
+    doc: &mut DocumentMut,
+    cargo_toml_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+) {
+    if !cargo_toml_path.starts_with(third_party_path) || !doc.contains_key("package") {
+        return;
+    }
+    if localize_lock.no_workspace_table {
+        // Only the table an earlier run added is empty
+        if doc
+            .get("workspace")
+            .and_then(Item::as_table_like)
+            .is_some_and(|workspace| workspace.is_empty())
+        {
+            doc.remove("workspace");
+        }
+    } else if !doc.contains_key("workspace") {
+        debug!("  Added an empty [workspace] table");
+        doc.insert("workspace", Item::Table(Table::new()));
+    }
+}
+
+// Path crates below a workspace root become members of the workspace unless it excludes them,
+// which would make every vendored crate a member and two versions of one crate a clash
+fn exclude_from_workspace(doc: &mut DocumentMut, cargo_toml_path: &Path, third_party_path: &Path) {
+    let Some(relative) = cargo_toml_path
+        .parent()
+        .and_then(|dir| third_party_path.strip_prefix(dir).ok())
+    else {
+        return;
+    };
+    let Some(workspace) = doc.get_mut("workspace").and_then(Item::as_table_like_mut) else {
+        return;
+    };
+    let relative = util::slashed(relative);
+    let Some(exclude) = workspace
+        .entry("exclude")
+        .or_insert(toml_edit::value(Array::new()))
+        .as_array_mut()
+    else {
+        return;
+    };
+    let excluded = exclude.iter().any(|entry| {
+        entry
+            .as_str()
+            .is_some_and(|entry| entry.trim_start_matches("./").trim_end_matches('/') == relative)
+    });
+    if !excluded {
+        debug!("  Excluded {relative} from the workspace");
+        exclude.push(relative);
+    }
+}
+
+// Code hosts leave vendored files out of language statistics and collapse them in diffs
+fn mark_vendored(project_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    if !safety::is_contained(Path::new(&localize_lock.third_party_dir)) {
+        return Ok(());
+    }
+    let path = project_path.join(".gitattributes");
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let line = format!(
+        "/{}/** linguist-vendored",
+        localize_lock.third_party_dir.replace('\\', "/").trim_end_matches('/')
+    );
+    if content.lines().any(|existing| existing.trim() == line) {
+        return Ok(());
+    }
+    let separator = if content.is_empty() || content.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    journal
+        .write(&path, format!("{content}{separator}{line}\n"))
+        .context("Failed to write .gitattributes")
+}
+
+// `--mode patch` leaves the manifests of vendored crates as they are otherwise
+fn detach_vendored_manifests(third_party_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    for locked in &localize_lock.packages {
+        let cargo_toml_path = third_party_path.join(&locked.path).join("Cargo.toml");
+        let Ok(content) = fs::read_to_string(&cargo_toml_path) else {
+            continue;
+        };
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .context(format!("Failed to parse {}", cargo_toml_path.display()))?;
+        detach_from_workspace(&mut doc, &cargo_toml_path, third_party_path, localize_lock);
+        if doc.to_string() != content {
+            journal
+                .write(&cargo_toml_path, doc.to_string())
+                .context(format!("Failed to write {}", cargo_toml_path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+// Package a manifest belongs to. Vendored crates are matched through the lock, since
+// metadata still knows freshly copied ones at their registry location.
+fn manifest_owner<'a>(
+    metadata: &'a Metadata,
+    cargo_toml_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+) -> Option<&'a cargo_metadata::Package> {
+    if let Some(package) = metadata.packages.iter().find(|p| p.manifest_path == cargo_toml_path) {
+        return Some(package);
+    }
+
+    let crate_dir = cargo_toml_path.parent()?.strip_prefix(third_party_path).ok()?;
+    let locked = localize_lock
+        .packages
+        .iter()
+        .find(|p| Path::new(&p.path) == crate_dir)?;
+    metadata.packages.iter().find(|p| {
+        p.name == locked.name
+            && p.version.to_string() == locked.version
+            && p.source.as_ref().map(|s| &s.repr) == locked.source.as_ref()
+    })
+}
+
+fn update_dependencies(
+    deps: &mut Table,
+    metadata: &Metadata,
+    owner: Option<&cargo_metadata::Package>,
+    cargo_toml_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    skip_path_deps: bool,
+) -> Result<()> {
+    for (dep_name, dep_value) in deps.iter_mut() {
+        // Entries pointing into the 3rd-party folder were rewritten before, those leading outside
+        // the project may be vendored by this run
+        if skip_path_deps
+            && dep_value
+                .get("path")
+                .and_then(|path| path.as_str())
+                .is_some_and(|path| {
+                    cargo_toml_path.parent().is_none_or(|dir| {
+                        util::normalize_path(&dir.join(path)).starts_with(util::normalize_path(third_party_path))
+                    })
+                })
+        {
+            continue;
+        }
+        if !dep_value.is_str() && !dep_value.is_table_like() {
+            continue;
+        }
+        // `dep = { workspace = true }` follows whatever `[workspace.dependencies]` says
+        if dep_value.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+            debug!("  Skipping dependency: {dep_name} (inherited from [workspace.dependencies])");
+            continue;
+        }
+
+        debug!("  Processing dependency: {dep_name}");
+
+        let package_name = dep_value.as_table_like().and_then(get_package_name_from_table);
+        let requirement = version_requirement(dep_value);
+        let resolved = match owner {
+            Some(owner) => resolve_dependency(metadata, owner, dep_name.get()),
+            None => resolve_workspace_dependency(metadata, dep_name.get(), requirement.as_ref()).or_else(|| {
+                find_package_for_dependency(
+                    metadata,
+                    dep_name.get(),
+                    package_name.as_deref(),
+                    requirement.as_ref(),
+                    dep_value.get("path").is_some(),
+                )
+            }),
+        };
+        let Some((package, features)) = resolved else {
+            debug!("    Skipping dependency: {dep_name} (not found in metadata)");
+            continue;
+        };
+        let Some(dep_path) = vendored_path(metadata, localize_lock, third_party_path, package) else {
+            debug!("    Skipping dependency: {dep_name} (not found in 3rd-party)");
+            if localize_lock.pin_git {
+                pin_git_dependency(dep_value, package);
+            }
+            continue;
+        };
+        let rel_path = pathdiff::diff_paths(&dep_path, cargo_toml_path.parent().unwrap())
+            .context("Failed to compute relative path")?;
+
+        // Simple version string dependencies become inline tables, so all three shapes
+        // are rewritten the same way
+        if dep_value.is_str() {
+            *dep_value = Item::Value(Value::InlineTable(toml_edit::InlineTable::new()));
+        }
+        let Some(table) = dep_value.as_table_like_mut() else {
+            continue;
+        };
+        let version = package.version.to_string();
+        let declared_features = localize_lock
+            .find_package(package, third_party_path)
+            .is_some_and(|locked| locked.declared_features);
+        let features = if declared_features { Vec::new() } else { features };
+        point_to_path(
+            table,
+            &rel_path,
+            &features,
+            localize_lock.keep_version.then_some(&version),
+        );
+        // Removed keys leave their spacing behind in inline tables
+        if let Some(inline) = dep_value.as_inline_table_mut() {
+            inline.fmt();
+        }
+
+        debug!(
+            "    Updated dependency: {dep_name} -> path = {}, features = {features:?}",
+            rel_path.display(),
+        );
+    }
+    Ok(())
+}
+
+// Points a git dependency left on its repository at the commit it resolved to,
+// so a moving branch or tag can't change what gets built
+fn pin_git_dependency(dep_value: &mut Item, package: &cargo_metadata::Package) {
+    let Some(commit) = package.source.as_ref().and_then(|s| lock::git_commit(&s.repr)) else {
+        return;
+    };
+    let Some(table) = dep_value.as_table_like_mut().filter(|table| table.contains_key("git")) else {
+        return;
+    };
+    if table.get("rev").and_then(|rev| rev.as_str()) == Some(commit) {
+        return;
+    }
+    table.remove("branch");
+    table.remove("tag");
+    table.insert("rev", toml_edit::value(commit));
+    if let Some(inline) = dep_value.as_inline_table_mut() {
+        inline.fmt();
+    }
+    debug!("    Pinned dependency: {} -> rev = {commit}", package.name);
+}
+
+// Replaces the source of a dependency entry with `path`. Everything else the entry says,
+// such as `optional`, `default-features` or `package`, is kept as written.
+// With `version`, the entry keeps a requirement on the vendored version next to `path`.
+fn point_to_path(table: &mut dyn TableLike, rel_path: &Path, features: &[String], version: Option<&str>) {
+    for key in ["version", "git", "branch", "tag", "rev"] {
+        table.remove(key);
+    }
+    // Next to a version, the registry says where the crate is published from (`cargo publish`
+    // needs it for crates of alternative registries), a plain path dependency has no use for it
+    if version.is_none() {
+        table.remove("registry");
+        table.remove("registry-index");
+    }
+
+    table.insert("path", toml_edit::value(util::slashed(rel_path)));
+    if let Some(version) = version {
+        table.insert("version", toml_edit::value(version));
+    }
+
+    // Add features if any, entries opting out of default features keep theirs as written
+    if !features.is_empty() && !disables_default_features(table) {
+        let mut feature_array = Array::new();
+        for feature in features {
+            feature_array.push(feature);
+        }
+        table.insert("features", toml_edit::value(feature_array));
+    }
+}
+
+// `default-features = false`, or its older `default_features` spelling
+fn disables_default_features(table: &dyn TableLike) -> bool {
+    ["default-features", "default_features"]
+        .iter()
+        .any(|key| table.get(key).and_then(|v| v.as_bool()) == Some(false))
+}
+
+fn vendored_path(
+    metadata: &Metadata,
+    localize_lock: &LocalizeLock,
+    third_party_path: &Path,
+    package: &cargo_metadata::Package,
+) -> Option<PathBuf> {
+    // Workspace members and other path crates are never vendored, whatever the lock holds
+    // under their name and version, path crates outside the project only with `--external-paths`
+    if package.source.is_none()
+        && !package.manifest_path.starts_with(third_party_path)
+        && (!localize_lock.external_paths || is_workspace_package(package, metadata.workspace_root.as_std_path()))
+    {
+        return None;
+    }
+    localize_lock
+        .find_package(package, third_party_path)
+        .map(|locked| third_party_path.join(&locked.path))
+        .filter(|path| path.exists())
+}
+
+// Follows a dependency entry of `owner` through the resolve graph. The entry key is the rename
+// if there is one, `owner`'s declared dependencies tell which crate and requirement it stands for.
+fn resolve_dependency<'a>(
+    metadata: &'a Metadata,
+    owner: &cargo_metadata::Package,
+    dep_key: &str,
+) -> Option<(&'a cargo_metadata::Package, Vec<String>)> {
+    let resolve = metadata.resolve.as_ref()?;
+    let owner_node = resolve.nodes.iter().find(|n| n.id == owner.id)?;
+    let resolved: Vec<&cargo_metadata::Package> = owner_node
+        .deps
+        .iter()
+        .filter_map(|dep| metadata.packages.iter().find(|p| p.id == dep.pkg))
+        .collect();
+
+    // The same key can be declared in several sections with different requirements. A crate the
+    // project's `[patch]` replaced resolves from another source than the one declared.
+    let declared: Vec<&cargo_metadata::Dependency> = owner
+        .dependencies
+        .iter()
+        .filter(|dependency| dependency.rename.as_deref().unwrap_or(&dependency.name) == dep_key)
+        .collect();
+    let package = [true, false].into_iter().find_map(|exact| {
+        declared.iter().find_map(|dependency| {
+            resolved.iter().find(|p| {
+                p.name == dependency.name
+                    && dependency.req.matches(&p.version)
+                    && declared_as(dependency, p, exact, &metadata.workspace_members)
+            })
+        })
+    })?;
+
+    let node = resolve.nodes.iter().find(|n| n.id == package.id)?;
+    Some((package, node.features.clone()))
+}
+
+// A workspace member and an external crate can share name and version, the resolved package
+// has to come from where the entry points: a `path` entry stands for the crate in that directory,
+// any other entry for a crate from a registry or git repository, or one a `[patch]` put in its
+// place when not `exact`
+fn declared_as(
+    dependency: &cargo_metadata::Dependency,
+    package: &cargo_metadata::Package,
+    exact: bool,
+    workspace_members: &[PackageId],
+) -> bool {
+    match (&dependency.path, &package.source) {
+        (Some(path), _) => package
+            .manifest_path
+            .parent()
+            .is_some_and(|dir| util::normalize_path(dir.as_std_path()) == util::normalize_path(path.as_std_path())),
+        // Two sources can provide the same name and version, the declared one tells them apart
+        (None, Some(source)) => {
+            !exact
+                || dependency.source.as_deref().is_none_or(|declared| {
+                    let resolved = source.repr.split('#').next().unwrap_or_default();
+                    declared == resolved || (lock::is_crates_io(declared) && lock::is_crates_io(resolved))
+                })
+        }
+        // Patched with a path crate, vendored or outside the workspace
+        (None, None) => !exact && !workspace_members.contains(&package.id),
+    }
+}
+
+// Resolves a `[workspace.dependencies]` entry through the first member declaring it
+// Members inheriting the entry resolved it, any other member declaring the key on its own may
+// have picked another version of the crate
+fn resolve_workspace_dependency<'a>(
+    metadata: &'a Metadata,
+    dep_key: &str,
+    requirement: Option<&VersionReq>,
+) -> Option<(&'a cargo_metadata::Package, Vec<String>)> {
+    metadata
+        .workspace_packages()
+        .into_iter()
+        .filter_map(|member| resolve_dependency(metadata, member, dep_key))
+        .find(|(package, _)| requirement.is_none_or(|requirement| requirement.matches(&package.version)))
+}
+
+// Entries no package declares, like those of a virtual manifest nothing inherits. With several
+// versions of the crate in the graph, the newest one the requirement accepts is what cargo would
+// pick, as long as something depends on it.
+fn find_package_for_dependency<'a>(
+    metadata: &'a Metadata,
+    dep_name: &'a str,
+    package_name: Option<&'a str>,
+    requirement: Option<&VersionReq>,
+    is_path: bool,
+) -> Option<(&'a cargo_metadata::Package, Vec<String>)> {
+    let resolve = metadata.resolve.as_ref()?;
+    let package_map: HashMap<&PackageId, &cargo_metadata::Package> =
+        metadata.packages.iter().map(|p| (&p.id, p)).collect();
+    let depended_on: HashSet<&PackageId> = resolve
+        .nodes
+        .iter()
+        .flat_map(|node| node.deps.iter().map(|dep| &dep.pkg))
+        .collect();
+    let actual_name = package_name.unwrap_or(dep_name);
+
+    resolve
+        .nodes
+        .iter()
+        .filter_map(|node| Some((*package_map.get(&node.id)?, node)))
+        // Entries without `path` never stand for a workspace member of the same name
+        .filter(|(package, _)| package.name == actual_name && (is_path || package.source.is_some()))
+        .filter(|(package, _)| requirement.is_none_or(|requirement| requirement.matches(&package.version)))
+        .max_by_key(|(package, _)| (depended_on.contains(&package.id), &package.version))
+        .map(|(package, node)| (package, node.features.clone()))
+}
+
+// Requirement of a `dep = "1.0"` or `dep = { version = "1.0" }` entry
+fn version_requirement(dep_value: &Item) -> Option<VersionReq> {
+    let version = match dep_value.as_str() {
+        Some(version) => version,
+        None => dep_value.get("version")?.as_str()?,
+    };
+    VersionReq::parse(version).ok()
+}
+
+fn get_package_name_from_table(table: &dyn TableLike) -> Option<String> {
+    table.get("package").and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::{LockedPackage, Strategy};
+    use serde_json::{Value, json};
+
+    const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    // A workspace of the members `app` and `util`, next to `wrapper` and a registry crate also
+    // named `util` that an earlier run vendored, and the metadata cargo reports for it. `wrapper`
+    // depends on the registry `util`, or on the member if the root manifest patches it in.
+    struct Fixture {
+        dir: tempfile::TempDir,
+        metadata: Metadata,
+        localize_lock: LocalizeLock,
+    }
+
+    impl Fixture {
+        // `app_dependencies` are the entries of `app_manifest` as metadata declares them, given
+        // the directory of the `util` member, `app_deps` what they resolved to
+        fn new(
+            root_extra: &str,
+            app_manifest: &str,
+            app_dependencies: impl FnOnce(PathBuf) -> Vec<Value>,
+            app_deps: &[(&str, &str)],
+        ) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path();
+            let files = [
+                (
+                    "Cargo.toml",
+                    format!("[workspace]\nmembers = [\"app\", \"util\"]\nresolver = \"2\"\n{root_extra}"),
+                ),
+                ("app/Cargo.toml", app_manifest.to_string()),
+                (
+                    "util/Cargo.toml",
+                    "[package]\nname = \"util\"\nversion = \"1.0.0\"\n\n[features]\nextra = []\n".to_string(),
+                ),
+                (
+                    "3rd-party/util-1.0.0/Cargo.toml",
+                    "[package]\nname = \"util\"\nversion = \"1.0.0\"\n".to_string(),
+                ),
+                (
+                    "3rd-party/wrapper-1.0.0/Cargo.toml",
+                    "[package]\nname = \"wrapper\"\nversion = \"1.0.0\"\n\n[dependencies]\nutil = \"1.0\"\n"
+                        .to_string(),
+                ),
+            ];
+            for (path, content) in files {
+                fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+                fs::write(root.join(path), content).unwrap();
+            }
+
+            let id = |key: &str| match key {
+                "app" | "member" => format!("path+file://{}/{key}#1.0.0", root.display()),
+                _ => format!("{REGISTRY}#{key}@1.0.0"),
+            };
+            let node = |key: &str, deps: &[(&str, &str)]| {
+                json!({
+                    "id": id(key),
+                    "deps": deps.iter().map(|(name, pkg)| json!({"name": name, "pkg": id(pkg), "dep_kinds": []})).collect::<Vec<_>>(),
+                    "dependencies": deps.iter().map(|(_, pkg)| id(pkg)).collect::<Vec<_>>(),
+                    "features": if key == "member" { ["extra"] } else { ["default"] },
+                })
+            };
+            let wrapper_util = if root_extra.contains("[patch") {
+                "member"
+            } else {
+                "util"
+            };
+            let registry = root.join("registry");
+            let metadata = json!({
+                "packages": [
+                    package(&id("app"), "app", root.join("app/Cargo.toml"), None, app_dependencies(root.join("util"))),
+                    package(&id("member"), "util", root.join("util/Cargo.toml"), None, Vec::new()),
+                    package(&id("util"), "util", registry.join("util-1.0.0/Cargo.toml"), Some(REGISTRY), Vec::new()),
+                    package(
+                        &id("wrapper"),
+                        "wrapper",
+                        registry.join("wrapper-1.0.0/Cargo.toml"),
+                        Some(REGISTRY),
+                        vec![dependency("util", None, None, None, &[])],
+                    ),
+                ],
+                "workspace_members": [id("app"), id("member")],
+                "resolve": {
+                    "nodes": [
+                        node("app", app_deps),
+                        node("member", &[]),
+                        node("util", &[]),
+                        node("wrapper", &[("util", wrapper_util)]),
+                    ],
+                    "root": null,
+                },
+                "workspace_root": root,
+                "target_directory": root.join("target"),
+                "version": 1,
+            });
+
+            let mut localize_lock = LocalizeLock::new(Strategy::Rewrite, "3rd-party");
+            localize_lock.packages = ["util", "wrapper"]
+                .into_iter()
+                .map(|name| LockedPackage {
+                    name: name.to_string(),
+                    version: "1.0.0".to_string(),
+                    source: Some(REGISTRY.to_string()),
+                    checksum: None,
+                    rev: None,
+                    features: Vec::new(),
+                    path: format!("{name}-1.0.0"),
+                    trimmed: false,
+                    ignored: Vec::new(),
+                    declared_features: false,
+                    pruned_targets: Vec::new(),
+                    nested_lockfile: None,
+                    frozen: false,
+                    patches: Vec::new(),
+                })
+                .collect();
+            Fixture {
+                dir,
+                metadata: serde_json::from_value(metadata).unwrap(),
+                localize_lock,
+            }
+        }
+
+        fn rewrite(&self) {
+            let root = self.dir.path();
+            let third_party_path = self.localize_lock.third_party_path(root);
+            update_cargo_toml(
+                &self.metadata,
+                root,
+                &third_party_path,
+                &self.localize_lock,
+                None,
+                &Observers::default(),
+                &Journal::default(),
+            )
+            .unwrap();
+        }
+
+        fn manifest(&self, path: &str) -> DocumentMut {
+            fs::read_to_string(self.dir.path().join(path)).unwrap().parse().unwrap()
+        }
+    }
+
+    // The version is the one the id ends in, `<source>#<name>@<version>` or `<source>#<version>`
+    fn package(id: &str, name: &str, manifest_path: PathBuf, source: Option<&str>, dependencies: Vec<Value>) -> Value {
+        json!({
+            "name": name,
+            "version": id.rsplit(['#', '@']).next(),
+            "id": id,
+            "source": source,
+            "manifest_path": manifest_path,
+            "dependencies": dependencies,
+            "targets": [],
+            "features": {},
+            "edition": "2021",
+        })
+    }
+
+    // A declared dependency, from the registry unless it has a `path`
+    fn dependency(
+        name: &str,
+        path: Option<&Path>,
+        kind: Option<&str>,
+        rename: Option<&str>,
+        features: &[&str],
+    ) -> Value {
+        json!({
+            "name": name,
+            "source": if path.is_none() { Some(REGISTRY) } else { None },
+            "req": "^1.0",
+            "kind": kind,
+            "optional": false,
+            "uses_default_features": true,
+            "features": features,
+            "target": null,
+            "rename": rename,
+            "registry": null,
+            "path": path,
+        })
+    }
+
+    fn path_of<'a>(doc: &'a DocumentMut, section: &str, key: &str) -> Option<&'a str> {
+        doc[section][key].get("path").and_then(Item::as_str)
+    }
+
+    #[test]
+    fn dev_dependency_on_member_with_features_keeps_its_path() {
+        let fixture = Fixture::new(
+            "",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\nwrapper = \"1.0\"\n\
+             util = { path = \"../util\" }\n\n[dev-dependencies]\nutil = { path = \"../util\", features = [\"extra\"] }\n",
+            |util| {
+                vec![
+                    dependency("wrapper", None, None, None, &[]),
+                    dependency("util", Some(&util), None, None, &[]),
+                    dependency("util", Some(&util), Some("dev"), None, &["extra"]),
+                ]
+            },
+            &[("util", "member"), ("wrapper", "wrapper")],
+        );
+        fixture.rewrite();
+
+        let app = fixture.manifest("app/Cargo.toml");
+        assert_eq!(path_of(&app, "dependencies", "util"), Some("../util"));
+        assert_eq!(path_of(&app, "dev-dependencies", "util"), Some("../util"));
+        assert_eq!(app["dev-dependencies"]["util"]["features"][0].as_str(), Some("extra"));
+        assert_eq!(
+            path_of(&app, "dependencies", "wrapper"),
+            Some("../3rd-party/wrapper-1.0.0")
+        );
+    }
+
+    #[test]
+    fn member_keeps_its_path_next_to_external_crate_of_same_name() {
+        let fixture = Fixture::new(
+            "",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\nutil = { path = \"../util\" }\n\
+             registry-util = { package = \"util\", version = \"1.0\" }\n",
+            |util| {
+                vec![
+                    dependency("util", Some(&util), None, None, &[]),
+                    dependency("util", None, None, Some("registry-util"), &[]),
+                ]
+            },
+            &[("registry_util", "util"), ("util", "member")],
+        );
+        fixture.rewrite();
+
+        let app = fixture.manifest("app/Cargo.toml");
+        assert_eq!(path_of(&app, "dependencies", "util"), Some("../util"));
+        assert_eq!(
+            path_of(&app, "dependencies", "registry-util"),
+            Some("../3rd-party/util-1.0.0")
+        );
+        assert_eq!(app["dependencies"]["registry-util"]["package"].as_str(), Some("util"));
+    }
+
+    #[test]
+    fn crate_patched_with_member_is_not_pointed_into_third_party() {
+        let fixture = Fixture::new(
+            "\n[patch.crates-io]\nutil = { path = \"util\" }\n",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\nutil = \"1.0\"\nwrapper = \"1.0\"\n",
+            |_| {
+                vec![
+                    dependency("util", None, None, None, &[]),
+                    dependency("wrapper", None, None, None, &[]),
+                ]
+            },
+            &[("util", "member"), ("wrapper", "wrapper")],
+        );
+        fixture.rewrite();
+
+        let root = fixture.manifest("Cargo.toml");
+        assert_eq!(root["patch"]["crates-io"]["util"]["path"].as_str(), Some("util"));
+        let app = fixture.manifest("app/Cargo.toml");
+        assert_eq!(app["dependencies"]["util"].as_str(), Some("1.0"));
+        assert_eq!(
+            path_of(&app, "dependencies", "wrapper"),
+            Some("../3rd-party/wrapper-1.0.0")
+        );
+        // The vendored crate depending on it follows the patch to the member as well
+        let wrapper = fixture.manifest("3rd-party/wrapper-1.0.0/Cargo.toml");
+        assert_eq!(wrapper["dependencies"]["util"].as_str(), Some("1.0"));
+    }
+
+    #[test]
+    fn two_versions_of_a_crate_resolve_by_requirement() {
+        // `app` depends on syn 1 and on `derive`, which depends on syn 2
+        let id = |key: &str| format!("{REGISTRY}#{key}");
+        let registry = Path::new("/registry");
+        let mut syn2 = dependency("syn", None, None, None, &[]);
+        syn2["req"] = json!("^2.0");
+        let node = |key: &str, deps: &[&str], features: &[&str]| {
+            json!({
+                "id": id(key),
+                "deps": deps.iter().map(|dep| json!({"name": dep.split('@').next(), "pkg": id(dep), "dep_kinds": []})).collect::<Vec<_>>(),
+                "dependencies": deps.iter().map(|dep| id(dep)).collect::<Vec<_>>(),
+                "features": features,
+            })
+        };
+        let metadata: Metadata = serde_json::from_value(json!({
+            "packages": [
+                package(
+                    &id("app@1.0.0"),
+                    "app",
+                    registry.join("app/Cargo.toml"),
+                    Some(REGISTRY),
+                    vec![dependency("syn", None, None, None, &[]), dependency("derive", None, None, None, &[])],
+                ),
+                package(&id("derive@1.0.0"), "derive", registry.join("derive-1.0.0/Cargo.toml"), Some(REGISTRY), vec![syn2]),
+                package(&id("syn@1.0.109"), "syn", registry.join("syn-1.0.109/Cargo.toml"), Some(REGISTRY), Vec::new()),
+                package(&id("syn@2.0.0"), "syn", registry.join("syn-2.0.0/Cargo.toml"), Some(REGISTRY), Vec::new()),
+            ],
+            "workspace_members": [],
+            "resolve": {
+                "nodes": [
+                    node("app@1.0.0", &["syn@1.0.109", "derive@1.0.0"], &[]),
+                    node("derive@1.0.0", &["syn@2.0.0"], &[]),
+                    node("syn@1.0.109", &[], &["full"]),
+                    node("syn@2.0.0", &[], &["derive"]),
+                ],
+                "root": null,
+            },
+            "workspace_root": "/workspace",
+            "target_directory": "/workspace/target",
+            "version": 1,
+        }))
+        .unwrap();
+        let app = &metadata.packages[0];
+        let derive = &metadata.packages[1];
+
+        let (syn, features) = resolve_dependency(&metadata, app, "syn").unwrap();
+        assert_eq!(
+            (syn.version.to_string(), features),
+            ("1.0.109".to_string(), vec!["full".to_string()])
+        );
+        let (syn, features) = resolve_dependency(&metadata, derive, "syn").unwrap();
+        assert_eq!(
+            (syn.version.to_string(), features),
+            ("2.0.0".to_string(), vec!["derive".to_string()])
+        );
+
+        let find = |requirement: Option<&str>| {
+            let requirement = requirement.map(|requirement| VersionReq::parse(requirement).unwrap());
+            find_package_for_dependency(&metadata, "syn", None, requirement.as_ref(), false)
+                .map(|(package, _)| package.version.to_string())
+        };
+        assert_eq!(find(Some("1")).as_deref(), Some("1.0.109"));
+        assert_eq!(find(Some("2")).as_deref(), Some("2.0.0"));
+        assert_eq!(find(None).as_deref(), Some("2.0.0"));
+        assert_eq!(find(Some("3")), None);
+    }
+
+    #[test]
+    fn rewrite_keeps_default_features_disabled() {
+        let fixture = Fixture::new(
+            "",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\n\
+             wrapper = { version = \"1.0\", default-features = false }\n\n[dev-dependencies]\nwrapper = \"1.0\"\n\n\
+             [build-dependencies.wrapper]\nversion = \"1.0\"\ndefault-features = false\n",
+            |_| {
+                vec![
+                    dependency("wrapper", None, None, None, &[]),
+                    dependency("wrapper", None, Some("dev"), None, &[]),
+                    dependency("wrapper", None, Some("build"), None, &[]),
+                ]
+            },
+            &[("wrapper", "wrapper")],
+        );
+        fixture.rewrite();
+
+        let app = fixture.manifest("app/Cargo.toml");
+        for section in ["dependencies", "build-dependencies"] {
+            let wrapper = &app[section]["wrapper"];
+            assert_eq!(path_of(&app, section, "wrapper"), Some("../3rd-party/wrapper-1.0.0"));
+            assert_eq!(wrapper["default-features"].as_bool(), Some(false));
+            assert!(wrapper.get("features").is_none());
+            assert!(wrapper.get("version").is_none());
+        }
+        // Without opting out, the entry asks for the features the crate resolved with
+        assert_eq!(
+            app["dev-dependencies"]["wrapper"]["features"][0].as_str(),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_optional_dependencies_optional() {
+        let fixture = Fixture::new(
+            "",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[features]\nwrap = [\"dep:wrapper\"]\n\n\
+             [dependencies]\nwrapper = { version = \"1.0\", optional = true }\n\n\
+             [target.'cfg(unix)'.dependencies.wrapper]\nversion = \"1.0\"\noptional = true\n",
+            |_| {
+                vec![
+                    dependency("wrapper", None, None, None, &[]),
+                    dependency("wrapper", None, None, None, &[]),
+                ]
+            },
+            &[("wrapper", "wrapper")],
+        );
+        fixture.rewrite();
+
+        let app = fixture.manifest("app/Cargo.toml");
+        let unix = &app["target"]["cfg(unix)"];
+        for wrapper in [&app["dependencies"]["wrapper"], &unix["dependencies"]["wrapper"]] {
+            assert_eq!(wrapper["path"].as_str(), Some("../3rd-party/wrapper-1.0.0"));
+            assert_eq!(wrapper["optional"].as_bool(), Some(true));
+        }
+        assert_eq!(app["features"]["wrap"][0].as_str(), Some("dep:wrapper"));
+    }
+
+    #[test]
+    fn renamed_dependencies_keep_their_package() {
+        let fixture = Fixture::new(
+            "\n[workspace.dependencies]\nshared = { package = \"wrapper\", version = \"1.0\" }\n",
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\n\n[dependencies]\n\
+             wrap = { package = \"wrapper\", version = \"1.0\" }\n\n\
+             [build-dependencies.wrapped]\npackage = \"wrapper\"\nversion = \"1.0\"\n",
+            |_| {
+                vec![
+                    dependency("wrapper", None, None, Some("wrap"), &[]),
+                    dependency("wrapper", None, Some("build"), Some("wrapped"), &[]),
+                ]
+            },
+            &[("wrap", "wrapper"), ("wrapped", "wrapper")],
+        );
+        fixture.rewrite();
+
+        let app = fixture.manifest("app/Cargo.toml");
+        for (section, key) in [("dependencies", "wrap"), ("build-dependencies", "wrapped")] {
+            assert_eq!(path_of(&app, section, key), Some("../3rd-party/wrapper-1.0.0"));
+            assert_eq!(app[section][key]["package"].as_str(), Some("wrapper"));
+        }
+        // Nothing inherits the workspace entry, it's found by the package it renames
+        let root = fixture.manifest("Cargo.toml");
+        let shared = &root["workspace"]["dependencies"]["shared"];
+        assert_eq!(shared["path"].as_str(), Some("3rd-party/wrapper-1.0.0"));
+        assert_eq!(shared["package"].as_str(), Some("wrapper"));
+    }
+}
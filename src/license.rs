@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+// Matches scoring at least this are taken as is, weaker ones down to REVIEW_SCORE are
+// reported but flagged for a person to check
+const CONFIDENT_SCORE: f32 = 0.9;
+const REVIEW_SCORE: f32 = 0.6;
+
+// Reference texts of the licenses common on crates.io, with their copyright lines removed
+const TEMPLATES: [(&str, &str); 10] = [
+    ("0BSD", include_str!("licenses/0BSD.txt")),
+    ("Apache-2.0", include_str!("licenses/Apache-2.0.txt")),
+    ("BSD-2-Clause", include_str!("licenses/BSD-2-Clause.txt")),
+    ("BSD-3-Clause", include_str!("licenses/BSD-3-Clause.txt")),
+    ("BSL-1.0", include_str!("licenses/BSL-1.0.txt")),
+    ("ISC", include_str!("licenses/ISC.txt")),
+    ("MIT", include_str!("licenses/MIT.txt")),
+    ("MPL-2.0", include_str!("licenses/MPL-2.0.txt")),
+    ("Unlicense", include_str!("licenses/Unlicense.txt")),
+    ("Zlib", include_str!("licenses/Zlib.txt")),
+];
+
+type Bigrams = HashSet<(String, String)>;
+
+/// A license recognized from a license text
+#[derive(Clone, Debug)]
+pub struct Detection {
+    /// SPDX identifier of the license
+    pub license: String,
+    /// How closely the text matched the license, from 0 to 1
+    pub score: f32,
+}
+
+impl Detection {
+    pub fn needs_review(&self) -> bool {
+        self.score < CONFIDENT_SCORE
+    }
+}
+
+/// Recognizes the license of a text, for vendored crates whose manifest declares none. The
+/// built-in classifier compares texts with the common licenses, another one (e.g. backed by
+/// askalono) is handed to a run as the `classifier` of its license policy or notices arguments.
+pub trait Classifier: Send + Sync {
+    fn classify(&self, text: &str) -> Option<Detection>;
+}
+
+// Recognizes the license of `text` with the classifier the run was given, or the built-in one
+pub(crate) fn classify(classifier: Option<&dyn Classifier>, text: &str) -> Option<Detection> {
+    classifier.unwrap_or(&Templates).classify(text)
+}
+
+// Scores texts by the word pairs they share with each reference text (Sørensen-Dice), which
+// shrugs off reflowed lines, punctuation and copyright holders
+struct Templates;
+
+impl Classifier for Templates {
+    fn classify(&self, text: &str) -> Option<Detection> {
+        static BIGRAMS: OnceLock<Vec<(&str, Bigrams)>> = OnceLock::new();
+        let templates = BIGRAMS.get_or_init(|| {
+            TEMPLATES
+                .iter()
+                .map(|(license, template)| (*license, bigrams(template)))
+                .collect()
+        });
+
+        let text = bigrams(text);
+        if text.is_empty() {
+            return None;
+        }
+        templates
+            .iter()
+            .map(|(license, template)| {
+                let shared = template.intersection(&text).count();
+                let score = 2.0 * shared as f32 / (template.len() + text.len()) as f32;
+                (license, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, score)| *score >= REVIEW_SCORE)
+            .map(|(license, score)| Detection {
+                license: license.to_string(),
+                score,
+            })
+    }
+}
+
+fn bigrams(text: &str) -> Bigrams {
+    let words: Vec<String> = text
+        .lines()
+        .filter(|line| !is_copyright_line(line))
+        .flat_map(|line| line.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words
+        .windows(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+// `Copyright (c) 2016 Someone`, but not license clauses mentioning the copyright notice
+fn is_copyright_line(line: &str) -> bool {
+    let line = line.trim_start_matches(|c: char| c.is_whitespace() || c == '/' || c == '#' || c == '*');
+    line.get(..9).is_some_and(|word| word.eq_ignore_ascii_case("copyright"))
+        && line[9..]
+            .trim_start()
+            .starts_with(|c: char| c.is_ascii_digit() || c == '(' || c == '©' || c == '[')
+}
@@ -0,0 +1,449 @@
+use crate::journal::Journal;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use toml_edit::DocumentMut;
+
+pub const LOCK_FILE_NAME: &str = "localize.lock";
+const LOCK_FORMAT_VERSION: u32 = 1;
+
+// State of the last localization: what was vendored, where from and how manifests were rewritten
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LocalizeLock {
+    pub version: u32,
+    pub strategy: Strategy,
+    pub third_party_dir: String,
+    // How crate directories are spread over subdirectories of the 3rd-party folder
+    #[serde(default, skip_serializing_if = "Shard::is_flat")]
+    pub shard: Shard,
+    // Targets the vendored set was restricted to, empty if all platforms were vendored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    // Whether vendored manifests were pruned down to `targets`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub prune_targets: bool,
+    // Package specs selected with `-p` and `--exclude`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    // Dependency depth limit, `None` if the whole closure was localized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    // Dependency kinds left on the registry
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_dev_deps: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_build_deps: bool,
+    // Target triple the dependency graph was resolved for with `--filter-platform`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_platform: Option<String>,
+    // Whether git dependencies left on their repository were pinned to the resolved commit
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pin_git: bool,
+    // Whether rewritten entries keep a `version` requirement next to `path`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub keep_version: bool,
+    // Whether vendored manifests were left without an empty `[workspace]` table
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_workspace_table: bool,
+    // Whether path dependencies outside the project were vendored too
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub external_paths: bool,
+    // Crates were vendored with `--vendor-only`, manifests don't point at them yet
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub manifests_pending: bool,
+    // Root build-dependencies rewritten to `path` because patching didn't apply to them (patch strategy)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub direct_rewrites: Vec<String>,
+    // Hosts crate sources were fetched from, pinned by the first run
+    #[serde(default, rename = "registry-host", skip_serializing_if = "Vec::is_empty")]
+    pub registry_hosts: Vec<RegistryHost>,
+    // `[patch]` entries of the project's own pointed at the crates they resolved to
+    #[serde(default, rename = "patch-entry", skip_serializing_if = "Vec::is_empty")]
+    pub patch_entries: Vec<PatchEntry>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RegistryHost {
+    pub host: String,
+    // SHA-256 of the public key of the root CA the host's certificate chains to, `None` if the
+    // host isn't reached over TLS or couldn't be verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_key: Option<String>,
+}
+
+// A `[patch.<table>]` entry of the root manifest that was pointed at a vendored crate, with what
+// it said before so it can be put back
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PatchEntry {
+    pub table: String,
+    pub key: String,
+    pub original: String,
+    // Directory of the crate it points at, relative to the 3rd-party folder
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Rewrite dependency entries in place to `path` dependencies
+    Rewrite,
+    /// Keep dependency entries, add `[patch]` entries for vendored crates to the root manifest
+    Patch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockfilePolicy {
+    /// Leave the crate's Cargo.lock as published
+    #[default]
+    Keep,
+    /// Remove it
+    Strip,
+    /// Rename it to `Cargo.lock.orig`, out of sight of lockfile scanners
+    Rename,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shard {
+    /// Every crate directly in the 3rd-party folder (`name-version`)
+    #[default]
+    Flat,
+    /// Subdirectories named after the first two hex digits of the crate name's SHA-256 (`3f/name-version`)
+    Hash,
+    /// Subdirectories named after the first two letters of the crate name (`se/serde-1.0.0`)
+    Prefix,
+    /// A directory per crate with one per version in it (`serde/1.0.0`)
+    Nested,
+    /// Crates vendored in a single version are named after the crate alone (`serde`), the others
+    /// `name-version`, like `cargo vendor` does
+    Vendor,
+}
+
+impl Shard {
+    fn is_flat(&self) -> bool {
+        *self == Shard::Flat
+    }
+
+    // Directory of a crate relative to the 3rd-party folder. The vendor layout only leaves the
+    // version out when it knows the crate comes in a single version, see `vendored_path`.
+    pub fn crate_path(self, name: &str, version: &str) -> String {
+        let dir_name = format!("{name}-{version}");
+        match self {
+            Shard::Flat | Shard::Vendor => dir_name,
+            Shard::Nested => format!("{name}/{version}"),
+            Shard::Hash => format!("{}/{dir_name}", &crate::journal::sha256(name.as_bytes())[..2]),
+            Shard::Prefix => format!("{}/{dir_name}", name.to_lowercase().chars().take(2).collect::<String>()),
+        }
+    }
+
+    // Directory of a crate relative to the 3rd-party folder, given whether the graph has a single
+    // version of it and whether another source provides the same name and version. Those get a
+    // short hash of their source appended so neither overwrites the other. Crates stay where an
+    // earlier run vendored them, and a name taken by another version is left to it.
+    pub fn vendored_path(
+        self,
+        package: &cargo_metadata::Package,
+        qualified: bool,
+        single_version: bool,
+        previous_lock: Option<&LocalizeLock>,
+        third_party_path: &Path,
+    ) -> String {
+        let (name, version) = (package.name.as_str(), package.version.to_string());
+        let source = package.source.as_ref().map(|source| source.repr.as_str());
+        let path = match self {
+            Shard::Vendor => {
+                if let Some(previous) = previous_lock.and_then(|lock| lock.find_source(name, &version, source)) {
+                    return previous.path.clone();
+                }
+                if single_version && !third_party_path.join(name).exists() {
+                    name.to_string()
+                } else {
+                    self.crate_path(name, &version)
+                }
+            }
+            _ => self.crate_path(name, &version),
+        };
+        match source {
+            Some(source) if qualified => format!("{path}-{}", source_qualifier(source)),
+            _ => path,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    // Commit a git dependency resolved to, whatever branch or tag the manifest named
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    #[serde(default)]
+    pub features: Vec<String>,
+    // Directory of the crate relative to the 3rd-party folder
+    pub path: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub trimmed: bool,
+    // Globs of files left out when the crate was copied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored: Vec<String>,
+    // Rewritten entries keep the features they declare instead of the resolved ones
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub declared_features: bool,
+    // `[target.'...']` tables removed from the vendored manifest, restorable from its backup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pruned_targets: Vec<String>,
+    // What was done with the Cargo.lock the crate shipped, `None` if it shipped none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nested_lockfile: Option<LockfilePolicy>,
+    // Marked with `cargo-localize freeze`, runs leave the crate as it is unless told `--unfreeze`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub frozen: bool,
+    // Patches from the project's `patches/<name>/` applied to the crate, in order
+    #[serde(default, rename = "patch", skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<AppliedPatch>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppliedPatch {
+    // File name in the crate's patches directory
+    pub file: String,
+    pub sha256: String,
+    // Files of the crate it changed, put back as published before the patches are applied again
+    pub touches: Vec<String>,
+}
+
+impl LockedPackage {
+    pub fn is_from_crates_io(&self) -> bool {
+        self.source.as_deref().is_some_and(is_crates_io)
+    }
+
+    // The qualifier the crate's directory carries when another source provides the same name and
+    // version, see `Shard::vendored_path`
+    pub fn qualifier(&self) -> Option<String> {
+        let qualifier = source_qualifier(self.source.as_deref()?);
+        self.path.ends_with(&format!("-{qualifier}")).then_some(qualifier)
+    }
+}
+
+// Short hash of a source, telling apart crates of the same name and version it and another source provide
+pub fn source_qualifier(source: &str) -> String {
+    crate::journal::sha256(source.as_bytes())[..8].to_string()
+}
+
+pub fn is_crates_io(source: &str) -> bool {
+    source == "registry+https://github.com/rust-lang/crates.io-index" || source == "sparse+https://index.crates.io/"
+}
+
+// Resolved commit of a `git+<url>?branch=...#<commit>` source
+pub fn git_commit(source: &str) -> Option<&str> {
+    source.strip_prefix("git+")?.split_once('#').map(|(_, commit)| commit)
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl LocalizeLock {
+    pub fn new(strategy: Strategy, third_party_dir: &str) -> Self {
+        Self {
+            version: LOCK_FORMAT_VERSION,
+            strategy,
+            third_party_dir: third_party_dir.to_string(),
+            shard: Shard::Flat,
+            targets: Vec::new(),
+            prune_targets: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            depth: None,
+            no_dev_deps: false,
+            no_build_deps: false,
+            filter_platform: None,
+            keep_version: false,
+            no_workspace_table: false,
+            external_paths: false,
+            pin_git: false,
+            manifests_pending: false,
+            direct_rewrites: Vec::new(),
+            registry_hosts: Vec::new(),
+            patch_entries: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+
+    // For commands that need a localized project
+    pub fn require(project_path: &Path) -> Result<Self> {
+        Self::load(project_path)?.ok_or_else(|| {
+            crate::LocalizeError::NotLocalized {
+                project: project_path.to_path_buf(),
+            }
+            .into()
+        })
+    }
+
+    pub fn load(project_path: &Path) -> Result<Option<Self>> {
+        let lock_path = project_path.join(LOCK_FILE_NAME);
+        if !lock_path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&lock_path).context(format!("Failed to read {LOCK_FILE_NAME}"))?;
+        let lock = Self::parse(&content)?;
+        if !crate::safety::is_contained(Path::new(&lock.third_party_dir))
+            && !is_shared_folder(project_path, &lock.third_party_dir)
+        {
+            anyhow::bail!(
+                "{LOCK_FILE_NAME} has third-party-dir `{}`, which isn't the 3rd-party folder of an enclosing project",
+                lock.third_party_dir
+            );
+        }
+        Ok(Some(lock))
+    }
+
+    // Resolved lexically, a nested project's folder is the enclosing project's `../3rd-party`
+    pub fn third_party_path(&self, project_path: &Path) -> PathBuf {
+        crate::util::normalize_path(&project_path.join(&self.third_party_dir))
+    }
+
+    // A localize.lock read from elsewhere, e.g. an earlier git revision
+    pub fn parse(content: &str) -> Result<Self> {
+        let lock: Self = toml::from_str(content).context(format!("Failed to parse {LOCK_FILE_NAME}"))?;
+        // Paths are joined onto the 3rd-party folder, which is removed from and written to
+        if let Some(package) = lock
+            .packages
+            .iter()
+            .find(|p| !crate::safety::is_contained(Path::new(&p.path)))
+        {
+            anyhow::bail!(
+                "{LOCK_FILE_NAME} records {} v{} at `{}`, which is outside the 3rd-party folder",
+                package.name,
+                package.version,
+                package.path
+            );
+        }
+        // Nested projects share the 3rd-party folder of the project around them, `load` checks it's that
+        let shared = Path::new(&lock.third_party_dir)
+            .components()
+            .skip_while(|component| matches!(component, Component::ParentDir))
+            .collect::<PathBuf>();
+        if !crate::safety::is_contained(&shared) {
+            anyhow::bail!(
+                "{LOCK_FILE_NAME} has third-party-dir `{}`, which is outside the project",
+                lock.third_party_dir
+            );
+        }
+        if lock.version > LOCK_FORMAT_VERSION {
+            anyhow::bail!(
+                "{LOCK_FILE_NAME} has format version {}, but this cargo-localize supports up to {LOCK_FORMAT_VERSION}",
+                lock.version
+            );
+        }
+
+        Ok(lock)
+    }
+
+    pub fn save(&mut self, project_path: &Path, journal: &Journal) -> Result<()> {
+        self.packages
+            .sort_by(|a, b| (&a.name, &a.version, &a.path).cmp(&(&b.name, &b.version, &b.path)));
+
+        let content = toml::to_string_pretty(self).context(format!("Failed to serialize {LOCK_FILE_NAME}"))?;
+        let content =
+            format!("# This file is generated by cargo-localize. It is not intended for manual editing.\n{content}");
+        journal
+            .write(&project_path.join(LOCK_FILE_NAME), content)
+            .context(format!("Failed to write {LOCK_FILE_NAME}"))
+    }
+
+    pub fn find(&self, name: &str, version: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name && p.version == version)
+    }
+
+    // Two sources can provide the same name and version, e.g. a fork and crates.io
+    pub fn find_source(&self, name: &str, version: &str, source: Option<&str>) -> Option<&LockedPackage> {
+        self.packages
+            .iter()
+            .find(|p| p.name == name && p.version == version && p.source.as_deref() == source)
+    }
+
+    // The entry of a package cargo resolved: crates resolving into the 3rd-party folder by their
+    // directory, the others by their source
+    pub fn find_package(&self, package: &cargo_metadata::Package, third_party_path: &Path) -> Option<&LockedPackage> {
+        match package
+            .manifest_path
+            .parent()
+            .and_then(|dir| dir.as_std_path().strip_prefix(third_party_path).ok())
+        {
+            Some(dir) => self.packages.iter().find(|p| Path::new(&p.path) == dir),
+            None => self.find_source(
+                &package.name,
+                &package.version.to_string(),
+                package.source.as_ref().map(|source| source.repr.as_str()),
+            ),
+        }
+    }
+}
+
+// Reads `(name, version) -> checksum` pairs from the project's Cargo.lock
+pub fn read_cargo_lock_checksums(project_path: &Path) -> Result<HashMap<(String, String), String>> {
+    let mut checksums = HashMap::new();
+
+    let cargo_lock = project_path.join("Cargo.lock");
+    if !cargo_lock.exists() {
+        return Ok(checksums);
+    }
+
+    let content = fs::read_to_string(&cargo_lock).context("Failed to read Cargo.lock")?;
+    let doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.lock")?;
+
+    if let Some(packages) = doc.get("package").and_then(|p| p.as_array_of_tables()) {
+        for package in packages {
+            let name = package.get("name").and_then(|v| v.as_str());
+            let version = package.get("version").and_then(|v| v.as_str());
+            let checksum = package.get("checksum").and_then(|v| v.as_str());
+            if let (Some(name), Some(version), Some(checksum)) = (name, version, checksum) {
+                checksums.insert((name.to_string(), version.to_string()), checksum.to_string());
+            }
+        }
+    }
+
+    Ok(checksums)
+}
+
+// Whether `third_party_dir` leads up to a project enclosing `project_path` and into the 3rd-party
+// folder its localize.lock records
+fn is_shared_folder(project_path: &Path, third_party_dir: &str) -> bool {
+    let mut enclosing = project_path.to_path_buf();
+    let mut rest = PathBuf::new();
+    for component in Path::new(third_party_dir).components() {
+        match component {
+            Component::ParentDir if rest.as_os_str().is_empty() => {
+                if !enclosing.pop() {
+                    return false;
+                }
+            }
+            Component::Normal(name) => rest.push(name),
+            _ => return false,
+        }
+    }
+    fs::read_to_string(enclosing.join(LOCK_FILE_NAME))
+        .ok()
+        .and_then(|content| LocalizeLock::parse(&content).ok())
+        .is_some_and(|lock| {
+            crate::safety::is_contained(Path::new(&lock.third_party_dir)) && Path::new(&lock.third_party_dir) == rest
+        })
+}
@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+// Overrides the level picked with `-v`/`-q`, e.g. `cargo_localize::registry=trace`
+const FILTER_ENV: &str = "CARGO_LOCALIZE_LOG";
+
+// Set while stdout is reserved for a machine-readable document
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+// Set with `--live`: lines about a crate are printed as they happen, not once the phase ends
+static LIVE: AtomicBool = AtomicBool::new(false);
+// Lines of the crates a phase is done with, keyed by their position in the phase's plan
+static PENDING: Mutex<Vec<(usize, Vec<u8>)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Lines of the crate the thread is working on
+    static BUFFER: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Plain lines, as a person reads them
+    Text,
+    /// One JSON object per event with all of its fields, for automation
+    Json,
+}
+
+// Installs the subscriber printing the run's events to stdout. Leaves one installed by a
+// library consumer alone.
+pub fn init(verbose: u8, quiet: bool, format: LogFormat, live: bool) {
+    LIVE.store(live, Ordering::Relaxed);
+    let level = match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        (false, _) => "trace",
+    };
+    let filter = EnvFilter::try_from_env(FILTER_ENV).unwrap_or_else(|_| EnvFilter::new(level));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| -> Box<dyn Write> {
+            if BUFFER.with_borrow(Option::is_some) {
+                Box::new(ToBuffer)
+            } else if TO_STDERR.load(Ordering::Relaxed) {
+                Box::new(std::io::stderr())
+            } else {
+                Box::new(std::io::stdout())
+            }
+        });
+    let _ = match format {
+        LogFormat::Text => builder.event_format(Plain).try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+}
+
+pub fn set_stderr(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+// Holds the lines printed about one crate until the guard is dropped, so crates handled side
+// by side don't interleave their lines. `position` orders the crate's lines on `flush`.
+pub fn buffer_crate(position: usize) -> CrateOutput {
+    // A crate handled inside another one's scope shares its buffer
+    let buffering = !LIVE.load(Ordering::Relaxed)
+        && BUFFER.with_borrow_mut(|buffer| {
+            let nested = buffer.is_some();
+            buffer.get_or_insert_default();
+            !nested
+        });
+    CrateOutput(buffering.then_some(position))
+}
+
+pub struct CrateOutput(Option<usize>);
+
+impl Drop for CrateOutput {
+    fn drop(&mut self) {
+        let Some(position) = self.0 else {
+            return;
+        };
+        let lines = BUFFER.with_borrow_mut(Option::take).unwrap_or_default();
+        if !lines.is_empty() {
+            PENDING.lock().unwrap().push((position, lines));
+        }
+    }
+}
+
+// Prints the buffered lines of every finished crate, in plan order
+pub fn flush() {
+    let mut pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    pending.sort_by_key(|(position, _)| *position);
+    let lines: Vec<u8> = pending.into_iter().flat_map(|(_, lines)| lines).collect();
+    if lines.is_empty() {
+        return;
+    }
+    let _ = if TO_STDERR.load(Ordering::Relaxed) {
+        std::io::stderr().lock().write_all(&lines)
+    } else {
+        std::io::stdout().lock().write_all(&lines)
+    };
+}
+
+struct ToBuffer;
+
+impl Write for ToBuffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        BUFFER.with_borrow_mut(|buffer| buffer.get_or_insert_default().extend_from_slice(bytes));
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Prints the message alone, warnings prefixed with their class as `warning[class]: `
+struct Plain;
+
+impl<S, N> FormatEvent<S, N> for Plain
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, _ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> std::fmt::Result {
+        let mut fields = PlainFields::default();
+        event.record(&mut fields);
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "error: ")?,
+            Level::WARN => match &fields.class {
+                Some(class) => write!(writer, "warning[{class}]: ")?,
+                None => write!(writer, "warning: ")?,
+            },
+            _ => {}
+        }
+        writeln!(writer, "{}", fields.message)
+    }
+}
+
+#[derive(Default)]
+struct PlainFields {
+    message: String,
+    class: Option<String>,
+}
+
+impl Visit for PlainFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            "class" => self.class = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        }
+    }
+}
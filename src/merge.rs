@@ -0,0 +1,181 @@
+use crate::edits::Edit;
+use crate::journal::Journal;
+use crate::lock::LockedPackage;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{info, warn};
+
+// Manifests are rewritten by every run, edits to them can't be told from the rewriting
+const UNMERGED_FILES: [&str; 2] = ["Cargo.toml", "Cargo.toml.bak"];
+
+// Hashes of the files a merge touched as the new version had them, `None` where it had no such
+// file. They're recorded as the pristine content, so merged edits stay edits.
+pub type Carried = BTreeMap<String, Option<String>>;
+
+pub struct Merged {
+    pub carried: Carried,
+    pub clean: Vec<String>,
+    // Files with conflict markers or where one side had to win, with why
+    pub conflicts: Vec<(String, String)>,
+}
+
+// Carries the local edits of an older vendored version over to the freshly vendored new one: a
+// three-way merge of each edited file between the old pristine source, the local copy and the
+// new version. Conflicts are left with markers in the new crate.
+pub fn merge_crate(
+    old: &LockedPackage,
+    new: &LockedPackage,
+    edits: &[Edit],
+    third_party_path: &Path,
+    journal: &Journal,
+) -> Result<Option<Merged>> {
+    let Some(pristine) = crate::diff::pristine(old)? else {
+        return Ok(None);
+    };
+    let old_dir = third_party_path.join(&old.path);
+    let new_dir = third_party_path.join(&new.path);
+    let mut merged = Merged {
+        carried: Carried::new(),
+        clean: Vec::new(),
+        conflicts: Vec::new(),
+    };
+
+    for file in edits.iter().map(Edit::file) {
+        if UNMERGED_FILES.contains(&file) {
+            merged.conflicts.push((
+                file.to_string(),
+                "manifest edits aren't merged, redo them by hand".to_string(),
+            ));
+            continue;
+        }
+        let base = pristine.get(Path::new(file)).cloned();
+        let local = read(&old_dir.join(file))?;
+        let new_path = new_dir.join(file);
+        let upstream = read(&new_path)?;
+        if local == upstream {
+            continue;
+        }
+        merged
+            .carried
+            .insert(file.to_string(), upstream.as_deref().map(crate::journal::sha256));
+        if base == upstream {
+            // Untouched upstream, the local version stands
+            match &local {
+                Some(local) => write(&new_path, local, journal)?,
+                None => journal
+                    .remove_file(&new_path)
+                    .context(format!("Failed to remove {file}"))?,
+            }
+            merged.clean.push(file.to_string());
+            continue;
+        }
+        match (local, upstream) {
+            (Some(local), Some(_)) if local.contains(&0) || std::str::from_utf8(&local).is_err() => {
+                write(&new_path, &local, journal)?;
+                merged.conflicts.push((
+                    file.to_string(),
+                    "binary file changed on both sides, the local version is kept".to_string(),
+                ));
+            }
+            (Some(local), Some(upstream)) => {
+                let labels = [
+                    "local".to_string(),
+                    format!("{} v{}", old.name, old.version),
+                    format!("{} v{}", new.name, new.version),
+                ];
+                let (content, conflicts) = merge_file(&local, base.as_deref().unwrap_or_default(), &upstream, &labels)?;
+                write(&new_path, &content, journal)?;
+                if conflicts == 0 {
+                    merged.clean.push(file.to_string());
+                } else {
+                    merged
+                        .conflicts
+                        .push((file.to_string(), format!("{conflicts} conflict(s), markers left in")));
+                }
+            }
+            (None, Some(_)) => merged.conflicts.push((
+                file.to_string(),
+                "removed locally but changed upstream, the new version is kept".to_string(),
+            )),
+            (Some(local), None) => {
+                write(&new_path, &local, journal)?;
+                merged.conflicts.push((
+                    file.to_string(),
+                    "changed locally but removed upstream, the local version is kept".to_string(),
+                ));
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(Some(merged))
+}
+
+pub fn report(old: &LockedPackage, new: &LockedPackage, merged: &Merged) {
+    info!(
+        "Merged local edits of {} v{} into v{}: {} clean, {} conflict(s)",
+        new.name,
+        old.version,
+        new.version,
+        merged.clean.len(),
+        merged.conflicts.len()
+    );
+    for file in &merged.clean {
+        info!("  {file}: merged cleanly");
+    }
+    for (file, reason) in &merged.conflicts {
+        warn!("{} v{}: {file}: {reason}", new.name, new.version);
+    }
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    fs::read(path)
+        .map(Some)
+        .context(format!("Failed to read {}", path.display()))
+}
+
+fn write(path: &Path, content: &[u8], journal: &Journal) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    journal
+        .write(path, content)
+        .context(format!("Failed to write {}", path.display()))
+}
+
+// `git merge-file`, which leaves conflict markers and reports how many conflicts there were
+fn merge_file(local: &[u8], base: &[u8], upstream: &[u8], labels: &[String; 3]) -> Result<(Vec<u8>, usize)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let scratch = std::env::temp_dir().join(format!(
+        "cargo-localize-merge-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&scratch).context("Failed to create a scratch directory")?;
+    let paths = [scratch.join("local"), scratch.join("base"), scratch.join("upstream")];
+    for (path, content) in paths.iter().zip([local, base, upstream]) {
+        fs::write(path, content).context("Failed to write a merge input")?;
+    }
+    let output = Command::new("git")
+        .arg("merge-file")
+        .arg("-p")
+        .args(labels.iter().flat_map(|label| ["-L", label.as_str()]))
+        .args(&paths)
+        .output();
+    let _ = fs::remove_dir_all(&scratch);
+    let output = output.context("Failed to run git merge-file")?;
+    // The exit code is the number of conflicts, negative on errors
+    match output.status.code() {
+        Some(conflicts @ 0..=127) => Ok((output.stdout, conflicts as usize)),
+        _ => bail!(
+            "git merge-file failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::observer::{Event, Observer, Phase};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetricsFormat {
+    /// Prometheus text exposition format, for the node_exporter textfile collector
+    Prometheus,
+    /// OTLP/JSON trace with one span per phase, for the collector's otlpjsonfile receiver
+    Otlp,
+}
+
+struct PhaseTiming {
+    phase: Phase,
+    started: SystemTime,
+    finished: Option<SystemTime>,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct Counts {
+    phases: Vec<PhaseTiming>,
+    copied: u64,
+    skipped: u64,
+    failed: u64,
+    bytes_copied: u64,
+    manifests_rewritten: u64,
+}
+
+// Collects run metrics from observer events
+pub struct Metrics {
+    started: SystemTime,
+    counts: Mutex<Counts>,
+}
+
+impl Observer for Metrics {
+    fn on_event(&self, event: &Event<'_>) {
+        let mut counts = self.counts.lock().unwrap();
+        match event {
+            Event::PhaseStarted(phase) => counts.phases.push(PhaseTiming {
+                phase: *phase,
+                started: SystemTime::now(),
+                finished: None,
+                failed: false,
+            }),
+            Event::PhaseFinished(phase) | Event::Failed { phase, .. } => {
+                let failed = matches!(event, Event::Failed { .. });
+                if let Some(timing) = counts.phases.iter_mut().rev().find(|t| t.phase == *phase) {
+                    timing.finished = Some(SystemTime::now());
+                    timing.failed = failed;
+                }
+            }
+            Event::CrateCopied { bytes, .. } => {
+                counts.copied += 1;
+                counts.bytes_copied += bytes;
+            }
+            Event::CrateSkipped { .. } => counts.skipped += 1,
+            Event::CrateFailed { .. } => counts.failed += 1,
+            Event::ManifestRewritten { .. } => counts.manifests_rewritten += 1,
+            Event::CopyPlanned { .. } | Event::RewritePlanned { .. } | Event::CrateStarted { .. } => {}
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: SystemTime::now(),
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    // Written whether the run succeeded or not, failed runs are what dashboards care about most
+    pub fn write(&self, output: &Path, format: MetricsFormat, project: &str, success: bool) -> Result<()> {
+        let finished = SystemTime::now();
+        let content = match format {
+            MetricsFormat::Prometheus => self.prometheus(project, success, finished)?,
+            MetricsFormat::Otlp => {
+                serde_json::to_string(&self.otlp(project, success, finished)).context("Failed to serialize metrics")?
+                    + "\n"
+            }
+        };
+
+        // Scrapers may read the file at any moment, never let them see half of it
+        let file_name = output.file_name().context("Metrics path has no file name")?;
+        let tmp = output.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+        fs::write(&tmp, content).context(format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, output).context(format!("Failed to write {}", output.display()))
+    }
+
+    fn prometheus(&self, project: &str, success: bool, finished: SystemTime) -> Result<String> {
+        let counts = self.counts.lock().unwrap();
+        let project = project.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| -> std::fmt::Result {
+            writeln!(out, "# HELP cargo_localize_{name} {help}")?;
+            writeln!(out, "# TYPE cargo_localize_{name} {kind}")?;
+            for (labels, value) in samples {
+                writeln!(out, "cargo_localize_{name}{{project=\"{project}\"{labels}}} {value}")?;
+            }
+            Ok(())
+        };
+
+        metric(
+            "run_duration_seconds",
+            "gauge",
+            "Duration of the last run",
+            &[("", seconds_between(self.started, finished))],
+        )?;
+        let phases: Vec<(String, f64)> = counts
+            .phases
+            .iter()
+            .map(|t| {
+                (
+                    format!(",phase=\"{}\"", phase_name(t.phase)),
+                    seconds_between(t.started, t.finished.unwrap_or(finished)),
+                )
+            })
+            .collect();
+        let phases: Vec<(&str, f64)> = phases.iter().map(|(labels, value)| (labels.as_str(), *value)).collect();
+        metric(
+            "phase_duration_seconds",
+            "gauge",
+            "Duration of each phase of the last run",
+            &phases,
+        )?;
+        metric(
+            "crates",
+            "gauge",
+            "Crates processed by the last run",
+            &[
+                (",result=\"copied\"", counts.copied as f64),
+                (",result=\"skipped\"", counts.skipped as f64),
+                (",result=\"failed\"", counts.failed as f64),
+            ],
+        )?;
+        metric(
+            "copied_bytes",
+            "gauge",
+            "Bytes copied into the 3rd-party folder by the last run",
+            &[("", counts.bytes_copied as f64)],
+        )?;
+        metric(
+            "manifests_rewritten",
+            "gauge",
+            "Manifests rewritten by the last run",
+            &[("", counts.manifests_rewritten as f64)],
+        )?;
+        metric(
+            "success",
+            "gauge",
+            "Whether the last run finished successfully",
+            &[("", if success { 1.0 } else { 0.0 })],
+        )?;
+        metric(
+            "last_run_timestamp_seconds",
+            "gauge",
+            "Unix time the last run finished",
+            &[("", seconds_between(UNIX_EPOCH, finished).floor())],
+        )?;
+        Ok(out)
+    }
+
+    fn otlp(&self, project: &str, success: bool, finished: SystemTime) -> Value {
+        let counts = self.counts.lock().unwrap();
+        let id =
+            crate::journal::sha256(format!("{project}:{}:{}", unix_nanos(self.started), std::process::id()).as_bytes());
+        let trace_id = &id[..32];
+        let root_span_id = &id[32..48];
+        let status = |ok: bool| json!({"code": if ok { 1 } else { 2 }});
+        let int = |key: &str, value: u64| json!({"key": key, "value": {"intValue": value.to_string()}});
+
+        let mut spans = vec![json!({
+            "traceId": trace_id,
+            "spanId": root_span_id,
+            "name": "cargo-localize",
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.started).to_string(),
+            "endTimeUnixNano": unix_nanos(finished).to_string(),
+            "attributes": [
+                {"key": "cargo_localize.project", "value": {"stringValue": project}},
+                int("cargo_localize.crates.copied", counts.copied),
+                int("cargo_localize.crates.skipped", counts.skipped),
+                int("cargo_localize.crates.failed", counts.failed),
+                int("cargo_localize.copied_bytes", counts.bytes_copied),
+                int("cargo_localize.manifests_rewritten", counts.manifests_rewritten),
+            ],
+            "status": status(success),
+        })];
+        for (i, timing) in counts.phases.iter().enumerate() {
+            spans.push(json!({
+                "traceId": trace_id,
+                "spanId": format!("{:016x}", i + 1),
+                "parentSpanId": root_span_id,
+                "name": phase_name(timing.phase),
+                "kind": 1,
+                "startTimeUnixNano": unix_nanos(timing.started).to_string(),
+                "endTimeUnixNano": unix_nanos(timing.finished.unwrap_or(finished)).to_string(),
+                "status": status(!timing.failed),
+            }));
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{"key": "service.name", "value": {"stringValue": "cargo-localize"}}],
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "cargo-localize", "version": env!("CARGO_PKG_VERSION")},
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Fetch => "fetch",
+        Phase::Metadata => "metadata",
+        Phase::Copy => "copy",
+        Phase::Prune => "prune",
+        Phase::Merge => "merge",
+        Phase::Rewrite => "rewrite",
+        Phase::WriteLock => "write_lock",
+        Phase::Verify => "verify",
+    }
+}
+
+fn seconds_between(from: SystemTime, to: SystemTime) -> f64 {
+    to.duration_since(from).map_or(0.0, |d| d.as_secs_f64())
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+}
@@ -0,0 +1,161 @@
+use crate::journal::Journal;
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock};
+use crate::observer::Observers;
+use crate::warnings::{self, WarningClass};
+use crate::{LocalizeArgs, NetworkArgs};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use tracing::info;
+use walkdir::WalkDir;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NestedPolicy {
+    /// Warn about them, their dependencies stay on the registry
+    #[default]
+    Report,
+    /// Localize them in the same run, into the project's 3rd-party folder
+    Localize,
+    /// Leave them alone without a word
+    Ignore,
+}
+
+// Independent Cargo projects inside the project: manifests that aren't part of its workspace, like
+// fuzz targets, examples with a lockfile of their own or an xtask outside the workspace. A nested
+// workspace is found by its root, whatever is below a nested project belongs to it.
+pub fn find(project_path: &Path, third_party_path: &Path, network: &NetworkArgs) -> Result<Vec<PathBuf>> {
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .no_deps()
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    // Path dependencies inside the workspace directory are members too
+    let members: HashSet<PathBuf> = metadata
+        .packages
+        .iter()
+        .filter_map(|package| package.manifest_path.parent())
+        .map(|dir| dir.as_std_path().to_path_buf())
+        .chain([metadata.workspace_root.as_std_path().to_path_buf()])
+        .collect();
+    let target_directory = metadata.target_directory.as_std_path();
+
+    let mut nested = Vec::new();
+    let mut entries = WalkDir::new(project_path).min_depth(1).sort_by_file_name().into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = entry.context(format!("Failed to read {}", project_path.display()))?;
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let dir = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        // Crates of a `cargo vendor` folder are sources, not projects
+        let vendored = dir.join(crate::vendor::CARGO_CHECKSUM_FILE_NAME).is_file();
+        if hidden || vendored || dir == third_party_path || dir == target_directory {
+            entries.skip_current_dir();
+            continue;
+        }
+        if members.contains(dir) || !is_project(&dir.join("Cargo.toml")) {
+            continue;
+        }
+        nested.push(dir.to_path_buf());
+        entries.skip_current_dir();
+    }
+    Ok(nested)
+}
+
+// Runs after the project itself was localized, reporting or localizing what `find` turns up
+pub fn process(
+    args: &LocalizeArgs,
+    sync: bool,
+    project_path: &Path,
+    observers: &Observers,
+    journal: &Journal,
+) -> Result<()> {
+    let policy = args.nested.unwrap_or_default();
+    if policy == NestedPolicy::Ignore {
+        return Ok(());
+    }
+    let third_party_path = crate::util::normalize_path(&project_path.join(args.project.third_party_dir()));
+    for dir in find(project_path, &third_party_path, &args.network)? {
+        let relative = dir.strip_prefix(project_path).unwrap_or(&dir).display().to_string();
+        let lockfile = if dir.join("Cargo.lock").exists() {
+            " with a Cargo.lock of its own"
+        } else {
+            ""
+        };
+        // Localized on its own, or by an earlier `--nested localize` run
+        if policy == NestedPolicy::Report && dir.join(LOCK_FILE_NAME).exists() {
+            continue;
+        }
+        if policy == NestedPolicy::Report {
+            warnings::warn(
+                WarningClass::Nested,
+                format!(
+                    "{relative} is an independent Cargo project{lockfile}, its dependencies stay on the registry \
+                     (--nested localize vendors them too)"
+                ),
+            );
+            continue;
+        }
+
+        // Only projects localized before have a tree to point their manifests at
+        if args.rewrite_only && LocalizeLock::load(&dir)?.is_none() {
+            continue;
+        }
+        info!("Localizing the nested project {relative}...");
+        let mut nested_args = args.clone();
+        nested_args.project.path = crate::ProjectPath {
+            project_path: dir.clone(),
+            manifest_path: None,
+        };
+        // Crates the project vendored already are reused, not copied twice
+        nested_args.project.third_party_dir = Some(
+            pathdiff::diff_paths(&third_party_path, &dir)
+                .context(format!(
+                    "{} can't be reached from {relative}",
+                    third_party_path.display()
+                ))?
+                .to_string_lossy()
+                .replace('\\', "/"),
+        );
+        nested_args.nested = Some(NestedPolicy::Ignore);
+        let nested_args = crate::config::apply(&nested_args, &dir)?;
+        crate::localize_project(&nested_args, sync, &dir, observers, journal)
+            .context(format!("Failed to localize {relative}"))?;
+    }
+    Ok(())
+}
+
+// Vendored crates of nested projects sharing the project's 3rd-party folder, by path in it
+pub fn shared_crates(project_path: &Path, third_party_path: &Path, network: &NetworkArgs) -> Result<HashSet<PathBuf>> {
+    let mut shared = HashSet::new();
+    for dir in find(project_path, third_party_path, network)? {
+        let Some(localize_lock) = LocalizeLock::load(&dir)? else {
+            continue;
+        };
+        let same_folder = dir
+            .join(&localize_lock.third_party_dir)
+            .canonicalize()
+            .is_ok_and(|path| {
+                third_party_path
+                    .canonicalize()
+                    .is_ok_and(|third_party| path == third_party)
+            });
+        if same_folder {
+            shared.extend(localize_lock.packages.iter().map(|locked| PathBuf::from(&locked.path)));
+        }
+    }
+    Ok(shared)
+}
+
+fn is_project(manifest: &Path) -> bool {
+    fs::read_to_string(manifest)
+        .ok()
+        .and_then(|content| content.parse::<DocumentMut>().ok())
+        .is_some_and(|doc| doc.contains_key("package") || doc.contains_key("workspace"))
+}
@@ -0,0 +1,105 @@
+use cargo_metadata::MetadataCommand;
+use std::process::Command;
+
+// Proxy cargo's own HTTP traffic is sent to when registries are off limits; nothing listens there
+const UNREACHABLE_PROXY: &str = "http://127.0.0.1:0";
+
+// Cargo's network and lockfile flags, forwarded to every cargo command a run starts
+#[derive(clap::Args, Clone, Default)]
+pub struct NetworkArgs {
+    /// Run without accessing the network, like cargo's `--offline`
+    #[clap(long)]
+    pub offline: bool,
+    /// Fail instead of updating Cargo.lock when it's missing or out of date, like cargo's `--locked`
+    #[clap(long)]
+    pub locked: bool,
+    /// Both `--offline` and `--locked`
+    #[clap(long)]
+    pub frozen: bool,
+    /// Which kinds of network access the run may use; the others are switched off for cargo and
+    /// the tool itself, not just left unused
+    #[clap(long, value_enum, value_name = "CLASSES", default_value = "all")]
+    pub network: NetworkPolicy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum NetworkPolicy {
+    /// Registries and git repositories
+    #[default]
+    All,
+    /// Registry indexes and crate downloads, git repositories aren't fetched
+    AllowRegistry,
+    /// Git repositories, registries aren't contacted
+    AllowGit,
+    /// No network access at all, same as `--offline`
+    None,
+}
+
+impl NetworkArgs {
+    pub fn offline(&self) -> bool {
+        self.offline || self.frozen || self.network == NetworkPolicy::None
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked || self.frozen
+    }
+
+    pub fn allows_registry(&self) -> bool {
+        !self.offline() && self.network != NetworkPolicy::AllowGit
+    }
+
+    pub fn allows_git(&self) -> bool {
+        !self.offline() && self.network != NetworkPolicy::AllowRegistry
+    }
+
+    pub fn cargo_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if self.offline() {
+            options.push("--offline".to_string());
+        }
+        if self.locked() {
+            options.push("--locked".to_string());
+        }
+        options
+    }
+
+    // Environment closing off what the policy doesn't allow. Git is fetched with the git CLI,
+    // which only speaks the protocols allowed to it and ignores cargo's HTTP proxy, while cargo
+    // reaches registries through that proxy.
+    fn cargo_env(&self) -> Vec<(&'static str, &'static str)> {
+        if self.offline() {
+            return Vec::new();
+        }
+        let mut env = Vec::new();
+        if !self.allows_git() || !self.allows_registry() {
+            env.push(("CARGO_NET_GIT_FETCH_WITH_CLI", "true"));
+        }
+        if !self.allows_git() {
+            env.push(("GIT_ALLOW_PROTOCOL", "file"));
+        }
+        if !self.allows_registry() {
+            env.push(("CARGO_HTTP_PROXY", UNREACHABLE_PROXY));
+        }
+        env
+    }
+
+    // `cargo <subcommand>` restricted to the allowed network
+    pub fn cargo(&self, subcommand: &str) -> Command {
+        let mut command = Command::new("cargo");
+        command
+            .arg(subcommand)
+            .args(self.cargo_options())
+            .envs(self.cargo_env());
+        command
+    }
+
+    // `cargo metadata` restricted to the allowed network, options other than the network flags
+    // are left to the caller
+    pub fn metadata(&self) -> MetadataCommand {
+        let mut command = MetadataCommand::new();
+        for (key, value) in self.cargo_env() {
+            command.env(key, value);
+        }
+        command
+    }
+}
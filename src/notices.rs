@@ -0,0 +1,610 @@
+use crate::config::{CONFIG_FILE_NAME, Channel};
+use crate::export::{InventoryEntry, inventory_entry};
+use crate::license::Detection;
+use crate::lock::LocalizeLock;
+use crate::obligations::{Form, Obligation};
+use anyhow::{Context, Result, bail};
+use cargo_metadata::{DependencyKind, PackageId};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const LICENSE_FILE_PREFIXES: [&str; 6] = ["LICENSE", "LICENCE", "COPYING", "NOTICE", "UNLICENSE", "COPYRIGHT"];
+// Words after `Copyright` that make a line license boilerplate rather than a statement
+const NOT_STATEMENTS: [&str; 14] = [
+    "notice",
+    "notices",
+    "license",
+    "licenses",
+    "owner",
+    "owners",
+    "holder",
+    "holders",
+    "law",
+    "laws",
+    "and",
+    "or",
+    "statement",
+    "statements",
+];
+// What license templates have where the statement goes, `<name>` alone would be an e-mail address
+const TEMPLATE_PLACEHOLDERS: [&str; 8] = [
+    "yyyy",
+    "<year>",
+    "[year]",
+    "{year}",
+    "[name",
+    "<name",
+    "{name",
+    "<copyright",
+];
+// Lines a source file may start with before its code, where copyright headers live
+const SOURCE_HEADER_LINES: usize = 50;
+
+#[derive(clap::Args)]
+pub struct NoticesArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// File to write, relative to the project; channels get `<FILE>.<channel>`
+    #[clap(short, long, value_name = "FILE", default_value = "NOTICES")]
+    output: PathBuf,
+    /// Write the NOTICES and obligations of this distribution channel (from `[channels.<name>]`
+    /// in .cargo-localize.toml) only, repeat for several channels
+    #[clap(long = "channel", value_name = "NAME")]
+    channels: Vec<String>,
+    /// Write the NOTICES and obligations of every configured channel
+    #[clap(long, conflicts_with = "channels")]
+    all_channels: bool,
+    /// Write a CSV matrix of each crate's license and obligations per channel to this file
+    #[clap(long, value_name = "FILE")]
+    matrix: Option<PathBuf>,
+    // Recognizes the license of crates whose manifest declares none, instead of the built-in one
+    #[clap(skip)]
+    pub classifier: Option<Arc<dyn crate::Classifier>>,
+}
+
+// One distinct license text
+struct LicenseText {
+    file_name: String,
+    text: String,
+}
+
+// License texts and detected licenses of every vendored crate
+struct Scan {
+    texts: BTreeMap<String, LicenseText>,
+    crate_texts: Vec<Vec<String>>,
+    // Licenses recognized from the texts of crates whose manifest declares none
+    crate_detections: Vec<Vec<Detection>>,
+    // Copyright statements of the license texts and source headers of each crate
+    crate_copyrights: Vec<Vec<String>>,
+}
+
+pub fn notices(args: &NoticesArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+
+    let entries = localize_lock
+        .packages
+        .iter()
+        .map(|locked| inventory_entry(&localize_lock, locked, &project_path))
+        .collect::<Result<Vec<_>>>()?;
+    let scan = scan(&project_path, &entries, args.classifier.as_deref())?;
+
+    if args.channels.is_empty() && !args.all_channels && args.matrix.is_none() {
+        let everything: Vec<usize> = (0..entries.len()).collect();
+        let output = project_path.join(&args.output);
+        return write_notices(&output, &localize_lock, &entries, &scan, &everything, None);
+    }
+
+    let channels = crate::config::channels(&project_path)?;
+    if channels.is_empty() {
+        bail!("No distribution channels configured, add `[channels.<name>]` tables to {CONFIG_FILE_NAME}");
+    }
+    for name in &args.channels {
+        if !channels.contains_key(name) {
+            bail!(
+                "No channel `{name}` in {CONFIG_FILE_NAME}, configured: {}",
+                channels.keys().cloned().collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+    let shipped = shipped_crates(&project_path, &args.network, &entries, &channels)?;
+
+    let selected: Vec<&String> = if args.all_channels {
+        channels.keys().collect()
+    } else {
+        args.channels.iter().collect()
+    };
+    for name in selected {
+        let mut output: OsString = project_path.join(&args.output).into();
+        output.push(format!(".{name}"));
+        let channel = (name.as_str(), &channels[name]);
+        write_notices(
+            Path::new(&output),
+            &localize_lock,
+            &entries,
+            &scan,
+            &shipped[name],
+            Some(channel),
+        )?;
+    }
+
+    if let Some(matrix) = &args.matrix {
+        let output = project_path.join(matrix);
+        write_matrix(&output, &entries, &scan, &channels, &shipped)?;
+    }
+    Ok(())
+}
+
+fn scan(project_path: &Path, entries: &[InventoryEntry], classifier: Option<&dyn crate::Classifier>) -> Result<Scan> {
+    // Texts are keyed by the hash of their normalized content, so copies differing
+    // only in line endings or trailing whitespace collapse into one
+    let mut texts: BTreeMap<String, LicenseText> = BTreeMap::new();
+    let mut crate_texts: Vec<Vec<String>> = Vec::new();
+    let mut crate_detections: Vec<Vec<Detection>> = Vec::new();
+    let mut crate_copyrights: Vec<Vec<String>> = Vec::new();
+    for entry in entries {
+        let mut hashes = Vec::new();
+        let mut detections: Vec<Detection> = Vec::new();
+        let mut copyrights = Vec::new();
+        for file in license_files(project_path, entry)? {
+            let content = fs::read(&file).context(format!("Failed to read {}", file.display()))?;
+            let text = normalize_text(&String::from_utf8_lossy(&content));
+            add_copyrights(&mut copyrights, text.lines());
+            let hash = crate::journal::sha256(text.as_bytes());
+            if entry.license.is_none()
+                && let Some(detection) = crate::license::classify(classifier, &text)
+                && !detections.iter().any(|known| known.license == detection.license)
+            {
+                detections.push(detection);
+            }
+
+            texts.entry(hash.clone()).or_insert_with(|| LicenseText {
+                file_name: file
+                    .file_name()
+                    .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+                text,
+            });
+            hashes.push(hash);
+        }
+        for file in source_files(&project_path.join(&entry.path)) {
+            let content = fs::read(&file).context(format!("Failed to read {}", file.display()))?;
+            let header = String::from_utf8_lossy(&content)
+                .lines()
+                .take(SOURCE_HEADER_LINES)
+                .map(str::trim)
+                .take_while(|line| {
+                    line.is_empty() || line.starts_with("//") || line.starts_with("/*") || line.starts_with('*')
+                })
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            add_copyrights(&mut copyrights, header.iter().map(String::as_str));
+        }
+        crate_texts.push(hashes);
+        crate_detections.push(detections);
+        crate_copyrights.push(copyrights);
+    }
+    Ok(Scan {
+        texts,
+        crate_texts,
+        crate_detections,
+        crate_copyrights,
+    })
+}
+
+// Writes the NOTICES of the crates at `shipped` (indices into `entries`), with the obligations
+// their licenses come with when written for a channel
+fn write_notices(
+    output: &Path,
+    localize_lock: &LocalizeLock,
+    entries: &[InventoryEntry],
+    scan: &Scan,
+    shipped: &[usize],
+    channel: Option<(&str, &Channel)>,
+) -> Result<()> {
+    let mut text_crates: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+    for &i in shipped {
+        for hash in &scan.crate_texts[i] {
+            text_crates.entry(hash).or_default().push(crate_label(&entries[i]));
+        }
+    }
+
+    // Most shared texts first, they're the ones reviewers recognize at a glance
+    let mut ordered: Vec<(&String, &Vec<String>)> = text_crates.iter().map(|(hash, crates)| (*hash, crates)).collect();
+    ordered.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+    let index: BTreeMap<&String, usize> = ordered
+        .iter()
+        .enumerate()
+        .map(|(i, (hash, _))| (*hash, i + 1))
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "THIRD-PARTY NOTICES")?;
+    writeln!(out)?;
+    match channel {
+        None => writeln!(
+            out,
+            "This project vendors {} crate(s) into {}/. Each crate is listed with its copyright statements,\n\
+             each distinct license text is included once below,",
+            entries.len(),
+            localize_lock.third_party_dir
+        )?,
+        Some((name, channel)) => writeln!(
+            out,
+            "The {name} channel ships {} of the {} crate(s) vendored into {}/, in {} form.\n\
+             Each crate is listed with its copyright statements, each distinct license text is included\n\
+             once below,",
+            shipped.len(),
+            entries.len(),
+            localize_lock.third_party_dir,
+            form_label(channel.form)
+        )?,
+    }
+    writeln!(out, "the [n] references show which texts apply to which crate.")?;
+    writeln!(out)?;
+    writeln!(out, "CRATES")?;
+    writeln!(out)?;
+    for &i in shipped {
+        let (entry, hashes, detections) = (&entries[i], &scan.crate_texts[i], &scan.crate_detections[i]);
+        let license = match &entry.license {
+            Some(license) => license.clone(),
+            None if detections.is_empty() => "license not declared".to_string(),
+            None => detected_label(detections),
+        };
+        let references = if hashes.is_empty() {
+            "no license text found".to_string()
+        } else {
+            let mut numbers: Vec<usize> = hashes.iter().map(|hash| index[hash]).collect();
+            numbers.sort();
+            numbers.iter().map(|n| format!("[{n}]")).collect::<Vec<_>>().join(" ")
+        };
+        writeln!(out, "  {} ({license}): {references}", crate_label(entry))?;
+        // Without a statement, the manifest's authors are the closest thing to a copyright holder
+        match scan.crate_copyrights[i].as_slice() {
+            [] if !entry.authors.is_empty() => writeln!(out, "    Authors: {}", entry.authors.join(", "))?,
+            copyrights => {
+                for copyright in copyrights {
+                    writeln!(out, "    {copyright}")?;
+                }
+            }
+        }
+    }
+
+    if let Some((_, channel)) = channel {
+        let mut by_obligation: BTreeMap<Obligation, Vec<String>> = BTreeMap::new();
+        for &i in shipped {
+            for obligation in crate_obligations(&entries[i], &scan.crate_detections[i], channel.form) {
+                by_obligation
+                    .entry(obligation)
+                    .or_default()
+                    .push(crate_label(&entries[i]));
+            }
+        }
+        writeln!(out)?;
+        writeln!(out, "OBLIGATIONS")?;
+        writeln!(out)?;
+        if by_obligation.is_empty() {
+            writeln!(
+                out,
+                "  None of the licenses ask anything of {} distributions.",
+                form_label(channel.form)
+            )?;
+        }
+        for (obligation, crates) in &by_obligation {
+            writeln!(out, "  {}: {}", obligation.description(), crates.join(", "))?;
+        }
+    }
+
+    for (hash, crates) in &ordered {
+        let license_text = &scan.texts[*hash];
+        writeln!(out)?;
+        writeln!(out, "{}", "=".repeat(80))?;
+        writeln!(
+            out,
+            "[{}] {} (sha256 {})",
+            index[hash],
+            license_text.file_name,
+            &hash[..16]
+        )?;
+        writeln!(out, "Used by: {}", crates.join(", "))?;
+        writeln!(out, "{}", "=".repeat(80))?;
+        writeln!(out)?;
+        writeln!(out, "{}", license_text.text)?;
+    }
+
+    fs::write(output, out).context(format!("Failed to write {}", output.display()))?;
+    let copies: usize = text_crates.values().map(Vec::len).sum();
+    println!(
+        "Wrote {}: {} crate(s), {} distinct license text(s) instead of {copies}",
+        output.display(),
+        shipped.len(),
+        text_crates.len()
+    );
+    let detected = shipped
+        .iter()
+        .filter(|&&i| !scan.crate_detections[i].is_empty())
+        .count();
+    let review = shipped
+        .iter()
+        .filter(|&&i| scan.crate_detections[i].iter().any(Detection::needs_review))
+        .count();
+    if detected > 0 {
+        println!(
+            "{detected} crate(s) declare no license, it was detected from their license texts ({review} marked for review)"
+        );
+    }
+    let unattributed = shipped
+        .iter()
+        .filter(|&&i| scan.crate_copyrights[i].is_empty() && entries[i].authors.is_empty())
+        .count();
+    if unattributed > 0 {
+        println!("{unattributed} crate(s) have neither a copyright statement nor authors, attribute them by hand");
+    }
+    Ok(())
+}
+
+// `crate,version,license,<channel>...`, each channel cell listing the obligations the crate
+// brings into it or `-` where it doesn't ship
+fn write_matrix(
+    output: &Path,
+    entries: &[InventoryEntry],
+    scan: &Scan,
+    channels: &BTreeMap<String, Channel>,
+    shipped: &BTreeMap<String, Vec<usize>>,
+) -> Result<()> {
+    let mut out = String::from("crate,version,license");
+    for name in channels.keys() {
+        out.push(',');
+        out.push_str(&csv_field(name));
+    }
+    out.push('\n');
+    for (i, entry) in entries.iter().enumerate() {
+        let detections = &scan.crate_detections[i];
+        let license = match &entry.license {
+            Some(license) => license.clone(),
+            None => detections
+                .iter()
+                .map(|d| d.license.as_str())
+                .collect::<Vec<_>>()
+                .join(" AND "),
+        };
+        write!(
+            out,
+            "{},{},{}",
+            csv_field(&entry.locked.name),
+            csv_field(&entry.locked.version),
+            csv_field(&license)
+        )?;
+        for (name, channel) in channels {
+            let cell = if !shipped[name].contains(&i) {
+                "-".to_string()
+            } else {
+                let obligations = crate_obligations(entry, detections, channel.form);
+                if obligations.is_empty() {
+                    "none".to_string()
+                } else {
+                    obligations.iter().map(|o| o.code()).collect::<Vec<_>>().join(" ")
+                }
+            };
+            write!(out, ",{}", csv_field(&cell))?;
+        }
+        out.push('\n');
+    }
+    fs::write(output, out).context(format!("Failed to write {}", output.display()))?;
+    println!(
+        "Wrote {}: {} crate(s) across {} channel(s)",
+        output.display(),
+        entries.len(),
+        channels.len()
+    );
+    Ok(())
+}
+
+// Obligations of the declared license, or of every license detected from the texts since they
+// can't tell whether those are alternatives
+fn crate_obligations(entry: &InventoryEntry, detections: &[Detection], form: Form) -> BTreeSet<Obligation> {
+    let expression = match &entry.license {
+        Some(license) => license.clone(),
+        None if detections.is_empty() => return BTreeSet::from([Obligation::Review]),
+        None => detections
+            .iter()
+            .map(|d| d.license.as_str())
+            .collect::<Vec<_>>()
+            .join(" AND "),
+    };
+    let mut obligations = crate::obligations::of_expression(&expression, form);
+    if detections.iter().any(Detection::needs_review) {
+        obligations.insert(Obligation::Review);
+    }
+    obligations
+}
+
+// Vendored crates (indices into `entries`) each channel ships: what its members depend on
+// through normal dependencies, and build dependencies too when it ships source. Binaries leave
+// proc macros and what only they use behind, they run on the build machine.
+fn shipped_crates(
+    project_path: &Path,
+    network: &crate::NetworkArgs,
+    entries: &[InventoryEntry],
+    channels: &BTreeMap<String, Channel>,
+) -> Result<BTreeMap<String, Vec<usize>>> {
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let resolve = metadata.resolve.as_ref().context("No resolve data in metadata")?;
+    let packages: BTreeMap<&PackageId, &cargo_metadata::Package> =
+        metadata.packages.iter().map(|package| (&package.id, package)).collect();
+    let nodes: BTreeMap<&PackageId, &cargo_metadata::Node> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+
+    let mut shipped = BTreeMap::new();
+    for (name, channel) in channels {
+        let mut queue: Vec<&PackageId> = Vec::new();
+        for member in &channel.members {
+            let package = metadata
+                .workspace_packages()
+                .into_iter()
+                .find(|package| &package.name == member)
+                .context(format!("Channel `{name}`: `{member}` isn't a workspace member"))?;
+            queue.push(&package.id);
+        }
+
+        let mut reached: BTreeSet<&PackageId> = queue.iter().copied().collect();
+        while let Some(id) = queue.pop() {
+            let Some(node) = nodes.get(id) else {
+                continue;
+            };
+            for dep in &node.deps {
+                let follows = dep.dep_kinds.iter().any(|info| match info.kind {
+                    DependencyKind::Normal => true,
+                    DependencyKind::Build => channel.form == Form::Source,
+                    _ => false,
+                });
+                let proc_macro = packages[&dep.pkg]
+                    .targets
+                    .iter()
+                    .any(|target| target.kind.iter().any(|kind| kind == "proc-macro"));
+                if follows && !(proc_macro && channel.form == Form::Binary) && reached.insert(&dep.pkg) {
+                    queue.push(&dep.pkg);
+                }
+            }
+        }
+
+        let indices = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                reached.iter().any(|id| {
+                    packages[id].name == entry.locked.name && packages[id].version.to_string() == entry.locked.version
+                })
+            })
+            .map(|(i, _)| i)
+            .collect();
+        shipped.insert(name.clone(), indices);
+    }
+    Ok(shipped)
+}
+
+fn form_label(form: Form) -> &'static str {
+    match form {
+        Form::Binary => "binary",
+        Form::Source => "source",
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// `detected: MIT, Apache-2.0`, the texts alone don't tell whether they're alternatives
+fn detected_label(detections: &[Detection]) -> String {
+    let licenses: Vec<String> = detections
+        .iter()
+        .map(|detection| {
+            if detection.needs_review() {
+                format!("{} (low confidence, review)", detection.license)
+            } else {
+                detection.license.clone()
+            }
+        })
+        .collect();
+    format!("detected: {}", licenses.join(", "))
+}
+
+fn crate_label(entry: &InventoryEntry) -> String {
+    format!("{} {}", entry.locked.name, entry.locked.version)
+}
+
+// License texts shipped at the crate root, plus the manifest's `license-file` wherever it is
+fn license_files(project_path: &Path, entry: &InventoryEntry) -> Result<Vec<PathBuf>> {
+    let crate_dir = project_path.join(&entry.path);
+    let mut files = Vec::new();
+
+    for dir_entry in fs::read_dir(&crate_dir).context(format!("Failed to read {}", crate_dir.display()))? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name().to_string_lossy().to_uppercase();
+        if dir_entry.file_type()?.is_file() && LICENSE_FILE_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+            files.push(dir_entry.path());
+        }
+    }
+    if let Some(license_file) = &entry.license_file {
+        let path = crate::util::normalize_path(&project_path.join(license_file));
+        if path.is_file() && !files.contains(&path) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+// Rust sources of the crate's library and binaries, whose headers may carry copyright statements
+fn source_files(crate_dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(crate_dir.join("src"))
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .map(walkdir::DirEntry::into_path)
+        .collect();
+    files.sort();
+    files
+}
+
+// Adds the copyright statements among `lines` not seen yet, with comment markers and
+// placeholders of license templates (`Copyright [yyyy] [name of copyright owner]`) left out
+fn add_copyrights<'a>(copyrights: &mut Vec<String>, lines: impl Iterator<Item = &'a str>) {
+    for line in lines {
+        let line = line
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '/' | '*' | '#' | '!' | '-'))
+            .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '/' | '*'));
+        let lower = line.to_lowercase();
+        let rest = if let Some(rest) = lower.strip_prefix("copyright") {
+            rest
+        } else if (lower.starts_with("(c)") || lower.starts_with('©')) && lower.contains(|c: char| c.is_ascii_digit())
+        {
+            // With a year, `(c)` alone also numbers list items
+            &lower
+        } else {
+            continue;
+        };
+        let next_word = rest
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|word| !word.is_empty())
+            .unwrap_or_default();
+        if next_word.is_empty()
+            || NOT_STATEMENTS.contains(&next_word)
+            || line.len() > 200
+            || TEMPLATE_PLACEHOLDERS
+                .iter()
+                .any(|placeholder| lower.contains(placeholder))
+        {
+            continue;
+        }
+        let statement = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !copyrights.iter().any(|known| known.eq_ignore_ascii_case(&statement)) {
+            copyrights.push(statement);
+        }
+    }
+}
+
+fn normalize_text(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end()).collect();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(start, |end| end + 1);
+    lines[start..end].join("\n")
+}
@@ -0,0 +1,103 @@
+use crate::spdx::Expression;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+// How a distribution channel hands the crates to its recipients
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Form {
+    // Compiled binaries, only what's linked in ships
+    #[default]
+    Binary,
+    // Source code, along with everything needed to build it
+    Source,
+}
+
+// What a license asks of whoever distributes the crate, from the lightest to the heaviest
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Obligation {
+    LicenseText,
+    NoticeFile,
+    MarkChanges,
+    CrateSource,
+    Relinking,
+    FullSource,
+    NetworkSource,
+    // Unknown or missing license, someone has to read the terms
+    Review,
+}
+
+impl Obligation {
+    pub fn code(self) -> &'static str {
+        match self {
+            Obligation::LicenseText => "license-text",
+            Obligation::NoticeFile => "notice-file",
+            Obligation::MarkChanges => "mark-changes",
+            Obligation::CrateSource => "crate-source",
+            Obligation::Relinking => "relinking",
+            Obligation::FullSource => "full-source",
+            Obligation::NetworkSource => "network-source",
+            Obligation::Review => "review",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Obligation::LicenseText => "Include the license text and copyright notices",
+            Obligation::NoticeFile => "Pass on the crate's NOTICE file, if it ships one",
+            Obligation::MarkChanges => "Mark files you changed as modified",
+            Obligation::CrateSource => "Make the crate's source available, with your changes",
+            Obligation::Relinking => "Let recipients replace the crate with a modified version (relinking)",
+            Obligation::FullSource => "Make the complete source of the product available under the same license",
+            Obligation::NetworkSource => "Offer the source to users interacting with the product over a network",
+            Obligation::Review => "License unknown or not declared, review its terms",
+        }
+    }
+}
+
+// Obligations of an SPDX license expression: all of them for `AND`, the lighter side for `OR`
+// since the distributor picks which license to follow. Expressions that don't parse need review.
+pub fn of_expression(expression: &str, form: Form) -> BTreeSet<Obligation> {
+    match Expression::parse(expression) {
+        Some(expression) => of(&expression, form),
+        None => BTreeSet::from([Obligation::Review]),
+    }
+}
+
+fn of(expression: &Expression, form: Form) -> BTreeSet<Obligation> {
+    match expression {
+        // Exceptions only loosen a license, its own obligations are an upper bound
+        Expression::License { id, .. } => of_license(id, form),
+        Expression::And(all) => all.iter().flat_map(|expression| of(expression, form)).collect(),
+        Expression::Or(alternatives) => alternatives
+            .iter()
+            .map(|expression| of(expression, form))
+            .min_by_key(weight)
+            .unwrap_or_default(),
+    }
+}
+
+// The heaviest obligation, then how many there are
+fn weight(obligations: &BTreeSet<Obligation>) -> (Option<Obligation>, usize) {
+    (obligations.last().copied(), obligations.len())
+}
+
+fn of_license(license: &str, form: Form) -> BTreeSet<Obligation> {
+    use Obligation::*;
+    let license = license.trim_end_matches('+');
+    let obligations: &[Obligation] = match license {
+        "0BSD" | "Unlicense" | "CC0-1.0" | "MIT-0" | "WTFPL" => &[],
+        "MIT" | "ISC" | "BSD-2-Clause" | "BSD-3-Clause" | "Unicode-DFS-2016" | "Unicode-3.0" | "NCSA" => &[LicenseText],
+        // Both only bind copies of the source
+        "BSL-1.0" | "Zlib" if form == Form::Binary => &[],
+        "BSL-1.0" => &[LicenseText],
+        "Zlib" => &[LicenseText, MarkChanges],
+        "Apache-2.0" => &[LicenseText, NoticeFile, MarkChanges],
+        "MPL-2.0" => &[LicenseText, CrateSource],
+        _ if license.starts_with("LGPL-") => &[LicenseText, CrateSource, Relinking],
+        _ if license.starts_with("GPL-") => &[LicenseText, FullSource],
+        _ if license.starts_with("AGPL-") => &[LicenseText, FullSource, NetworkSource],
+        _ => &[Review],
+    };
+    obligations.iter().copied().collect()
+}
@@ -0,0 +1,152 @@
+use crate::LocalizeError;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Receives typed events as a localization run progresses, e.g. to drive a custom UI or
+/// collect metrics. Events arrive in order on the thread running the localization.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event<'_>);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// `cargo fetch` downloads missing crates
+    Fetch,
+    /// The dependency graph is resolved with `cargo metadata`
+    Metadata,
+    /// Crate sources are copied into the 3rd-party folder
+    Copy,
+    /// Target tables of other platforms are removed from vendored manifests
+    Prune,
+    /// Local edits of upgraded crates are merged into their new version
+    Merge,
+    /// Manifests are pointed at the vendored crates
+    Rewrite,
+    /// `localize.lock` is written
+    WriteLock,
+    /// The localized project is checked with cargo, with `--verify`
+    Verify,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Fetch => "run cargo fetch",
+            Phase::Metadata => "resolve the dependency graph",
+            Phase::Copy => "copy crate sources",
+            Phase::Prune => "prune target tables",
+            Phase::Merge => "merge local edits",
+            Phase::Rewrite => "rewrite manifests",
+            Phase::WriteLock => "write localize.lock",
+            Phase::Verify => "verify the localized project",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Vendored by an earlier run, its manifest already points into the 3rd-party folder
+    AlreadyLocalized,
+    /// The crate's directory already exists in the 3rd-party folder
+    AlreadyVendored,
+    /// Only needed by filtered out targets or dependency kinds
+    NotNeeded,
+    WorkspaceMember,
+    /// A path dependency outside the project, vendored with `--external-paths` only
+    ExternalPath,
+    /// A workspace package has the same name and version
+    SharesMemberVersion,
+    /// Left out by `-p` / `--exclude`
+    NotSelected,
+    /// Deeper than `--depth`
+    TooDeep,
+}
+
+#[derive(Debug)]
+pub enum Event<'a> {
+    PhaseStarted(Phase),
+    PhaseFinished(Phase),
+    /// The copy phase is about to copy `crates` crates, about `bytes` in total
+    CopyPlanned {
+        crates: usize,
+        bytes: u64,
+    },
+    CrateStarted {
+        name: &'a str,
+        version: &'a str,
+    },
+    CrateCopied {
+        name: &'a str,
+        version: &'a str,
+        /// Where the crate was copied from, its registry source or git checkout
+        source: &'a Path,
+        path: &'a Path,
+        /// Size of the crate's files once copied and stripped
+        bytes: u64,
+    },
+    CrateSkipped {
+        name: &'a str,
+        version: &'a str,
+        reason: SkipReason,
+    },
+    CrateFailed {
+        name: &'a str,
+        version: &'a str,
+        error: &'a LocalizeError,
+    },
+    /// The rewrite phase is about to rewrite `manifests` manifests
+    RewritePlanned {
+        manifests: usize,
+    },
+    ManifestRewritten {
+        path: &'a Path,
+    },
+    /// The run stopped with an error during `phase`
+    Failed {
+        phase: Phase,
+        error: &'a LocalizeError,
+    },
+}
+
+// Observers of a run, handed to it by a library consumer or added for `--metrics`, progress bars
+// and the JSON report. They travel with the run, so runs side by side only see their own events.
+#[derive(Default)]
+pub struct Observers(Vec<Arc<dyn Observer>>);
+
+impl Observers {
+    pub fn add(&mut self, observer: Arc<dyn Observer>) {
+        self.0.push(observer);
+    }
+
+    pub fn emit(&self, event: Event<'_>) {
+        for observer in &self.0 {
+            observer.on_event(&event);
+        }
+    }
+
+    // Runs `f` as `phase`, reporting its start and how it ended, and prints what its crates buffered
+    pub fn phase<T>(&self, phase: Phase, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        self.emit(Event::PhaseStarted(phase));
+        let result = f();
+        crate::logging::flush();
+        match result {
+            Ok(value) => {
+                self.emit(Event::PhaseFinished(phase));
+                Ok(value)
+            }
+            Err(error) => {
+                // Errors of a kind of their own pass through, the rest are pinned to the phase
+                let error = match error.downcast::<LocalizeError>() {
+                    Ok(error) => error,
+                    Err(error) => LocalizeError::Phase {
+                        phase,
+                        source: error.into(),
+                    },
+                };
+                self.emit(Event::Failed { phase, error: &error });
+                Err(error.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::lock::{LocalizeLock, LockedPackage};
+use anyhow::{Context, Result, bail};
+use cargo_metadata::semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use tracing::{debug, info, warn};
+
+const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+// Index files fetched at once
+const WORKERS: usize = 8;
+
+#[derive(clap::Args)]
+pub struct OutdatedArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Sparse index to ask about crates.io crates, e.g. a mirror
+    #[clap(long, value_name = "URL", default_value = CRATES_IO_INDEX)]
+    index_url: String,
+    /// List every vendored crate, not only the outdated ones
+    #[clap(long)]
+    all: bool,
+}
+
+// What the index knows about newer releases of one vendored crate
+struct Newer {
+    // Newest release a caret requirement on the vendored version accepts
+    compatible: Option<Version>,
+    latest: Option<Version>,
+    yanked: bool,
+}
+
+// Asks the registries' sparse indexes for releases newer than the vendored versions. Once every
+// dependency is a path dependency `cargo outdated` has nothing left to compare, the lock still
+// knows where each crate came from.
+pub fn outdated(args: &OutdatedArgs) -> Result<()> {
+    if !args.network.allows_registry() {
+        bail!("Finding newer versions needs the registries' indexes, which the network policy rules out");
+    }
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+
+    let mut by_index: BTreeMap<String, Vec<&LockedPackage>> = BTreeMap::new();
+    for locked in &localize_lock.packages {
+        match index_of(locked, &args.index_url) {
+            Some(index) => by_index.entry(index).or_default().push(locked),
+            None => debug!(
+                "{} v{} isn't from a sparse registry, skipped",
+                locked.name, locked.version
+            ),
+        }
+    }
+    let queries: Vec<(&str, &LockedPackage)> = by_index
+        .iter()
+        .flat_map(|(index, packages)| packages.iter().map(move |locked| (index.as_str(), *locked)))
+        .collect();
+    info!("Checking {} crate(s) against their registry index...", queries.len());
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(BTreeMap::new());
+    thread::scope(|scope| {
+        for _ in 0..WORKERS.min(queries.len()) {
+            scope.spawn(|| {
+                while let Some((index, locked)) = queries.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = newer(index, locked);
+                    results
+                        .lock()
+                        .unwrap()
+                        .insert((locked.name.clone(), locked.version.clone()), result);
+                }
+            });
+        }
+    });
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for ((name, version), result) in results.into_inner().unwrap() {
+        let newer = match result {
+            Ok(newer) => newer,
+            Err(error) => {
+                warn!("{name} v{version}: {error:#}");
+                failed += 1;
+                continue;
+            }
+        };
+        let outdated = newer.compatible.is_some() || newer.latest.is_some() || newer.yanked;
+        if !outdated && !args.all {
+            continue;
+        }
+        let show = |version: &Option<Version>| version.as_ref().map_or("-".to_string(), ToString::to_string);
+        rows.push([
+            name,
+            if newer.yanked {
+                format!("{version} (yanked)")
+            } else {
+                version
+            },
+            show(&newer.compatible),
+            show(&newer.latest),
+        ]);
+    }
+    let skipped = localize_lock.packages.len() - queries.len();
+
+    if rows.is_empty() {
+        println!("Every vendored crate is on its newest version");
+    } else {
+        let header = ["Crate", "Vendored", "Compatible", "Latest"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([header[column].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        for row in [header.map(String::from)].iter().chain(&rows) {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            println!("{}", cells.join("  ").trim_end());
+        }
+    }
+    if skipped > 0 {
+        println!("{skipped} crate(s) from git or a registry without a sparse index weren't checked");
+    }
+    if failed > 0 {
+        bail!("{failed} crate(s) couldn't be looked up");
+    }
+    Ok(())
+}
+
+// Sparse index the crate's source is served by
+fn index_of(locked: &LockedPackage, crates_io_index: &str) -> Option<String> {
+    if locked.is_from_crates_io() {
+        return Some(crates_io_index.to_string());
+    }
+    locked
+        .source
+        .as_deref()?
+        .strip_prefix("sparse+")
+        .map(ToString::to_string)
+}
+
+fn newer(index: &str, locked: &LockedPackage) -> Result<Newer> {
+    let url = format!("{}/{}", index.trim_end_matches('/'), index_path(&locked.name));
+    let content = crate::http::get(&url)?;
+    let vendored = Version::parse(&locked.version).context(format!("Invalid version {}", locked.version))?;
+    let compatible_with = VersionReq::parse(&format!("^{vendored}"))?;
+
+    let mut newer = Newer {
+        compatible: None,
+        latest: None,
+        yanked: false,
+    };
+    for line in String::from_utf8_lossy(&content)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+    {
+        let release: serde_json::Value =
+            serde_json::from_str(line).context(format!("Malformed index entry at {url}"))?;
+        let Some(version) = release["vers"]
+            .as_str()
+            .and_then(|version| Version::parse(version).ok())
+        else {
+            continue;
+        };
+        let yanked = release["yanked"].as_bool().unwrap_or(false);
+        if version == vendored {
+            newer.yanked = yanked;
+        }
+        // Pre-releases only count for crates vendored as one
+        if yanked || version <= vendored || (!version.pre.is_empty() && vendored.pre.is_empty()) {
+            continue;
+        }
+        // Releases a caret requirement doesn't accept make the latest column, as they need a manifest change
+        if compatible_with.matches(&version) {
+            newer.compatible = newer.compatible.max(Some(version.clone()));
+        } else {
+            newer.latest = newer.latest.max(Some(version));
+        }
+    }
+    Ok(newer)
+}
+
+// Where a sparse index keeps a crate's file: by length for short names, else by the first four
+// letters, all lowercase
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::edits::CHECKSUMS_FILE_NAME;
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock};
+use crate::util::format_size;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+use walkdir::WalkDir;
+
+// `sha256sum` listing of every packed file, checked by `unpack` and by `sha256sum -c`
+pub const SUMS_FILE_NAME: &str = "SHA256SUMS";
+
+#[derive(clap::Args)]
+pub struct PackArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Archive to write (default: `<project folder name>-vendor.tar.zst` in the project)
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Zstandard compression level, 1 (fast) to 22 (small)
+    #[clap(long, value_name = "N", default_value = "19", value_parser = clap::value_parser!(i32).range(1..=22))]
+    level: i32,
+}
+
+// Bundles the 3rd-party folder with localize.lock into one `.tar.zst`, for air-gapped build
+// environments or release assets. The same tree always gives the same bytes: entries are sorted,
+// with their time set to SOURCE_DATE_EPOCH (or 0), no owners and only the executable bit kept.
+pub fn pack(args: &PackArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    if localize_lock.manifests_pending {
+        bail!("Manifests don't point at the vendored crates yet, run with --rewrite-only first");
+    }
+    if !crate::safety::is_contained(Path::new(&localize_lock.third_party_dir)) {
+        bail!(
+            "{} is shared with an enclosing project, pack that one",
+            localize_lock.third_party_dir
+        );
+    }
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    if !third_party_path.is_dir() {
+        bail!("{} doesn't exist, nothing to pack", third_party_path.display());
+    }
+    let output = args.output.clone().unwrap_or_else(|| {
+        let name = project_path
+            .file_name()
+            .map_or("project".into(), |name| name.to_string_lossy());
+        project_path.join(format!("{name}-vendor.tar.zst"))
+    });
+    let mtime = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    info!("Packing {}...", third_party_path.display());
+    let file = fs::File::create(&output).context(format!("Failed to create {}", output.display()))?;
+    let encoder = zstd::Encoder::new(file, args.level).context("Failed to start compression")?;
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+
+    let mut sums = String::new();
+    for name in [LOCK_FILE_NAME, CHECKSUMS_FILE_NAME] {
+        let path = project_path.join(name);
+        if path.is_file() {
+            append_file(&mut builder, &path, Path::new(name), mtime, &mut sums)?;
+        }
+    }
+    // Crates linked from a store are packed with their content, the archive has to stand alone
+    for entry in WalkDir::new(&third_party_path)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+    {
+        let entry = entry.context(format!("Failed to read {}", third_party_path.display()))?;
+        let relative = Path::new(&localize_lock.third_party_dir).join(entry.path().strip_prefix(&third_party_path)?);
+        if entry.file_type().is_dir() {
+            let mut header = header(tar::EntryType::Directory, 0o755, 0, mtime);
+            builder
+                .append_data(&mut header, &relative, std::io::empty())
+                .context(format!("Failed to pack {}", relative.display()))?;
+        } else if entry.file_type().is_file() {
+            append_file(&mut builder, entry.path(), &relative, mtime, &mut sums)?;
+        }
+    }
+    let files = sums.lines().count();
+    let mut header = header(tar::EntryType::Regular, 0o644, sums.len() as u64, mtime);
+    builder
+        .append_data(&mut header, SUMS_FILE_NAME, sums.as_bytes())
+        .context(format!("Failed to pack {SUMS_FILE_NAME}"))?;
+
+    let encoder = builder.into_inner().context("Failed to finish the archive")?;
+    let mut file = encoder.finish().context("Failed to finish compression")?;
+    file.flush().context(format!("Failed to write {}", output.display()))?;
+    drop(file);
+
+    println!(
+        "Packed {} crate(s), {files} file(s) into {} ({}), sha256 {}",
+        localize_lock.packages.len(),
+        output.display(),
+        format_size(fs::metadata(&output).map(|metadata| metadata.len()).unwrap_or_default()),
+        crate::journal::file_sha256(&output)?
+    );
+    Ok(())
+}
+
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+    mtime: u64,
+    sums: &mut String,
+) -> Result<()> {
+    sums.push_str(&format!(
+        "{}  {}\n",
+        crate::journal::file_sha256(path)?,
+        name.to_string_lossy().replace('\\', "/")
+    ));
+    let metadata = fs::metadata(path).context(format!("Failed to read {}", path.display()))?;
+    let mode = if is_executable(&metadata) { 0o755 } else { 0o644 };
+    let mut header = header(tar::EntryType::Regular, mode, metadata.len(), mtime);
+    let file = fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    builder
+        .append_data(&mut header, name, file)
+        .context(format!("Failed to pack {}", name.display()))
+}
+
+fn header(entry_type: tar::EntryType, mode: u32, size: u64, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
@@ -0,0 +1,75 @@
+use crate::journal::Journal;
+use crate::lock::{LocalizeLock, LockedPackage};
+use crate::vendor::CARGO_CHECKSUM_FILE_NAME;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+// Files packaging leaves next to a crate's sources: the extraction marker, the commit it was
+// published from, the manifest before cargo normalized it and the checksums of a `cargo vendor`
+// folder. None of them describes the crate once its manifest is rewritten.
+const REGISTRY_FILES: [&str; 4] = [
+    ".cargo-ok",
+    ".cargo_vcs_info.json",
+    "Cargo.toml.orig",
+    CARGO_CHECKSUM_FILE_NAME,
+];
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackagingPolicy {
+    /// Leave them as the registry shipped them
+    Keep,
+    /// Remove them
+    #[default]
+    Delete,
+    /// Keep them, with `.cargo-checksum.json` listing the files as vendored
+    Regenerate,
+}
+
+// Runs on a freshly copied crate. Crates linked from the store only lose their links.
+pub fn clean(crate_dir: &Path, policy: PackagingPolicy, journal: &Journal) -> Result<()> {
+    let stale: &[&str] = match policy {
+        PackagingPolicy::Keep => &[],
+        PackagingPolicy::Delete => &REGISTRY_FILES,
+        // Written again once the manifest is rewritten
+        PackagingPolicy::Regenerate => &[CARGO_CHECKSUM_FILE_NAME],
+    };
+    for name in stale {
+        let path = crate_dir.join(name);
+        if fs::symlink_metadata(&path).is_ok() {
+            journal
+                .remove_file(&path)
+                .context(format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+// Lists every file of the vendored crates as they are after the rewrite, the way `cargo vendor`
+// does, for tools reading the folder as a vendor directory. Cargo itself doesn't check path crates.
+pub fn regenerate(third_party_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        if crate_dir.is_dir() {
+            write_checksums(locked, &crate_dir, journal)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_checksums(locked: &LockedPackage, crate_dir: &Path, journal: &Journal) -> Result<()> {
+    let mut files = crate::edits::hash_crate(crate_dir)?;
+    files.remove(CARGO_CHECKSUM_FILE_NAME);
+    files.remove("Cargo.toml.bak");
+    let checksums = serde_json::json!({
+        "files": files,
+        "package": locked.checksum,
+    });
+    journal
+        .write(&crate_dir.join(CARGO_CHECKSUM_FILE_NAME), checksums.to_string())
+        .context(format!(
+            "Failed to write the checksums of {} v{}",
+            locked.name, locked.version
+        ))
+}
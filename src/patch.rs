@@ -0,0 +1,453 @@
+use crate::journal::Journal;
+use crate::lock::{LocalizeLock, LockedPackage, PatchEntry};
+use crate::util::normalize_path;
+use anyhow::{Context, Result};
+use cargo_metadata::{DependencyKind, Metadata};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+use tracing::info;
+
+// Localizes through `[patch.<source>]` entries in the root manifest instead of rewriting
+// dependency entries. Root build-dependencies the patches don't take effect for are rewritten
+// to path dependencies directly, their names are recorded in the lock.
+#[allow(clippy::too_many_arguments)]
+pub fn localize_with_patches(
+    project_path: &Path,
+    third_party_path: &Path,
+    metadata: &Metadata,
+    localize_lock: &mut LocalizeLock,
+    filter_platform: Option<&str>,
+    network: &crate::NetworkArgs,
+    observers: &crate::observer::Observers,
+    journal: &Journal,
+) -> Result<()> {
+    let manifest_path = project_path.join("Cargo.toml");
+    crate::backup_manifest(&manifest_path, journal)?;
+
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    remove_patches(&mut doc, project_path, third_party_path, &localize_lock.patch_entries);
+    let registries = crate::registry::alternative_registries(project_path);
+    let pointed = point_patch_entries(
+        &mut doc,
+        metadata,
+        project_path,
+        third_party_path,
+        localize_lock,
+        &registries,
+    );
+    let patched = add_patches(&mut doc, localize_lock, &registries, &pointed);
+    crate::exclude_from_workspace(&mut doc, &manifest_path, third_party_path);
+    crate::detach_vendored_manifests(third_party_path, localize_lock, journal)?;
+    journal
+        .write(&manifest_path, doc.to_string())
+        .context("Failed to write Cargo.toml")?;
+    info!("  Patched {patched} crate(s) in the root Cargo.toml");
+
+    // Patches are applied per source and resolver, check what the build-dependencies resolved to
+    let metadata = network
+        .metadata()
+        .manifest_path(&manifest_path)
+        .other_options(crate::platform::cargo_options(filter_platform, network))
+        .exec()
+        .context("Failed to get cargo metadata of the patched project")?;
+    let unpatched = unpatched_build_dependencies(&metadata, third_party_path, localize_lock);
+
+    if !unpatched.is_empty() {
+        let root = metadata.root_package();
+        let mut fallback_lock = LocalizeLock::new(localize_lock.strategy, &localize_lock.third_party_dir);
+        fallback_lock.packages = unpatched.into_iter().cloned().collect();
+        fallback_lock.keep_version = localize_lock.keep_version;
+        for deps in build_dependency_tables_mut(&mut doc) {
+            crate::update_dependencies(
+                deps,
+                &metadata,
+                root,
+                &manifest_path,
+                third_party_path,
+                &fallback_lock,
+                true,
+            )?;
+        }
+        journal
+            .write(&manifest_path, doc.to_string())
+            .context("Failed to write Cargo.toml")?;
+    }
+    observers.emit(crate::Event::ManifestRewritten { path: &manifest_path });
+
+    // Includes entries rewritten by earlier runs
+    localize_lock.direct_rewrites = build_dependency_tables_mut(&mut doc)
+        .into_iter()
+        .flat_map(|deps| deps.iter())
+        .filter(|(_, dep)| points_into(dep, project_path, third_party_path))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    localize_lock.direct_rewrites.sort();
+    localize_lock.direct_rewrites.dedup();
+    if !localize_lock.direct_rewrites.is_empty() {
+        info!(
+            "  Not patchable, rewritten as path build-dependencies: {}",
+            localize_lock.direct_rewrites.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn points_into(dep: &Item, manifest_dir: &Path, third_party_path: &Path) -> bool {
+    dep.get("path")
+        .and_then(|path| path.as_str())
+        .is_some_and(|path| normalize_path(&manifest_dir.join(path)).starts_with(third_party_path))
+}
+
+// Points the project's own `[patch]` entries at the vendored crates they resolved to, in the root
+// manifest, and records them in the lock
+pub fn point_root_patches(
+    project_path: &Path,
+    third_party_path: &Path,
+    metadata: &Metadata,
+    localize_lock: &mut LocalizeLock,
+    journal: &Journal,
+) -> Result<()> {
+    let manifest_path = project_path.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    let registries = crate::registry::alternative_registries(project_path);
+    point_patch_entries(
+        &mut doc,
+        metadata,
+        project_path,
+        third_party_path,
+        localize_lock,
+        &registries,
+    );
+    if doc.to_string() != content {
+        journal
+            .write(&manifest_path, doc.to_string())
+            .context("Failed to write Cargo.toml")?;
+    }
+    Ok(())
+}
+
+// A crate the project patches resolves from the patch's source, an entry for the vendored copy
+// under that source wouldn't take effect: patches don't chain. The project's entry is pointed at
+// the copy instead, what it said before is kept in the lock for `update` to resolve against.
+// Returns the vendored directories the entries point at.
+fn point_patch_entries(
+    doc: &mut DocumentMut,
+    metadata: &Metadata,
+    manifest_dir: &Path,
+    third_party_path: &Path,
+    localize_lock: &mut LocalizeLock,
+    registries: &BTreeMap<String, String>,
+) -> HashSet<String> {
+    let mut pointed = HashSet::new();
+    let mut entries = Vec::new();
+    let Some(patch_table) = doc.get_mut("patch").and_then(|p| p.as_table_like_mut()) else {
+        localize_lock.patch_entries.clear();
+        return pointed;
+    };
+    for (table, patches) in patch_table.iter_mut() {
+        let Some(patches) = patches.as_table_like_mut() else {
+            continue;
+        };
+        for (key, entry) in patches.iter_mut() {
+            let recorded = localize_lock
+                .patch_entries
+                .iter()
+                .find(|recorded| recorded.table == table.get() && recorded.key == key.get());
+            if points_into(entry, manifest_dir, third_party_path) {
+                // Pointed by an earlier run, or added by the patch strategy
+                if let Some(recorded) = recorded {
+                    pointed.insert(recorded.path.clone());
+                    entries.push(recorded.clone());
+                }
+                continue;
+            }
+            let Some(locked) = patched_package(entry, key.get(), metadata, manifest_dir, third_party_path, registries)
+                .and_then(|package| localize_lock.find_package(package, third_party_path))
+                .filter(|locked| third_party_path.join(&locked.path).is_dir())
+            else {
+                continue;
+            };
+            let mut pointing = InlineTable::new();
+            if let Some(package) = entry.get("package").and_then(|package| package.as_str()) {
+                pointing.insert("package", package.into());
+            }
+            pointing.insert(
+                "path",
+                format!("{}/{}", localize_lock.third_party_dir, locked.path).into(),
+            );
+            info!("  Pointed [patch.{table}] {key} at {}", locked.path);
+            pointed.insert(locked.path.clone());
+            // `[patch.<table>.<key>]` tables are kept inline
+            let Ok(original) = entry.clone().into_value() else {
+                continue;
+            };
+            entries.push(PatchEntry {
+                table: table.get().to_string(),
+                key: key.get().to_string(),
+                original: original.decorated("", "").to_string(),
+                path: locked.path.clone(),
+            });
+            *entry = Item::Value(Value::InlineTable(pointing));
+        }
+    }
+    localize_lock.patch_entries = entries;
+    pointed
+}
+
+// The package a `[patch]` entry put in place of the registry's: from the git repository, the
+// directory or the registry it names
+fn patched_package<'a>(
+    entry: &Item,
+    key: &str,
+    metadata: &'a Metadata,
+    manifest_dir: &Path,
+    third_party_path: &Path,
+    registries: &BTreeMap<String, String>,
+) -> Option<&'a cargo_metadata::Package> {
+    let name = entry.get("package").and_then(|package| package.as_str()).unwrap_or(key);
+    let requirement = entry
+        .get("version")
+        .and_then(|version| version.as_str())
+        .and_then(|version| cargo_metadata::semver::VersionReq::parse(version).ok());
+    let git = entry.get("git").and_then(|git| git.as_str());
+    let path = entry.get("path").and_then(|path| path.as_str());
+    let registry = entry.get("registry").and_then(|registry| registry.as_str());
+    metadata
+        .packages
+        .iter()
+        .filter(|package| package.name == name && !package.manifest_path.starts_with(third_party_path))
+        .filter(|package| {
+            requirement
+                .as_ref()
+                .is_none_or(|requirement| requirement.matches(&package.version))
+        })
+        .find(|package| {
+            let source = package.source.as_ref().map(|source| source.repr.as_str());
+            match (git, path, registry, source) {
+                (Some(git), _, _, Some(source)) => source.strip_prefix("git+").is_some_and(|url| {
+                    let url = url.split(['?', '#']).next().unwrap_or(url);
+                    url.trim_end_matches('/') == git.trim_end_matches('/')
+                }),
+                (_, Some(path), _, None) => package
+                    .manifest_path
+                    .parent()
+                    .is_some_and(|dir| normalize_path(dir.as_std_path()) == normalize_path(&manifest_dir.join(path))),
+                (_, _, Some(registry), Some(source)) => registries.get(source).is_some_and(|name| name == registry),
+                _ => false,
+            }
+        })
+}
+
+// Adds an entry for every vendored crate, grouped by the source it replaces, but for the ones
+// the project's own entries were pointed at. Returns the number of entries.
+fn add_patches(
+    doc: &mut DocumentMut,
+    localize_lock: &LocalizeLock,
+    registries: &BTreeMap<String, String>,
+    pointed: &HashSet<String>,
+) -> usize {
+    let mut by_source: BTreeMap<String, Vec<&LockedPackage>> = BTreeMap::new();
+    for locked in localize_lock
+        .packages
+        .iter()
+        .filter(|locked| !pointed.contains(&locked.path))
+    {
+        if let Some(source) = locked
+            .source
+            .as_deref()
+            .and_then(|source| patch_source(source, registries))
+        {
+            by_source.entry(source).or_default().push(locked);
+        }
+    }
+
+    let mut patched = 0;
+    let patch_table = doc
+        .entry("patch")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut();
+    let Some(patch_table) = patch_table else {
+        return 0;
+    };
+
+    for (source, packages) in by_source {
+        let Some(entries) = patch_table
+            .entry(&source)
+            .or_insert(Item::Table(Table::new()))
+            .as_table_mut()
+        else {
+            continue;
+        };
+
+        for locked in &packages {
+            let path = format!("{}/{}", localize_lock.third_party_dir, locked.path);
+            let mut entry = InlineTable::new();
+            // Several versions of one crate need distinct keys
+            let key = if packages.iter().filter(|p| p.name == locked.name).count() > 1 {
+                entry.insert("package", locked.name.as_str().into());
+                format!("{}-{}", locked.name, locked.version.replace(['.', '+'], "_"))
+            } else {
+                locked.name.clone()
+            };
+            entry.insert("path", path.into());
+            entries.insert(&key, Item::Value(Value::InlineTable(entry)));
+            patched += 1;
+        }
+    }
+
+    patched
+}
+
+// Drops the patches of an earlier patch-mode run from the root manifest
+pub fn unpatch_root(
+    project_path: &Path,
+    third_party_path: &Path,
+    kept: &[PatchEntry],
+    journal: &Journal,
+) -> Result<()> {
+    let manifest_path = project_path.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    remove_patches(&mut doc, project_path, third_party_path, kept);
+    journal
+        .write(&manifest_path, doc.to_string())
+        .context("Failed to write Cargo.toml")
+}
+
+// Removes patch entries pointing into the 3rd-party folder, e.g. left by a previous run. The
+// project's own entries pointed there stay.
+pub fn remove_patches(doc: &mut DocumentMut, manifest_dir: &Path, third_party_path: &Path, kept: &[PatchEntry]) {
+    let Some(patch_table) = doc.get_mut("patch").and_then(|p| p.as_table_like_mut()) else {
+        return;
+    };
+
+    for (table, entries) in patch_table.iter_mut() {
+        if let Some(entries) = entries.as_table_like_mut() {
+            let vendored: Vec<String> = entries
+                .iter()
+                .filter(|(_, entry)| points_into(entry, manifest_dir, third_party_path))
+                .filter(|(key, _)| !kept.iter().any(|kept| kept.table == table.get() && kept.key == *key))
+                .map(|(key, _)| key.to_string())
+                .collect();
+            for key in vendored {
+                entries.remove(&key);
+            }
+        }
+    }
+
+    let emptied: Vec<String> = patch_table
+        .iter()
+        .filter(|(_, entries)| entries.as_table_like().is_some_and(|entries| entries.is_empty()))
+        .map(|(source, _)| source.to_string())
+        .collect();
+    for source in emptied {
+        patch_table.remove(&source);
+    }
+    if patch_table.is_empty() {
+        doc.remove("patch");
+    }
+}
+
+// Puts the project's own `[patch]` entries back the way they were before they were pointed at
+// vendored crates
+pub fn restore_patches(doc: &mut DocumentMut, entries: &[PatchEntry]) {
+    for recorded in entries {
+        let Some(entry) = doc
+            .get_mut("patch")
+            .and_then(|patch| patch.get_mut(&recorded.table))
+            .and_then(|patches| patches.get_mut(&recorded.key))
+        else {
+            continue;
+        };
+        if let Ok(original) = recorded.original.parse::<Value>() {
+            *entry = Item::Value(original);
+        }
+    }
+}
+
+// Source a `[patch.<table>]` table patches: crates.io, a registry by name, or the URL of a
+// registry index or git repository
+pub fn patched_source(table: &str, registries: &BTreeMap<String, String>) -> String {
+    if table == "crates-io" {
+        return "registry+https://github.com/rust-lang/crates.io-index".to_string();
+    }
+    if let Some((source, _)) = registries.iter().find(|(_, name)| *name == table) {
+        return source.clone();
+    }
+    if table.starts_with("sparse+") {
+        return table.to_string();
+    }
+    format!("git+{table}")
+}
+
+// `[patch]` key of the source a package came from: the name of a registry, or the URL of its
+// index (with the `sparse+` prefix of sparse ones) or repository
+fn patch_source(source: &str, registries: &BTreeMap<String, String>) -> Option<String> {
+    if crate::lock::is_crates_io(source) {
+        return Some("crates-io".to_string());
+    }
+    if let Some(name) = registries.get(source) {
+        return Some(name.clone());
+    }
+    if source.starts_with("sparse+") {
+        return Some(source.to_string());
+    }
+    if let Some(url) = source.strip_prefix("registry+") {
+        return Some(url.to_string());
+    }
+    source
+        .strip_prefix("git+")
+        .map(|url| url.split(['?', '#']).next().unwrap_or(url).to_string())
+}
+
+// Vendored packages the root package still takes from their original source as build-dependencies
+fn unpatched_build_dependencies<'a>(
+    metadata: &Metadata,
+    third_party_path: &Path,
+    localize_lock: &'a LocalizeLock,
+) -> Vec<&'a LockedPackage> {
+    let (Some(root), Some(resolve)) = (metadata.root_package(), metadata.resolve.as_ref()) else {
+        return Vec::new();
+    };
+    let Some(node) = resolve.nodes.iter().find(|n| n.id == root.id) else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    node.deps
+        .iter()
+        .filter(|dep| dep.dep_kinds.iter().any(|kind| kind.kind == DependencyKind::Build))
+        .filter_map(|dep| metadata.packages.iter().find(|p| p.id == dep.pkg))
+        .filter(|package| !package.manifest_path.starts_with(third_party_path))
+        .filter_map(|package| localize_lock.find_package(package, third_party_path))
+        .filter(|locked| seen.insert(&locked.path))
+        .collect()
+}
+
+fn build_dependency_tables_mut(doc: &mut DocumentMut) -> Vec<&mut Table> {
+    let mut tables = Vec::new();
+
+    for (key, item) in doc.as_table_mut().iter_mut() {
+        if key.get() == "build-dependencies" {
+            tables.extend(item.as_table_mut());
+        } else if key.get() == "target"
+            && let Some(target_table) = item.as_table_mut()
+        {
+            for (_, target_value) in target_table.iter_mut() {
+                if let Some(deps) = target_value.get_mut("build-dependencies") {
+                    tables.extend(deps.as_table_mut());
+                }
+            }
+        }
+    }
+
+    tables
+}
@@ -0,0 +1,340 @@
+use crate::config::SymlinkPolicy;
+use crate::journal::Journal;
+use crate::lock::{AppliedPatch, LockedPackage};
+use anyhow::{Context, Result, bail};
+use fs_extra::dir::CopyOptions;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Folder of the project with a subfolder per crate name, the patches in it apply in file name order
+pub const PATCHES_DIR: &str = "patches";
+
+// One patch file of the project, with the hash that tells whether it changed since it was applied
+#[derive(Clone, Debug)]
+pub struct PatchFile {
+    file: String,
+    path: PathBuf,
+    sha256: String,
+}
+
+// The patches kept in the project, by crate name
+#[derive(Default, Debug)]
+pub struct Patches {
+    crates: BTreeMap<String, Vec<PatchFile>>,
+}
+
+impl Patches {
+    pub fn load(project_path: &Path) -> Result<Self> {
+        let dir = project_path.join(PATCHES_DIR);
+        let mut patches = Self::default();
+        if !dir.is_dir() {
+            return Ok(patches);
+        }
+        for entry in fs::read_dir(&dir).context(format!("Failed to read {}", dir.display()))? {
+            let crate_dir = entry?.path();
+            let Some(name) = crate_dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !crate_dir.is_dir() {
+                continue;
+            }
+            let mut files = Vec::new();
+            for entry in fs::read_dir(&crate_dir).context(format!("Failed to read {}", crate_dir.display()))? {
+                let path = entry?.path();
+                if !path.is_file() || path.extension().is_none_or(|extension| extension != "patch") {
+                    continue;
+                }
+                let content = fs::read(&path).context(format!("Failed to read {}", path.display()))?;
+                files.push(PatchFile {
+                    file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                    sha256: crate::journal::sha256(&content),
+                    path,
+                });
+            }
+            files.sort_by(|a, b| a.file.cmp(&b.file));
+            if !files.is_empty() {
+                patches.crates.insert(name.to_string(), files);
+            }
+        }
+        Ok(patches)
+    }
+
+    pub fn for_crate(&self, name: &str) -> &[PatchFile] {
+        self.crates.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    // Whether the crate carries exactly the patches the project has for it now
+    pub fn is_current(&self, locked: &LockedPackage) -> bool {
+        let current = self.for_crate(&locked.name);
+        current.len() == locked.patches.len()
+            && current
+                .iter()
+                .zip(&locked.patches)
+                .all(|(patch, applied)| patch.file == applied.file && patch.sha256 == applied.sha256)
+    }
+}
+
+// Applies the crate's patches to a fresh copy of it, in order. A patch that doesn't apply fails the
+// run, as the crate would otherwise be vendored without it.
+pub fn apply(crate_dir: &Path, patches: &[PatchFile], journal: &Journal) -> Result<Vec<AppliedPatch>> {
+    let (applied, _) = patch_files(
+        crate_dir,
+        |file| read(&crate_dir.join(file)),
+        &BTreeSet::new(),
+        patches,
+        journal,
+    )?;
+    Ok(applied)
+}
+
+// Brings a crate vendored earlier in line with the project's patches for it: the files the
+// recorded patches changed are put back as published, then the current patches are applied.
+// Returns the files written, `None` if the published crate isn't in cargo's cache.
+pub fn refresh(
+    locked: &LockedPackage,
+    crate_dir: &Path,
+    patches: &Patches,
+    journal: &Journal,
+) -> Result<Option<(Vec<AppliedPatch>, BTreeSet<String>)>> {
+    let Some(pristine) = crate::diff::pristine(locked)? else {
+        return Ok(None);
+    };
+    unlink(crate_dir, journal)?;
+    let restore: BTreeSet<String> = locked
+        .patches
+        .iter()
+        .flat_map(|applied| applied.touches.iter().cloned())
+        .collect();
+    let (applied, written) = patch_files(
+        crate_dir,
+        |file| Ok(pristine.get(Path::new(file)).cloned()),
+        &restore,
+        patches.for_crate(&locked.name),
+        journal,
+    )?;
+    if written.contains("Cargo.toml") {
+        let manifest = crate_dir.join("Cargo.toml");
+        if locked.trimmed && manifest.is_file() {
+            let mut doc = fs::read_to_string(&manifest)
+                .context(format!("Failed to read {}", manifest.display()))?
+                .parse::<toml_edit::DocumentMut>()
+                .context(format!("Failed to parse {}", manifest.display()))?;
+            crate::trim::remove_trimmed_sections(&mut doc);
+            journal
+                .write(&manifest, doc.to_string())
+                .context(format!("Failed to write {}", manifest.display()))?;
+        }
+        // Taken again from the patched manifest once the run rewrites it
+        let backup = crate_dir.join("Cargo.toml.bak");
+        if backup.is_file() {
+            journal
+                .remove_file(&backup)
+                .context(format!("Failed to remove {}", backup.display()))?;
+        }
+    }
+    Ok(Some((applied, written)))
+}
+
+// Patches the files of `crate_dir` as `base` has them, `None` for files it doesn't have, in a
+// scratch folder and writes the result back through the journal. Files in `restore` are put back
+// to `base` even when no patch touches them any more. Files `base` has but the crate doesn't were
+// trimmed away and stay away.
+fn patch_files(
+    crate_dir: &Path,
+    base: impl Fn(&str) -> Result<Option<Vec<u8>>>,
+    restore: &BTreeSet<String>,
+    patches: &[PatchFile],
+    journal: &Journal,
+) -> Result<(Vec<AppliedPatch>, BTreeSet<String>)> {
+    let mut applied = Vec::new();
+    let mut files = restore.clone();
+    for patch in patches {
+        let touches = touched(&patch.path)?;
+        files.extend(touches.iter().cloned());
+        applied.push(AppliedPatch {
+            file: patch.file.clone(),
+            sha256: patch.sha256.clone(),
+            touches,
+        });
+    }
+    if files.is_empty() {
+        return Ok((applied, BTreeSet::new()));
+    }
+
+    let scratch = scratch_dir();
+    let written = patch_in(&scratch, crate_dir, &base, &files, restore, patches, journal);
+    let _ = fs::remove_dir_all(&scratch);
+    Ok((applied, written?))
+}
+
+fn patch_in(
+    scratch: &Path,
+    crate_dir: &Path,
+    base: impl Fn(&str) -> Result<Option<Vec<u8>>>,
+    files: &BTreeSet<String>,
+    restore: &BTreeSet<String>,
+    patches: &[PatchFile],
+    journal: &Journal,
+) -> Result<BTreeSet<String>> {
+    fs::create_dir_all(scratch).context("Failed to create a scratch directory")?;
+    let mut originals = BTreeMap::new();
+    for file in files {
+        let original = base(file)?;
+        if let Some(content) = &original {
+            let path = crate::safety::safe_join(scratch, file)?;
+            fs::create_dir_all(path.parent().unwrap_or(scratch))?;
+            fs::write(&path, content).context(format!("Failed to write {}", path.display()))?;
+        }
+        originals.insert(file, original.is_some());
+    }
+    for patch in patches {
+        git_apply(scratch, &patch.path, &[])?;
+    }
+
+    let mut written = BTreeSet::new();
+    for (file, in_base) in originals {
+        let target = crate::safety::safe_join(crate_dir, file)?;
+        if in_base && !target.exists() && !restore.contains(file) {
+            continue;
+        }
+        match read(&scratch.join(file))? {
+            Some(content) if read(&target)?.as_ref() != Some(&content) => {
+                if let Some(parent) = target.parent() {
+                    journal
+                        .create_dir_all(parent)
+                        .context(format!("Failed to create {}", parent.display()))?;
+                }
+                journal
+                    .write(&target, content)
+                    .context(format!("Failed to write {}", target.display()))?;
+            }
+            None if target.is_file() => {
+                journal
+                    .remove_file(&target)
+                    .context(format!("Failed to remove {}", target.display()))?;
+            }
+            _ => continue,
+        }
+        written.insert(file.clone());
+    }
+    Ok(written)
+}
+
+// The files of a crate, as `files` has them by path relative to it, once the patches are applied.
+// What a vendored crate should hold, for comparing it with its published source.
+pub fn patched(files: &BTreeMap<PathBuf, Vec<u8>>, patches: &[PatchFile]) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut patched = files.clone();
+    if patches.is_empty() {
+        return Ok(patched);
+    }
+    let mut touches = BTreeSet::new();
+    for patch in patches {
+        touches.extend(touched(&patch.path)?);
+    }
+    let scratch = scratch_dir();
+    let result = (|| -> Result<()> {
+        fs::create_dir_all(&scratch).context("Failed to create a scratch directory")?;
+        for file in &touches {
+            if let Some(content) = files.get(Path::new(file)) {
+                let path = crate::safety::safe_join(&scratch, file)?;
+                fs::create_dir_all(path.parent().unwrap_or(&scratch))?;
+                fs::write(&path, content).context(format!("Failed to write {}", path.display()))?;
+            }
+        }
+        for patch in patches {
+            git_apply(&scratch, &patch.path, &[])?;
+        }
+        for file in &touches {
+            match read(&scratch.join(file))? {
+                Some(content) => patched.insert(PathBuf::from(file), content),
+                None => patched.remove(Path::new(file)),
+            };
+        }
+        Ok(())
+    })();
+    let _ = fs::remove_dir_all(&scratch);
+    result.map(|()| patched)
+}
+
+fn scratch_dir() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "cargo-localize-patch-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+// Files a patch changes, as `git apply --numstat` lists them
+fn touched(patch: &Path) -> Result<Vec<String>> {
+    let stats = git_apply(&std::env::temp_dir(), patch, &["--numstat", "-z"])?;
+    // `<added>\t<removed>\t<file>\0`, renames as `<added>\t<removed>\t\0<from>\0<to>\0`
+    Ok(String::from_utf8_lossy(&stats)
+        .split('\0')
+        .map(|stat| stat.rsplit('\t').next().unwrap_or_default())
+        .filter(|file| !file.is_empty())
+        .map(ToString::to_string)
+        .collect())
+}
+
+// `git apply` run in `dir`. Any repository around it is kept out of sight, git would take the
+// patch's paths as relative to its root otherwise.
+fn git_apply(dir: &Path, patch: &Path, options: &[&str]) -> Result<Vec<u8>> {
+    let patch = patch
+        .canonicalize()
+        .context(format!("Failed to find {}", patch.display()))?;
+    let output = Command::new("git")
+        .arg("apply")
+        .args(options)
+        .arg(&patch)
+        .current_dir(dir)
+        .env("GIT_CEILING_DIRECTORIES", dir.parent().unwrap_or(dir))
+        .output()
+        .context("Failed to run git apply")?;
+    if !output.status.success() {
+        bail!(
+            "Patch {} doesn't apply: {}",
+            patch.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    fs::read(path)
+        .map(Some)
+        .context(format!("Failed to read {}", path.display()))
+}
+
+// A crate linked from the store gets its own copy of what it links to, patching it would change
+// the copy every project shares
+fn unlink(crate_dir: &Path, journal: &Journal) -> Result<()> {
+    for entry in fs::read_dir(crate_dir).context(format!("Failed to read {}", crate_dir.display()))? {
+        let link = entry?.path();
+        if !fs::symlink_metadata(&link)?.file_type().is_symlink() {
+            continue;
+        }
+        let target = crate_dir.join(fs::read_link(&link)?);
+        journal
+            .remove_file(&link)
+            .context(format!("Failed to remove {}", link.display()))?;
+        if target.is_dir() {
+            journal.copy_dir(&target, &link, &CopyOptions::new(), SymlinkPolicy::Preserve)
+        } else {
+            journal.copy_file(&target, &link)
+        }
+        .context(format!(
+            "Failed to copy {} into {}",
+            target.display(),
+            crate_dir.display()
+        ))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::journal::Journal;
+use anyhow::{Context, Result};
+use cargo_platform::{Cfg, Platform};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use toml_edit::DocumentMut;
+use tracing::debug;
+
+// Target triple together with the cfg values rustc reports for it
+pub struct TargetPlatform {
+    pub triple: String,
+    cfgs: Vec<Cfg>,
+}
+
+impl TargetPlatform {
+    pub fn new(triple: Option<&str>) -> Result<Self> {
+        let triple = match triple {
+            Some(triple) => triple.to_string(),
+            None => host_triple()?,
+        };
+
+        let output = Command::new("rustc")
+            .args(["--print", "cfg", "--target", &triple])
+            .output()
+            .context("Failed to run rustc --print cfg")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "rustc doesn't know target {triple}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let cfgs = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| Cfg::from_str(line).ok())
+            .collect();
+
+        Ok(Self { triple, cfgs })
+    }
+
+    pub fn matches(&self, platform: &Platform) -> bool {
+        platform.matches(&self.triple, &self.cfgs)
+    }
+}
+
+fn host_triple() -> Result<String> {
+    let output = Command::new("rustc")
+        .arg("-vV")
+        .output()
+        .context("Failed to run rustc -vV")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.to_string())
+        .context("Failed to detect host target triple")
+}
+
+// `cargo metadata` options resolving only the dependencies the given target needs
+pub fn filter_options(filter_platform: Option<&str>) -> Vec<String> {
+    filter_platform
+        .map(|triple| vec!["--filter-platform".to_string(), triple.to_string()])
+        .unwrap_or_default()
+}
+
+// Options of the cargo commands resolving the dependency graph
+pub fn cargo_options(filter_platform: Option<&str>, network: &crate::NetworkArgs) -> Vec<String> {
+    let mut options = filter_options(filter_platform);
+    options.extend(network.cargo_options());
+    options
+}
+
+fn matches_any(targets: &[TargetPlatform], key: &str) -> bool {
+    // Keep tables we can't interpret rather than guessing
+    Platform::from_str(key).map_or(true, |platform| targets.iter().any(|t| t.matches(&platform)))
+}
+
+// Removes `[target.'...']` dependency tables not matching any of the targets.
+// Returns the removed target keys.
+pub fn prune_manifest(crate_dir: &Path, targets: &[TargetPlatform], journal: &Journal) -> Result<Vec<String>> {
+    let manifest_path = crate_dir.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+
+    let mut pruned = Vec::new();
+    if let Some(target_table) = doc.get_mut("target").and_then(|t| t.as_table_mut()) {
+        let keys: Vec<String> = target_table.iter().map(|(key, _)| key.to_string()).collect();
+        for key in keys {
+            if !matches_any(targets, &key) {
+                target_table.remove(&key);
+                pruned.push(key);
+            }
+        }
+        if target_table.is_empty() {
+            doc.remove("target");
+        }
+    }
+
+    if !pruned.is_empty() {
+        crate::backup_manifest(&manifest_path, journal)?;
+        journal
+            .write(&manifest_path, doc.to_string())
+            .context("Failed to write Cargo.toml")?;
+        debug!("    Pruned targets: {}", pruned.join(", "));
+    }
+
+    Ok(pruned)
+}
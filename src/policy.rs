@@ -0,0 +1,165 @@
+use crate::filter::glob_match;
+use crate::spdx::Expression;
+use anyhow::Result;
+use cargo_metadata::{Metadata, Package, PackageId};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+use tracing::debug;
+
+#[derive(clap::Args, Clone, Default)]
+pub struct LicensePolicyArgs {
+    /// Only vendor crates whose license expression can be met with these licenses
+    /// (comma-separated SPDX identifiers, globs like `BSD-*` work)
+    #[clap(long, value_name = "LICENSES", value_delimiter = ',')]
+    pub allow_licenses: Vec<String>,
+    /// Refuse to vendor crates that can't be used without one of these licenses (e.g. `GPL-*`)
+    #[clap(long, value_name = "LICENSES", value_delimiter = ',')]
+    pub deny_licenses: Vec<String>,
+    // Recognizes the license of crates with only a `license-file`, instead of the built-in one
+    #[clap(skip)]
+    pub classifier: Option<Arc<dyn crate::Classifier>>,
+}
+
+impl LicensePolicyArgs {
+    fn is_empty(&self) -> bool {
+        self.allow_licenses.is_empty() && self.deny_licenses.is_empty()
+    }
+
+    // `id` alone or `id WITH exception` has to match
+    fn accepts(&self, id: &str, exception: Option<&str>) -> bool {
+        let with = exception.map(|exception| format!("{id} WITH {exception}"));
+        let matches =
+            |pattern: &String| glob_match(pattern, id) || with.as_deref().is_some_and(|with| glob_match(pattern, with));
+        (self.allow_licenses.is_empty() || self.allow_licenses.iter().any(matches))
+            && !self.deny_licenses.iter().any(matches)
+    }
+
+    // An `OR` needs one acceptable side, an `AND` all of them
+    fn satisfied_by(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::License { id, exception } => self.accepts(id, exception.as_deref()),
+            Expression::And(all) => all.iter().all(|expression| self.satisfied_by(expression)),
+            Expression::Or(alternatives) => alternatives.iter().any(|expression| self.satisfied_by(expression)),
+        }
+    }
+}
+
+// Refuses to go on if a crate about to be vendored is outside the license policy, listing each
+// one with the direct dependencies of workspace members that pull it in. Crates declaring only a
+// `license-file` are judged by the license recognized from it; crates whose license can't be
+// told only pass a policy without an allow list.
+pub fn check(metadata: &Metadata, candidates: &[&Package], policy: &LicensePolicyArgs) -> Result<()> {
+    if policy.is_empty() {
+        return Ok(());
+    }
+
+    let mut violators: Vec<(&Package, String)> = Vec::new();
+    for package in candidates {
+        let (license, expression) = match license(package, policy.classifier.as_deref()) {
+            Some((license, expression)) => (license, expression),
+            None if policy.allow_licenses.is_empty() => continue,
+            None => ("no license declared".to_string(), None),
+        };
+        if !expression.is_some_and(|expression| policy.satisfied_by(&expression)) {
+            violators.push((package, license));
+        }
+    }
+    debug!(
+        "{} of {} crate(s) outside the license policy",
+        violators.len(),
+        candidates.len()
+    );
+    if violators.is_empty() {
+        return Ok(());
+    }
+
+    let pulled_in_by = top_level_dependencies(metadata);
+    let mut report = format!("{} crate(s) are outside the license policy:", violators.len());
+    for (package, license) in &violators {
+        let via = pulled_in_by
+            .get(&package.id)
+            .map_or("no workspace member".to_string(), |via| via.join(", "));
+        write!(
+            report,
+            "\n  {} v{} ({license}), pulled in by {via}",
+            package.name, package.version
+        )?;
+    }
+    Err(crate::LocalizeError::Refused {
+        reason: crate::Refusal::License,
+        crates: violators
+            .iter()
+            .map(|(package, _)| crate::CrateRef {
+                name: package.name.clone(),
+                version: package.version.to_string(),
+                path: package
+                    .manifest_path
+                    .parent()
+                    .map(|dir| dir.as_std_path().to_path_buf()),
+            })
+            .collect(),
+        message: report,
+    }
+    .into())
+}
+
+// The declared expression, or the license recognized from the `license-file`
+fn license(package: &Package, classifier: Option<&dyn crate::Classifier>) -> Option<(String, Option<Expression>)> {
+    if let Some(license) = &package.license {
+        return Some((license.clone(), Expression::parse(license)));
+    }
+    let license_file = package.license_file()?;
+    let text = fs::read_to_string(license_file).ok()?;
+    let detection = crate::license::classify(classifier, &text)?;
+    let expression = Expression::License {
+        id: detection.license.clone(),
+        exception: None,
+    };
+    Some((format!("detected: {}", detection.license), Some(expression)))
+}
+
+// For each package, the direct dependencies of workspace members it's reached through, as
+// `dependency (member)`, or `member (direct)` for those direct dependencies themselves
+fn top_level_dependencies(metadata: &Metadata) -> HashMap<PackageId, Vec<String>> {
+    let Some(resolve) = &metadata.resolve else {
+        return HashMap::new();
+    };
+    let nodes: HashMap<&PackageId, &cargo_metadata::Node> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let names: HashMap<&PackageId, &str> = metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package.name.as_str()))
+        .collect();
+    let members: HashSet<&PackageId> = metadata.workspace_members.iter().collect();
+
+    let mut pulled_in_by: HashMap<PackageId, Vec<String>> = HashMap::new();
+    for member in &metadata.workspace_members {
+        let Some(node) = nodes.get(member) else {
+            continue;
+        };
+        for direct in node.deps.iter().filter(|dep| !members.contains(&dep.pkg)) {
+            let label = format!("{} ({})", names[&direct.pkg], names[member]);
+            let mut seen: HashSet<&PackageId> = HashSet::from([&direct.pkg]);
+            let mut queue = vec![&direct.pkg];
+            while let Some(id) = queue.pop() {
+                let label = if id == &direct.pkg {
+                    format!("{} (direct)", names[member])
+                } else {
+                    label.clone()
+                };
+                let via = pulled_in_by.entry(id.clone()).or_default();
+                if !via.contains(&label) {
+                    via.push(label);
+                }
+                for dep in nodes.get(id).map_or(&[][..], |node| node.deps.as_slice()) {
+                    if seen.insert(&dep.pkg) {
+                        queue.push(&dep.pkg);
+                    }
+                }
+            }
+        }
+    }
+    pulled_in_by
+}
@@ -0,0 +1,293 @@
+use crate::journal::Journal;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use tracing::debug;
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+
+// Device names Windows reserves in every directory, whatever the extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const INVALID_CHARS: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+// Target tables whose entries may point at a renamed file
+const TARGET_TABLES: [&str; 4] = ["bin", "example", "test", "bench"];
+// Files declaring modules that live next to them
+const MODULE_ROOTS: [&str; 4] = ["lib.rs", "main.rs", "mod.rs", "build.rs"];
+
+// Why a file name won't check out the same everywhere
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    // `aux.rs`, `con`, `lpt1.txt`
+    Reserved,
+    TrailingDotOrSpace,
+    InvalidCharacter(char),
+    NotUtf8,
+    // Decomposed (NFD) while every other OS and git store composed names as they're given
+    NotNfc,
+    // Another name in the directory only differs by case or normalization form
+    Collision(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Reserved => write!(f, "reserved name on Windows"),
+            Problem::TrailingDotOrSpace => write!(f, "trailing dot or space, dropped by Windows"),
+            Problem::InvalidCharacter(c) => write!(f, "`{}` isn't allowed on Windows", c.escape_default()),
+            Problem::NotUtf8 => write!(f, "not valid UTF-8"),
+            Problem::NotNfc => write!(f, "decomposed Unicode, macOS and Windows tools may not find it"),
+            Problem::Collision(other) => write!(f, "collides with `{other}` on case-insensitive file systems"),
+        }
+    }
+}
+
+// A problematic entry of a crate directory, by path relative to it
+pub struct Finding {
+    pub path: PathBuf,
+    pub problem: Problem,
+    // New path relative to the crate, once renamed
+    pub renamed: Option<PathBuf>,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}", slashed(&self.path), self.problem)?;
+        if let Some(renamed) = &self.renamed {
+            write!(f, ", renamed to {}", slashed(renamed))?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Entries of a vendored crate that wouldn't check out cleanly on Windows or macOS
+pub fn audit(crate_dir: &Path) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut folded: HashMap<(PathBuf, String), String> = HashMap::new();
+    for entry in WalkDir::new(crate_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.context(format!("Failed to read {}", crate_dir.display()))?;
+        let path = entry.path().strip_prefix(crate_dir)?.to_path_buf();
+        let Some(name) = entry.file_name().to_str() else {
+            findings.push(finding(path, Problem::NotUtf8));
+            continue;
+        };
+        let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if let Some(problem) = name_problem(name) {
+            findings.push(finding(path.clone(), problem));
+        }
+        let key = name.nfc().collect::<String>().to_lowercase();
+        if let Some(other) = folded.insert((parent, key), name.to_string()) {
+            findings.push(finding(path, Problem::Collision(other)));
+        }
+    }
+    Ok(findings)
+}
+
+// Renames the files with a name Windows rejects, or a decomposed one, and points `mod`
+// declarations and manifest paths at the new names. Directories and collisions are only reported,
+// there's no telling what refers to them.
+pub fn fix(crate_dir: &Path, findings: &mut [Finding], journal: &Journal) -> Result<()> {
+    let mut renames = Vec::new();
+    for finding in findings.iter_mut() {
+        if matches!(finding.problem, Problem::NotUtf8 | Problem::Collision(_))
+            || !crate_dir.join(&finding.path).is_file()
+        {
+            continue;
+        }
+        let Some(name) = finding.path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let renamed = finding.path.with_file_name(portable_name(name));
+        if renamed == finding.path || crate_dir.join(&renamed).exists() {
+            debug!("    Not renaming {}", slashed(&finding.path));
+            continue;
+        }
+        fs::rename(crate_dir.join(&finding.path), crate_dir.join(&renamed))
+            .context(format!("Failed to rename {}", slashed(&finding.path)))?;
+        debug!("    Renamed {} to {}", slashed(&finding.path), slashed(&renamed));
+        renames.push((finding.path.clone(), renamed.clone()));
+        finding.renamed = Some(renamed);
+    }
+    if renames.is_empty() {
+        return Ok(());
+    }
+    fix_manifest(crate_dir, &renames, journal)?;
+    for (from, to) in renames
+        .iter()
+        .filter(|(from, _)| from.extension().is_some_and(|ext| ext == "rs"))
+    {
+        fix_module_declaration(crate_dir, from, to, journal)?;
+    }
+    Ok(())
+}
+
+fn finding(path: PathBuf, problem: Problem) -> Finding {
+    Finding {
+        path,
+        problem,
+        renamed: None,
+    }
+}
+
+fn name_problem(name: &str) -> Option<Problem> {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        return Some(Problem::Reserved);
+    }
+    if name.ends_with(['.', ' ']) {
+        return Some(Problem::TrailingDotOrSpace);
+    }
+    if let Some(c) = name.chars().find(|c| INVALID_CHARS.contains(c) || c.is_control()) {
+        return Some(Problem::InvalidCharacter(c));
+    }
+    if !unicode_normalization::is_nfc(name) {
+        return Some(Problem::NotNfc);
+    }
+    None
+}
+
+// `aux.rs` becomes `aux_.rs`, `notes.` becomes `notes`, `a:b` becomes `a_b`
+fn portable_name(name: &str) -> String {
+    let name: String = name
+        .nfc()
+        .map(|c| {
+            if INVALID_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    let (stem, extension) = match name.split_once('.') {
+        Some((stem, extension)) => (stem, Some(extension)),
+        None => (name, None),
+    };
+    let stem = if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        format!("{}_", stem.trim_end())
+    } else if stem.is_empty() {
+        "_".to_string()
+    } else {
+        stem.to_string()
+    };
+    match extension {
+        Some(extension) => format!("{stem}.{extension}"),
+        None => stem,
+    }
+}
+
+// Points `package.build`, `readme`, `license-file` and target `path`s at renamed files
+fn fix_manifest(crate_dir: &Path, renames: &[(PathBuf, PathBuf)], journal: &Journal) -> Result<()> {
+    let manifest_path = crate_dir.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+    let renamed = |value: &toml_edit::Item| {
+        let value = Path::new(value.as_str()?.trim_start_matches("./")).to_path_buf();
+        renames
+            .iter()
+            .find(|(from, _)| *from == value)
+            .map(|(_, to)| slashed(to))
+    };
+
+    let mut changed = false;
+    let mut update = |table: &mut dyn toml_edit::TableLike, key: &str| {
+        if let Some(to) = table.get(key).and_then(renamed) {
+            table.insert(key, toml_edit::value(to));
+            changed = true;
+        }
+    };
+    if let Some(package) = doc.get_mut("package").and_then(|package| package.as_table_like_mut()) {
+        for key in ["build", "readme", "license-file"] {
+            update(package, key);
+        }
+    }
+    if let Some(lib) = doc.get_mut("lib").and_then(|lib| lib.as_table_like_mut()) {
+        update(lib, "path");
+    }
+    for table in TARGET_TABLES {
+        if let Some(targets) = doc.get_mut(table).and_then(|targets| targets.as_array_of_tables_mut()) {
+            for target in targets.iter_mut() {
+                update(target, "path");
+            }
+        }
+    }
+    if changed {
+        crate::backup_manifest(&manifest_path, journal)?;
+        journal
+            .write(&manifest_path, doc.to_string())
+            .context("Failed to write Cargo.toml")?;
+    }
+    Ok(())
+}
+
+// Adds `#[path = "..."]` to the `mod` declaring a renamed module file. It's declared next to it
+// (`lib.rs`, `main.rs`, `mod.rs` or a sibling) or by the file the directory is named after.
+fn fix_module_declaration(crate_dir: &Path, from: &Path, to: &Path, journal: &Journal) -> Result<()> {
+    let Some(module) = from.file_stem().and_then(|stem| stem.to_str()) else {
+        return Ok(());
+    };
+    let dir = from.parent().unwrap_or(Path::new(""));
+    let new_name = slashed(Path::new(to.file_name().unwrap_or_default()));
+    // Target directories hold crate roots only, elsewhere only these declare modules next to them
+    let roots_only = dir
+        .file_name()
+        .is_some_and(|name| ["bin", "examples", "tests", "benches"].contains(&name.to_string_lossy().as_ref()));
+    let mut declaring: Vec<(PathBuf, String)> = fs::read_dir(crate_dir.join(dir))
+        .context(format!("Failed to read {}", slashed(dir)))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs") && path.is_file())
+        .filter(|path| {
+            roots_only
+                || path
+                    .file_name()
+                    .is_some_and(|name| MODULE_ROOTS.iter().any(|root| name == *root))
+        })
+        .map(|path| (path, new_name.clone()))
+        .collect();
+    if let (Some(parent), Some(dir_name)) = (dir.parent(), dir.file_name()) {
+        let owner = crate_dir.join(parent).join(dir_name).with_extension("rs");
+        if owner.is_file() {
+            declaring.push((owner, format!("{}/{new_name}", dir_name.to_string_lossy())));
+        }
+    }
+
+    let declaration = format!("mod {module};");
+    for (file, path) in declaring {
+        let content = fs::read_to_string(&file).context(format!("Failed to read {}", file.display()))?;
+        if !content
+            .lines()
+            .any(|line| line.trim() == declaration || line.trim().ends_with(&format!(" {declaration}")))
+        {
+            continue;
+        }
+        let mut fixed = String::with_capacity(content.len());
+        for line in content.split_inclusive('\n') {
+            let trimmed = line.trim();
+            if trimmed == declaration || trimmed.ends_with(&format!(" {declaration}")) {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                fixed.push_str(&format!("{indent}#[path = \"{path}\"]\n"));
+            }
+            fixed.push_str(line);
+        }
+        journal
+            .write(&file, fixed)
+            .context(format!("Failed to write {}", file.display()))?;
+    }
+    Ok(())
+}
+
+fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
@@ -0,0 +1,79 @@
+use crate::journal::Journal;
+use crate::lock::LocalizeLock;
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::path::Path;
+use tracing::warn;
+
+pub const DATA_FILE_NAME: &str = "localize.verify";
+const PRELUDE_FILE_NAME: &str = "localize_prelude.rs";
+const PRELUDE: &str = include_str!("prelude/localize_prelude.rs");
+
+#[derive(clap::Args)]
+pub struct PreludeArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+}
+
+// Writes the build-prelude check next to localize.lock, with the hashes it checks against
+pub fn prelude(args: &PreludeArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+
+    let edits = crate::edits::find(&project_path, &localize_lock)?;
+    if !edits.is_empty() {
+        let crates: Vec<&str> = edits.keys().map(String::as_str).collect();
+        warn!(
+            "{} crate(s) have local edits, recorded as they are now: {}",
+            crates.len(),
+            crates.join(", ")
+        );
+    }
+    let journal = Journal::default();
+    write_data(&project_path, &localize_lock, &journal)?;
+    journal
+        .write(&project_path.join(PRELUDE_FILE_NAME), PRELUDE)
+        .context(format!("Failed to write {PRELUDE_FILE_NAME}"))?;
+
+    println!("Wrote {PRELUDE_FILE_NAME} and {DATA_FILE_NAME}, check the vendored crates from a build script with:");
+    println!();
+    println!("    #[path = \"<relative path to>/{PRELUDE_FILE_NAME}\"]");
+    println!("    mod localize_prelude;");
+    println!();
+    println!("    fn main() {{");
+    println!("        localize_prelude::verify();");
+    println!("    }}");
+    println!();
+    println!("Every run of cargo-localize keeps {DATA_FILE_NAME} up to date, commit both files");
+    Ok(())
+}
+
+// Keeps localize.verify in step with the vendored crates once a project checks them at build time
+pub fn refresh(project_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    if !project_path.join(DATA_FILE_NAME).exists() {
+        return Ok(());
+    }
+    write_data(project_path, localize_lock, journal)
+}
+
+// `D <crate dir>` for each crate and `F <sha256> <file>` for each of its files, paths relative to
+// the project root
+fn write_data(project_path: &Path, localize_lock: &LocalizeLock, journal: &Journal) -> Result<()> {
+    let mut data = format!(
+        "# Generated by cargo-localize, checked by {PRELUDE_FILE_NAME} before builds. Every run refreshes it.\n"
+    );
+    for locked in &localize_lock.packages {
+        let dir = format!("{}/{}", localize_lock.third_party_dir, locked.path);
+        let crate_dir = project_path.join(&dir);
+        if !crate_dir.is_dir() {
+            continue;
+        }
+        writeln!(data, "D {dir}")?;
+        for (file, hash) in crate::edits::hash_crate(&crate_dir)? {
+            writeln!(data, "F {hash} {dir}/{file}")?;
+        }
+    }
+    journal
+        .write(&project_path.join(DATA_FILE_NAME), data)
+        .context(format!("Failed to write {DATA_FILE_NAME}"))
+}
@@ -0,0 +1,160 @@
+// Generated by cargo-localize. Checks before a build that the vendored crates are exactly what
+// localize.verify records, so edits or tampering in the 3rd-party folder stop the build.
+//
+// From a build script:
+//
+//     #[path = "../localize_prelude.rs"]
+//     mod localize_prelude;
+//
+//     fn main() {
+//         localize_prelude::verify();
+//     }
+//
+// `CARGO_LOCALIZE_SKIP_VERIFY=1` skips the check, e.g. while editing a vendored crate.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DATA_FILE_NAME: &str = "localize.verify";
+
+// Panics with the files that differ, from a build script. Cargo reruns it when a vendored crate
+// changes.
+pub fn verify() {
+    println!("cargo:rerun-if-env-changed=CARGO_LOCALIZE_SKIP_VERIFY");
+    if std::env::var_os("CARGO_LOCALIZE_SKIP_VERIFY").is_some_and(|skip| skip != "0") {
+        println!("cargo:warning=CARGO_LOCALIZE_SKIP_VERIFY is set, the vendored crates weren't verified");
+        return;
+    }
+    let start = std::env::var_os("CARGO_MANIFEST_DIR").map_or_else(|| PathBuf::from("."), PathBuf::from);
+    let data_file = find_data_file(&start).unwrap_or_else(|| panic!("{DATA_FILE_NAME} not found above {}", start.display()));
+    let root = data_file.parent().unwrap_or(Path::new("."));
+    println!("cargo:rerun-if-changed={}", data_file.display());
+    for dir in crate_dirs(&data_file) {
+        println!("cargo:rerun-if-changed={}", root.join(dir).display());
+    }
+    if let Err(problems) = check(&data_file) {
+        panic!(
+            "The vendored crates don't match {DATA_FILE_NAME}:\n  {}\n\
+             Undo the edits, or set CARGO_LOCALIZE_SKIP_VERIFY=1 to build anyway",
+            problems.join("\n  ")
+        );
+    }
+}
+
+// The files that differ from the recorded ones, for an xtask or a test
+pub fn check(data_file: &Path) -> Result<(), Vec<String>> {
+    let root = data_file.parent().unwrap_or(Path::new("."));
+    let data = fs::read_to_string(data_file).map_err(|error| vec![format!("{}: {error}", data_file.display())])?;
+    let mut problems = Vec::new();
+    let mut recorded = Vec::new();
+    for line in data.lines() {
+        let Some((hash, path)) = line.strip_prefix("F ").and_then(|rest| rest.split_once(' ')) else {
+            continue;
+        };
+        recorded.push(root.join(path));
+        match fs::read(root.join(path)) {
+            Ok(content) if hex(&sha256(&content)) == hash => {}
+            Ok(_) => problems.push(format!("{path} (changed)")),
+            Err(_) => problems.push(format!("{path} (missing)")),
+        }
+    }
+    for dir in crate_dirs(data_file) {
+        let mut files = Vec::new();
+        list_files(&root.join(dir), &mut files);
+        for file in files.into_iter().filter(|file| !recorded.contains(file)) {
+            let shown = file.strip_prefix(root).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+            problems.push(format!("{shown} (added)"));
+        }
+    }
+    if problems.is_empty() { Ok(()) } else { Err(problems) }
+}
+
+fn find_data_file(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|dir| dir.join(DATA_FILE_NAME)).find(|file| file.is_file())
+}
+
+fn crate_dirs(data_file: &Path) -> Vec<String> {
+    let data = fs::read_to_string(data_file).unwrap_or_default();
+    data.lines().filter_map(|line| line.strip_prefix("D ")).map(str::to_string).collect()
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// SHA-256 (FIPS 180-4), so the check has no dependencies
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (chunk, value) in digest.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
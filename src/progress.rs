@@ -0,0 +1,76 @@
+use crate::observer::{Event, Observer, Phase};
+use crate::util::format_size;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Bars {
+    copy: Option<ProgressBar>,
+    copied_bytes: u64,
+    estimated_bytes: u64,
+    rewrite: Option<ProgressBar>,
+}
+
+// Draws the copy and rewrite phases as progress bars, hidden when stderr isn't a terminal
+#[derive(Default)]
+pub struct Progress {
+    bars: Mutex<Bars>,
+}
+
+impl Observer for Progress {
+    fn on_event(&self, event: &Event<'_>) {
+        let mut bars = self.bars.lock().unwrap();
+        match event {
+            Event::CopyPlanned { crates, bytes } => {
+                bars.estimated_bytes = *bytes;
+                bars.copied_bytes = 0;
+                let bar = new_bar(*crates as u64, "Copying  ", "crates");
+                bar.set_message(bytes_message(0, *bytes));
+                bars.copy = Some(bar);
+            }
+            Event::CrateCopied { bytes, .. } => {
+                bars.copied_bytes += bytes;
+                let message = bytes_message(bars.copied_bytes, bars.estimated_bytes);
+                if let Some(bar) = &bars.copy {
+                    bar.inc(1);
+                    bar.set_message(message);
+                }
+            }
+            Event::RewritePlanned { manifests } => {
+                bars.rewrite = Some(new_bar(*manifests as u64, "Rewriting", "manifests"));
+            }
+            Event::ManifestRewritten { .. } => {
+                if let Some(bar) = &bars.rewrite {
+                    bar.inc(1);
+                }
+            }
+            Event::PhaseFinished(Phase::Copy) => {
+                if let Some(bar) = bars.copy.take() {
+                    bar.finish();
+                }
+            }
+            Event::PhaseFinished(Phase::Rewrite) => {
+                if let Some(bar) = bars.rewrite.take() {
+                    bar.finish();
+                }
+            }
+            Event::Failed { .. } => {
+                for bar in [bars.copy.take(), bars.rewrite.take()].into_iter().flatten() {
+                    bar.abandon();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn new_bar(len: u64, prefix: &'static str, unit: &str) -> ProgressBar {
+    let style = ProgressStyle::with_template(&format!("{{prefix}} [{{wide_bar}}] {{pos}}/{{len}} {unit} {{msg}}"))
+        .expect("Progress bar template is valid")
+        .progress_chars("=> ");
+    ProgressBar::new(len).with_style(style).with_prefix(prefix)
+}
+
+fn bytes_message(copied: u64, estimated: u64) -> String {
+    format!("({} of about {})", format_size(copied), format_size(estimated))
+}
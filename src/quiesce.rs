@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use tracing::info;
+
+pub const MARKER_FILE_NAME: &str = ".cargo-localize.quiesce";
+const WATCHMAN_STATE: &str = "cargo-localize";
+
+// Tells file watchers a bulk change is in progress: a marker file exists at the project root
+// for the whole run, and watchman subscribers with `defer` see a `cargo-localize` state.
+pub struct Quiesce {
+    marker: PathBuf,
+    watchman: Option<Child>,
+}
+
+impl Quiesce {
+    pub fn enter(project_path: &Path, command: &str) -> Result<Self> {
+        let marker = project_path.join(MARKER_FILE_NAME);
+        fs::write(&marker, format!("{command} {}\n", std::process::id()))
+            .context(format!("Failed to create {MARKER_FILE_NAME}"))?;
+        info!("Quiescing file watchers ({MARKER_FILE_NAME})");
+
+        Ok(Self {
+            marker,
+            watchman: watchman_state(project_path, "state-enter"),
+        })
+    }
+}
+
+impl Drop for Quiesce {
+    fn drop(&mut self) {
+        if let Some(mut watchman) = self.watchman.take() {
+            if let Some(stdin) = watchman.stdin.as_mut() {
+                let root = self.marker.parent().unwrap_or(Path::new("."));
+                let _ = writeln!(stdin, "{}", state_command("state-leave", root));
+            }
+            drop(watchman.stdin.take());
+            let _ = watchman.wait();
+        }
+        let _ = fs::remove_file(&self.marker);
+    }
+}
+
+// Watchman leaves a state as soon as the client disconnects, so a persistent client is
+// kept open until the run ends. Projects without watchman simply get no state.
+fn watchman_state(root: &Path, command: &str) -> Option<Child> {
+    let mut child = Command::new("watchman")
+        .args(["--persistent", "--json-command", "--no-pretty"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    writeln!(child.stdin.as_mut()?, "{}", state_command(command, root)).ok()?;
+    Some(child)
+}
+
+fn state_command(command: &str, root: &Path) -> String {
+    serde_json::json!([command, root, WATCHMAN_STATE]).to_string()
+}
+
+// Directory vendored crates are assembled in before being moved into place with a single rename.
+// It lives in the target directory, which watchers ignore.
+pub fn staging_dir(target_directory: &Path) -> Result<PathBuf> {
+    let staging = target_directory.join("cargo-localize-staging");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).context("Failed to clean staging directory")?;
+    }
+    fs::create_dir_all(&staging).context("Failed to create staging directory")?;
+    Ok(staging)
+}
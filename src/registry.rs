@@ -0,0 +1,543 @@
+use crate::lock::LockedPackage;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use tracing::{debug, info, trace};
+
+// Written by cargo once a `.crate` archive is fully extracted
+const CARGO_OK_FILE_NAME: &str = ".cargo-ok";
+
+// Overrides where registry crates are looked for, like `--registry-src`, with the platform's path separator
+const REGISTRY_ENV: &str = "CARGO_LOCALIZE_REGISTRY";
+
+// Directory cargo extracts registry crates into, one subdirectory per registry index
+pub fn src_dir() -> Result<PathBuf> {
+    src_dirs(&[])?
+        .into_iter()
+        .next()
+        .context("Failed to find Cargo registry directory, point at it with CARGO_LOCALIZE_REGISTRY")
+}
+
+// Directories holding extracted registry crates: the ones given with `--registry-src`, else those
+// in `CARGO_LOCALIZE_REGISTRY`, else cargo's own if there is one. Docker builds and CI caches
+// often keep the registry where neither the home directory nor `CARGO_HOME` lead.
+pub fn src_dirs(given: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let given = if given.is_empty() {
+        std::env::var_os(REGISTRY_ENV)
+            .map(|paths| {
+                std::env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        given.to_vec()
+    };
+    if given.is_empty() {
+        return Ok(cargo_src_dir().into_iter().collect());
+    }
+    for src_dir in &given {
+        if !src_dir.is_dir() {
+            anyhow::bail!("Registry directory {} doesn't exist", src_dir.display());
+        }
+    }
+    Ok(given)
+}
+
+// Extracted registry crates by name and version, scanned once so looking up a crate doesn't
+// walk the registry again
+pub struct SourceIndex {
+    src_dirs: Vec<PathBuf>,
+    crates: HashMap<(String, String), PathBuf>,
+}
+
+impl SourceIndex {
+    // Each registry index has a directory of its own in a `registry/src`, with the crates it
+    // extracted as `<name>-<version>` in it. The first directory given wins for crates in several.
+    pub fn scan(src_dirs: Vec<PathBuf>) -> Result<Self> {
+        let mut crates = HashMap::new();
+        for src_dir in &src_dirs {
+            debug!("Using cargo registry: {}", src_dir.display());
+            for index_entry in fs::read_dir(src_dir).context(format!("Failed to read {}", src_dir.display()))? {
+                let index_entry = index_entry?;
+                if !index_entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let index_dir = index_entry.path();
+                trace!("    Indexing registry: {}", index_dir.display());
+                for crate_entry in
+                    fs::read_dir(&index_dir).context(format!("Failed to read {}", index_dir.display()))?
+                {
+                    let crate_entry = crate_entry?;
+                    if !crate_entry.file_type()?.is_dir() {
+                        continue;
+                    }
+                    if let Some(key) = split_dir_name(&crate_entry.file_name().to_string_lossy()) {
+                        crates.entry(key).or_insert_with(|| crate_entry.path());
+                    }
+                }
+            }
+        }
+        Ok(SourceIndex { src_dirs, crates })
+    }
+
+    pub fn find(&self, name: &str, version: &str) -> Result<&Path> {
+        trace!("  Looking for crate source: {name}-{version}");
+        self.crates
+            .get(&(name.to_string(), version.to_string()))
+            .map(PathBuf::as_path)
+            .with_context(|| {
+                if self.src_dirs.is_empty() {
+                    return format!(
+                        "Crate {name}:{version} not found: no Cargo registry directory, point at it with --registry-src"
+                    );
+                }
+                let searched: Vec<String> = self.src_dirs.iter().map(|src| src.display().to_string()).collect();
+                format!(
+                    "Crate {name}:{version} not found in Cargo registry at {}",
+                    searched.join(", ")
+                )
+            })
+    }
+}
+
+// `<name>-<version>`, split where the rest is a version: names and pre-releases have dashes too
+fn split_dir_name(dir_name: &str) -> Option<(String, String)> {
+    dir_name
+        .match_indices('-')
+        .map(|(position, _)| (&dir_name[..position], &dir_name[position + 1..]))
+        .find(|(name, version)| !name.is_empty() && cargo_metadata::semver::Version::parse(version).is_ok())
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+}
+
+fn cargo_src_dir() -> Option<PathBuf> {
+    // Try multiple possible cargo registry locations
+    let possible_cargo_homes = vec![
+        dirs::home_dir().map(|p| p.join(".cargo/registry/src")),
+        std::env::var("CARGO_HOME")
+            .ok()
+            .map(|p| PathBuf::from(p).join("registry/src")),
+    ];
+
+    possible_cargo_homes
+        .into_iter()
+        .find_map(|p| p.filter(|path| path.exists()))
+}
+
+// Names cargo's configuration gives alternative registries (`[registries.<name>] index = ...` or
+// `CARGO_REGISTRIES_<NAME>_INDEX`), by the source their crates resolve from. The configuration
+// closest to the project wins, like it does for cargo.
+pub fn alternative_registries(project_path: &Path) -> BTreeMap<String, String> {
+    let mut registries = BTreeMap::new();
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")));
+    let config_dirs = project_path
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .collect::<Vec<_>>();
+    for config_dir in config_dirs.iter().rev() {
+        for file in ["config", "config.toml"] {
+            let Some(config) = fs::read_to_string(config_dir.join(file))
+                .ok()
+                .and_then(|content| content.parse::<DocumentMut>().ok())
+            else {
+                continue;
+            };
+            let Some(entries) = config.get("registries").and_then(|r| r.as_table_like()) else {
+                continue;
+            };
+            for (name, entry) in entries.iter() {
+                if let Some(index) = entry.get("index").and_then(|index| index.as_str()) {
+                    registries.retain(|_, known| *known != name);
+                    registries.insert(source_of_index(index), name.to_string());
+                }
+            }
+        }
+    }
+    for (key, index) in std::env::vars() {
+        if let Some(name) = key
+            .strip_prefix("CARGO_REGISTRIES_")
+            .and_then(|key| key.strip_suffix("_INDEX"))
+        {
+            let name = name.to_lowercase().replace('_', "-");
+            registries.retain(|_, known| *known != name);
+            registries.insert(source_of_index(&index), name);
+        }
+    }
+    registries
+}
+
+// Source id of the crates of a registry index: `sparse+<url>` as given, `registry+<url>` for git indexes
+fn source_of_index(index: &str) -> String {
+    if index.starts_with("sparse+") {
+        index.to_string()
+    } else {
+        format!("registry+{index}")
+    }
+}
+
+// Checks the extracted sources of every registry crate in Cargo.lock. This runs before
+// `cargo metadata`, which would otherwise stumble over a damaged manifest first.
+pub fn verify_sources(checksums: &HashMap<(String, String), String>, src_dirs: &[PathBuf]) -> Result<()> {
+    let mut repaired = 0;
+    for src_dir in src_dirs {
+        for index_entry in fs::read_dir(src_dir).context(format!("Failed to read {}", src_dir.display()))? {
+            let index_dir = index_entry?.path();
+            for ((name, version), checksum) in checksums {
+                let source_path = index_dir.join(format!("{name}-{version}"));
+                if source_path.is_dir() && ensure_intact(&source_path, name, version, checksum)? {
+                    repaired += 1;
+                }
+            }
+        }
+    }
+    if repaired > 0 {
+        info!("Re-extracted {repaired} damaged registry source(s)");
+    }
+    Ok(())
+}
+
+// Makes sure an extracted registry source is complete before it's vendored. Interrupted
+// extractions and damaged trees are replaced with a fresh extraction of the cached `.crate`,
+// otherwise the broken tree would only surface once an offline build fails.
+// Returns whether the source had to be extracted again.
+fn ensure_intact(source_path: &Path, name: &str, version: &str, checksum: &str) -> Result<bool> {
+    // A `.crate` with another checksum is the same name and version from a different registry
+    let archive = match cached_archive(source_path, name, version) {
+        Some(archive) => {
+            let bytes = fs::read(&archive).context(format!("Failed to read {}", archive.display()))?;
+            (crate::journal::sha256(&bytes) == checksum).then_some((archive, bytes))
+        }
+        None => None,
+    };
+
+    let problem = match source_problem(source_path, name, version) {
+        Some(problem) => problem,
+        None => match &archive {
+            Some((_, bytes)) => match differing_file(source_path, bytes)? {
+                Some(problem) => problem,
+                None => return Ok(false),
+            },
+            None => return Ok(false),
+        },
+    };
+
+    let Some((archive, bytes)) = archive else {
+        anyhow::bail!(
+            "Registry source of {name} v{version} is damaged ({problem}) and no cached .crate matches \
+             its checksum, remove {} and run `cargo fetch`",
+            source_path.display()
+        );
+    };
+    crate::warnings::warn(
+        crate::WarningClass::RepairedSource,
+        format!("Registry source of {name} v{version} is damaged ({problem}), extracting it again"),
+    );
+
+    fs::remove_dir_all(source_path).context(format!("Failed to remove {}", source_path.display()))?;
+    let extract_to = source_path
+        .parent()
+        .context("Registry source has no parent directory")?;
+    extract(&bytes, extract_to, &format!("{name}-{version}"))
+        .context(format!("Failed to extract {}", archive.display()))?;
+    fs::write(source_path.join(CARGO_OK_FILE_NAME), r#"{"v":1}"#).context("Failed to write .cargo-ok")?;
+
+    if let Some(problem) = source_problem(source_path, name, version) {
+        anyhow::bail!("Registry source of {name} v{version} is still damaged after extracting it again: {problem}");
+    }
+    Ok(true)
+}
+
+fn source_problem(source_path: &Path, name: &str, version: &str) -> Option<String> {
+    if !source_path.join(CARGO_OK_FILE_NAME).is_file() {
+        return Some(format!("{CARGO_OK_FILE_NAME} is missing"));
+    }
+
+    let manifest_path = source_path.join("Cargo.toml");
+    let Ok(content) = fs::read_to_string(&manifest_path) else {
+        return Some("Cargo.toml can't be read".to_string());
+    };
+    let Ok(doc) = content.parse::<DocumentMut>() else {
+        return Some("Cargo.toml doesn't parse".to_string());
+    };
+    let field = |key: &str| doc.get("package")?.get(key)?.as_str().map(|value| value.to_string());
+    if field("name").as_deref() != Some(name) {
+        return Some(format!("Cargo.toml isn't the manifest of {name}"));
+    }
+    if field("version").as_deref() != Some(version) {
+        return Some(format!("Cargo.toml doesn't declare version {version}"));
+    }
+    None
+}
+
+// First file of the archive that is missing from the extracted tree or has a different size
+fn differing_file(source_path: &Path, bytes: &[u8]) -> Result<Option<String>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?;
+        let relative: PathBuf = path.components().skip(1).collect();
+        match fs::metadata(source_path.join(&relative)) {
+            Ok(metadata) if metadata.len() == entry.size() => {}
+            Ok(_) => return Ok(Some(format!("{} has a different size", relative.display()))),
+            Err(_) => return Ok(Some(format!("{} is missing", relative.display()))),
+        }
+    }
+    Ok(None)
+}
+
+// Size of a registry crate once extracted, from its extracted sources or else its cached `.crate`.
+// `None` if cargo never downloaded it.
+pub fn crate_size(name: &str, version: &str) -> Option<u64> {
+    let src_dir = src_dir().ok()?;
+    for index_entry in fs::read_dir(&src_dir).ok()?.flatten() {
+        let source_path = index_entry.path().join(format!("{name}-{version}"));
+        if source_path.is_dir() {
+            return Some(crate::util::dir_size(&source_path));
+        }
+        if let Some(archive) = cached_archive(&source_path, name, version) {
+            return unpacked_size(&archive).ok();
+        }
+    }
+    None
+}
+
+fn unpacked_size(archive: &Path) -> Result<u64> {
+    let file = fs::File::open(archive).context(format!("Failed to open {}", archive.display()))?;
+    let mut size = 0;
+    for entry in tar::Archive::new(GzDecoder::new(file)).entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            size += entry.header().size()?;
+        }
+    }
+    Ok(size)
+}
+
+// Cached `.crate` of a crates.io crate, `None` if cargo never downloaded it
+pub fn crates_io_archive(name: &str, version: &str) -> Option<PathBuf> {
+    let cache_dir = src_dir().ok()?.parent()?.join("cache");
+    fs::read_dir(cache_dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|index_dir| {
+            index_dir.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy();
+                name.starts_with("index.crates.io-") || name.starts_with("github.com-")
+            })
+        })
+        .map(|index_dir| index_dir.join(format!("{name}-{version}.crate")))
+        .find(|archive| archive.is_file())
+}
+
+// Cached `.crate` of a crate from any registry, `None` if cargo never downloaded it or it's a git
+// crate. Cache directories are named `<index host>-<hash>`, crates of several registries on one
+// host are told apart by their checksum.
+pub fn registry_archive(locked: &LockedPackage) -> Option<PathBuf> {
+    if locked.is_from_crates_io() {
+        return crates_io_archive(&locked.name, &locked.version);
+    }
+    let source = locked.source.as_deref()?;
+    let url = source
+        .strip_prefix("registry+")
+        .or_else(|| source.strip_prefix("sparse+"))?;
+    let authority = url
+        .split_once("://")
+        .map_or("", |(_, rest)| rest.split(['/', '?', '#']).next().unwrap_or_default());
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let prefix = format!("{}-", host.split(':').next().unwrap_or_default());
+
+    let cache_dir = src_dir().ok()?.parent()?.join("cache");
+    fs::read_dir(cache_dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|index_dir| {
+            index_dir
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .map(|index_dir| index_dir.join(format!("{}-{}.crate", locked.name, locked.version)))
+        .filter(|archive| archive.is_file())
+        .find(|archive| {
+            locked
+                .checksum
+                .as_ref()
+                .is_none_or(|checksum| crate::journal::file_sha256(archive).is_ok_and(|sha256| sha256 == *checksum))
+        })
+}
+
+// Files of a `.crate` archive by their path inside the crate
+pub fn archive_files(bytes: &[u8]) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        // Below the `<name>-<version>/` folder every archive has, like `extract` unpacks it
+        let path = entry.path()?.into_owned();
+        let relative: PathBuf = path.components().skip(1).collect();
+        if !crate::safety::is_contained(&path)
+            || !relative.as_os_str().is_empty() && !crate::safety::is_contained(&relative)
+        {
+            anyhow::bail!("Archive entry {} is outside of the crate", path.display());
+        }
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(relative, content);
+    }
+    Ok(files)
+}
+
+// `registry/src/<index>/<name>-<version>` was extracted from `registry/cache/<index>/<name>-<version>.crate`
+fn cached_archive(source_path: &Path, name: &str, version: &str) -> Option<PathBuf> {
+    let index_dir = source_path.parent()?;
+    let registry_dir = index_dir.parent()?.parent()?;
+    let archive = registry_dir
+        .join("cache")
+        .join(index_dir.file_name()?)
+        .join(format!("{name}-{version}.crate"));
+    archive.is_file().then_some(archive)
+}
+
+// Unpacks the archive's `<name>-<version>/` tree into `destination`, refusing anything outside it
+fn extract(bytes: &[u8], destination: &Path, root: &str) -> Result<()> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !path.starts_with(root) || !crate::safety::is_contained(&path) {
+            anyhow::bail!("Archive entry {} is outside of {root}/", path.display());
+        }
+        if !entry.unpack_in(destination)? {
+            anyhow::bail!("Archive entry {} would be unpacked outside of {root}/", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SymlinkPolicy;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tar::{EntryType, Header};
+
+    // A `.crate`-like archive written byte by byte, so entries `tar::Builder` would refuse to
+    // write (`..`, absolute paths) end up in it as an attacker would put them there
+    fn crafted(entries: &[(&str, EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, entry_type, content) in entries {
+            let mut header = Header::new_gnu();
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            if *entry_type == EntryType::Symlink {
+                header.as_gnu_mut().unwrap().linkname[..content.len()].copy_from_slice(content.as_bytes());
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, std::io::empty()).unwrap();
+            } else {
+                header.set_size(content.len() as u64);
+                header.set_cksum();
+                builder.append(&header, content.as_bytes()).unwrap();
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn archive_files_reads_the_crate() {
+        let bytes = crafted(&[
+            ("foo-1.0.0/Cargo.toml", EntryType::Regular, "[package]"),
+            ("foo-1.0.0/src/lib.rs", EntryType::Regular, "fn main() {}"),
+        ]);
+        let files = archive_files(&bytes).unwrap();
+        assert_eq!(files[Path::new("src/lib.rs")], b"fn main() {}");
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn archive_files_refuses_parent_directories() {
+        let bytes = crafted(&[("foo-1.0.0/../../etc/cron.d/evil", EntryType::Regular, "x")]);
+        assert!(archive_files(&bytes).is_err());
+    }
+
+    #[test]
+    fn archive_files_refuses_absolute_paths() {
+        let bytes = crafted(&[("/etc/cron.d/evil", EntryType::Regular, "x")]);
+        assert!(archive_files(&bytes).is_err());
+    }
+
+    #[test]
+    fn archive_files_refuses_escaping_directories() {
+        let bytes = crafted(&[
+            ("foo-1.0.0/..", EntryType::Directory, ""),
+            ("foo-1.0.0/src/lib.rs", EntryType::Regular, ""),
+        ]);
+        assert!(archive_files(&bytes).is_err());
+    }
+
+    #[test]
+    fn extract_unpacks_the_crate() {
+        let destination = tempfile::tempdir().unwrap();
+        let bytes = crafted(&[("foo-1.0.0/src/lib.rs", EntryType::Regular, "fn main() {}")]);
+        extract(&bytes, destination.path(), "foo-1.0.0").unwrap();
+        let crate_dir = destination.path().join("foo-1.0.0");
+        assert_eq!(
+            fs::read_to_string(crate_dir.join("src/lib.rs")).unwrap(),
+            "fn main() {}"
+        );
+        crate::safety::verify_crate_tree(&crate_dir, SymlinkPolicy::Follow).unwrap();
+    }
+
+    #[test]
+    fn extract_refuses_parent_directories() {
+        let destination = tempfile::tempdir().unwrap();
+        let bytes = crafted(&[("foo-1.0.0/../evil", EntryType::Regular, "x")]);
+        assert!(extract(&bytes, &destination.path().join("dir"), "foo-1.0.0").is_err());
+        assert!(!destination.path().join("evil").exists());
+    }
+
+    #[test]
+    fn extract_refuses_absolute_paths() {
+        let destination = tempfile::tempdir().unwrap();
+        let bytes = crafted(&[("/tmp/cargo-localize-evil", EntryType::Regular, "x")]);
+        assert!(extract(&bytes, destination.path(), "foo-1.0.0").is_err());
+    }
+
+    #[test]
+    fn extract_refuses_entries_of_other_crates() {
+        let destination = tempfile::tempdir().unwrap();
+        let bytes = crafted(&[("bar-1.0.0/src/lib.rs", EntryType::Regular, "x")]);
+        assert!(extract(&bytes, destination.path(), "foo-1.0.0").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_an_extracted_crate_are_refused() {
+        let destination = tempfile::tempdir().unwrap();
+        let bytes = crafted(&[
+            ("foo-1.0.0/src/lib.rs", EntryType::Regular, ""),
+            ("foo-1.0.0/passwd", EntryType::Symlink, "/etc/passwd"),
+            ("foo-1.0.0/up", EntryType::Symlink, "../../.."),
+        ]);
+        extract(&bytes, destination.path(), "foo-1.0.0").unwrap();
+        let crate_dir = destination.path().join("foo-1.0.0");
+        assert!(crate::safety::verify_crate_tree(&crate_dir, SymlinkPolicy::Follow).is_err());
+    }
+}
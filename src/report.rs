@@ -0,0 +1,153 @@
+use crate::LocalizeError;
+use crate::lock::LocalizeLock;
+use crate::observer::{Event, Observer, SkipReason};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Progress and summary lines
+    Human,
+    /// A JSON document describing the run on stdout once it's over, log lines go to stderr
+    Json,
+}
+
+#[derive(Default)]
+struct Collected {
+    // Source each crate copied by this run came from, by name and version
+    sources: HashMap<(String, String), PathBuf>,
+    skipped: Vec<SkippedCrate>,
+    failed: Vec<FailedCrate>,
+    manifests_rewritten: Vec<PathBuf>,
+}
+
+// Collects what a run did for the `--output json` document
+#[derive(Default)]
+pub struct Report {
+    collected: Mutex<Collected>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    project: PathBuf,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    crates: Vec<VendoredCrate>,
+    skipped: Vec<SkippedCrate>,
+    failed: Vec<FailedCrate>,
+    manifests_rewritten: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct VendoredCrate {
+    name: String,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    // Only known for crates copied by this run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_path: Option<PathBuf>,
+    destination: PathBuf,
+    features: Vec<String>,
+    copied: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct SkippedCrate {
+    name: String,
+    version: String,
+    reason: &'static str,
+}
+
+#[derive(Serialize, Clone)]
+struct FailedCrate {
+    name: String,
+    version: String,
+    error: String,
+}
+
+impl Observer for Report {
+    fn on_event(&self, event: &Event<'_>) {
+        let mut collected = self.collected.lock().unwrap();
+        match event {
+            Event::CrateCopied {
+                name, version, source, ..
+            } => {
+                collected
+                    .sources
+                    .insert((name.to_string(), version.to_string()), source.to_path_buf());
+            }
+            Event::CrateSkipped { name, version, reason } => collected.skipped.push(SkippedCrate {
+                name: name.to_string(),
+                version: version.to_string(),
+                reason: reason_name(*reason),
+            }),
+            Event::CrateFailed { name, version, error } => collected.failed.push(FailedCrate {
+                name: name.to_string(),
+                version: version.to_string(),
+                error: crate::error::chain(*error),
+            }),
+            Event::ManifestRewritten { path } => collected.manifests_rewritten.push(path.to_path_buf()),
+            _ => {}
+        }
+    }
+}
+
+impl Report {
+    // Prints the document, vendored crates are read from the lock the run left behind
+    pub fn print(&self, project_path: &Path, error: Option<&LocalizeError>) -> Result<()> {
+        let collected = self.collected.lock().unwrap();
+        let sources = &collected.sources;
+        let localize_lock = LocalizeLock::load(project_path)?;
+        let crates = localize_lock
+            .iter()
+            .flat_map(|lock| {
+                let third_party_path = lock.third_party_path(project_path);
+                lock.packages.iter().map(move |locked| {
+                    let source_path = sources.get(&(locked.name.clone(), locked.version.clone())).cloned();
+                    VendoredCrate {
+                        name: locked.name.clone(),
+                        version: locked.version.clone(),
+                        source: locked.source.clone(),
+                        copied: source_path.is_some(),
+                        source_path,
+                        destination: third_party_path.join(&locked.path),
+                        features: locked.features.clone(),
+                    }
+                })
+            })
+            .collect();
+
+        let document = Document {
+            project: project_path.to_path_buf(),
+            success: error.is_none(),
+            error: error.map(|error| crate::error::chain(error)),
+            crates,
+            skipped: collected.skipped.clone(),
+            failed: collected.failed.clone(),
+            manifests_rewritten: collected.manifests_rewritten.clone(),
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&document).context("Failed to serialize the report")?
+        );
+        Ok(())
+    }
+}
+
+fn reason_name(reason: SkipReason) -> &'static str {
+    match reason {
+        SkipReason::AlreadyLocalized => "already-localized",
+        SkipReason::AlreadyVendored => "already-vendored",
+        SkipReason::NotNeeded => "not-needed",
+        SkipReason::WorkspaceMember => "workspace-member",
+        SkipReason::ExternalPath => "external-path",
+        SkipReason::SharesMemberVersion => "shares-member-version",
+        SkipReason::NotSelected => "not-selected",
+        SkipReason::TooDeep => "too-deep",
+    }
+}
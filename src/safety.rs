@@ -0,0 +1,106 @@
+use crate::config::SymlinkPolicy;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+// Relative path made of plain components only, so joining it can't leave the base directory
+pub fn is_contained(relative: &Path) -> bool {
+    !relative.as_os_str().is_empty() && relative.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+pub fn safe_join(base: &Path, relative: &str) -> Result<PathBuf> {
+    if !is_contained(Path::new(relative)) {
+        anyhow::bail!("Refusing path `{relative}`: it would leave {}", base.display());
+    }
+    Ok(base.join(relative))
+}
+
+// Crate sources come from archives and repositories we don't control. Before a crate is copied
+// into the tree every entry has to be a regular file, a directory or a symlink resolving inside
+// the crate, otherwise the copy could pull in files from elsewhere on the machine.
+pub fn verify_crate_tree(root: &Path, symlinks: SymlinkPolicy) -> Result<()> {
+    let root = root
+        .canonicalize()
+        .context(format!("Failed to resolve {}", root.display()))?;
+
+    for entry in WalkDir::new(&root).follow_links(false) {
+        let entry = entry.context(format!("Failed to read {}", root.display()))?;
+        let relative = entry.path().strip_prefix(&root)?;
+        if !relative.as_os_str().is_empty() && !is_contained(relative) {
+            anyhow::bail!(
+                "{} has an entry outside of it: {}",
+                root.display(),
+                entry.path().display()
+            );
+        }
+
+        let file_type = entry.file_type();
+        // Links left out can point anywhere
+        if file_type.is_symlink() && symlinks == SymlinkPolicy::Skip {
+            continue;
+        }
+        if file_type.is_symlink() {
+            let target =
+                fs::canonicalize(entry.path()).context(format!("{} is a dangling symlink", entry.path().display()))?;
+            if !target.starts_with(&root) {
+                anyhow::bail!(
+                    "{} is a symlink pointing outside the crate to {}",
+                    entry.path().display(),
+                    target.display()
+                );
+            }
+        } else if !file_type.is_file() && !file_type.is_dir() {
+            anyhow::bail!("{} is not a regular file or directory", entry.path().display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_join_keeps_paths_inside() {
+        let base = Path::new("/project/3rd-party");
+        assert_eq!(
+            safe_join(base, "serde-1.0.0/src").unwrap(),
+            Path::new("/project/3rd-party/serde-1.0.0/src")
+        );
+    }
+
+    #[test]
+    fn safe_join_refuses_escaping_paths() {
+        let base = Path::new("/project/3rd-party");
+        for relative in ["../outside", "serde/../../outside", "/etc/passwd", "", "."] {
+            assert!(safe_join(base, relative).is_err(), "{relative} was accepted");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_crate_tree_accepts_links_inside_the_crate() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(crate_dir.path().join("src")).unwrap();
+        fs::write(crate_dir.path().join("src/lib.rs"), "").unwrap();
+        std::os::unix::fs::symlink("src/lib.rs", crate_dir.path().join("lib.rs")).unwrap();
+        verify_crate_tree(crate_dir.path(), SymlinkPolicy::Follow).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_crate_tree_refuses_links_outside_the_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_dir = dir.path().join("foo-1.0.0");
+        fs::create_dir(&crate_dir).unwrap();
+        fs::write(dir.path().join("secret"), "").unwrap();
+        std::os::unix::fs::symlink("../secret", crate_dir.join("secret")).unwrap();
+        assert!(verify_crate_tree(&crate_dir, SymlinkPolicy::Follow).is_err());
+
+        fs::remove_file(crate_dir.join("secret")).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", crate_dir.join("passwd")).unwrap();
+        assert!(verify_crate_tree(&crate_dir, SymlinkPolicy::Follow).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::export::{InventoryEntry, inventory_entry};
+use crate::lock::LocalizeLock;
+use crate::util::{format_timestamp, unix_now};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(clap::Args)]
+pub struct SbomArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// SBOM format
+    #[clap(long, value_enum, default_value = "spdx")]
+    format: SbomFormat,
+    /// Write the SBOM to a file instead of stdout
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SbomFormat {
+    /// SPDX 2.3 (JSON)
+    Spdx,
+    /// CycloneDX 1.5 (JSON)
+    Cyclonedx,
+}
+
+// A workspace member, the subject of the SBOM
+struct Member {
+    name: String,
+    version: String,
+}
+
+// Packages of the SBOM keyed by name and version, with the ones each depends on
+type Dependencies = BTreeMap<(String, String), BTreeSet<(String, String)>>;
+
+pub fn sbom(args: &SbomArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let entries = localize_lock
+        .packages
+        .iter()
+        .map(|locked| inventory_entry(&localize_lock, locked, &project_path))
+        .collect::<Result<Vec<_>>>()?;
+
+    // The crates are listed from localize.lock either way, the graph only adds the edges
+    let (members, dependencies) = match graph(&project_path, &args.network, &entries) {
+        Ok(graph) => graph,
+        Err(error) => {
+            warn!("Couldn't resolve the dependency graph, the SBOM lists crates without their dependencies: {error:#}");
+            (Vec::new(), Dependencies::new())
+        }
+    };
+
+    let project_name = project_path
+        .file_name()
+        .map_or("project".to_string(), |name| name.to_string_lossy().to_string());
+    let timestamp = format_timestamp(unix_now());
+    // Unique per document, as both formats ask
+    let id = crate::journal::sha256(format!("{}\n{timestamp}", project_path.display()).as_bytes());
+    let document = match args.format {
+        SbomFormat::Spdx => spdx(&entries, &members, &dependencies, &project_name, &timestamp, &id),
+        SbomFormat::Cyclonedx => cyclonedx(&entries, &members, &dependencies, &project_name, &timestamp, &id),
+    };
+    let content = serde_json::to_string_pretty(&document).context("Failed to serialize SBOM")?;
+
+    match &args.output {
+        Some(output) => {
+            fs::write(output, content + "\n").context(format!("Failed to write {}", output.display()))?;
+            eprintln!("Wrote an SBOM of {} crate(s) to {}", entries.len(), output.display());
+        }
+        None => println!("{content}"),
+    }
+    Ok(())
+}
+
+// Workspace members and the edges between them and the vendored crates
+fn graph(
+    project_path: &Path,
+    network: &crate::NetworkArgs,
+    entries: &[InventoryEntry],
+) -> Result<(Vec<Member>, Dependencies)> {
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let resolve = metadata.resolve.as_ref().context("No resolve data in metadata")?;
+
+    let key = |id: &cargo_metadata::PackageId| {
+        metadata
+            .packages
+            .iter()
+            .find(|package| &package.id == id)
+            .map(|package| (package.name.clone(), package.version.to_string()))
+    };
+    let members: Vec<Member> = metadata
+        .workspace_packages()
+        .iter()
+        .map(|package| Member {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+        })
+        .collect();
+    let listed: BTreeSet<(String, String)> = entries
+        .iter()
+        .map(|entry| (entry.locked.name.clone(), entry.locked.version.clone()))
+        .chain(
+            members
+                .iter()
+                .map(|member| (member.name.clone(), member.version.clone())),
+        )
+        .collect();
+
+    let mut dependencies = Dependencies::new();
+    for node in &resolve.nodes {
+        let Some(package) = key(&node.id).filter(|package| listed.contains(package)) else {
+            continue;
+        };
+        let depends_on = node
+            .deps
+            .iter()
+            .filter_map(|dep| key(&dep.pkg))
+            .filter(|dep| listed.contains(dep))
+            .collect();
+        dependencies.insert(package, depends_on);
+    }
+    Ok((members, dependencies))
+}
+
+fn spdx(
+    entries: &[InventoryEntry],
+    members: &[Member],
+    dependencies: &Dependencies,
+    project_name: &str,
+    timestamp: &str,
+    id: &str,
+) -> Value {
+    let mut packages: Vec<Value> = members
+        .iter()
+        .map(|member| {
+            json!({
+                "name": member.name,
+                "SPDXID": spdx_id(&member.name, &member.version),
+                "versionInfo": member.version,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "primaryPackagePurpose": "APPLICATION",
+            })
+        })
+        .collect();
+    packages.extend(entries.iter().map(|entry| {
+        let mut package = json!({
+            "name": entry.locked.name,
+            "SPDXID": spdx_id(&entry.locked.name, &entry.locked.version),
+            "versionInfo": entry.locked.version,
+            "downloadLocation": download_location(entry).unwrap_or_else(|| "NOASSERTION".to_string()),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": entry.license.as_deref().unwrap_or("NOASSERTION"),
+            "copyrightText": "NOASSERTION",
+            "primaryPackagePurpose": "LIBRARY",
+            "sourceInfo": format!("vendored at {}", entry.path),
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": entry.purl(),
+            }],
+        });
+        if let Some(checksum) = &entry.locked.checksum {
+            package["checksums"] = json!([{"algorithm": "SHA256", "checksumValue": checksum}]);
+        }
+        if let Some(homepage) = &entry.homepage {
+            package["homepage"] = json!(homepage);
+        }
+        if let Some(description) = &entry.description {
+            package["description"] = json!(description);
+        }
+        if !entry.authors.is_empty() {
+            package["originator"] = json!(format!("Person: {}", entry.authors.join(", ")));
+        }
+        package
+    }));
+
+    // The document describes the workspace members, or every crate when they aren't known
+    let described: Vec<String> = if members.is_empty() {
+        entries
+            .iter()
+            .map(|entry| spdx_id(&entry.locked.name, &entry.locked.version))
+            .collect()
+    } else {
+        members
+            .iter()
+            .map(|member| spdx_id(&member.name, &member.version))
+            .collect()
+    };
+    let mut relationships: Vec<Value> = described
+        .iter()
+        .map(|described| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": described,
+            })
+        })
+        .collect();
+    for ((name, version), depends_on) in dependencies {
+        relationships.extend(depends_on.iter().map(|(dep_name, dep_version)| {
+            json!({
+                "spdxElementId": spdx_id(name, version),
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id(dep_name, dep_version),
+            })
+        }));
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": project_name,
+        "documentNamespace": format!("https://spdx.org/spdxdocs/cargo-localize/{project_name}-{id}"),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: cargo-localize-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "documentDescribes": described,
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+fn cyclonedx(
+    entries: &[InventoryEntry],
+    members: &[Member],
+    dependencies: &Dependencies,
+    project_name: &str,
+    timestamp: &str,
+    id: &str,
+) -> Value {
+    let bom_ref = |name: &str, version: &str| format!("pkg:cargo/{name}@{version}");
+
+    let mut components: Vec<Value> = members
+        .iter()
+        .map(|member| {
+            json!({
+                "type": "application",
+                "bom-ref": bom_ref(&member.name, &member.version),
+                "name": member.name,
+                "version": member.version,
+            })
+        })
+        .collect();
+    components.extend(entries.iter().map(|entry| {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": entry.purl(),
+            "name": entry.locked.name,
+            "version": entry.locked.version,
+            "purl": entry.purl(),
+            "properties": [{"name": "cargo-localize:path", "value": entry.path}],
+        });
+        if let Some(description) = &entry.description {
+            component["description"] = json!(description);
+        }
+        if !entry.authors.is_empty() {
+            component["author"] = json!(entry.authors.join(", "));
+        }
+        if let Some(checksum) = &entry.locked.checksum {
+            component["hashes"] = json!([{"alg": "SHA-256", "content": checksum}]);
+        }
+        if let Some(license) = &entry.license {
+            component["licenses"] = json!([{"expression": license}]);
+        }
+        let references: Vec<Value> = [
+            ("distribution", entry.download_url()),
+            (
+                "vcs",
+                entry
+                    .git_origin()
+                    .map(|(url, _)| url)
+                    .or_else(|| entry.repository.clone()),
+            ),
+            ("website", entry.homepage.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(kind, url)| url.map(|url| json!({"type": kind, "url": url})))
+        .collect();
+        if !references.is_empty() {
+            component["externalReferences"] = json!(references);
+        }
+        component
+    }));
+
+    let mut graph = vec![json!({
+        "ref": "project",
+        "dependsOn": members.iter().map(|member| bom_ref(&member.name, &member.version)).collect::<Vec<_>>(),
+    })];
+    graph.extend(dependencies.iter().map(|((name, version), depends_on)| {
+        json!({
+            "ref": bom_ref(name, version),
+            "dependsOn": depends_on.iter().map(|(name, version)| bom_ref(name, version)).collect::<Vec<_>>(),
+        })
+    }));
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid(id)),
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "cargo-localize",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": {"type": "application", "bom-ref": "project", "name": project_name},
+        },
+        "components": components,
+        "dependencies": graph,
+    })
+}
+
+// Registry download, or the repository and commit for git crates
+fn download_location(entry: &InventoryEntry) -> Option<String> {
+    entry.download_url().or_else(|| {
+        entry
+            .git_origin()
+            .map(|(url, revision)| format!("git+{url}@{revision}"))
+    })
+}
+
+// SPDX identifiers only allow letters, digits, `.` and `-`
+fn spdx_id(name: &str, version: &str) -> String {
+    let id: String = format!("{name}-{version}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("SPDXRef-Package-{id}")
+}
+
+// Random-looking version 4 UUID from the first 32 hex digits of a hash
+fn uuid(hash: &str) -> String {
+    let hex = &hash[..32];
+    format!(
+        "{}-{}-4{}-8{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[13..16],
+        &hex[17..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,82 @@
+// SPDX license expression, e.g. `(MIT OR Apache-2.0) AND Unicode-3.0`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    License { id: String, exception: Option<String> },
+    And(Vec<Expression>),
+    Or(Vec<Expression>),
+}
+
+impl Expression {
+    // `AND` binds tighter than `OR`, the legacy `/` separator reads as `OR`
+    pub fn parse(text: &str) -> Option<Expression> {
+        let spaced = text.replace('(', " ( ").replace(')', " ) ").replace('/', " OR ");
+        let mut parser = Parser {
+            tokens: spaced.split_whitespace().collect(),
+            position: 0,
+        };
+        let expression = parser.or()?;
+        (parser.position == parser.tokens.len()).then_some(expression)
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Option<Expression> {
+        let mut alternatives = vec![self.and()?];
+        while self.eat("OR") {
+            alternatives.push(self.and()?);
+        }
+        Some(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Expression::Or(alternatives)
+        })
+    }
+
+    fn and(&mut self) -> Option<Expression> {
+        let mut all = vec![self.term()?];
+        while self.eat("AND") {
+            all.push(self.term()?);
+        }
+        Some(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            Expression::And(all)
+        })
+    }
+
+    fn term(&mut self) -> Option<Expression> {
+        let token = *self.tokens.get(self.position)?;
+        self.position += 1;
+        if token == "(" {
+            let inner = self.or()?;
+            return self.eat(")").then_some(inner);
+        }
+        if [")", "AND", "OR", "WITH"].contains(&token) {
+            return None;
+        }
+        let exception = if self.eat("WITH") {
+            let exception = *self.tokens.get(self.position)?;
+            self.position += 1;
+            Some(exception.to_string())
+        } else {
+            None
+        };
+        Some(Expression::License {
+            id: token.to_string(),
+            exception,
+        })
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let matches = self.tokens.get(self.position) == Some(&token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+}
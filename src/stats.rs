@@ -0,0 +1,156 @@
+use crate::lock::LocalizeLock;
+use crate::util::{dir_size, format_size, format_timestamp, unix_now};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct StatsArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Show the statistics recorded by every run instead of the current state
+    #[clap(long)]
+    history: bool,
+}
+
+// Footprint of the vendored tree after one run
+#[derive(Serialize, Deserialize)]
+struct RunStats {
+    timestamp: u64,
+    command: String,
+    crates: usize,
+    size: u64,
+    // Crates vendored in more than one version
+    duplicates: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    advisories: Option<usize>,
+}
+
+impl RunStats {
+    fn collect(localize_lock: &LocalizeLock, third_party_path: &Path, command: &str) -> Self {
+        Self {
+            timestamp: unix_now(),
+            command: command.to_string(),
+            crates: localize_lock.packages.len(),
+            size: localize_lock
+                .packages
+                .iter()
+                .map(|locked| dir_size(&third_party_path.join(&locked.path)))
+                .sum(),
+            duplicates: duplicate_versions(localize_lock).len(),
+            advisories: None,
+        }
+    }
+}
+
+// Appends the statistics of a finished run to the project's history
+pub fn record(project_path: &Path, localize_lock: &LocalizeLock, command: &str) -> Result<()> {
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let stats = RunStats::collect(localize_lock, &third_party_path, command);
+    let history_path = history_path(project_path)?;
+    if let Some(parent) = history_path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&history_path)
+        .context(format!("Failed to open {}", history_path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&stats)?).context(format!("Failed to write {}", history_path.display()))
+}
+
+pub fn stats(args: &StatsArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+
+    if args.history {
+        return print_history(&project_path);
+    }
+
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let stats = RunStats::collect(&localize_lock, &third_party_path, "");
+    println!("Vendored crates: {}", stats.crates);
+    println!("Vendored size:   {}", format_size(stats.size));
+    println!("Duplicate versions: {}", stats.duplicates);
+    for (name, versions) in duplicate_versions(&localize_lock) {
+        println!("  {name}: {}", versions.join(", "));
+    }
+    Ok(())
+}
+
+fn print_history(project_path: &Path) -> Result<()> {
+    let history_path = history_path(project_path)?;
+    let content = match fs::read_to_string(&history_path) {
+        Ok(content) => content,
+        Err(_) => {
+            println!("No runs recorded for {} yet", project_path.display());
+            return Ok(());
+        }
+    };
+    let runs = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str::<RunStats>)
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Failed to parse {}", history_path.display()))?;
+
+    println!(
+        "{:<22} {:<9} {:>7} {:>11} {:>11} {:>11}",
+        "Date", "Command", "Crates", "Size", "Duplicates", "Advisories"
+    );
+    for run in &runs {
+        println!(
+            "{:<22} {:<9} {:>7} {:>11} {:>11} {:>11}",
+            format_timestamp(run.timestamp),
+            run.command,
+            run.crates,
+            format_size(run.size),
+            run.duplicates,
+            run.advisories.map_or("-".to_string(), |count| count.to_string())
+        );
+    }
+
+    if let (Some(first), Some(last)) = (runs.first(), runs.last())
+        && runs.len() > 1
+    {
+        let size_change = if last.size >= first.size {
+            format!("+{}", format_size(last.size - first.size))
+        } else {
+            format!("-{}", format_size(first.size - last.size))
+        };
+        println!(
+            "Since {}: {:+} crate(s), {size_change}, {:+} duplicate(s)",
+            format_timestamp(first.timestamp),
+            last.crates as i64 - first.crates as i64,
+            last.duplicates as i64 - first.duplicates as i64
+        );
+    }
+    Ok(())
+}
+
+fn duplicate_versions(localize_lock: &LocalizeLock) -> BTreeMap<&str, Vec<&str>> {
+    let mut versions: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for locked in &localize_lock.packages {
+        versions.entry(&locked.name).or_default().push(&locked.version);
+    }
+    versions.retain(|_, versions| versions.len() > 1);
+    versions
+}
+
+// History lives in the user's state directory, one file per project path
+fn history_path(project_path: &Path) -> Result<PathBuf> {
+    let state_dir = dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .context("Failed to find the user state directory")?;
+    let name = project_path
+        .file_name()
+        .map_or("project".to_string(), |name| name.to_string_lossy().to_string());
+    let hash = crate::journal::sha256(project_path.to_string_lossy().as_bytes());
+    Ok(state_dir
+        .join("cargo-localize/history")
+        .join(format!("{name}-{}.jsonl", &hash[..12])))
+}
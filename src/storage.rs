@@ -0,0 +1,135 @@
+use crate::util::format_size;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// Filesystems reached over the network, where copies wait on round trips rather than the disk
+const NETWORK_FILESYSTEMS: [&str; 12] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "fuse.sshfs",
+    "fuse.s3fs",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Storage {
+    /// NVMe drive
+    Nvme,
+    /// SATA or other solid state drive
+    Ssd,
+    /// Spinning disk
+    Hdd,
+    /// Network share (NFS, SMB, ...)
+    Network,
+}
+
+pub struct CopyTuning {
+    // Crates copied at the same time
+    pub jobs: usize,
+    // Buffer each file is copied through
+    pub buffer_size: usize,
+}
+
+// Picks how crates are copied into `destination`, from the storage it's on unless `--jobs` or
+// `--storage` say otherwise. Spinning disks lose to seeking when several crates are copied at
+// once, network shares to round trips when they aren't.
+pub fn tune(destination: &Path, jobs: Option<usize>, storage: Option<Storage>) -> CopyTuning {
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    let (storage, how) = match storage {
+        Some(storage) => (Some(storage), "--storage"),
+        None => (detect(destination), "detected"),
+    };
+    let (default_jobs, buffer_size) = match storage {
+        Some(Storage::Nvme) => (cpus.clamp(2, 16), 256 * 1024),
+        Some(Storage::Ssd) => (cpus.clamp(2, 8), 256 * 1024),
+        Some(Storage::Hdd) => (1, 1024 * 1024),
+        Some(Storage::Network) => (16, 1024 * 1024),
+        None => (cpus.clamp(1, 4), 64 * 1024),
+    };
+    let tuning = CopyTuning {
+        jobs: jobs.unwrap_or(default_jobs),
+        buffer_size,
+    };
+    let storage = match storage {
+        Some(storage) => format!("{storage:?} ({how})").to_lowercase(),
+        None => "unknown".to_string(),
+    };
+    debug!(
+        "Storage of {}: {storage}, copying {} crate(s) at a time through {} buffers",
+        destination.display(),
+        tuning.jobs,
+        format_size(tuning.buffer_size as u64)
+    );
+    tuning
+}
+
+// Storage behind the filesystem holding `path`, from the mount table and the block device's
+// queue attributes. `None` where they can't be read, e.g. outside Linux or on overlay mounts.
+fn detect(path: &Path) -> Option<Storage> {
+    let path = path.ancestors().find_map(|path| path.canonicalize().ok())?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    // `<id> <parent> <major:minor> <root> <mount point> <options> [optional fields] - <type> ...`
+    let (device, fs_type) = mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let fields: Vec<&str> = mount.split(' ').collect();
+            let mount_point = PathBuf::from(unescape(fields.get(4)?));
+            let fs_type = filesystem.split(' ').next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point, fields[2].to_string(), fs_type.to_string()))
+        })
+        .max_by_key(|(mount_point, _, _)| mount_point.components().count())
+        .map(|(_, device, fs_type)| (device, fs_type))?;
+    if NETWORK_FILESYSTEMS.contains(&fs_type.as_str()) {
+        return Some(Storage::Network);
+    }
+
+    // Partitions keep their queue attributes in the whole disk's directory
+    let device_dir = fs::canonicalize(format!("/sys/dev/block/{device}")).ok()?;
+    let rotational = [device_dir.as_path(), device_dir.parent()?]
+        .iter()
+        .find_map(|dir| fs::read_to_string(dir.join("queue/rotational")).ok())?;
+    Some(if rotational.trim() == "1" {
+        Storage::Hdd
+    } else if device_dir
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with("nvme"))
+    {
+        Storage::Nvme
+    } else {
+        Storage::Ssd
+    })
+}
+
+// Mount points escape spaces, tabs, newlines and backslashes as octal (`\040`)
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let code = rest
+            .get(index + 1..index + 4)
+            .and_then(|code| u8::from_str_radix(code, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(code as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
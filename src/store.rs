@@ -0,0 +1,82 @@
+use crate::config::SymlinkPolicy;
+use anyhow::{Context, Result};
+use fs_extra::dir::CopyOptions;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// Files every project rewrites for itself, everything else is shared
+const PROJECT_FILES: [&str; 1] = ["Cargo.toml"];
+
+// User-level directory holding one pristine copy of each crate, shared by all projects using `--store`
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    pub fn open(dir: Option<&Path>) -> Result<Self> {
+        let dir = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => dirs::data_dir()
+                .context("Failed to find the user data directory, pass --store-dir")?
+                .join("cargo-localize/store"),
+        };
+        fs::create_dir_all(&dir).context(format!("Failed to create store {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn contains(&self, dir_name: &str) -> bool {
+        self.dir.join(dir_name).exists()
+    }
+
+    // Copies the crate into the store unless an earlier run of any project did already
+    pub fn add(&self, source_path: &Path, dir_name: &str, symlinks: SymlinkPolicy) -> Result<PathBuf> {
+        let stored = crate::safety::safe_join(&self.dir, dir_name)?;
+        if stored.exists() {
+            debug!("  Found in store: {}", stored.display());
+            return Ok(stored);
+        }
+
+        // Other projects may be reading the store, entries appear with a single rename
+        let partial = self.dir.join(format!(".{dir_name}.{}.partial", std::process::id()));
+        if partial.exists() {
+            fs::remove_dir_all(&partial).context(format!("Failed to remove {}", partial.display()))?;
+        }
+        let options = CopyOptions::new();
+        crate::util::copy_tree(source_path, &partial, &options, &[], &[], symlinks)
+            .context(format!("Failed to copy {} into the store", source_path.display()))?;
+        if let Err(error) = fs::rename(&partial, &stored) {
+            fs::remove_dir_all(&partial).ok();
+            // Another project stored the same crate meanwhile
+            if !stored.exists() {
+                return Err(error).context(format!("Failed to add {} to the store", stored.display()));
+            }
+        }
+        debug!("  Stored: {}", stored.display());
+        Ok(stored)
+    }
+}
+
+// Creates `crate_dir` with its own copy of the manifest and symlinks into the store for the rest
+pub fn link_crate(stored: &Path, crate_dir: &Path) -> Result<()> {
+    fs::create_dir_all(crate_dir).context(format!("Failed to create {}", crate_dir.display()))?;
+    for entry in fs::read_dir(stored).context(format!("Failed to read {}", stored.display()))? {
+        let entry = entry?;
+        let target = entry.path();
+        let link = crate_dir.join(entry.file_name());
+        if PROJECT_FILES.iter().any(|name| entry.file_name() == *name) {
+            fs::copy(&target, &link).context(format!("Failed to copy {}", target.display()))?;
+        } else {
+            crate::util::symlink(&target, &link, entry.file_type()?.is_dir()).context(format!(
+                "Failed to link {} to {}",
+                link.display(),
+                target.display()
+            ))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use crate::advisories::{AdvisoryArgs, Found};
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock, LockedPackage};
+use crate::util::{dir_size, format_size};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(clap::Args)]
+pub struct SummaryArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    #[clap(flatten)]
+    advisories: AdvisoryArgs,
+    /// Git revision whose localize.lock the vendored set is compared with, e.g. the target branch
+    /// of a pull request
+    #[clap(long, value_name = "REV", default_value = "HEAD")]
+    since: String,
+    /// A pull request description in Markdown, commit message trailers or JSON
+    #[clap(long, value_enum, default_value = "markdown")]
+    format: SummaryFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SummaryFormat {
+    /// Tables of the changed crates, sizes and advisories for a pull request description
+    Markdown,
+    /// `Vendored-Crate: name version sha256:...` trailers to append to a commit message
+    Trailers,
+    /// The same as a JSON document
+    Json,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Change {
+    Added,
+    Bumped,
+    Removed,
+}
+
+#[derive(Serialize)]
+struct ChangedCrate {
+    change: Change,
+    name: String,
+    // Version vendored now, the removed one for removed crates
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_version: Option<String>,
+    // `sha256:<checksum>` of a registry crate or `git:<commit>` of a git one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    // Vendored size, `None` for removed crates whose directory is gone and cargo never downloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Advisory {
+    id: String,
+    name: String,
+    version: String,
+    title: String,
+    kind: String,
+    url: String,
+}
+
+// Vendored set changes since a git revision, for review automation to parse out of pull requests
+// and the history instead of diffing localize.lock
+pub fn summary(args: &SummaryArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let previous = lock_at(&project_path, &args.since)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let previous_third_party_path = previous
+        .as_ref()
+        .map_or(third_party_path.clone(), |lock| lock.third_party_path(&project_path));
+
+    let current: BTreeMap<(&str, &str), &LockedPackage> = localize_lock
+        .packages
+        .iter()
+        .map(|locked| ((locked.name.as_str(), locked.version.as_str()), locked))
+        .collect();
+    let before: BTreeMap<(&str, &str), &LockedPackage> = previous
+        .iter()
+        .flat_map(|lock| &lock.packages)
+        .map(|locked| ((locked.name.as_str(), locked.version.as_str()), locked))
+        .collect();
+    let added: Vec<&LockedPackage> = current
+        .iter()
+        .filter(|(key, _)| !before.contains_key(*key))
+        .map(|(_, locked)| *locked)
+        .collect();
+    let removed: Vec<&LockedPackage> = before
+        .iter()
+        .filter(|(key, _)| !current.contains_key(*key))
+        .map(|(_, locked)| *locked)
+        .collect();
+
+    let added_names: Vec<&str> = added.iter().map(|p| p.name.as_str()).collect();
+    let removed_names: Vec<&str> = removed.iter().map(|p| p.name.as_str()).collect();
+    let bumps: BTreeMap<usize, usize> = crate::diff::bumps(&added_names, &removed_names).into_iter().collect();
+    let mut crates = Vec::new();
+    for (index, locked) in added.iter().enumerate() {
+        let previous_version = bumps
+            .get(&index)
+            .map(|removed_index| removed[*removed_index].version.clone());
+        let entry = crate::export::inventory_entry(&localize_lock, locked, &project_path)?;
+        crates.push(ChangedCrate {
+            change: if previous_version.is_some() {
+                Change::Bumped
+            } else {
+                Change::Added
+            },
+            name: locked.name.clone(),
+            version: locked.version.clone(),
+            previous_version,
+            origin: origin(locked),
+            license: entry.license,
+            size: Some(dir_size(&third_party_path.join(&locked.path))),
+        });
+    }
+    for locked in &removed {
+        if crates
+            .iter()
+            .any(|krate| krate.previous_version.as_deref() == Some(&locked.version) && krate.name == locked.name)
+        {
+            continue;
+        }
+        crates.push(ChangedCrate {
+            change: Change::Removed,
+            name: locked.name.clone(),
+            version: locked.version.clone(),
+            previous_version: None,
+            origin: origin(locked),
+            license: None,
+            size: previous_size(locked, &previous_third_party_path),
+        });
+    }
+    crates.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    let added_size: u64 = crates
+        .iter()
+        .filter(|krate| krate.change != Change::Removed)
+        .filter_map(|krate| krate.size)
+        .sum();
+    let removed_size: u64 = removed
+        .iter()
+        .filter_map(|locked| previous_size(locked, &previous_third_party_path))
+        .sum();
+
+    let changed: Vec<LockedPackage> = added.iter().map(|locked| (*locked).clone()).collect();
+    let advisories: Option<Vec<Advisory>> = match crate::advisories::database(&args.advisories, &args.network) {
+        Some(db) => {
+            let (found, _) = crate::advisories::find(&db, &changed, &args.advisories.ignore_advisories)?;
+            Some(found.iter().map(advisory).collect())
+        }
+        None => None,
+    };
+
+    match args.format {
+        SummaryFormat::Json => {
+            let document = serde_json::json!({
+                "since": args.since,
+                "crates": crates,
+                "added-size": added_size,
+                "removed-size": removed_size,
+                "vendored-crates": localize_lock.packages.len(),
+                "advisories": advisories,
+            });
+            println!("{}", serde_json::to_string_pretty(&document)?);
+        }
+        SummaryFormat::Trailers => print_trailers(&crates, advisories.as_deref(), added_size, removed_size),
+        SummaryFormat::Markdown => print_markdown(
+            &args.since,
+            &crates,
+            advisories.as_deref(),
+            (added_size, removed_size),
+            localize_lock.packages.len(),
+        ),
+    }
+    Ok(())
+}
+
+// localize.lock at a git revision, `None` if the project wasn't localized yet back then
+fn lock_at(project_path: &Path, rev: &str) -> Result<Option<LocalizeLock>> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(project_path)
+            .output()
+            .context("Failed to run git")
+    };
+    let commit = format!("{rev}^{{commit}}");
+    let output = git(&["rev-parse", "--verify", "--quiet", &commit])?;
+    if !output.status.success() {
+        bail!("{rev} isn't a git revision");
+    }
+    let output = git(&["show", &format!("{rev}:./{LOCK_FILE_NAME}")])?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    LocalizeLock::parse(&String::from_utf8_lossy(&output.stdout))
+        .context(format!("{LOCK_FILE_NAME} at {rev}"))
+        .map(Some)
+}
+
+// Size of a version no longer vendored: its directory if `gc` didn't remove it yet, or else its
+// registry source
+fn previous_size(locked: &LockedPackage, third_party_path: &Path) -> Option<u64> {
+    let crate_dir = third_party_path.join(&locked.path);
+    if crate_dir.is_dir() {
+        return Some(dir_size(&crate_dir));
+    }
+    locked
+        .source
+        .as_deref()
+        .filter(|source| !source.starts_with("git+"))
+        .and_then(|_| crate::registry::crate_size(&locked.name, &locked.version))
+}
+
+fn origin(locked: &LockedPackage) -> Option<String> {
+    match (&locked.checksum, &locked.rev) {
+        (Some(checksum), _) => Some(format!("sha256:{checksum}")),
+        (None, Some(rev)) => Some(format!("git:{rev}")),
+        (None, None) => None,
+    }
+}
+
+fn advisory(found: &Found) -> Advisory {
+    Advisory {
+        id: found.id.clone(),
+        name: found.name.clone(),
+        version: found.version.clone(),
+        title: found.title.clone(),
+        kind: found
+            .informational
+            .clone()
+            .unwrap_or_else(|| "vulnerability".to_string()),
+        url: found.url(),
+    }
+}
+
+fn signed_size(added: u64, removed: u64) -> String {
+    format!(
+        "{}{}",
+        if added >= removed { "+" } else { "-" },
+        format_size(added.abs_diff(removed))
+    )
+}
+
+// One `Key: value` line per change, the format `git interpret-trailers` reads
+fn print_trailers(crates: &[ChangedCrate], advisories: Option<&[Advisory]>, added_size: u64, removed_size: u64) {
+    for krate in crates {
+        let origin = krate
+            .origin
+            .as_deref()
+            .map(|origin| format!(" {origin}"))
+            .unwrap_or_default();
+        match (krate.change, &krate.previous_version) {
+            (Change::Bumped, Some(previous)) => println!(
+                "Vendored-Crate-Bumped: {} {previous} -> {}{origin}",
+                krate.name, krate.version
+            ),
+            (Change::Removed, _) => println!("Vendored-Crate-Removed: {} {}", krate.name, krate.version),
+            _ => println!("Vendored-Crate: {} {}{origin}", krate.name, krate.version),
+        }
+    }
+    for advisory in advisories.unwrap_or_default() {
+        println!(
+            "Vendored-Advisory: {} {} {} ({})",
+            advisory.id, advisory.name, advisory.version, advisory.kind
+        );
+    }
+    if !crates.is_empty() {
+        println!("Vendored-Size-Change: {}", signed_size(added_size, removed_size));
+    }
+}
+
+fn print_markdown(
+    since: &str,
+    crates: &[ChangedCrate],
+    advisories: Option<&[Advisory]>,
+    (added_size, removed_size): (u64, u64),
+    vendored: usize,
+) {
+    println!("## Vendored crates");
+    println!();
+    if crates.is_empty() {
+        println!("No changes to the vendored crates since `{since}`.");
+        return;
+    }
+    let count = |change: Change| crates.iter().filter(|krate| krate.change == change).count();
+    println!(
+        "{} added, {} bumped, {} removed since `{since}`; {vendored} crate(s) vendored, size {} (+{} / -{}).",
+        count(Change::Added),
+        count(Change::Bumped),
+        count(Change::Removed),
+        signed_size(added_size, removed_size),
+        format_size(added_size),
+        format_size(removed_size),
+    );
+    println!();
+    println!("| Change | Crate | Version | License | Size | Origin |");
+    println!("|---|---|---|---|---|---|");
+    for krate in crates {
+        let (change, version) = match (krate.change, &krate.previous_version) {
+            (Change::Bumped, Some(previous)) => ("bumped", format!("{previous} → {}", krate.version)),
+            (Change::Removed, _) => ("removed", krate.version.clone()),
+            _ => ("added", krate.version.clone()),
+        };
+        println!(
+            "| {change} | `{}` | {version} | {} | {} | {} |",
+            krate.name,
+            krate.license.as_deref().unwrap_or(""),
+            krate.size.map(format_size).unwrap_or_default(),
+            krate
+                .origin
+                .as_deref()
+                .map(|origin| format!("`{}`", short_origin(origin)))
+                .unwrap_or_default(),
+        );
+    }
+    println!();
+    println!("### Advisories");
+    println!();
+    match advisories {
+        None => println!("Not checked, pass `--advisories` to look the new versions up."),
+        Some([]) => println!("None for the added and bumped crates."),
+        Some(advisories) => {
+            for advisory in advisories {
+                println!(
+                    "- [{}]({}) `{}` {} ({}): {}",
+                    advisory.id, advisory.url, advisory.name, advisory.version, advisory.kind, advisory.title
+                );
+            }
+        }
+    }
+}
+
+// Checksums and commits are long, a prefix identifies them in a table
+fn short_origin(origin: &str) -> &str {
+    let end = origin
+        .find(':')
+        .map_or(origin.len(), |colon| colon + 13)
+        .min(origin.len());
+    &origin[..end]
+}
@@ -0,0 +1,280 @@
+use crate::filter::PackageSpec;
+use crate::lock::LocalizeLock;
+use anyhow::{Context, Result, bail};
+use cargo_metadata::{DependencyKind, Metadata, Node, Package, PackageId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(clap::Args)]
+pub struct TreeArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Levels of dependencies to show below the workspace members
+    #[clap(long, value_name = "N")]
+    depth: Option<usize>,
+    /// Repeat the dependencies of crates shown before instead of marking them with `(*)`
+    #[clap(long)]
+    no_dedupe: bool,
+}
+
+#[derive(clap::Args)]
+pub struct WhyArgs {
+    /// Vendored crate to explain: `name`, `name@version` or a glob
+    spec: PackageSpec,
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+}
+
+// The resolved graph of a localized project, with what it needs to tell vendored crates apart
+struct Graph {
+    metadata: Metadata,
+    nodes: HashMap<PackageId, Node>,
+    packages: HashMap<PackageId, Package>,
+    vendored: HashSet<PackageId>,
+}
+
+impl Graph {
+    fn load(project: &crate::ProjectPath, network: &crate::NetworkArgs) -> Result<(Self, LocalizeLock)> {
+        let project_path = project.root()?;
+        let localize_lock = LocalizeLock::require(&project_path)?;
+        let third_party_path = localize_lock.third_party_path(&project_path);
+        let third_party_path = third_party_path.canonicalize().unwrap_or(third_party_path);
+        let metadata = network
+            .metadata()
+            .manifest_path(project_path.join("Cargo.toml"))
+            .other_options(crate::platform::cargo_options(
+                localize_lock.filter_platform.as_deref(),
+                network,
+            ))
+            .exec()
+            .context("Failed to get cargo metadata")?;
+        let resolve = metadata
+            .resolve
+            .as_ref()
+            .context("Cargo resolved no dependency graph")?;
+        let nodes = resolve
+            .nodes
+            .iter()
+            .map(|node| (node.id.clone(), node.clone()))
+            .collect();
+        let packages: HashMap<PackageId, Package> = metadata
+            .packages
+            .iter()
+            .map(|package| (package.id.clone(), package.clone()))
+            .collect();
+        // Vendored crates resolve into the 3rd-party folder, whether rewritten to or patched in
+        let vendored = packages
+            .values()
+            .filter(|package| package.manifest_path.starts_with(&third_party_path))
+            .map(|package| package.id.clone())
+            .collect();
+        Ok((
+            Graph {
+                metadata,
+                nodes,
+                packages,
+                vendored,
+            },
+            localize_lock,
+        ))
+    }
+
+    fn members(&self) -> Vec<&PackageId> {
+        let mut members: Vec<&PackageId> = self.metadata.workspace_members.iter().collect();
+        members.sort_by_key(|id| self.label(id));
+        members
+    }
+
+    // Dependencies of a crate, sorted, with the kinds they're declared as
+    fn dependencies(&self, id: &PackageId) -> Vec<(&PackageId, Option<DependencyKind>)> {
+        let mut dependencies: Vec<_> = self
+            .nodes
+            .get(id)
+            .map_or(&[][..], |node| node.deps.as_slice())
+            .iter()
+            .map(|dep| {
+                let kinds: HashSet<DependencyKind> = dep.dep_kinds.iter().map(|info| info.kind).collect();
+                // Only worth a mark when the crate isn't a normal dependency as well
+                let kind = (!kinds.contains(&DependencyKind::Normal))
+                    .then(|| kinds.into_iter().next())
+                    .flatten();
+                (&dep.pkg, kind)
+            })
+            .collect();
+        dependencies.sort_by_key(|(id, _)| self.label(id));
+        dependencies
+    }
+
+    fn label(&self, id: &PackageId) -> String {
+        let Some(package) = self.packages.get(id) else {
+            return id.repr.clone();
+        };
+        let origin = if self.metadata.workspace_members.contains(id) || self.vendored.contains(id) {
+            ""
+        } else if package.source.is_some() {
+            " (not vendored)"
+        } else {
+            " (path)"
+        };
+        format!("{} v{}{origin}", package.name, package.version)
+    }
+}
+
+fn kind_mark(kind: Option<DependencyKind>) -> &'static str {
+    match kind {
+        Some(DependencyKind::Development) => " [dev]",
+        Some(DependencyKind::Build) => " [build]",
+        _ => "",
+    }
+}
+
+// Prints the dependency tree of every workspace member, the way `cargo tree` does
+pub fn tree(args: &TreeArgs) -> Result<()> {
+    let (graph, _) = Graph::load(&args.project, &args.network)?;
+    let mut shown = HashSet::new();
+    let members = graph.members();
+    for (index, member) in members.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("{}", graph.label(member));
+        print_dependencies(&graph, member, "", 1, args, &mut shown);
+    }
+    println!(
+        "\n{} vendored crate(s); `(*)` marks crates whose dependencies are shown above",
+        graph.vendored.len()
+    );
+    Ok(())
+}
+
+fn print_dependencies<'a>(
+    graph: &'a Graph,
+    id: &'a PackageId,
+    prefix: &str,
+    level: usize,
+    args: &TreeArgs,
+    shown: &mut HashSet<&'a PackageId>,
+) {
+    if args.depth.is_some_and(|depth| level > depth) {
+        return;
+    }
+    let dependencies = graph.dependencies(id);
+    for (index, (dependency, kind)) in dependencies.iter().enumerate() {
+        let last = index + 1 == dependencies.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let repeated = !args.no_dedupe && !shown.insert(*dependency);
+        let has_dependencies = !graph.dependencies(dependency).is_empty();
+        println!(
+            "{prefix}{branch}{}{}{}",
+            graph.label(dependency),
+            kind_mark(*kind),
+            if repeated && has_dependencies { " (*)" } else { "" }
+        );
+        if !repeated {
+            print_dependencies(graph, dependency, &format!("{prefix}{indent}"), level + 1, args, shown);
+        }
+    }
+}
+
+// For each vendored crate matching the spec, the shortest chain from every direct dependency of
+// the workspace that leads to it
+pub fn why(args: &WhyArgs) -> Result<()> {
+    let (graph, localize_lock) = Graph::load(&args.project, &args.network)?;
+    let mut targets: Vec<&PackageId> = graph
+        .packages
+        .values()
+        .filter(|package| args.spec.matches(&package.name, &package.version))
+        .filter(|package| !graph.metadata.workspace_members.contains(&package.id))
+        .map(|package| &package.id)
+        .collect();
+    if targets.is_empty() {
+        bail!("No crate matching `{}` is in the dependency graph", args.spec);
+    }
+    targets.sort_by_key(|id| graph.label(id));
+
+    // Who depends on whom, walked backwards from the crate
+    let mut dependents: HashMap<&PackageId, Vec<&PackageId>> = HashMap::new();
+    for node in graph.nodes.values() {
+        for dep in &node.deps {
+            dependents.entry(&dep.pkg).or_default().push(&node.id);
+        }
+    }
+    for list in dependents.values_mut() {
+        list.sort_by_key(|id| graph.label(id));
+    }
+
+    for (index, target) in targets.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        let package = &graph.packages[*target];
+        match localize_lock.find(&package.name, &package.version.to_string()) {
+            Some(locked) => println!(
+                "{} v{} is vendored in {}/{}",
+                package.name, package.version, localize_lock.third_party_dir, locked.path
+            ),
+            None => println!("{} v{} isn't vendored", package.name, package.version),
+        }
+        let chains = chains(&graph, &dependents, target);
+        if chains.is_empty() {
+            println!("  no workspace member depends on it");
+        }
+        for chain in chains {
+            let steps: Vec<String> = chain
+                .windows(2)
+                .map(|pair| {
+                    let kind = graph
+                        .dependencies(pair[0])
+                        .into_iter()
+                        .find(|(id, _)| *id == pair[1])
+                        .and_then(|(_, kind)| kind);
+                    format!("{}{}", graph.label(pair[1]), kind_mark(kind))
+                })
+                .collect();
+            println!("  {} -> {}", graph.label(chain[0]), steps.join(" -> "));
+        }
+    }
+    Ok(())
+}
+
+// Shortest paths from each workspace member's direct dependency down to `target`, member first
+fn chains<'a>(
+    graph: &'a Graph,
+    dependents: &HashMap<&'a PackageId, Vec<&'a PackageId>>,
+    target: &'a PackageId,
+) -> Vec<Vec<&'a PackageId>> {
+    // Breadth first from the crate up, so the first way to each crate found is the shortest
+    let mut towards: HashMap<&PackageId, &PackageId> = HashMap::new();
+    let mut queue = VecDeque::from([target]);
+    let mut seen = HashSet::from([target]);
+    let mut chains = Vec::new();
+    while let Some(id) = queue.pop_front() {
+        for dependent in dependents.get(id).map_or(&[][..], Vec::as_slice) {
+            if graph.metadata.workspace_members.contains(dependent) {
+                let mut chain = vec![*dependent, id];
+                let mut next = id;
+                while let Some(step) = towards.get(next) {
+                    chain.push(step);
+                    next = step;
+                }
+                if !chains.contains(&chain) {
+                    chains.push(chain);
+                }
+                continue;
+            }
+            if seen.insert(dependent) {
+                towards.insert(dependent, id);
+                queue.push_back(dependent);
+            }
+        }
+    }
+    chains.sort_by_key(|chain| (chain.len(), chain.iter().map(|id| graph.label(id)).collect::<Vec<_>>()));
+    chains
+}
@@ -0,0 +1,276 @@
+use crate::journal::Journal;
+use crate::lock::LockfilePolicy;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use tracing::debug;
+use walkdir::WalkDir;
+
+// Directories holding targets which are never built for a dependency
+pub const TRIM_DIRS: [&str; 3] = ["tests", "benches", "examples"];
+// Documentation and CI configuration, only removed with `--minimal`
+pub const MINIMAL_DIRS: [&str; 5] = ["docs", "doc", ".github", ".circleci", ".gitlab"];
+pub const MINIMAL_FILES: [&str; 9] = [
+    ".travis.yml",
+    ".gitlab-ci.yml",
+    "appveyor.yml",
+    ".appveyor.yml",
+    "azure-pipelines.yml",
+    ".cirrus.yml",
+    "codecov.yml",
+    ".codecov.yml",
+    "bors.toml",
+];
+// Manifest sections describing those targets
+const TRIM_SECTIONS: [&str; 3] = ["test", "bench", "example"];
+// Target kinds that are built when the crate is used as a dependency
+const BUILD_TARGET_KINDS: [&str; 8] = [
+    "lib",
+    "rlib",
+    "dylib",
+    "cdylib",
+    "staticlib",
+    "proc-macro",
+    "bin",
+    "custom-build",
+];
+const INCLUDE_MACROS: [&str; 3] = ["include!(", "include_str!(", "include_bytes!("];
+// Where `--nested-lockfiles rename` moves a crate's own Cargo.lock
+pub const RENAMED_LOCKFILE: &str = "Cargo.lock.orig";
+
+// Applies the policy to the Cargo.lock a freshly copied crate ships, which cargo ignores for
+// dependencies but lockfile scanners pick up. Returns `None` if the crate ships none.
+pub fn nested_lockfile(crate_dir: &Path, policy: LockfilePolicy, journal: &Journal) -> Result<Option<LockfilePolicy>> {
+    let lockfile = crate_dir.join("Cargo.lock");
+    if fs::symlink_metadata(&lockfile).is_err() {
+        return Ok(None);
+    }
+    match policy {
+        LockfilePolicy::Keep => {}
+        LockfilePolicy::Strip => {
+            journal.remove_file(&lockfile).context("Failed to remove Cargo.lock")?;
+            debug!("    Removed Cargo.lock");
+        }
+        LockfilePolicy::Rename => {
+            journal
+                .copy_file(&lockfile, &crate_dir.join(RENAMED_LOCKFILE))
+                .context(format!("Failed to rename Cargo.lock to {RENAMED_LOCKFILE}"))?;
+            journal.remove_file(&lockfile).context("Failed to remove Cargo.lock")?;
+            debug!("    Renamed Cargo.lock to {RENAMED_LOCKFILE}");
+        }
+    }
+    Ok(Some(policy))
+}
+
+// Removes test, bench and example payload from a freshly copied crate, and with `minimal`
+// documentation and CI configuration too.
+// Returns `false` if trimming was skipped because the crate would not stay complete.
+pub fn trim_crate(
+    package: &cargo_metadata::Package,
+    crate_dir: &Path,
+    minimal: bool,
+    journal: &Journal,
+) -> Result<bool> {
+    let manifest_path = crate_dir.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest_path).context("Failed to read Cargo.toml")?;
+    let mut doc = content.parse::<DocumentMut>().context("Failed to parse Cargo.toml")?;
+
+    let targets = target_files(package, &doc);
+    let mut protected = input_files(crate_dir, &targets, &doc);
+    protected.extend(targets.iter().cloned());
+
+    // A trim directory holding anything the build needs is kept as a whole, so sibling modules survive too
+    let minimal_dirs: &[&str] = if minimal { &MINIMAL_DIRS } else { &[] };
+    let removed_dirs: Vec<PathBuf> = TRIM_DIRS
+        .iter()
+        .chain(minimal_dirs)
+        .map(PathBuf::from)
+        .filter(|dir| crate_dir.join(dir).is_dir())
+        .filter(|dir| {
+            let keep = protected.iter().any(|file| file.starts_with(dir));
+            if keep {
+                debug!("    Keeping {}/ (referenced by the build)", dir.display());
+            }
+            !keep
+        })
+        .collect();
+    let minimal_files: &[&str] = if minimal { &MINIMAL_FILES } else { &[] };
+    let removed_files: Vec<PathBuf> = minimal_files
+        .iter()
+        .map(PathBuf::from)
+        .filter(|file| crate_dir.join(file).is_file() && !protected.contains(file))
+        .collect();
+
+    remove_trimmed_sections(&mut doc);
+
+    let missing = missing_targets(&targets, crate_dir, &removed_dirs);
+    if !missing.is_empty() {
+        debug!("    Not trimming {}: the crate would be incomplete", package.name);
+        for file in &missing {
+            debug!("      missing: {}", file.display());
+        }
+        return Ok(false);
+    }
+
+    crate::backup_manifest(&manifest_path, journal)?;
+    for dir in &removed_dirs {
+        journal
+            .remove_dir_all(&crate_dir.join(dir))
+            .context(format!("Failed to remove {}", dir.display()))?;
+        debug!("    Trimmed {}/", dir.display());
+    }
+    for file in &removed_files {
+        journal
+            .remove_file(&crate_dir.join(file))
+            .context(format!("Failed to remove {}", file.display()))?;
+        debug!("    Trimmed {}", file.display());
+    }
+    journal
+        .write(&manifest_path, doc.to_string())
+        .context("Failed to write Cargo.toml")?;
+
+    Ok(true)
+}
+
+pub fn remove_trimmed_sections(doc: &mut DocumentMut) {
+    for section in TRIM_SECTIONS {
+        doc.remove(section);
+    }
+}
+
+// Collects crate-relative source paths of targets built for a dependency
+fn target_files(package: &cargo_metadata::Package, doc: &DocumentMut) -> HashSet<PathBuf> {
+    let mut targets = HashSet::new();
+    let package_root = package.manifest_path.parent().map(|p| p.as_std_path().to_path_buf());
+
+    for target in &package.targets {
+        if !target
+            .kind
+            .iter()
+            .any(|kind| BUILD_TARGET_KINDS.contains(&kind.as_str()))
+        {
+            continue;
+        }
+        if let Some(relative) = package_root
+            .as_ref()
+            .and_then(|root| target.src_path.as_std_path().strip_prefix(root).ok())
+        {
+            targets.insert(relative.to_path_buf());
+        }
+    }
+
+    // Explicit manifest paths, in case metadata came from a differently laid out source
+    let package_table = doc.get("package");
+    let explicit_paths = [
+        package_table.and_then(|p| p.get("build")).and_then(|v| v.as_str()),
+        doc.get("lib").and_then(|l| l.get("path")).and_then(|v| v.as_str()),
+    ];
+    targets.extend(explicit_paths.into_iter().flatten().map(normalize));
+    if let Some(bins) = doc.get("bin").and_then(|b| b.as_array_of_tables()) {
+        targets.extend(
+            bins.iter()
+                .filter_map(|bin| bin.get("path").and_then(|v| v.as_str()))
+                .map(normalize),
+        );
+    }
+
+    targets
+}
+
+// Collects crate-relative paths of other files the build reads: `include!`-style macro inputs,
+// build script `rerun-if-changed` inputs, readme and license files
+fn input_files(crate_dir: &Path, targets: &HashSet<PathBuf>, doc: &DocumentMut) -> HashSet<PathBuf> {
+    let mut inputs = HashSet::new();
+
+    for entry in WalkDir::new(crate_dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(crate_dir) else {
+            continue;
+        };
+        // Sources inside trim directories only matter if the build uses them
+        if TRIM_DIRS.iter().any(|dir| relative.starts_with(dir)) && !targets.contains(relative) {
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(path) else {
+            continue;
+        };
+        let source_dir = relative.parent().unwrap_or(Path::new(""));
+        inputs.extend(include_inputs(&source, source_dir));
+        inputs.extend(rerun_if_changed_inputs(&source));
+    }
+
+    if let Some(package_table) = doc.get("package") {
+        for key in ["readme", "license-file"] {
+            if let Some(path) = package_table.get(key).and_then(|v| v.as_str()) {
+                inputs.insert(normalize(path));
+            }
+        }
+    }
+
+    inputs
+}
+
+// Every target source must still exist once the trim directories are gone
+fn missing_targets(targets: &HashSet<PathBuf>, crate_dir: &Path, removed_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = targets
+        .iter()
+        .filter(|file| !crate_dir.join(file).is_file() || removed_dirs.iter().any(|dir| file.starts_with(dir)))
+        .cloned()
+        .collect();
+    missing.sort();
+    missing
+}
+
+fn include_inputs(source: &str, source_dir: &Path) -> Vec<PathBuf> {
+    let mut inputs = Vec::new();
+
+    for macro_name in INCLUDE_MACROS {
+        for (start, _) in source.match_indices(macro_name) {
+            let args = source[start + macro_name.len()..].trim_start();
+            if let Some(literal) = string_literal(args) {
+                inputs.push(normalize_in(source_dir, literal));
+            } else if let Some(rest) = args
+                .strip_prefix("concat!(")
+                .map(str::trim_start)
+                .and_then(|rest| rest.strip_prefix("env!(\"CARGO_MANIFEST_DIR\")"))
+                .map(|rest| rest.trim_start().trim_start_matches(',').trim_start())
+                && let Some(literal) = string_literal(rest)
+            {
+                inputs.push(normalize(literal.trim_start_matches('/')));
+            }
+        }
+    }
+
+    inputs
+}
+
+fn rerun_if_changed_inputs(source: &str) -> Vec<PathBuf> {
+    source
+        .match_indices("rerun-if-changed=")
+        .filter_map(|(start, marker)| {
+            let rest = &source[start + marker.len()..];
+            let end = rest.find(|c: char| c == '"' || c.is_whitespace()).unwrap_or(rest.len());
+            let path = &rest[..end];
+            (!path.is_empty() && !path.contains('{')).then(|| normalize(path))
+        })
+        .collect()
+}
+
+fn string_literal(text: &str) -> Option<&str> {
+    let text = text.strip_prefix('"')?;
+    let end = text.find('"')?;
+    Some(&text[..end])
+}
+
+fn normalize_in(base: &Path, path: &str) -> PathBuf {
+    normalize(&base.join(path).to_string_lossy())
+}
+
+fn normalize(path: &str) -> PathBuf {
+    crate::util::normalize_path(Path::new(path))
+}
@@ -0,0 +1,206 @@
+use crate::edits::CHECKSUMS_FILE_NAME;
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock};
+use crate::pack::SUMS_FILE_NAME;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+// Where the archive is extracted and verified before anything in the project is touched
+const STAGING_DIR_NAME: &str = ".cargo-localize-unpack";
+
+#[derive(clap::Args)]
+pub struct UnpackArgs {
+    /// Archive written by `cargo-localize pack`
+    archive: PathBuf,
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    #[clap(flatten)]
+    network: crate::NetworkArgs,
+    /// Replace the 3rd-party folder and localize.lock the project already has
+    #[clap(long)]
+    force: bool,
+}
+
+// Restores a vendored tree from a `pack` archive: extracts it next to the project, checks every
+// file against the archive's checksums, moves it into place and points the manifests at it the way
+// the packed localize.lock records
+pub fn unpack(args: &UnpackArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let staging = project_path.join(STAGING_DIR_NAME);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).context(format!("Failed to clean {}", staging.display()))?;
+    }
+    let result = extract(&args.archive, &staging).and_then(|()| verify(&staging));
+    let localize_lock = match result {
+        Ok(localize_lock) => localize_lock,
+        Err(error) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(error);
+        }
+    };
+
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let existing: Vec<PathBuf> = [project_path.join(LOCK_FILE_NAME), third_party_path.clone()]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if !existing.is_empty() && !args.force {
+        let _ = fs::remove_dir_all(&staging);
+        bail!(
+            "The project already has {}, pass --force to replace it",
+            existing
+                .iter()
+                .map(|path| path.strip_prefix(&project_path).unwrap_or(path).display().to_string())
+                .collect::<Vec<_>>()
+                .join(" and ")
+        );
+    }
+
+    info!("Moving the vendored tree into {}...", project_path.display());
+    if third_party_path.exists() {
+        fs::remove_dir_all(&third_party_path).context(format!("Failed to remove {}", third_party_path.display()))?;
+    }
+    if let Some(parent) = third_party_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(staging.join(&localize_lock.third_party_dir), &third_party_path)
+        .context(format!("Failed to move {}", third_party_path.display()))?;
+    for name in [LOCK_FILE_NAME, CHECKSUMS_FILE_NAME] {
+        let target = project_path.join(name);
+        if staging.join(name).is_file() {
+            fs::rename(staging.join(name), &target).context(format!("Failed to move {name}"))?;
+        } else if target.exists() {
+            fs::remove_file(&target).context(format!("Failed to remove {name}"))?;
+        }
+    }
+    fs::remove_dir_all(&staging).context(format!("Failed to remove {}", staging.display()))?;
+
+    // The manifests are pointed at the tree the way the run that packed it did
+    let mut localize_args = crate::LocalizeArgs::default();
+    localize_args.project.path = args.project.clone();
+    localize_args.project.third_party_dir = Some(localize_lock.third_party_dir.clone());
+    localize_args.network = args.network.clone();
+    localize_args.mode = Some(localize_lock.strategy);
+    localize_args.keep_version = localize_lock.keep_version;
+    localize_args.no_workspace_table = localize_lock.no_workspace_table;
+    localize_args.pin_git = localize_lock.pin_git;
+    localize_args.rewrite_only = true;
+    crate::localize(&localize_args, false).context(format!(
+        "The vendored tree was restored, but the manifests couldn't be pointed at it; \
+         run `cargo-localize --rewrite-only` once {} resolves",
+        project_path.join("Cargo.toml").display()
+    ))?;
+
+    println!(
+        "Unpacked {} crate(s) into {}",
+        localize_lock.packages.len(),
+        third_party_path.display()
+    );
+    Ok(())
+}
+
+// `pack` writes plain files and directories only, anything else isn't from it
+fn extract(archive: &Path, staging: &Path) -> Result<()> {
+    info!("Extracting {}...", archive.display());
+    let file = fs::File::open(archive).context(format!("Failed to open {}", archive.display()))?;
+    let decoder = zstd::Decoder::new(file).context("Failed to start decompression")?;
+    let mut tar = tar::Archive::new(decoder);
+    fs::create_dir_all(staging).context(format!("Failed to create {}", staging.display()))?;
+    for entry in tar.entries().context(format!("Failed to read {}", archive.display()))? {
+        let mut entry = entry.context(format!("Failed to read {}", archive.display()))?;
+        let path = entry.path()?.into_owned();
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!(
+                "{} holds an entry outside the project, {}",
+                archive.display(),
+                path.display()
+            );
+        }
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Directory => {}
+            _ => bail!(
+                "{} holds {}, which isn't a file or a directory",
+                archive.display(),
+                path.display()
+            ),
+        }
+        entry
+            .unpack_in(staging)
+            .context(format!("Failed to extract {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// Every file has to be listed with its hash and be there, nothing else may come along
+fn verify(staging: &Path) -> Result<LocalizeLock> {
+    let localize_lock = LocalizeLock::load(staging)?.context(format!("The archive has no {LOCK_FILE_NAME}"))?;
+    let sums = fs::read_to_string(staging.join(SUMS_FILE_NAME)).context(format!(
+        "The archive has no {SUMS_FILE_NAME}, it wasn't written by `pack`"
+    ))?;
+    let mut expected: BTreeMap<&str, &str> = BTreeMap::new();
+    for line in sums.lines() {
+        let (hash, path) = line
+            .split_once("  ")
+            .context(format!("Malformed {SUMS_FILE_NAME} line `{line}`"))?;
+        expected.insert(path, hash);
+    }
+
+    info!("Verifying {} file(s)...", expected.len());
+    let mut problems = Vec::new();
+    for entry in WalkDir::new(staging).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(staging)?.to_string_lossy().replace('\\', "/");
+        if relative == SUMS_FILE_NAME {
+            continue;
+        }
+        match expected.remove(relative.as_str()) {
+            None => problems.push(format!("{relative}: not listed in {SUMS_FILE_NAME}")),
+            Some(hash) if crate::journal::file_sha256(entry.path())? != hash => {
+                problems.push(format!("{relative}: checksum mismatch"))
+            }
+            Some(_) => {}
+        }
+    }
+    problems.extend(expected.keys().map(|path| format!("{path}: missing")));
+    let allowed = [LOCK_FILE_NAME, CHECKSUMS_FILE_NAME, SUMS_FILE_NAME];
+    let third_party_dir = Path::new(&localize_lock.third_party_dir);
+    for entry in fs::read_dir(staging)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if !allowed.contains(&name.as_ref()) && !third_party_dir.starts_with(name.as_ref()) {
+            problems.push(format!("{name}: not part of a vendored tree"));
+        }
+    }
+    for locked in &localize_lock.packages {
+        let manifest = staging
+            .join(&localize_lock.third_party_dir)
+            .join(&locked.path)
+            .join("Cargo.toml");
+        if !manifest.is_file() {
+            problems.push(format!("{} v{}: not in the archive", locked.name, locked.version));
+        }
+    }
+    if !problems.is_empty() {
+        problems.sort();
+        for problem in &problems {
+            println!("  {problem}");
+        }
+        bail!("The archive doesn't match its checksums, nothing was restored");
+    }
+
+    // Edits packed along with the crates stay edits, as localize.checksums recorded them
+    let edits = crate::edits::find(staging, &localize_lock)?;
+    if !edits.is_empty() {
+        warn!("The archive carries local edits of {} vendored crate(s)", edits.len());
+    }
+    Ok(localize_lock)
+}
@@ -0,0 +1,263 @@
+use crate::lock::LocalizeLock;
+use crate::report::OutputFormat;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+#[derive(clap::Args)]
+pub struct AuditUnsafeArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Report format: a table, or a JSON document
+    #[clap(long, value_enum, default_value = "human")]
+    output: OutputFormat,
+    /// List every vendored crate, not only those using `unsafe`
+    #[clap(long)]
+    all: bool,
+}
+
+// `unsafe` in one vendored crate, by what it's used for
+#[derive(Serialize, Default)]
+struct UnsafeUse {
+    name: String,
+    version: String,
+    functions: usize,
+    blocks: usize,
+    impls: usize,
+    traits: usize,
+    // `#![forbid(unsafe_code)]`, the compiler holds the crate to it
+    forbids_unsafe: bool,
+}
+
+impl UnsafeUse {
+    fn total(&self) -> usize {
+        self.functions + self.blocks + self.impls + self.traits
+    }
+}
+
+// Counts `unsafe` in the sources of every vendored crate, the way cargo-geiger does for a build:
+// a snapshot of the unsafe code a project takes responsibility for when it vendors its dependencies.
+// Tests, benches and examples aren't built for a dependency and are left out.
+pub fn audit_unsafe(args: &AuditUnsafeArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+
+    let mut crates = Vec::new();
+    for locked in &localize_lock.packages {
+        let crate_dir = third_party_path.join(&locked.path);
+        if !crate_dir.is_dir() {
+            continue;
+        }
+        let mut usage = UnsafeUse {
+            name: locked.name.clone(),
+            version: locked.version.clone(),
+            ..UnsafeUse::default()
+        };
+        for file in source_files(&crate_dir) {
+            let source = fs::read(&file).context(format!("Failed to read {}", file.display()))?;
+            count(&tokens(&String::from_utf8_lossy(&source)), &mut usage);
+        }
+        crates.push(usage);
+    }
+    let using = crates.iter().filter(|usage| usage.total() > 0).count();
+    let forbidding = crates.iter().filter(|usage| usage.forbids_unsafe).count();
+    if !args.all {
+        crates.retain(|usage| usage.total() > 0);
+    }
+    crates.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.name.cmp(&b.name)));
+
+    if args.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "crates": crates }))?
+        );
+        return Ok(());
+    }
+    if !crates.is_empty() {
+        let header = ["Crate", "Version", "Functions", "Blocks", "Impls", "Traits", ""];
+        let rows: Vec<[String; 7]> = crates
+            .iter()
+            .map(|usage| {
+                [
+                    usage.name.clone(),
+                    usage.version.clone(),
+                    usage.functions.to_string(),
+                    usage.blocks.to_string(),
+                    usage.impls.to_string(),
+                    usage.traits.to_string(),
+                    if usage.forbids_unsafe { "forbids unsafe" } else { "" }.to_string(),
+                ]
+            })
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([header[column].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        for row in [header.map(String::from)].iter().chain(&rows) {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            println!("{}", cells.join("  ").trim_end());
+        }
+        println!();
+    }
+    println!(
+        "{using} of {} vendored crate(s) use unsafe code, {forbidding} forbid it",
+        localize_lock.packages.len()
+    );
+    Ok(())
+}
+
+// Rust sources built when the crate is a dependency: everything but tests, benches, examples
+// and what a path crate's own builds left
+fn source_files(crate_dir: &Path) -> Vec<std::path::PathBuf> {
+    WalkDir::new(crate_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() != 1
+                || !crate::trim::TRIM_DIRS
+                    .iter()
+                    .chain(&["target", ".git"])
+                    .any(|dir| entry.file_name() == *dir)
+        })
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+// Identifiers and punctuation of a Rust source, without comments and literals, which mention
+// `unsafe` without using it
+fn tokens(source: &str) -> Vec<String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            // Block comments nest
+            let mut depth = 0;
+            while i < chars.len() {
+                if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                    depth += 1;
+                    i += 2;
+                } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        } else if c == '"' {
+            i = skip_string(&chars, i + 1);
+        } else if c == '\'' {
+            // `'a'` and `'\n'` are characters, `'a` a lifetime
+            if next == Some('\\') {
+                i += 2;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                i += 1;
+            } else if chars.get(i + 2) == Some(&'\'') {
+                i += 3;
+            } else {
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            match (word.as_str(), chars.get(i)) {
+                // Byte and C strings
+                ("b" | "c", Some('"')) => i = skip_string(&chars, i + 1),
+                // Raw strings, `r#type` is a raw identifier
+                ("r" | "br" | "cr", Some('"' | '#')) => {
+                    let hashes = chars[i..].iter().take_while(|c| **c == '#').count();
+                    if chars.get(i + hashes) == Some(&'"') {
+                        i = skip_raw_string(&chars, i + hashes + 1, hashes);
+                    }
+                }
+                _ => tokens.push(word),
+            }
+        } else {
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+            i += 1;
+        }
+    }
+    tokens
+}
+
+// Index after the closing quote of a string starting at `i`
+fn skip_string(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn skip_raw_string(chars: &[char], mut i: usize, hashes: usize) -> usize {
+    while i < chars.len() {
+        if chars[i] == '"' && chars[i + 1..].iter().take(hashes).filter(|c| **c == '#').count() == hashes {
+            return i + 1 + hashes;
+        }
+        i += 1;
+    }
+    i
+}
+
+fn count(tokens: &[String], usage: &mut UnsafeUse) {
+    for (index, token) in tokens.iter().enumerate() {
+        let next = |offset: usize| tokens.get(index + offset).map_or("", String::as_str);
+        match token.as_str() {
+            "unsafe" => match (next(1), next(2)) {
+                ("fn", _) | ("extern", "fn") => usage.functions += 1,
+                // Every function of an `unsafe extern` block is unsafe to call, counted as one
+                ("extern", "{") => usage.functions += 1,
+                ("{", _) => usage.blocks += 1,
+                ("impl", _) => usage.impls += 1,
+                ("auto" | "trait", _) => usage.traits += 1,
+                // `#[unsafe(no_mangle)]` and the like
+                _ => {}
+            },
+            "forbid"
+                if index >= 2
+                    && tokens[index - 2] == "!"
+                    && tokens[index - 1] == "["
+                    && tokens[index + 1..]
+                        .iter()
+                        .take_while(|token| *token != "]")
+                        .any(|token| token == "unsafe_code") =>
+            {
+                usage.forbids_unsafe = true;
+            }
+            _ => {}
+        }
+    }
+}
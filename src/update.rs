@@ -0,0 +1,892 @@
+use crate::NetworkArgs;
+use crate::journal::{self, Journal};
+use crate::lock::{LocalizeLock, LockedPackage, Strategy};
+use crate::observer::Observers;
+use crate::util::{dir_size, format_size, normalize_path};
+use anyhow::{Context, Result};
+use cargo_metadata::Metadata;
+use cargo_metadata::semver::{Version, VersionReq};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Table};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+#[derive(clap::Args, Clone)]
+pub struct UpdateArgs {
+    /// Package to update (`name` or `name@version`), everything if omitted
+    spec: Option<String>,
+    #[clap(flatten)]
+    localize: crate::LocalizeArgs,
+    /// Apply the update without asking for confirmation
+    #[clap(short = 'y', long)]
+    yes: bool,
+    /// Only show how the vendored set would change
+    #[clap(long, conflicts_with = "yes")]
+    dry_run: bool,
+}
+
+#[derive(clap::Args, Clone)]
+pub struct UpgradeArgs {
+    /// Vendored crate to upgrade: `name` for its newest compatible release, `name@version` for
+    /// that release, also across an incompatible version
+    spec: String,
+    /// Vendored version to replace, when several versions of the crate are vendored
+    #[clap(long, value_name = "VERSION")]
+    from: Option<String>,
+    #[clap(flatten)]
+    localize: crate::LocalizeArgs,
+    /// Apply the upgrade without asking for confirmation
+    #[clap(short = 'y', long)]
+    yes: bool,
+    /// Only show how the vendored set would change
+    #[clap(long, conflicts_with = "yes")]
+    dry_run: bool,
+}
+
+// Temporary copy of the project, removed when dropped
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn new() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("cargo-localize-update-{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).context("Failed to clean scratch directory")?;
+        }
+        fs::create_dir_all(&path).context("Failed to create scratch directory")?;
+        Ok(Self { path })
+    }
+
+    // Copy of the project with its members pointing at the registry again
+    fn prepare(project_path: &Path, member_manifests: &[PathBuf], localize_lock: &LocalizeLock) -> Result<Self> {
+        let scratch = Self::new()?;
+        info!("Preparing scratch copy in {}...", scratch.path.display());
+        copy_project(
+            project_path,
+            &scratch.path,
+            &localize_lock.third_party_path(project_path),
+        )?;
+        let scratch_third_party = scratch.path.join(&localize_lock.third_party_dir);
+        let registries = crate::registry::alternative_registries(project_path);
+        for manifest in member_manifests {
+            unlocalize_manifest(
+                &scratch.path.join(manifest),
+                &scratch_third_party,
+                localize_lock,
+                &registries,
+                &Journal::default(),
+            )?;
+        }
+        Ok(scratch)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Vendored set before and after the update
+#[derive(Default)]
+struct Delta {
+    added: Vec<(String, String)>,
+    removed: Vec<(String, String)>,
+    bumped: Vec<(String, String, String)>,
+    added_size: u64,
+    removed_size: u64,
+}
+
+impl Delta {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.bumped.is_empty()
+    }
+
+    // Whether the vendored crate is bumped or removed
+    fn replaces(&self, locked: &LockedPackage) -> bool {
+        self.bumped
+            .iter()
+            .map(|(name, from, _)| (name, from))
+            .chain(self.removed.iter().map(|(name, version)| (name, version)))
+            .any(|(name, version)| *name == locked.name && *version == locked.version)
+    }
+
+    fn print(&self) {
+        for (name, from, to) in &self.bumped {
+            println!("  bump    {name} {from} -> {to}");
+        }
+        for (name, version) in &self.added {
+            println!("  add     {name} {version}");
+        }
+        for (name, version) in &self.removed {
+            println!("  remove  {name} {version}");
+        }
+        println!(
+            "{} bumped, {} added, {} removed; vendor size {}{} (+{} / -{})",
+            self.bumped.len(),
+            self.added.len(),
+            self.removed.len(),
+            if self.added_size >= self.removed_size { "+" } else { "-" },
+            format_size(self.added_size.abs_diff(self.removed_size)),
+            format_size(self.added_size),
+            format_size(self.removed_size),
+        );
+    }
+}
+
+pub fn update(args: &UpdateArgs) -> Result<()> {
+    if args.localize.network.locked() {
+        anyhow::bail!("Updating rewrites Cargo.lock, it can't run with --locked or --frozen");
+    }
+    let project_path = args.localize.project.path.root()?;
+    let args = &UpdateArgs {
+        localize: crate::config::apply(&args.localize, &project_path)?,
+        ..args.clone()
+    };
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+
+    let network = &args.localize.network;
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let member_manifests = member_manifests(&metadata, &project_path);
+    let scratch = Scratch::prepare(&project_path, &member_manifests, &localize_lock)?;
+
+    let frozen = crate::freeze::frozen(&project_path, &localize_lock.packages)?;
+    info!("Resolving dependencies...");
+    cargo(&scratch.path, &["generate-lockfile"], network)?;
+    if let Some(spec) = &args.spec {
+        // Everything but the requested package stays at its vendored version
+        pin_to_vendored(&scratch.path, &localize_lock.packages, network)?;
+        cargo(&scratch.path, &["update", "-p", spec], network)?;
+    } else if !frozen.is_empty() && !args.localize.unfreeze {
+        pin_to_vendored(&scratch.path, frozen.iter().copied(), network)?;
+    }
+    cargo(&scratch.path, &["fetch"], network)?;
+
+    let updated = network
+        .metadata()
+        .manifest_path(scratch.path.join("Cargo.toml"))
+        .other_options(crate::platform::cargo_options(
+            args.localize.filter_platform.as_deref(),
+            network,
+        ))
+        .exec()
+        .context("Failed to get cargo metadata of the updated project")?;
+    let targets = args.localize.target_platforms()?;
+    let delta = compute_delta(&localize_lock, &updated, &third_party_path, &args.localize, &targets);
+
+    if delta.is_empty() {
+        println!("Vendored crates are up to date");
+        return Ok(());
+    }
+    println!("Vendored set changes:");
+    delta.print();
+
+    if args.dry_run {
+        return Ok(());
+    }
+    let touched: Vec<&LockedPackage> = frozen.iter().copied().filter(|locked| delta.replaces(locked)).collect();
+    crate::freeze::protect(&touched, "would be bumped or removed", args.localize.unfreeze)?;
+    if !args.yes && args.localize.ci {
+        anyhow::bail!("--ci never prompts, pass --yes to apply the update or --dry-run to only show it");
+    }
+    if !args.yes && !confirm("Apply this update?")? {
+        println!("Update cancelled, nothing was changed");
+        return Ok(());
+    }
+
+    let _quiesce = args.localize.quiesce(&project_path, "update")?;
+    let journal = Journal::begin(&project_path, "update", args.localize.fsevents_friendly)?;
+    apply(
+        &args.localize,
+        &project_path,
+        &scratch.path,
+        &member_manifests,
+        &localize_lock,
+        &delta,
+        None,
+        &journal,
+    )
+    .context("Update didn't finish, run `cargo-localize doctor` to inspect what was changed")?;
+    finish_unless_denied(journal, &project_path, &args.localize)?;
+
+    info!("Run `cargo-localize gc` to remove the replaced crates");
+    Ok(())
+}
+
+// Warnings `--deny` blocks undo the changes, so a blocked run leaves the tree as it was
+fn finish_unless_denied(journal: Journal, project_path: &Path, args: &crate::LocalizeArgs) -> Result<()> {
+    if let Err(error) = crate::warnings::deny(&args.blocking_warnings()) {
+        if journal.abandon()? && (args.no_rollback || !journal::roll_back(project_path)?) {
+            return Err(error.context("Run `cargo-localize doctor` to inspect what was changed"));
+        }
+        return Err(error);
+    }
+    journal.finish()
+}
+
+// Replaces one vendored crate with another release of it. Members asking for an incompatible
+// version are pointed at the new one, and so are the vendored crates depending on it, so the old
+// directory can go in the same run.
+pub fn upgrade(args: &UpgradeArgs) -> Result<()> {
+    if args.localize.network.locked() {
+        anyhow::bail!("Upgrading rewrites Cargo.lock, it can't run with --locked or --frozen");
+    }
+    let project_path = args.localize.project.path.root()?;
+    let args = &UpgradeArgs {
+        localize: crate::config::apply(&args.localize, &project_path)?,
+        ..args.clone()
+    };
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+
+    let (name, requested) = match args.spec.split_once('@') {
+        Some((name, version)) => (
+            name,
+            Some(Version::parse(version).context(format!("Invalid version {version}"))?),
+        ),
+        None => (args.spec.as_str(), None),
+    };
+    let old = vendored_version(&localize_lock, name, args.from.as_deref())?;
+    if !old
+        .source
+        .as_deref()
+        .is_some_and(|source| source.starts_with("registry+") || source.starts_with("sparse+"))
+    {
+        anyhow::bail!(
+            "{name} v{} isn't from a registry, change the revision its dependents ask for instead",
+            old.version
+        );
+    }
+    let old_version = Version::parse(&old.version).context(format!("Invalid version {}", old.version))?;
+    // A release a caret requirement on the vendored version doesn't accept needs the manifests changed
+    let compatible = requested
+        .as_ref()
+        .is_none_or(|version| VersionReq::parse(&format!("^{old_version}")).is_ok_and(|req| req.matches(version)));
+    if requested.as_ref() == Some(&old_version) {
+        println!("{name} v{old_version} is vendored already");
+        return Ok(());
+    }
+
+    let network = &args.localize.network;
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let member_manifests = member_manifests(&metadata, &project_path);
+    let scratch = Scratch::prepare(&project_path, &member_manifests, &localize_lock)?;
+
+    info!("Resolving dependencies...");
+    cargo(&scratch.path, &["generate-lockfile"], network)?;
+    pin_to_vendored(&scratch.path, &localize_lock.packages, network)?;
+    let spec = format!("{name}@{old_version}");
+    match &requested {
+        None => cargo(&scratch.path, &["update", "-p", &spec], network)?,
+        Some(version) if compatible => cargo(
+            &scratch.path,
+            &["update", "-p", &spec, "--precise", &version.to_string()],
+            network,
+        )?,
+        Some(version) => {
+            let mut direct = false;
+            for manifest in &member_manifests {
+                direct |= require(&scratch.path.join(manifest), name, version, &Journal::default())?;
+            }
+            if !direct {
+                anyhow::bail!(
+                    "No workspace member depends on {name} directly, and v{version} doesn't match what the crates \
+                     depending on it ask for. Pick a release compatible with v{old_version}, or add {name} to a \
+                     member's dependencies first"
+                );
+            }
+            precise(&scratch.path, name, &old_version, version, network)?;
+        }
+    }
+    cargo(&scratch.path, &["fetch"], network)?;
+
+    let updated = network
+        .metadata()
+        .manifest_path(scratch.path.join("Cargo.toml"))
+        .other_options(crate::platform::cargo_options(
+            args.localize.filter_platform.as_deref(),
+            network,
+        ))
+        .exec()
+        .context("Failed to get cargo metadata of the upgraded project")?;
+    let resolved = updated
+        .packages
+        .iter()
+        .filter(|package| package.source.is_some() && package.name == name && package.version != old_version)
+        .map(|package| &package.version)
+        .max();
+    let target = match (&requested, resolved) {
+        (Some(version), Some(resolved)) if resolved == version => version.clone(),
+        (Some(version), _) => anyhow::bail!("cargo didn't resolve {name} v{version} for the project"),
+        (None, Some(resolved)) => resolved.clone(),
+        (None, None) => {
+            println!("{name} v{old_version} is the newest compatible release");
+            return Ok(());
+        }
+    };
+    let targets = args.localize.target_platforms()?;
+    let delta = compute_delta(&localize_lock, &updated, &third_party_path, &args.localize, &targets);
+    println!("Upgrading {name} v{old_version} to v{target}:");
+    delta.print();
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let frozen = crate::freeze::frozen(&project_path, [old])?;
+    crate::freeze::protect(&frozen, "would be replaced", args.localize.unfreeze)?;
+    let edits = crate::edits::find(&project_path, &localize_lock)?;
+    let at_risk: Vec<_> = edits.get(&old.path).map(|edits| (old, edits)).into_iter().collect();
+    crate::edits::protect(&at_risk, "would be replaced", args.localize.force)?;
+    if !args.yes && args.localize.ci {
+        anyhow::bail!("--ci never prompts, pass --yes to apply the upgrade or --dry-run to only show it");
+    }
+    if !args.yes && !confirm("Apply this upgrade?")? {
+        println!("Upgrade cancelled, nothing was changed");
+        return Ok(());
+    }
+
+    let _quiesce = args.localize.quiesce(&project_path, "upgrade")?;
+    let journal = Journal::begin(&project_path, "upgrade", args.localize.fsevents_friendly)?;
+    apply(
+        &args.localize,
+        &project_path,
+        &scratch.path,
+        &member_manifests,
+        &localize_lock,
+        &delta,
+        (!compatible).then_some((name, &target)),
+        &journal,
+    )
+    .and_then(|()| replace(&project_path, old, &target, &args.localize.network, &journal))
+    .context("Upgrade didn't finish, run `cargo-localize doctor` to inspect what was changed")?;
+    finish_unless_denied(journal, &project_path, &args.localize)
+}
+
+// Points the vendored crates still depending on the old release at the new one and removes the
+// old release once nothing resolves to it anymore
+fn replace(
+    project_path: &Path,
+    old: &LockedPackage,
+    version: &Version,
+    network: &NetworkArgs,
+    journal: &Journal,
+) -> Result<()> {
+    let mut localize_lock = LocalizeLock::require(project_path)?;
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let old_dir = third_party_path.join(&old.path);
+    let new_dir = match localize_lock.find(&old.name, &version.to_string()) {
+        Some(locked) => third_party_path.join(&locked.path),
+        None => anyhow::bail!("{} v{version} wasn't vendored", old.name),
+    };
+
+    // The patch strategy leaves vendored manifests as published, their requirements hold
+    if localize_lock.strategy == Strategy::Rewrite {
+        for locked in &localize_lock.packages {
+            let manifest = third_party_path.join(&locked.path).join("Cargo.toml");
+            if manifest.exists() && repoint(&manifest, &old_dir, &new_dir, version, journal)? {
+                warn!(
+                    "{} v{} asked for {} v{}, now depends on v{version}",
+                    locked.name, locked.version, old.name, old.version
+                );
+            }
+        }
+    }
+
+    let metadata = network
+        .metadata()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata of the upgraded project")?;
+    let used = metadata
+        .packages
+        .iter()
+        .any(|package| package.manifest_path.parent().is_some_and(|dir| dir == old_dir));
+    if used {
+        warn!(
+            "{} v{} is still used by crates asking for it, kept next to v{version}",
+            old.name, old.version
+        );
+        return Ok(());
+    }
+
+    if old_dir.exists() {
+        info!("Removing {} v{}", old.name, old.version);
+        journal
+            .remove_dir_all(&old_dir)
+            .context(format!("Failed to remove {}", old_dir.display()))?;
+        // Shards left empty go too
+        if let Some(shard_dir) = old_dir.parent().filter(|parent| *parent != third_party_path) {
+            let _ = fs::remove_dir(shard_dir);
+        }
+    }
+    localize_lock.packages.retain(|locked| locked.path != old.path);
+    localize_lock.save(project_path, journal)?;
+    crate::index::write(project_path, &localize_lock, journal)?;
+    crate::edits::Checksums::forget(project_path, std::slice::from_ref(&old.path), journal)?;
+    crate::prelude::refresh(project_path, &localize_lock, journal)
+}
+
+// The vendored release of `name` to upgrade, `from` picks one when there are several
+fn vendored_version<'a>(localize_lock: &'a LocalizeLock, name: &str, from: Option<&str>) -> Result<&'a LockedPackage> {
+    let vendored: Vec<&LockedPackage> = localize_lock
+        .packages
+        .iter()
+        .filter(|locked| locked.name == name && from.is_none_or(|from| locked.version == from))
+        .collect();
+    match vendored.as_slice() {
+        [locked] => Ok(locked),
+        [] => match from {
+            Some(from) => anyhow::bail!("{name} v{from} isn't vendored"),
+            None => anyhow::bail!("{name} isn't vendored"),
+        },
+        several => {
+            let versions: Vec<&str> = several.iter().map(|locked| locked.version.as_str()).collect();
+            anyhow::bail!(
+                "Several versions of {name} are vendored ({}), pick one with --from",
+                versions.join(", ")
+            )
+        }
+    }
+}
+
+// Re-localizes the project on the scratch copy's lockfile, with the members asking for
+// `requirement` the way the scratch copy's do
+#[allow(clippy::too_many_arguments)]
+fn apply(
+    args: &crate::LocalizeArgs,
+    project_path: &Path,
+    scratch_path: &Path,
+    member_manifests: &[PathBuf],
+    localize_lock: &LocalizeLock,
+    delta: &Delta,
+    requirement: Option<(&str, &Version)>,
+    journal: &Journal,
+) -> Result<()> {
+    let third_party_path = localize_lock.third_party_path(project_path);
+    let registries = crate::registry::alternative_registries(project_path);
+
+    // Point every manifest back at the registry, so cargo resolves the updated lockfile
+    for manifest in member_manifests {
+        unlocalize_manifest(
+            &project_path.join(manifest),
+            &third_party_path,
+            localize_lock,
+            &registries,
+            journal,
+        )?;
+        if let Some((name, version)) = requirement {
+            require(&project_path.join(manifest), name, version, journal)?;
+        }
+    }
+    // Crates on their way out keep their manifest, or it would count as a local edit
+    for locked in localize_lock.packages.iter().filter(|locked| !delta.replaces(locked)) {
+        let manifest = third_party_path.join(&locked.path).join("Cargo.toml");
+        if manifest.exists() {
+            unlocalize_manifest(&manifest, &third_party_path, localize_lock, &registries, journal)?;
+        }
+    }
+    journal
+        .copy_file(&scratch_path.join("Cargo.lock"), &project_path.join("Cargo.lock"))
+        .context("Failed to copy the updated Cargo.lock")?;
+
+    crate::localize_project(args, false, project_path, &Observers::default(), journal)
+}
+
+// Manifests of workspace members (and the workspace root) relative to the project
+fn member_manifests(metadata: &Metadata, project_path: &Path) -> Vec<PathBuf> {
+    let mut manifests: BTreeSet<PathBuf> = metadata
+        .workspace_packages()
+        .iter()
+        .filter_map(|p| p.manifest_path.as_std_path().strip_prefix(project_path).ok())
+        .map(Path::to_path_buf)
+        .collect();
+    manifests.insert(PathBuf::from("Cargo.toml"));
+    manifests.into_iter().collect()
+}
+
+fn copy_project(project_path: &Path, scratch_path: &Path, third_party_path: &Path) -> Result<()> {
+    let walker = WalkDir::new(project_path).into_iter().filter_entry(|e| {
+        let name = e.file_name();
+        e.path() != third_party_path
+            && name != "target"
+            && name != ".git"
+            && name != journal::JOURNAL_FILE_NAME
+            && name != journal::TRASH_DIR_NAME
+    });
+
+    for entry in walker {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(project_path)?;
+        let destination = scratch_path.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&destination).context(format!("Failed to create {}", destination.display()))?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), &destination).context(format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Turns path dependencies into the vendor directory back into registry requirements
+// on the vendored versions. Git crates stay path dependencies.
+pub fn unlocalize_manifest(
+    manifest_path: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    registries: &BTreeMap<String, String>,
+    journal: &Journal,
+) -> Result<()> {
+    let content = fs::read_to_string(manifest_path).context(format!("Failed to read {}", manifest_path.display()))?;
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .context(format!("Failed to parse {}", manifest_path.display()))?;
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+
+    crate::patch::restore_patches(&mut doc, &localize_lock.patch_entries);
+    crate::patch::remove_patches(&mut doc, manifest_dir, third_party_path, &[]);
+    let mut changed = doc.to_string() != content;
+    for deps in crate::dependency_tables_mut(&mut doc) {
+        changed |= unlocalize_table(deps, manifest_dir, third_party_path, localize_lock, registries);
+    }
+    if let Some(deps) = crate::workspace_dependencies_mut(&mut doc) {
+        changed |= unlocalize_table(deps, manifest_dir, third_party_path, localize_lock, registries);
+    }
+
+    if changed {
+        journal
+            .write(manifest_path, doc.to_string())
+            .context(format!("Failed to write {}", manifest_path.display()))?;
+    }
+    Ok(())
+}
+
+fn unlocalize_table(
+    deps: &mut Table,
+    manifest_dir: &Path,
+    third_party_path: &Path,
+    localize_lock: &LocalizeLock,
+    registries: &BTreeMap<String, String>,
+) -> bool {
+    let mut changed = false;
+    for (_, dep_value) in deps.iter_mut() {
+        let Some(path) = dep_value.get("path").and_then(|p| p.as_str()) else {
+            continue;
+        };
+        let Some(locked) = vendored_package(manifest_dir, path, third_party_path, localize_lock) else {
+            continue;
+        };
+        // Crates the project patches are declared against the source the patch replaces, whose
+        // entry is put back along with the patch
+        let patched = localize_lock
+            .patch_entries
+            .iter()
+            .find(|entry| entry.path == locked.path)
+            .map(|entry| crate::patch::patched_source(&entry.table, registries));
+        let Some(source) = patched.as_deref().or_else(|| {
+            locked
+                .source
+                .as_deref()
+                .filter(|source| source.starts_with("registry+") || source.starts_with("sparse+"))
+        }) else {
+            continue;
+        };
+        let Some(table) = dep_value.as_table_like_mut() else {
+            continue;
+        };
+
+        table.remove("path");
+        table.insert("version", toml_edit::value(locked.version.clone()));
+        // Crates of alternative registries lost their `registry` key to the rewrite, unless
+        // `--keep-version` kept it, crates of a patched git repository their `git` key
+        if let Some(url) = source.strip_prefix("git+") {
+            table.insert("git", toml_edit::value(url));
+        } else if !crate::lock::is_crates_io(source)
+            && !table.contains_key("registry")
+            && !table.contains_key("registry-index")
+        {
+            match registries.get(source) {
+                Some(name) => table.insert("registry", toml_edit::value(name.as_str())),
+                None => table.insert(
+                    "registry-index",
+                    toml_edit::value(source.strip_prefix("registry+").unwrap_or(source)),
+                ),
+            };
+        }
+        if let Some(inline) = dep_value.as_inline_table_mut() {
+            inline.fmt();
+        }
+        changed = true;
+    }
+    changed
+}
+
+fn vendored_package<'a>(
+    manifest_dir: &Path,
+    path: &str,
+    third_party_path: &Path,
+    localize_lock: &'a LocalizeLock,
+) -> Option<&'a LockedPackage> {
+    let target = normalize_path(&manifest_dir.join(path));
+    let relative = target.strip_prefix(third_party_path).ok()?;
+    localize_lock.packages.iter().find(|p| Path::new(&p.path) == relative)
+}
+
+// Pins the given vendored crates to their version in the scratch project's lockfile
+fn pin_to_vendored<'a>(
+    scratch_path: &Path,
+    packages: impl IntoIterator<Item = &'a LockedPackage>,
+    network: &NetworkArgs,
+) -> Result<()> {
+    let packages: Vec<&LockedPackage> = packages.into_iter().collect();
+    let resolved = network
+        .metadata()
+        .manifest_path(scratch_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata of the scratch project")?;
+
+    let mut resolved_versions: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for package in resolved.packages.iter().filter(|p| p.source.is_some()) {
+        resolved_versions
+            .entry(package.name.as_str())
+            .or_default()
+            .push(package.version.to_string());
+    }
+
+    for (name, versions) in resolved_versions {
+        let vendored: Vec<&LockedPackage> = packages.iter().copied().filter(|p| p.name == name).collect();
+        // Ambiguous with several versions of a crate in the graph, cargo keeps those as they are
+        if let ([resolved], [vendored]) = (versions.as_slice(), vendored.as_slice())
+            && resolved != &vendored.version
+        {
+            // Pinning a crate may already have moved its companions (e.g. serde and serde_derive),
+            // anything that couldn't be pinned shows up in the delta
+            let spec = format!("{name}@{resolved}");
+            let _ = cargo(
+                scratch_path,
+                &["update", "-p", &spec, "--precise", &vendored.version],
+                network,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Moves every resolved release of `name` but `keep` to `version`, cargo picks the newest release a
+// changed requirement accepts
+fn precise(scratch_path: &Path, name: &str, keep: &Version, version: &Version, network: &NetworkArgs) -> Result<()> {
+    let resolved = network
+        .metadata()
+        .manifest_path(scratch_path.join("Cargo.toml"))
+        .other_options(network.cargo_options())
+        .exec()
+        .context("Failed to get cargo metadata of the scratch project")?;
+    for package in resolved
+        .packages
+        .iter()
+        .filter(|p| p.source.is_some() && p.name == name)
+    {
+        if package.version != *keep && package.version != *version {
+            let spec = format!("{name}@{}", package.version);
+            cargo(
+                scratch_path,
+                &["update", "-p", &spec, "--precise", &version.to_string()],
+                network,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Makes the manifest's entries for `name` ask for `version` where their requirement doesn't
+// accept it. Whether there's an entry for the crate at all.
+fn require(manifest_path: &Path, name: &str, version: &Version, journal: &Journal) -> Result<bool> {
+    let content = fs::read_to_string(manifest_path).context(format!("Failed to read {}", manifest_path.display()))?;
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .context(format!("Failed to parse {}", manifest_path.display()))?;
+
+    let mut found = false;
+    for deps in crate::dependency_tables_mut(&mut doc) {
+        found |= require_table(deps, name, version);
+    }
+    if let Some(deps) = crate::workspace_dependencies_mut(&mut doc) {
+        found |= require_table(deps, name, version);
+    }
+
+    if doc.to_string() != content {
+        journal
+            .write(manifest_path, doc.to_string())
+            .context(format!("Failed to write {}", manifest_path.display()))?;
+    }
+    Ok(found)
+}
+
+fn require_table(deps: &mut Table, name: &str, version: &Version) -> bool {
+    let mut found = false;
+    for (key, dep_value) in deps.iter_mut() {
+        let package = dep_value.get("package").and_then(|p| p.as_str()).unwrap_or(key.get());
+        if package != name {
+            continue;
+        }
+        found = true;
+        let requirement = match dep_value.as_str() {
+            Some(requirement) => Some(requirement),
+            None => dep_value.get("version").and_then(|v| v.as_str()),
+        };
+        if requirement
+            .and_then(|requirement| VersionReq::parse(requirement).ok())
+            .is_none_or(|requirement| requirement.matches(version))
+        {
+            continue;
+        }
+        match dep_value.as_table_like_mut() {
+            Some(table) => {
+                table.insert("version", toml_edit::value(version.to_string()));
+            }
+            None => *dep_value = toml_edit::value(version.to_string()),
+        }
+    }
+    found
+}
+
+// Moves path dependencies on `old_dir` to `new_dir`, with the version they keep next to the path
+fn repoint(manifest_path: &Path, old_dir: &Path, new_dir: &Path, version: &Version, journal: &Journal) -> Result<bool> {
+    let content = fs::read_to_string(manifest_path).context(format!("Failed to read {}", manifest_path.display()))?;
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .context(format!("Failed to parse {}", manifest_path.display()))?;
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let rel_path = pathdiff::diff_paths(new_dir, manifest_dir).context("Failed to compute relative path")?;
+
+    let mut changed = false;
+    for deps in crate::dependency_tables_mut(&mut doc) {
+        for (_, dep_value) in deps.iter_mut() {
+            let Some(path) = dep_value.get("path").and_then(|p| p.as_str()) else {
+                continue;
+            };
+            if normalize_path(&manifest_dir.join(path)) != old_dir {
+                continue;
+            }
+            let Some(table) = dep_value.as_table_like_mut() else {
+                continue;
+            };
+            table.insert("path", toml_edit::value(crate::util::slashed(&rel_path)));
+            if table.contains_key("version") {
+                table.insert("version", toml_edit::value(version.to_string()));
+            }
+            changed = true;
+        }
+    }
+
+    if changed {
+        journal
+            .write(manifest_path, doc.to_string())
+            .context(format!("Failed to write {}", manifest_path.display()))?;
+    }
+    Ok(changed)
+}
+
+fn compute_delta(
+    localize_lock: &LocalizeLock,
+    updated: &Metadata,
+    third_party_path: &Path,
+    args: &crate::LocalizeArgs,
+    targets: &[crate::platform::TargetPlatform],
+) -> Delta {
+    let depths = args.max_depth().map(|_| crate::filter::dependency_depths(updated));
+    let needed = crate::needed_packages(updated, targets, args.no_dev_deps, args.no_build_deps);
+    let wanted: BTreeMap<(String, String), PathBuf> = updated
+        .packages
+        .iter()
+        .filter(|p| p.source.is_some())
+        .filter(|p| crate::filter::is_selected(&args.packages, &args.exclude, &p.name, &p.version))
+        .filter(|p| crate::within_depth(args.max_depth(), depths.as_ref(), &p.id))
+        .filter(|p| needed.as_ref().is_none_or(|needed| needed.contains(&p.id)))
+        .map(|p| {
+            let source_dir = p.manifest_path.parent().map(|d| d.as_std_path().to_path_buf());
+            ((p.name.clone(), p.version.to_string()), source_dir.unwrap_or_default())
+        })
+        .collect();
+    let vendored: BTreeSet<(String, String)> = localize_lock
+        .packages
+        .iter()
+        .map(|p| (p.name.clone(), p.version.clone()))
+        .collect();
+
+    let mut delta = Delta::default();
+    let added: Vec<(String, String)> = wanted.keys().filter(|k| !vendored.contains(*k)).cloned().collect();
+    let removed: Vec<(String, String)> = vendored.iter().filter(|k| !wanted.contains_key(*k)).cloned().collect();
+
+    delta.added_size = added.iter().map(|key| dir_size(&wanted[key])).sum();
+    delta.removed_size = removed
+        .iter()
+        .filter_map(|(name, version)| localize_lock.find(name, version))
+        .map(|locked| dir_size(&third_party_path.join(&locked.path)))
+        .sum();
+
+    let added_names: Vec<&str> = added.iter().map(|(name, _)| name.as_str()).collect();
+    let removed_names: Vec<&str> = removed.iter().map(|(name, _)| name.as_str()).collect();
+    let bumps = crate::diff::bumps(&added_names, &removed_names);
+    for &(added_index, removed_index) in &bumps {
+        let (name, to) = added[added_index].clone();
+        delta.bumped.push((name, removed[removed_index].1.clone(), to));
+    }
+    delta.added = added
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !bumps.iter().any(|(bumped, _)| bumped == index))
+        .map(|(_, key)| key)
+        .collect();
+    delta.removed = removed
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !bumps.iter().any(|(_, bumped)| bumped == index))
+        .map(|(_, key)| key)
+        .collect();
+
+    delta
+}
+
+fn cargo(dir: &Path, args: &[&str], network: &NetworkArgs) -> Result<()> {
+    let output = network
+        .cargo(args[0])
+        .args(&args[1..])
+        .current_dir(dir)
+        .output()
+        .context(format!("Failed to run cargo {}", args.join(" ")))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
@@ -0,0 +1,81 @@
+use crate::lock::LocalizeLock;
+use crate::util::{dir_size, format_size};
+use anyhow::Result;
+use std::path::Path;
+use tracing::info;
+
+// Copies shouldn't leave the disk completely full, builds still need room afterwards
+const HEADROOM: u64 = 256 * 1024 * 1024;
+
+// Estimates what copying `packages` takes and stops before a copy would run out of space.
+// Returns the estimate.
+pub fn preflight(packages: &[&cargo_metadata::Package], destination: &Path) -> Result<u64> {
+    if packages.is_empty() {
+        return Ok(0);
+    }
+
+    let estimate: u64 = packages
+        .iter()
+        .filter_map(|package| package.manifest_path.parent())
+        .map(|source| dir_size(source.as_std_path()))
+        .sum();
+    info!(
+        "About to copy {} crate(s), about {}",
+        packages.len(),
+        format_size(estimate)
+    );
+
+    // The destination may not exist yet, its closest existing ancestor is on the same filesystem
+    let Some(existing) = destination.ancestors().find(|path| path.exists()) else {
+        return Ok(estimate);
+    };
+    let Ok(available) = fs2::available_space(existing) else {
+        return Ok(estimate);
+    };
+    if estimate + HEADROOM > available {
+        anyhow::bail!(
+            "Not enough free space on the filesystem of {}: copying needs about {} (plus {} headroom), {} available",
+            destination.display(),
+            format_size(estimate),
+            format_size(HEADROOM),
+            format_size(available)
+        );
+    }
+    Ok(estimate)
+}
+
+// Crates listed in the size report without `--verbose`
+const REPORT_TOP: usize = 10;
+
+// Per-crate sizes of the vendored tree, largest first
+pub fn print_report(localize_lock: &LocalizeLock, third_party_path: &Path) {
+    let mut sizes: Vec<(u64, String)> = localize_lock
+        .packages
+        .iter()
+        .map(|locked| {
+            (
+                dir_size(&third_party_path.join(&locked.path)),
+                format!("{} v{}", locked.name, locked.version),
+            )
+        })
+        .collect();
+    if sizes.is_empty() {
+        return;
+    }
+    sizes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    info!("Vendored crates by size:");
+    let shown = if tracing::enabled!(tracing::Level::DEBUG) {
+        sizes.len()
+    } else {
+        REPORT_TOP
+    };
+    for (size, label) in sizes.iter().take(shown) {
+        info!("  {:>10}  {label}", format_size(*size));
+    }
+    if sizes.len() > shown {
+        info!("  {:>10}  {} more, --verbose lists all", "...", sizes.len() - shown);
+    }
+    let total: u64 = sizes.iter().map(|(size, _)| size).sum();
+    info!("  {:>10}  total, {} crate(s)", format_size(total), sizes.len());
+}
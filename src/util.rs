@@ -0,0 +1,210 @@
+use crate::config::SymlinkPolicy;
+use anyhow::Result;
+use fs_extra::dir::CopyOptions;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tracing::debug;
+use walkdir::WalkDir;
+
+// Resolves `.` and `..` components lexically, without touching the filesystem
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Paths written into manifests use forward slashes, which cargo takes on every platform, so a
+// manifest rewritten on Windows still works in a checkout on Linux
+pub fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Windows refuses paths longer than 260 characters unless they're in the verbatim `\\?\` form,
+// which deep crate trees in a deep checkout run into. Other platforms take paths as they are.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let Some(absolute_str) = absolute.to_str() else {
+        return absolute;
+    };
+    if absolute_str.starts_with(r"\\?\") {
+        absolute
+    } else if let Some(share) = absolute_str.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{share}"))
+    } else {
+        PathBuf::from(format!(r"\\?\{absolute_str}"))
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+// Copies the contents of `from` into `to`, but for the top-level entries named in `skip`, treating
+// symlinks the way `symlinks` says. Links that can't be created, e.g. on Windows without the
+// privilege, are followed instead, so a crate never fails to copy over one.
+pub fn copy_tree(
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+    skip: &[&str],
+    ignore: &[String],
+    symlinks: SymlinkPolicy,
+) -> Result<()> {
+    copy_entries(from, to, tree_entries(from, skip, ignore, symlinks), options, symlinks)
+}
+
+// Entries under `from` that `copy_tree` copies
+pub fn tree_entries<'a>(
+    from: &'a Path,
+    skip: &'a [&str],
+    ignore: &'a [String],
+    symlinks: SymlinkPolicy,
+) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+    WalkDir::new(from)
+        .min_depth(1)
+        .follow_links(symlinks == SymlinkPolicy::Follow)
+        .into_iter()
+        .filter_entry(move |entry| {
+            (entry.depth() > 1 || !skip.iter().any(|name| entry.file_name() == *name)) && !ignored(from, entry, ignore)
+        })
+}
+
+// Copies entries of `tree_entries(from, ..)` into `to`
+pub fn copy_entries(
+    from: &Path,
+    to: &Path,
+    entries: impl IntoIterator<Item = walkdir::Result<walkdir::DirEntry>>,
+    options: &CopyOptions,
+    symlinks: SymlinkPolicy,
+) -> Result<()> {
+    let file_options = fs_extra::file::CopyOptions::new()
+        .overwrite(options.overwrite)
+        .buffer_size(options.buffer_size);
+    fs::create_dir_all(to)?;
+    for entry in entries {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        let file_type = entry.file_type();
+        if file_type.is_symlink() {
+            if symlinks == SymlinkPolicy::Skip {
+                debug!("  Left out symlink {}", entry.path().display());
+                continue;
+            }
+            let is_dir = entry.path().is_dir();
+            if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_dir()) {
+                fs::remove_dir_all(&target)?;
+            } else if fs::symlink_metadata(&target).is_ok() {
+                fs::remove_file(&target)?;
+            }
+            if let Err(error) = symlink(&fs::read_link(entry.path())?, &target, is_dir) {
+                debug!(
+                    "  Couldn't create symlink {} ({error}), copying what it points to",
+                    target.display()
+                );
+                if is_dir {
+                    copy_tree(entry.path(), &target, options, &[], &[], SymlinkPolicy::Follow)?;
+                } else {
+                    fs_extra::file::copy(entry.path(), &target, &file_options)?;
+                }
+            }
+        } else if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs_extra::file::copy(entry.path(), &target, &file_options)?;
+        }
+    }
+    Ok(())
+}
+
+// Whether an entry matches one of the `ignore` globs. The manifest is kept whatever they say, the
+// crate is no crate without it.
+fn ignored(root: &Path, entry: &walkdir::DirEntry, ignore: &[String]) -> bool {
+    if ignore.is_empty() || (entry.depth() == 1 && entry.file_name() == "Cargo.toml") {
+        return false;
+    }
+    let relative = slashed(entry.path().strip_prefix(root).unwrap_or(entry.path()));
+    let ignored = ignore
+        .iter()
+        .any(|pattern| crate::filter::path_glob_match(pattern, &relative));
+    if ignored {
+        debug!("  Left out {relative}");
+    }
+    ignored
+}
+
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path, _is_dir: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path, is_dir: bool) -> std::io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+// Total size of regular files under `path`
+pub fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+// Formats unix seconds as an RFC 3339 UTC timestamp
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,273 @@
+use crate::lock::{LOCK_FILE_NAME, LocalizeLock, LockedPackage};
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+// Every crate `cargo vendor` writes carries one, cargo checks the crate's files against it
+pub const CARGO_CHECKSUM_FILE_NAME: &str = ".cargo-checksum.json";
+// The source name `cargo vendor` tells cargo to replace the registries with
+const VENDORED_SOURCES: &str = "vendored-sources";
+// Left out of exported crates: cargo's extraction marker, the manifest backup and stale checksums
+const NOT_EXPORTED: [&str; 3] = [".cargo-ok", "Cargo.toml.bak", CARGO_CHECKSUM_FILE_NAME];
+
+#[derive(clap::Args)]
+pub struct ImportVendorArgs {
+    /// Folder written by `cargo vendor`
+    #[clap(long, value_name = "DIR", default_value = "vendor")]
+    from: PathBuf,
+    /// Keep the folder and cargo's source replacement once the crates are localized
+    #[clap(long)]
+    keep_vendor: bool,
+    #[clap(flatten)]
+    localize: crate::LocalizeArgs,
+}
+
+#[derive(clap::Args)]
+pub struct ExportVendorArgs {
+    #[clap(flatten)]
+    project: crate::ProjectPath,
+    /// Folder to write, in the layout `cargo vendor` produces
+    #[clap(short, long, value_name = "DIR", default_value = "vendor")]
+    output: PathBuf,
+    /// Replace the folder if it exists
+    #[clap(long)]
+    force: bool,
+}
+
+// Localizes a project that used `cargo vendor`: cargo still resolves through the vendored
+// folder, so the crates are copied from there without downloading anything. Afterwards the
+// manifests point into the 3rd-party folder and the source replacement is dropped with the folder.
+pub fn import(args: &ImportVendorArgs) -> Result<()> {
+    let project_path = args.localize.project.path.root()?;
+    if LocalizeLock::load(&project_path)?.is_some() {
+        bail!("{LOCK_FILE_NAME} already exists, the project is localized already");
+    }
+    let vendor_path = project_path.join(&args.from);
+    let crates = fs::read_dir(&vendor_path)
+        .context(format!("Failed to read {}", vendor_path.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join(CARGO_CHECKSUM_FILE_NAME).is_file())
+        .count();
+    if crates == 0 {
+        bail!("{} holds no crates vendored by `cargo vendor`", vendor_path.display());
+    }
+    let config_path = cargo_config(&project_path)
+        .filter(|config| replacement(config, &vendor_path).is_some())
+        .context(format!(
+            "Cargo doesn't read {} yet, add the source replacement `cargo vendor` printed to .cargo/config.toml",
+            vendor_path.display()
+        ))?;
+
+    info!(
+        "Importing {crates} crate(s) from {}...",
+        vendor_path
+            .strip_prefix(&project_path)
+            .unwrap_or(&vendor_path)
+            .display()
+    );
+    crate::localize(&args.localize, false)?;
+    if args.keep_vendor {
+        return Ok(());
+    }
+
+    // The manifests no longer reach the registries, the replacement has nothing left to replace
+    let content = fs::read_to_string(&config_path).context(format!("Failed to read {}", config_path.display()))?;
+    let mut config: DocumentMut = content
+        .parse()
+        .context(format!("Failed to parse {}", config_path.display()))?;
+    if let Some(name) = replacement(&config_path, &vendor_path)
+        && let Some(sources) = config.get_mut("source").and_then(Item::as_table_like_mut)
+    {
+        sources.remove(&name);
+        for (_, source) in sources.iter_mut() {
+            let replaced = source
+                .get("replace-with")
+                .and_then(Item::as_str)
+                .is_some_and(|replace_with| replace_with == name);
+            if replaced && let Some(source) = source.as_table_like_mut() {
+                source.remove("replace-with");
+            }
+        }
+        // What's left of a replaced source is only its location, which cargo knows anyway
+        let bare: Vec<String> = sources
+            .iter()
+            .filter(|(_, source)| {
+                source.as_table_like().is_none_or(|source| {
+                    source
+                        .iter()
+                        .all(|(key, _)| ["registry", "git", "branch", "tag", "rev"].contains(&key))
+                })
+            })
+            .map(|(name, _)| name.to_string())
+            .collect();
+        for name in bare {
+            sources.remove(&name);
+        }
+        if sources.is_empty() {
+            config.remove("source");
+        }
+    }
+    if config.is_empty() {
+        fs::remove_file(&config_path).context(format!("Failed to remove {}", config_path.display()))?;
+        if let Some(cargo_dir) = config_path.parent() {
+            let _ = fs::remove_dir(cargo_dir);
+        }
+    } else {
+        fs::write(&config_path, config.to_string()).context(format!("Failed to write {}", config_path.display()))?;
+    }
+    fs::remove_dir_all(&vendor_path).context(format!("Failed to remove {}", vendor_path.display()))?;
+    println!(
+        "Removed {} and its source replacement from {}",
+        vendor_path.display(),
+        config_path.display()
+    );
+    Ok(())
+}
+
+// Writes the vendored crates the way `cargo vendor` would have: one folder per crate with the
+// registry's manifest and a `.cargo-checksum.json`, and the source replacement to point cargo at it
+pub fn export(args: &ExportVendorArgs) -> Result<()> {
+    let project_path = args.project.root()?;
+    let localize_lock = LocalizeLock::require(&project_path)?;
+    let third_party_path = localize_lock.third_party_path(&project_path);
+    let output = project_path.join(&args.output);
+    if output.exists() {
+        if !args.force {
+            bail!("{} already exists, pass --force to replace it", output.display());
+        }
+        fs::remove_dir_all(&output).context(format!("Failed to remove {}", output.display()))?;
+    }
+
+    // Crates vendored in more than one version get theirs in the folder name, like `cargo vendor` does
+    let mut versions: HashMap<&str, usize> = HashMap::new();
+    for locked in &localize_lock.packages {
+        *versions.entry(locked.name.as_str()).or_default() += 1;
+    }
+    info!(
+        "Exporting {} crate(s) to {}...",
+        localize_lock.packages.len(),
+        output.display()
+    );
+    let mut sources = BTreeMap::new();
+    for locked in &localize_lock.packages {
+        let Some(source) = &locked.source else {
+            warn!(
+                "{} v{} has no registry or git source, left out",
+                locked.name, locked.version
+            );
+            continue;
+        };
+        let dir_name = match versions[locked.name.as_str()] {
+            1 => locked.name.clone(),
+            _ => format!("{}-{}", locked.name, locked.version),
+        };
+        export_crate(locked, &third_party_path.join(&locked.path), &output.join(dir_name))?;
+        sources.insert(source_key(source), source.clone());
+    }
+
+    let output_dir = output
+        .strip_prefix(&project_path)
+        .unwrap_or(&output)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let mut config = String::new();
+    for (key, source) in &sources {
+        config.push_str(&format!("[source.{}]\n", toml_edit::Key::new(key.as_str())));
+        if let Some(url) = source.strip_prefix("git+") {
+            let url = url.split_once('#').map_or(url, |(url, _)| url);
+            let (url, query) = url.split_once('?').unwrap_or((url, ""));
+            config.push_str(&format!("git = \"{url}\"\n"));
+            for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                config.push_str(&format!("{name} = \"{value}\"\n"));
+            }
+        } else if key != "crates-io" {
+            let url = source.strip_prefix("registry+").unwrap_or(source);
+            config.push_str(&format!("registry = \"{url}\"\n"));
+        }
+        config.push_str(&format!("replace-with = \"{VENDORED_SOURCES}\"\n\n"));
+    }
+    config.push_str(&format!("[source.{VENDORED_SOURCES}]\ndirectory = \"{output_dir}\"\n"));
+    println!(
+        "To build from {output_dir} instead of {}, point the manifests back at the registries",
+        localize_lock.third_party_dir
+    );
+    println!("and add this to .cargo/config.toml:\n\n{config}");
+    Ok(())
+}
+
+// Copies the crate with its original manifest, local edits included, and lists every file's
+// hash; `package` is the `.crate` checksum Cargo.lock records, git crates have none
+fn export_crate(locked: &LockedPackage, crate_dir: &Path, target: &Path) -> Result<()> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(crate_dir).follow_links(true).sort_by_file_name() {
+        let entry = entry.context(format!("Failed to read {}", crate_dir.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(crate_dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if NOT_EXPORTED.contains(&relative.as_str()) {
+            continue;
+        }
+        // The backup is the manifest as the registry shipped it, before rewriting and pruning
+        let source = match relative.as_str() {
+            "Cargo.toml" if crate_dir.join("Cargo.toml.bak").is_file() => crate_dir.join("Cargo.toml.bak"),
+            _ => entry.path().to_path_buf(),
+        };
+        let destination = target.join(&relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+        }
+        fs::copy(&source, &destination).context(format!("Failed to copy {}", source.display()))?;
+        files.insert(relative, crate::journal::file_sha256(&destination)?);
+    }
+    let checksum = serde_json::json!({
+        "files": files,
+        "package": locked.checksum,
+    });
+    fs::write(target.join(CARGO_CHECKSUM_FILE_NAME), checksum.to_string()).context(format!(
+        "Failed to write the checksums of {} v{}",
+        locked.name, locked.version
+    ))
+}
+
+// How cargo's config names a source: crates.io by its name, others by their id without the commit
+fn source_key(source: &str) -> String {
+    if crate::lock::is_crates_io(source) {
+        return "crates-io".to_string();
+    }
+    source.split_once('#').map_or(source, |(source, _)| source).to_string()
+}
+
+fn cargo_config(project_path: &Path) -> Option<PathBuf> {
+    ["config.toml", "config"]
+        .into_iter()
+        .map(|name| project_path.join(".cargo").join(name))
+        .find(|path| path.is_file())
+}
+
+// Name of the directory source in the config that reads the vendored folder
+fn replacement(config_path: &Path, vendor_path: &Path) -> Option<String> {
+    let config: DocumentMut = fs::read_to_string(config_path).ok()?.parse().ok()?;
+    let base = config_path.parent()?.parent()?;
+    let vendor_path = vendor_path.canonicalize().ok()?;
+    config
+        .get("source")?
+        .as_table_like()?
+        .iter()
+        .find(|(_, source)| {
+            source
+                .get("directory")
+                .and_then(Item::as_str)
+                .and_then(|directory| base.join(directory).canonicalize().ok())
+                .is_some_and(|directory| directory == vendor_path)
+        })
+        .map(|(name, _)| name.to_string())
+}
@@ -0,0 +1,192 @@
+use crate::CrateRef;
+use crate::lock::LocalizeLock;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::info;
+
+// Where cargo names the crate an error is about, right after an opening backtick
+const BLAMING_PHRASES: &[&str] = &[
+    "could not compile `",
+    "failed to select a version for `",
+    "failed to select a version for the requirement `",
+    "no matching package named `",
+    "failed to load manifest for dependency `",
+    "failed to get `",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Verification {
+    /// `cargo metadata --offline`: the dependency graph resolves from the vendored crates alone
+    Resolve,
+    /// `cargo check --offline`: the workspace compiles against them as well
+    Check,
+}
+
+impl Verification {
+    fn subcommand(self) -> &'static str {
+        match self {
+            Verification::Resolve => "metadata",
+            Verification::Check => "check",
+        }
+    }
+}
+
+// Runs cargo offline on the localized project, so a rewrite that broke resolution or the build
+// shows up now rather than on the next build. The Cargo.lock cargo writes along the way is put
+// back the way the run left it, a failure names the vendored crates cargo blamed.
+pub fn verify(project_path: &Path, verification: Verification) -> Result<()> {
+    info!(
+        "Verifying the localized project with `cargo {} --offline`...",
+        verification.subcommand()
+    );
+    let lockfile = project_path.join("Cargo.lock");
+    let before = fs::read(&lockfile).ok();
+    let localize_lock = LocalizeLock::load(project_path)?;
+
+    let output = command(project_path, verification, localize_lock.as_ref())
+        .output()
+        .context(format!("Failed to run cargo {}", verification.subcommand()))?;
+
+    match before {
+        Some(content) => fs::write(&lockfile, content).context("Failed to restore Cargo.lock")?,
+        None if lockfile.exists() => fs::remove_file(&lockfile).context("Failed to remove Cargo.lock")?,
+        None => {}
+    }
+    if !output.status.success() {
+        let output = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+        let crates = match &localize_lock {
+            Some(localize_lock) => blamed(&output, localize_lock),
+            None => Vec::new(),
+        };
+        return Err(crate::LocalizeError::Unverified {
+            command: verification.subcommand().to_string(),
+            crates,
+            output,
+        }
+        .into());
+    }
+    info!(
+        "The localized project resolves{}",
+        match verification {
+            Verification::Resolve => "",
+            Verification::Check => " and compiles",
+        }
+    );
+    Ok(())
+}
+
+// The cargo command checking the project for the platforms it was localized for: crates only
+// other targets need may have been left on the registry, or pruned from vendored manifests
+fn command(project_path: &Path, verification: Verification, localize_lock: Option<&LocalizeLock>) -> Command {
+    let mut command = Command::new("cargo");
+    command
+        .arg(verification.subcommand())
+        .arg("--offline")
+        .arg("--manifest-path")
+        .arg(project_path.join("Cargo.toml"));
+    match verification {
+        Verification::Resolve => command.args(["--format-version", "1"]).stdout(Stdio::null()),
+        Verification::Check => command.args(["--workspace", "--quiet"]),
+    };
+    let targets: Vec<&str> = match localize_lock {
+        Some(localize_lock) if !localize_lock.targets.is_empty() => {
+            localize_lock.targets.iter().map(String::as_str).collect()
+        }
+        Some(localize_lock) => localize_lock.filter_platform.as_deref().into_iter().collect(),
+        None => Vec::new(),
+    };
+    let option = match verification {
+        Verification::Resolve => "--filter-platform",
+        Verification::Check => "--target",
+    };
+    for target in targets {
+        command.args([option, target]);
+    }
+    command
+}
+
+// Vendored crates cargo's errors name, in the order they come up
+fn blamed(output: &str, localize_lock: &LocalizeLock) -> Vec<CrateRef> {
+    let mut crates: Vec<CrateRef> = Vec::new();
+    for line in output.lines() {
+        for phrase in BLAMING_PHRASES {
+            let Some((_, rest)) = line.split_once(phrase) else {
+                continue;
+            };
+            // `name` alone, or a requirement like `name = "^1.0"`
+            let name = rest
+                .split('`')
+                .next()
+                .and_then(|quoted| quoted.split([' ', '=']).next())
+                .unwrap_or_default();
+            for locked in localize_lock.packages.iter().filter(|locked| locked.name == name) {
+                let krate = CrateRef {
+                    path: Some(Path::new(&localize_lock.third_party_dir).join(&locked.path)),
+                    ..locked.into()
+                };
+                if !crates.contains(&krate) {
+                    crates.push(krate);
+                }
+            }
+        }
+    }
+    crates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::Strategy;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn verify_resolves_for_the_targets_of_the_run() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(
+            project.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"1.0.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(project.path().join("src")).unwrap();
+        fs::write(project.path().join("src/lib.rs"), "").unwrap();
+        let mut localize_lock = LocalizeLock::new(Strategy::Rewrite, "3rd-party");
+        localize_lock.targets = vec![
+            "wasm32-unknown-unknown".to_string(),
+            "x86_64-unknown-linux-gnu".to_string(),
+        ];
+        localize_lock
+            .save(project.path(), &crate::journal::Journal::default())
+            .unwrap();
+
+        let resolve = command(project.path(), Verification::Resolve, Some(&localize_lock));
+        assert!(args(&resolve).ends_with(&[
+            "--filter-platform".to_string(),
+            "wasm32-unknown-unknown".to_string(),
+            "--filter-platform".to_string(),
+            "x86_64-unknown-linux-gnu".to_string(),
+        ]));
+        let check = command(project.path(), Verification::Check, Some(&localize_lock));
+        assert!(args(&check).ends_with(&[
+            "--target".to_string(),
+            "wasm32-unknown-unknown".to_string(),
+            "--target".to_string(),
+            "x86_64-unknown-linux-gnu".to_string(),
+        ]));
+        verify(project.path(), Verification::Resolve).unwrap();
+        assert!(!project.path().join("Cargo.lock").exists());
+
+        localize_lock.targets.clear();
+        localize_lock.filter_platform = Some("x86_64-unknown-linux-gnu".to_string());
+        let check = command(project.path(), Verification::Check, Some(&localize_lock));
+        assert!(args(&check).ends_with(&["--target".to_string(), "x86_64-unknown-linux-gnu".to_string()]));
+        assert!(!args(&command(project.path(), Verification::Check, None)).contains(&"--target".to_string()));
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum WarningClass {
+    /// Vendored crates the dependency graph no longer uses
+    Unused,
+    /// Pruned manifests whose backup is gone, their target tables can't be restored
+    MissingBackup,
+    /// Damaged registry sources that were extracted again
+    RepairedSource,
+    /// An interrupted run left `localize.journal` behind
+    Interrupted,
+    /// Crates come from a host that wasn't pinned, or a pinned host chains to another root CA
+    RegistryHost,
+    /// A vendored crate version has a RustSec vulnerability or unsoundness advisory
+    Advisory,
+    /// A vendored file name won't check out on Windows or macOS
+    Portability,
+    /// An independent Cargo project inside the project keeps its dependencies on the registry
+    Nested,
+    /// Patches of a vendored crate that changed can't be applied again, its published source is missing
+    Patch,
+    /// A vendored build script looks like it reaches the network at compile time
+    NetworkBuildScript,
+    /// A vendored build script looks like it reads files outside the crate at compile time
+    FilesystemBuildScript,
+    /// Every class above
+    All,
+}
+
+impl WarningClass {
+    fn name(self) -> &'static str {
+        match self {
+            WarningClass::Unused => "unused",
+            WarningClass::MissingBackup => "missing-backup",
+            WarningClass::RepairedSource => "repaired-source",
+            WarningClass::Interrupted => "interrupted",
+            WarningClass::RegistryHost => "registry-host",
+            WarningClass::Advisory => "advisory",
+            WarningClass::Portability => "portability",
+            WarningClass::Nested => "nested-project",
+            WarningClass::Patch => "patch",
+            WarningClass::NetworkBuildScript => "network-build-script",
+            WarningClass::FilesystemBuildScript => "filesystem-build-script",
+            WarningClass::All => "all",
+        }
+    }
+}
+
+// Warnings raised by the current run, checked against `--deny` once it's over
+static RAISED: Mutex<Vec<WarningClass>> = Mutex::new(Vec::new());
+
+// Printed as `warning[class]: message`, a stable prefix pipelines can grep for
+pub fn warn(class: WarningClass, message: impl std::fmt::Display) {
+    tracing::warn!(class = class.name(), "{message}");
+    RAISED.lock().unwrap().push(class);
+}
+
+// Forgets the warnings of a run that failed before they were checked, for the next run in the
+// same process
+pub fn clear() {
+    RAISED.lock().unwrap().clear();
+}
+
+// Fails the run if it raised a warning of a blocking class
+pub fn deny(blocking: &[WarningClass]) -> anyhow::Result<()> {
+    let raised = std::mem::take(&mut *RAISED.lock().unwrap());
+    let blocked: Vec<WarningClass> = raised
+        .into_iter()
+        .filter(|class| {
+            blocking
+                .iter()
+                .any(|blocking| *blocking == WarningClass::All || blocking == class)
+        })
+        .collect();
+    if !blocked.is_empty() {
+        let mut classes = blocked.clone();
+        classes.sort_by_key(|class| class.name());
+        classes.dedup();
+        return Err(crate::LocalizeError::Denied {
+            count: blocked.len(),
+            classes,
+        }
+        .into());
+    }
+    Ok(())
+}
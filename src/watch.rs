@@ -0,0 +1,123 @@
+use crate::config::CONFIG_FILE_NAME;
+use crate::{LocalizeArgs, LocalizeError};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[derive(clap::Args)]
+pub struct WatchArgs {
+    #[clap(flatten)]
+    localize: LocalizeArgs,
+    /// How often the manifests are checked for changes, in milliseconds
+    #[clap(long, value_name = "MS", default_value = "1000")]
+    interval: u64,
+}
+
+// What the files watched held when last looked at, `None` for files that didn't exist
+type Snapshot = BTreeMap<PathBuf, Option<String>>;
+
+// Syncs the project, then again whenever the manifests of the workspace or the config file change,
+// until interrupted. Manifests are polled rather than watched, which works the same on every
+// platform and file system. A run that fails is reported and the next change tries again, unless
+// it left the project unfinished.
+pub fn watch(args: &WatchArgs) -> Result<()> {
+    let project_path = args.localize.project.path.root()?;
+    let interval = Duration::from_millis(args.interval.max(1));
+
+    let mut files = watched_files(args, &project_path)?;
+    sync(args)?;
+    let mut snapshot = take_snapshot(&files)?;
+    info!(
+        "Watching {} file(s) of {} for dependency changes, Ctrl-C to stop",
+        files.len(),
+        project_path.display()
+    );
+    loop {
+        thread::sleep(interval);
+        if take_snapshot(&files)? == snapshot {
+            continue;
+        }
+        // Editors and `cargo add` may write several files, the run waits until they're done
+        let mut settled = take_snapshot(&files)?;
+        loop {
+            thread::sleep(interval);
+            let current = take_snapshot(&files)?;
+            if current == settled {
+                break;
+            }
+            settled = current;
+        }
+        let changed: Vec<String> = files
+            .iter()
+            .filter(|file| snapshot.get(*file) != settled.get(*file))
+            .map(|file| crate::util::slashed(file.strip_prefix(&project_path).unwrap_or(file)))
+            .collect();
+        info!("{} changed, syncing", changed.join(", "));
+        sync(args)?;
+        // Members may have come or gone, and the run rewrote what it localized
+        files = watched_files(args, &project_path).unwrap_or(files);
+        snapshot = take_snapshot(&files)?;
+    }
+}
+
+fn sync(args: &WatchArgs) -> Result<()> {
+    match crate::localize(&args.localize, true) {
+        Ok(()) => Ok(()),
+        // Nothing runs until the journal is dealt with
+        Err(error @ (LocalizeError::Interrupted { .. } | LocalizeError::Unfinished { .. })) => Err(error.into()),
+        Err(error) => {
+            crate::warnings::clear();
+            let hint = error.hint();
+            warn!("{:#}", anyhow::Error::from(error));
+            if let Some(hint) = hint {
+                warn!("{hint}");
+            }
+            Ok(())
+        }
+    }
+}
+
+// The root manifest, the manifests of the workspace members and the config file. Not the lockfile,
+// which runs delete or regenerate and every later build rewrites, so watching it would retrigger.
+fn watched_files(args: &WatchArgs, project_path: &Path) -> Result<Vec<PathBuf>> {
+    let manifest = project_path.join("Cargo.toml");
+    let metadata = args
+        .localize
+        .network
+        .metadata()
+        .manifest_path(&manifest)
+        .no_deps()
+        .exec()
+        .context("Failed to get cargo metadata")?;
+    let mut files: Vec<PathBuf> = metadata
+        .workspace_packages()
+        .into_iter()
+        .map(|package| package.manifest_path.clone().into_std_path_buf())
+        .chain([
+            manifest,
+            metadata.workspace_root.join("Cargo.toml").into_std_path_buf(),
+            project_path.join(CONFIG_FILE_NAME),
+        ])
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn take_snapshot(files: &[PathBuf]) -> Result<Snapshot> {
+    files
+        .iter()
+        .map(|file| {
+            let hash = match fs::read(file) {
+                Ok(content) => Some(crate::journal::sha256(&content)),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                Err(error) => return Err(error).context(format!("Failed to read {}", file.display())),
+            };
+            Ok((file.clone(), hash))
+        })
+        .collect()
+}